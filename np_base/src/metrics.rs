@@ -0,0 +1,116 @@
+use std::fmt::{Display, Write};
+
+/// Prometheus 文本格式（text/plain; version=0.0.4）指标输出
+///
+/// 同一指标的样本必须连续写出：先调用 `family` 写入 HELP/TYPE，再逐条调用 `sample`。
+#[derive(Default)]
+pub struct MetricsWriter {
+    buf: String,
+}
+
+/// Prometheus 文本格式的 Content-Type
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 写入指标的 HELP 与 TYPE 行，metric_type 为 gauge 或 counter
+    pub fn family(&mut self, name: &str, metric_type: &str, help: &str) -> &mut Self {
+        let _ = writeln!(self.buf, "# HELP {} {}", name, escape_help(help));
+        let _ = writeln!(self.buf, "# TYPE {} {}", name, metric_type);
+        self
+    }
+
+    /// 写入一条样本
+    pub fn sample(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        value: impl Display,
+    ) -> &mut Self {
+        self.buf.push_str(name);
+        if !labels.is_empty() {
+            self.buf.push('{');
+            for (i, (key, val)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.buf.push(',');
+                }
+                let _ = write!(self.buf, "{}=\"{}\"", key, escape_label_value(val));
+            }
+            self.buf.push('}');
+        }
+        let _ = writeln!(self.buf, " {}", value);
+        self
+    }
+
+    /// 写入只有一条无标签样本的指标
+    pub fn single(
+        &mut self,
+        name: &str,
+        metric_type: &str,
+        help: &str,
+        value: impl Display,
+    ) -> &mut Self {
+        self.family(name, metric_type, help)
+            .sample(name, &[], value)
+    }
+
+    pub fn finish(self) -> String {
+        self.buf
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 隧道类型在指标标签中的名称
+pub fn tunnel_type_label(tunnel_type: u32) -> &'static str {
+    match tunnel_type {
+        0 => "tcp",
+        1 => "udp",
+        2 => "socks5",
+        3 => "http",
+        4 => "socks4",
+        5 => "mixed",
+        6 => "transparent",
+        7 => "vhost",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_writer() {
+        let mut writer = MetricsWriter::new();
+        writer
+            .family("npipe_player_online", "gauge", "Whether the user is online")
+            .sample(
+                "npipe_player_online",
+                &[("user", "a\"b\\c\n"), ("protocol", "tcp")],
+                1,
+            );
+        writer.single("npipe_players_total", "gauge", "Registered users", 3);
+        assert_eq!(
+            writer.finish(),
+            "# HELP npipe_player_online Whether the user is online\n\
+             # TYPE npipe_player_online gauge\n\
+             npipe_player_online{user=\"a\\\"b\\\\c\\n\",protocol=\"tcp\"} 1\n\
+             # HELP npipe_players_total Registered users\n\
+             # TYPE npipe_players_total gauge\n\
+             npipe_players_total 3\n"
+        );
+    }
+}
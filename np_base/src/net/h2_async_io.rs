@@ -0,0 +1,197 @@
+use bytes::{Buf, Bytes, BytesMut};
+use h2::{RecvStream, SendStream};
+use http::HeaderMap;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// gRPC 消息头长度：1 字节压缩标志 + 4 字节消息长度
+const GRPC_HEADER_LEN: usize = 5;
+
+/// 单次写入的最大长度，避免一次占用过多流控窗口
+const MAX_WRITE_LEN: usize = 64 * 1024;
+
+/// HTTP/2 流的窗口大小
+pub(crate) const STREAM_WINDOW_SIZE: u32 = 1024 * 1024;
+
+/// HTTP/2 连接的窗口大小，多条流共享
+pub(crate) const CONNECTION_WINDOW_SIZE: u32 = 16 * 1024 * 1024;
+
+/// gRPC 请求与响应的 content-type
+pub const GRPC_CONTENT_TYPE: &str = "application/grpc";
+
+/// 包装一条 HTTP/2 流，使其实现 `AsyncRead` + `AsyncWrite`
+///
+/// gRPC 模式下写入的数据封装为 gRPC 消息（长度前缀），读取时去掉消息头，
+/// 经过只放行 gRPC 的 CDN 与反向代理时看起来与普通的双向流 RPC 一致。
+pub struct H2AsyncIo {
+    send: SendStream<Bytes>,
+    recv: RecvStream,
+    grpc: bool,
+    /// 关闭写入时发送的 trailers，服务端 gRPC 模式下为 `grpc-status: 0`
+    trailers: Option<HeaderMap>,
+    /// 已解码、未读完的数据
+    read_buf: Bytes,
+    /// gRPC 模式下尚未解码的数据
+    incoming: BytesMut,
+    /// 当前 gRPC 消息剩余的长度
+    message_remaining: usize,
+}
+
+impl H2AsyncIo {
+    pub fn new(send: SendStream<Bytes>, recv: RecvStream, grpc: bool) -> Self {
+        Self {
+            send,
+            recv,
+            grpc,
+            trailers: None,
+            read_buf: Bytes::new(),
+            incoming: BytesMut::new(),
+            message_remaining: 0,
+        }
+    }
+
+    pub(crate) fn with_trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+
+    /// 从 incoming 中取出下一段 gRPC 消息内容
+    fn decode_grpc(&mut self) -> Option<Bytes> {
+        loop {
+            if self.message_remaining == 0 {
+                if self.incoming.len() < GRPC_HEADER_LEN {
+                    return None;
+                }
+                let len = u32::from_be_bytes([
+                    self.incoming[1],
+                    self.incoming[2],
+                    self.incoming[3],
+                    self.incoming[4],
+                ]);
+                self.incoming.advance(GRPC_HEADER_LEN);
+                self.message_remaining = len as usize;
+                continue;
+            }
+            let n = self.message_remaining.min(self.incoming.len());
+            if n == 0 {
+                return None;
+            }
+            self.message_remaining -= n;
+            return Some(self.incoming.split_to(n).freeze());
+        }
+    }
+}
+
+fn to_io_error(err: h2::Error) -> io::Error {
+    if err.is_io() {
+        err.into_io()
+            .unwrap_or_else(|| io::Error::other("h2 io error"))
+    } else {
+        io::Error::other(err)
+    }
+}
+
+impl AsyncRead for H2AsyncIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            // 优先从缓存读取残留数据（read_buf.slice() 是 O(1)）
+            if !self.read_buf.is_empty() {
+                let n = std::cmp::min(self.read_buf.len(), buf.remaining());
+                buf.put_slice(&self.read_buf[..n]);
+                self.read_buf = self.read_buf.slice(n..);
+                return Poll::Ready(Ok(()));
+            }
+            if self.grpc {
+                if let Some(data) = self.decode_grpc() {
+                    self.read_buf = data;
+                    continue;
+                }
+            }
+
+            match ready!(self.recv.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    // 数据交给上层后立即归还流控窗口
+                    let _ = self.recv.flow_control().release_capacity(data.len());
+                    if self.grpc {
+                        self.incoming.extend_from_slice(&data);
+                    } else {
+                        self.read_buf = data;
+                    }
+                }
+                Some(Err(err)) => {
+                    // 对端正常关闭流
+                    if err.reason() == Some(h2::Reason::NO_ERROR) {
+                        return Poll::Ready(Ok(()));
+                    }
+                    return Poll::Ready(Err(to_io_error(err)));
+                }
+                // EOF
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for H2AsyncIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let header_len = if self.grpc { GRPC_HEADER_LEN } else { 0 };
+        let len = buf.len().min(MAX_WRITE_LEN);
+
+        // 等待流控窗口，gRPC 模式下至少要能写入消息头与 1 字节数据
+        let capacity = loop {
+            let capacity = self.send.capacity();
+            if capacity > header_len {
+                break capacity;
+            }
+            self.send.reserve_capacity(header_len + len);
+            match ready!(self.send.poll_capacity(cx)) {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Poll::Ready(Err(to_io_error(err))),
+                None => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            }
+        };
+
+        let n = len.min(capacity - header_len);
+        let data = if self.grpc {
+            let mut data = BytesMut::with_capacity(GRPC_HEADER_LEN + n);
+            data.extend_from_slice(&[0]);
+            data.extend_from_slice(&(n as u32).to_be_bytes());
+            data.extend_from_slice(&buf[..n]);
+            data.freeze()
+        } else {
+            Bytes::copy_from_slice(&buf[..n])
+        };
+        self.send.send_data(data, false).map_err(to_io_error)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // 数据由连接任务发送
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let result = match self.trailers.take() {
+            Some(trailers) => self.send.send_trailers(trailers),
+            None => self.send.send_data(Bytes::new(), true),
+        };
+        // 流已经关闭或被重置时忽略错误
+        if let Err(err) = result {
+            log::trace!("h2 stream shutdown error: {err}");
+        }
+        Poll::Ready(Ok(()))
+    }
+}
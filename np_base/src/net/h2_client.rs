@@ -0,0 +1,75 @@
+use crate::net::h2_async_io::{
+    H2AsyncIo, CONNECTION_WINDOW_SIZE, GRPC_CONTENT_TYPE, STREAM_WINDOW_SIZE,
+};
+use anyhow::anyhow;
+use bytes::Bytes;
+use h2::client::SendRequest;
+use http::{header, Method, Request, StatusCode};
+use log::debug;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// HTTP/2 客户端连接
+///
+/// 在同一条连接上为每个转发连接打开一条长期存在的流（一次 POST 请求），
+/// 与 QUIC 的双向流用法一致。
+pub struct H2Client {
+    send_request: SendRequest<Bytes>,
+    uri: String,
+    grpc: bool,
+}
+
+impl H2Client {
+    /// 在已建立的 TCP 或 TLS 连接上完成 HTTP/2 握手，连接由后台任务驱动
+    ///
+    /// `authority` 与 `path` 用于请求的 `:authority` 与 `:path`，
+    /// `grpc` 为 true 时以 gRPC 双向流的形式收发数据。
+    pub async fn handshake<S>(
+        stream: S,
+        tls: bool,
+        authority: &str,
+        path: &str,
+        grpc: bool,
+    ) -> anyhow::Result<Self>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (send_request, connection) = h2::client::Builder::new()
+            .initial_window_size(STREAM_WINDOW_SIZE)
+            .initial_connection_window_size(CONNECTION_WINDOW_SIZE)
+            .handshake(stream)
+            .await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!("HTTP/2 connection error: {err}");
+            }
+        });
+
+        let scheme = if tls { "https" } else { "http" };
+        Ok(Self {
+            send_request,
+            uri: format!("{scheme}://{authority}{path}"),
+            grpc,
+        })
+    }
+
+    /// 打开一条新的流
+    pub async fn open_stream(&self) -> anyhow::Result<H2AsyncIo> {
+        let mut send_request = self.send_request.clone().ready().await?;
+
+        let request = Request::builder().method(Method::POST).uri(&self.uri);
+        let request = if self.grpc {
+            request
+                .header(header::CONTENT_TYPE, GRPC_CONTENT_TYPE)
+                .header(header::TE, "trailers")
+        } else {
+            request.header(header::CONTENT_TYPE, "application/octet-stream")
+        };
+        let (response, send) = send_request.send_request(request.body(())?, false)?;
+
+        let response = response.await?;
+        if response.status() != StatusCode::OK {
+            return Err(anyhow!("HTTP/2 stream rejected: {}", response.status()));
+        }
+        Ok(H2AsyncIo::new(send, response.into_body(), self.grpc))
+    }
+}
@@ -0,0 +1,414 @@
+use crate::net::h2_async_io::{
+    H2AsyncIo, CONNECTION_WINDOW_SIZE, GRPC_CONTENT_TYPE, STREAM_WINDOW_SIZE,
+};
+use crate::net::session_delegate::CreateSessionDelegateCallback;
+use crate::net::{net_session, proxy_protocol, tls};
+use bytes::Bytes;
+use h2::server::SendResponse;
+use http::{header, HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use log::{debug, error};
+use log::{info, trace};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::select;
+use tokio::sync::{broadcast, mpsc};
+use tokio_rustls::TlsAcceptor;
+
+struct Server {
+    notify_shutdown: broadcast::Sender<()>,
+    shutdown_complete_tx: mpsc::Sender<()>,
+}
+
+impl Server {
+    async fn start_server(
+        &self,
+        listener: TcpListener,
+        on_create_session_delegate_callback: CreateSessionDelegateCallback,
+        tls_configuration: Option<tls::TlsConfiguration>,
+        tls_client_auth: Option<tls::ClientAuthConfiguration>,
+        proxy_protocol: bool,
+    ) -> anyhow::Result<()> {
+        // TLS 下通过 ALPN 协商 h2，未启用 TLS 时使用 h2c（prior knowledge）
+        let tls_acceptor = tls_configuration
+            .map(|tls_configuration| {
+                let mut config = tls::server_config(tls_configuration, tls_client_auth)?;
+                config.alpn_protocols = vec![b"h2".to_vec()];
+                anyhow::Ok(TlsAcceptor::from(Arc::new(config)))
+            })
+            .transpose()?;
+        let on_create_session_delegate_callback = Arc::new(on_create_session_delegate_callback);
+
+        loop {
+            let (mut stream, addr) = listener.accept().await?;
+
+            let tls_acceptor = tls_acceptor.clone();
+            let callback = on_create_session_delegate_callback.clone();
+            let notify_shutdown = self.notify_shutdown.clone();
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+
+            // 新连接单独起一个异步任务处理
+            tokio::spawn(async move {
+                trace!("HTTP/2 Server new connection: {}", addr);
+
+                // 位于负载均衡之后时，以 PROXY protocol 头部中的地址作为客户端地址
+                let addr = if proxy_protocol {
+                    match proxy_protocol::read_header(&mut stream).await {
+                        Ok(src) => src.unwrap_or(addr),
+                        Err(err) => {
+                            debug!("HTTP/2 Server proxy protocol error: {err}, peer addr: {addr}");
+                            return;
+                        }
+                    }
+                } else {
+                    addr
+                };
+
+                if let Some(tls_acceptor) = tls_acceptor {
+                    match tls::try_tls(stream, tls_acceptor).await {
+                        Ok(stream) => {
+                            let identities = tls::client_identities(&stream);
+                            serve_connection(
+                                stream,
+                                addr,
+                                identities,
+                                callback,
+                                notify_shutdown,
+                                shutdown_complete.clone(),
+                            )
+                            .await;
+                        }
+                        Err(err) => {
+                            debug!("HTTP/2 Server tls error: {err}");
+                        }
+                    }
+                } else {
+                    serve_connection(
+                        stream,
+                        addr,
+                        tls::ClientIdentities::default(),
+                        callback,
+                        notify_shutdown,
+                        shutdown_complete.clone(),
+                    )
+                    .await;
+                }
+
+                trace!("HTTP/2 Server disconnect: {}", addr);
+                // 反向通知此会话结束
+                drop(shutdown_complete);
+            });
+        }
+    }
+}
+
+/// 处理一条 HTTP/2 连接，每条请求流作为一个独立会话
+async fn serve_connection<S>(
+    stream: S,
+    addr: SocketAddr,
+    identities: tls::ClientIdentities,
+    callback: Arc<CreateSessionDelegateCallback>,
+    notify_shutdown: broadcast::Sender<()>,
+    shutdown_complete: mpsc::Sender<()>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut connection = match h2::server::Builder::new()
+        .initial_window_size(STREAM_WINDOW_SIZE)
+        .initial_connection_window_size(CONNECTION_WINDOW_SIZE)
+        .handshake::<_, Bytes>(stream)
+        .await
+    {
+        Ok(connection) => connection,
+        Err(err) => {
+            debug!("HTTP/2 Server handshake error: {err}, peer addr: {addr}");
+            return;
+        }
+    };
+
+    let mut shutdown = notify_shutdown.subscribe();
+    let mut draining = false;
+    loop {
+        // accept 同时驱动连接上所有流的读写
+        select! {
+            result = connection.accept() => {
+                match result {
+                    Some(Ok((request, respond))) => {
+                        let Some(stream) = accept_stream(request, respond) else {
+                            continue;
+                        };
+                        trace!("HTTP/2 stream opened from {}", addr);
+
+                        let mut delegate = callback();
+                        if !identities.is_empty() {
+                            delegate.set_client_identities(identities.clone());
+                        }
+                        let stream_shutdown = notify_shutdown.subscribe();
+                        let shutdown_complete_stream = shutdown_complete.clone();
+                        tokio::spawn(async move {
+                            net_session::run(
+                                net_session::create_session_id(),
+                                addr,
+                                delegate,
+                                stream_shutdown,
+                                stream,
+                            )
+                            .await;
+
+                            trace!("HTTP/2 stream stopped from {}", addr);
+                            // 反向通知此会话结束
+                            drop(shutdown_complete_stream);
+                        });
+                    }
+                    Some(Err(err)) => {
+                        debug!("HTTP/2 Server connection error: {err}, peer addr: {addr}");
+                        break;
+                    }
+                    None => {
+                        draining = true;
+                        break;
+                    }
+                }
+            }
+            _ = shutdown.recv() => {
+                break;
+            }
+        }
+    }
+
+    // 对端不再打开新流后继续驱动连接，直到已有的流全部结束
+    if draining {
+        select! {
+            _ = std::future::poll_fn(|cx| connection.poll_closed(cx)) => {}
+            _ = shutdown.recv() => {}
+        }
+    }
+}
+
+/// 回应请求并包装为数据流，只接受 POST 请求，其余请求返回 404
+fn accept_stream(
+    request: Request<h2::RecvStream>,
+    mut respond: SendResponse<Bytes>,
+) -> Option<H2AsyncIo> {
+    let grpc = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(GRPC_CONTENT_TYPE));
+
+    let status = if request.method() == Method::POST {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    };
+    let mut response = Response::builder().status(status);
+    if status == StatusCode::OK {
+        response = response.header(
+            header::CONTENT_TYPE,
+            if grpc {
+                GRPC_CONTENT_TYPE
+            } else {
+                "application/octet-stream"
+            },
+        );
+    }
+    let response = response.body(()).ok()?;
+
+    if status != StatusCode::OK {
+        let _ = respond.send_response(response, true);
+        return None;
+    }
+    let send = match respond.send_response(response, false) {
+        Ok(send) => send,
+        Err(err) => {
+            debug!("HTTP/2 Server send response error: {err}");
+            return None;
+        }
+    };
+
+    let stream = H2AsyncIo::new(send, request.into_body(), grpc);
+    if grpc {
+        // gRPC 响应以 trailers 结束
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        Some(stream.with_trailers(trailers))
+    } else {
+        Some(stream)
+    }
+}
+
+pub struct Builder {
+    create_session_delegate_callback: CreateSessionDelegateCallback,
+    tls_configuration: Option<tls::TlsConfiguration>,
+    tls_client_auth: Option<tls::ClientAuthConfiguration>,
+    proxy_protocol: bool,
+}
+
+impl Builder {
+    pub fn new(create_session_delegate_callback: CreateSessionDelegateCallback) -> Self {
+        Self {
+            create_session_delegate_callback,
+            tls_configuration: None,
+            tls_client_auth: None,
+            proxy_protocol: false,
+        }
+    }
+
+    /// 连接开头必须携带 PROXY protocol v1/v2 头部，用于位于负载均衡之后的监听器
+    pub fn set_proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    pub fn set_tls_configuration<A: ToString>(mut self, certificate: A, key: A) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::File {
+            certificate: certificate.to_string(),
+            key: key.to_string(),
+        });
+        self
+    }
+
+    /// 使用可热更新的证书，证书轮换后无需重启监听器
+    pub fn set_tls_cert_resolver(mut self, resolver: Arc<tls::ReloadableCertResolver>) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::Reloadable(resolver));
+        self
+    }
+
+    /// 校验客户端证书（mTLS），`required` 为 false 时未出示证书的客户端也可以连接
+    pub fn set_tls_client_auth<A: ToString>(mut self, ca: A, required: bool) -> Self {
+        self.tls_client_auth = Some(tls::ClientAuthConfiguration {
+            ca: ca.to_string(),
+            required,
+        });
+        self
+    }
+
+    pub async fn build_with_listener(
+        self,
+        listener: TcpListener,
+        shutdown_condition: impl Future,
+    ) -> anyhow::Result<()> {
+        let (notify_shutdown, _) = broadcast::channel::<()>(1);
+        let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
+
+        let server = Server {
+            notify_shutdown,
+            shutdown_complete_tx,
+        };
+
+        select! {
+            res = server.start_server(listener, self.create_session_delegate_callback, self.tls_configuration, self.tls_client_auth, self.proxy_protocol) => {
+                if let Err(err) = res {
+                    error!("HTTP/2 Server error: {}", err);
+                }
+            },
+            _ = shutdown_condition => {
+                info!("HTTP/2 Server shutting down");
+            }
+        }
+
+        // 解构server中的变量
+        let Server {
+            notify_shutdown,
+            shutdown_complete_tx,
+        } = server;
+
+        // 连接任务持有 notify_shutdown 的克隆，需要主动发送 shutdown 消息
+        let _ = notify_shutdown.send(());
+        drop(notify_shutdown);
+        // 此处必须将 shutdown_complete_tx 并销毁，否则会一直卡在shutdown_complete_rx.recv().await
+        drop(shutdown_complete_tx);
+
+        // 等待服务器优雅退出任务
+        let wait_task = async {
+            let _ = shutdown_complete_rx.recv().await;
+        };
+
+        // 设置超时时间，无法优雅退出则强制退出
+        if tokio::time::timeout(Duration::from_secs(60), wait_task)
+            .await
+            .is_err()
+        {
+            error!("HTTP/2 Server exit timeout, forced exit");
+        }
+
+        info!("HTTP/2 Server shutdown finish");
+
+        Ok(())
+    }
+
+    pub async fn build<A: ToSocketAddrs>(
+        self,
+        addr: A,
+        shutdown_condition: impl Future,
+    ) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(&addr).await?;
+        self.build_with_listener(listener, shutdown_condition).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::h2_client::H2Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_h2_stream() {
+        for grpc in [false, true] {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // 服务端把每条流收到的数据原样写回
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut connection = h2::server::handshake(stream).await.unwrap();
+                while let Some(Ok((request, respond))) = connection.accept().await {
+                    let mut stream = accept_stream(request, respond).unwrap();
+                    tokio::spawn(async move {
+                        let mut buf = vec![0u8; 8192];
+                        loop {
+                            let n = stream.read(&mut buf).await.unwrap();
+                            if n == 0 {
+                                break;
+                            }
+                            stream.write_all(&buf[..n]).await.unwrap();
+                        }
+                        stream.shutdown().await.unwrap();
+                    });
+                }
+            });
+
+            let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let client = H2Client::handshake(stream, false, &addr.to_string(), "/", grpc)
+                .await
+                .unwrap();
+
+            // 超过流控窗口的数据，两条流并行
+            let data: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+            let mut tasks = Vec::new();
+            for _ in 0..2 {
+                let stream = client.open_stream().await.unwrap();
+                let data = data.clone();
+                tasks.push(tokio::spawn(async move {
+                    let (mut reader, mut writer) = tokio::io::split(stream);
+                    let expected = data.clone();
+                    let write = tokio::spawn(async move {
+                        writer.write_all(&data).await.unwrap();
+                        writer.shutdown().await.unwrap();
+                    });
+                    let mut received = Vec::new();
+                    reader.read_to_end(&mut received).await.unwrap();
+                    write.await.unwrap();
+                    assert!(received == expected);
+                }));
+            }
+            for task in tasks {
+                task.await.unwrap();
+            }
+        }
+    }
+}
@@ -3,12 +3,12 @@ use crate::net::{net_session, tls};
 use log::{debug, error};
 use log::{info, trace};
 use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
 use tokio::select;
 use tokio::sync::{broadcast, mpsc};
 use tokio_kcp::{KcpConfig, KcpListener};
-use tokio_rustls::TlsAcceptor;
 
 struct Server {
     notify_shutdown: broadcast::Sender<()>,
@@ -21,14 +21,17 @@ impl Server {
         mut listener: KcpListener,
         on_create_session_delegate_callback: CreateSessionDelegateCallback,
         tls_configuration: Option<tls::TlsConfiguration>,
+        tls_client_auth: Option<tls::ClientAuthConfiguration>,
     ) -> anyhow::Result<()> {
-        let tls_acceptor = tls_configuration.map(TlsAcceptor::try_from).transpose()?;
+        let tls_acceptor = tls_configuration
+            .map(|tls_configuration| tls::server_acceptor(tls_configuration, tls_client_auth))
+            .transpose()?;
 
         loop {
             let (stream, addr) = listener.accept().await?;
 
             let tls_acceptor = tls_acceptor.clone();
-            let delegate = on_create_session_delegate_callback();
+            let mut delegate = on_create_session_delegate_callback();
             let shutdown = self.notify_shutdown.subscribe();
             let shutdown_complete = self.shutdown_complete_tx.clone();
 
@@ -39,6 +42,10 @@ impl Server {
                 if let Some(tls_acceptor) = tls_acceptor {
                     match tls::try_tls(stream, tls_acceptor).await {
                         Ok(stream) => {
+                            let identities = tls::client_identities(&stream);
+                            if !identities.is_empty() {
+                                delegate.set_client_identities(identities);
+                            }
                             net_session::run(
                                 net_session::create_session_id(),
                                 addr,
@@ -75,6 +82,7 @@ pub struct Builder {
     create_session_delegate_callback: CreateSessionDelegateCallback,
     kcp_config: KcpConfig,
     tls_configuration: Option<tls::TlsConfiguration>,
+    tls_client_auth: Option<tls::ClientAuthConfiguration>,
 }
 
 impl Builder {
@@ -83,6 +91,7 @@ impl Builder {
             create_session_delegate_callback,
             kcp_config: KcpConfig::default(),
             tls_configuration: None,
+            tls_client_auth: None,
         }
     }
 
@@ -92,13 +101,28 @@ impl Builder {
     }
 
     pub fn set_tls_configuration<A: ToString>(mut self, certificate: A, key: A) -> Self {
-        self.tls_configuration = Some(tls::TlsConfiguration {
+        self.tls_configuration = Some(tls::TlsConfiguration::File {
             certificate: certificate.to_string(),
             key: key.to_string(),
         });
         self
     }
 
+    /// 使用可热更新的证书，证书轮换后无需重启监听器
+    pub fn set_tls_cert_resolver(mut self, resolver: Arc<tls::ReloadableCertResolver>) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::Reloadable(resolver));
+        self
+    }
+
+    /// 校验客户端证书（mTLS），`required` 为 false 时未出示证书的客户端也可以连接
+    pub fn set_tls_client_auth<A: ToString>(mut self, ca: A, required: bool) -> Self {
+        self.tls_client_auth = Some(tls::ClientAuthConfiguration {
+            ca: ca.to_string(),
+            required,
+        });
+        self
+    }
+
     pub async fn build_with_listener(
         self,
         listener: KcpListener,
@@ -113,7 +137,7 @@ impl Builder {
         };
 
         select! {
-            res = server.start_server(listener, self.create_session_delegate_callback, self.tls_configuration) => {
+            res = server.start_server(listener, self.create_session_delegate_callback, self.tls_configuration, self.tls_client_auth) => {
                 if let Err(err) = res {
                     error!("KCP Server error: {}", err);
                 }
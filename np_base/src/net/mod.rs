@@ -5,13 +5,23 @@ use std::pin::Pin;
 use std::time::Duration;
 
 pub mod net_session;
+pub mod proxy_protocol;
 
 pub mod session_delegate;
 pub mod tcp_server;
 pub mod tls;
+pub mod tls_terminate;
+#[cfg(target_os = "linux")]
+pub mod transparent;
 pub mod udp_server;
 pub mod udp_session;
 
+#[cfg(feature = "h2")]
+pub mod h2_async_io;
+#[cfg(feature = "h2")]
+pub mod h2_client;
+#[cfg(feature = "h2")]
+pub mod h2_server;
 #[cfg(feature = "kcp")]
 pub mod kcp_server;
 #[cfg(feature = "quic")]
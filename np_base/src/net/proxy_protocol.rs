@@ -0,0 +1,216 @@
+//! HAProxy PROXY protocol v1/v2
+//!
+//! 出口连接目标时在数据前写入头部，携带真实客户端地址；
+//! 服务端监听器位于负载均衡之后时从连接开头读取头部，取得真实客户端地址。
+
+use anyhow::anyhow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// v2 头部签名
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// v1 头部最大长度（含 CRLF）
+const V1_MAX_LEN: usize = 107;
+
+/// 读取头部的超时时间
+const READ_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+impl ProxyProtocolVersion {
+    /// 0 为不发送
+    pub fn from_u32(version: u32) -> Option<Self> {
+        match version {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            _ => None,
+        }
+    }
+}
+
+/// 生成头部，源地址未知时生成 UNKNOWN (v1) 或 LOCAL (v2) 头部
+pub fn encode(version: ProxyProtocolVersion, src: Option<SocketAddr>, dst: SocketAddr) -> Vec<u8> {
+    let addrs = src.map(|src| normalize(src, dst));
+    match version {
+        ProxyProtocolVersion::V1 => match addrs {
+            Some((src, dst)) => format!(
+                "PROXY {} {} {} {} {}\r\n",
+                if src.is_ipv4() { "TCP4" } else { "TCP6" },
+                src.ip(),
+                dst.ip(),
+                src.port(),
+                dst.port()
+            )
+            .into_bytes(),
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        },
+        ProxyProtocolVersion::V2 => {
+            let mut header = V2_SIGNATURE.to_vec();
+            match addrs {
+                Some((src, dst)) => {
+                    // PROXY 命令
+                    header.push(0x21);
+                    let mut body = Vec::with_capacity(36);
+                    match (src.ip(), dst.ip()) {
+                        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+                            // TCP over IPv4
+                            header.push(0x11);
+                            body.extend_from_slice(&src_ip.octets());
+                            body.extend_from_slice(&dst_ip.octets());
+                        }
+                        (src_ip, dst_ip) => {
+                            // TCP over IPv6
+                            header.push(0x21);
+                            body.extend_from_slice(&to_ipv6(src_ip).octets());
+                            body.extend_from_slice(&to_ipv6(dst_ip).octets());
+                        }
+                    }
+                    body.extend_from_slice(&src.port().to_be_bytes());
+                    body.extend_from_slice(&dst.port().to_be_bytes());
+                    header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+                    header.extend_from_slice(&body);
+                }
+                None => {
+                    // LOCAL 命令，地址族未指定
+                    header.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+                }
+            }
+            header
+        }
+    }
+}
+
+/// 读取连接开头的头部，返回真实客户端地址
+///
+/// 头部为 UNKNOWN/LOCAL 时返回 None，调用方继续使用连接的对端地址。
+/// 逐字节读取 v1 头部，不会多读头部之后的数据。
+pub async fn read_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> anyhow::Result<Option<SocketAddr>> {
+    tokio::time::timeout(READ_HEADER_TIMEOUT, read_header_internal(stream))
+        .await
+        .map_err(|_| anyhow!("PROXY protocol header timeout"))?
+}
+
+async fn read_header_internal<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> anyhow::Result<Option<SocketAddr>> {
+    // v1 头部最短 15 字节，v2 头部最短 16 字节，先读取 12 字节不会越界
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == V2_SIGNATURE {
+        let mut head = [0u8; 4];
+        stream.read_exact(&mut head).await?;
+        if head[0] >> 4 != 2 {
+            return Err(anyhow!("unsupported PROXY protocol version"));
+        }
+        let len = u16::from_be_bytes([head[2], head[3]]) as usize;
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+
+        // LOCAL 命令
+        if head[0] & 0x0F == 0 {
+            return Ok(None);
+        }
+        let src = match head[1] >> 4 {
+            1 if len >= 12 => {
+                let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+                SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([body[8], body[9]]))
+            }
+            2 if len >= 36 => {
+                // IPv4 映射地址还原为 IPv4
+                let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&body[..16])?).to_canonical();
+                SocketAddr::new(ip, u16::from_be_bytes([body[32], body[33]]))
+            }
+            // AF_UNSPEC、AF_UNIX 等
+            _ => return Ok(None),
+        };
+        return Ok(Some(src));
+    }
+
+    if !prefix.starts_with(b"PROXY ") {
+        return Err(anyhow!("missing PROXY protocol header"));
+    }
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(anyhow!("PROXY protocol header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields.as_slice() {
+        ["PROXY", "TCP4" | "TCP6", src_ip, _dst_ip, src_port, _dst_port] => {
+            let ip: IpAddr = src_ip.parse()?;
+            let port: u16 = src_port.parse()?;
+            Ok(Some(SocketAddr::new(ip.to_canonical(), port)))
+        }
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        _ => Err(anyhow!("invalid PROXY protocol header")),
+    }
+}
+
+/// 源地址与目标地址地址族不同时统一为 IPv6
+fn normalize(src: SocketAddr, dst: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let src = SocketAddr::new(src.ip().to_canonical(), src.port());
+    let dst = SocketAddr::new(dst.ip().to_canonical(), dst.port());
+    if src.is_ipv4() == dst.is_ipv4() {
+        (src, dst)
+    } else {
+        (
+            SocketAddr::new(IpAddr::V6(to_ipv6(src.ip())), src.port()),
+            SocketAddr::new(IpAddr::V6(to_ipv6(dst.ip())), dst.port()),
+        )
+    }
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_proxy_protocol() {
+        let dst: SocketAddr = "10.0.0.2:80".parse().unwrap();
+        for src in ["192.168.1.10:5000", "[2001:db8::1]:6000"] {
+            let src: SocketAddr = src.parse().unwrap();
+            for version in [ProxyProtocolVersion::V1, ProxyProtocolVersion::V2] {
+                let mut data = encode(version, Some(src), dst);
+                data.extend_from_slice(b"payload");
+                let mut reader = data.as_slice();
+                assert_eq!(read_header(&mut reader).await.unwrap(), Some(src));
+                assert_eq!(reader, b"payload");
+            }
+        }
+
+        assert_eq!(
+            encode(
+                ProxyProtocolVersion::V1,
+                Some("192.168.1.10:5000".parse().unwrap()),
+                dst
+            ),
+            b"PROXY TCP4 192.168.1.10 10.0.0.2 5000 80\r\n"
+        );
+        for version in [ProxyProtocolVersion::V1, ProxyProtocolVersion::V2] {
+            let data = encode(version, None, dst);
+            assert_eq!(read_header(&mut data.as_slice()).await.unwrap(), None);
+        }
+        assert!(read_header(&mut b"GET / HTTP/1.1\r\n\r\n".as_slice())
+            .await
+            .is_err());
+    }
+}
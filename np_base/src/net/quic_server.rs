@@ -26,11 +26,10 @@ impl Server {
             anyhow::anyhow!("QUIC requires TLS, but TLS is not enabled in config")
         })?;
 
+        // s2n-quic 使用自己的 TLS 配置，证书在监听器启动时读取
+        let (certificate, key) = tls_config.paths();
         let mut server = QUICServer::builder()
-            .with_tls((
-                Path::new(&tls_config.certificate),
-                Path::new(&tls_config.key),
-            ))?
+            .with_tls((Path::new(&certificate), Path::new(&key)))?
             .with_io(io)?
             .with_congestion_controller(s2n_quic_core::recovery::bbr::Endpoint::default())?
             .start()?;
@@ -103,13 +102,19 @@ impl Builder {
     }
 
     pub fn set_tls_configuration<A: ToString>(mut self, certificate: A, key: A) -> Self {
-        self.tls_configuration = Some(tls::TlsConfiguration {
+        self.tls_configuration = Some(tls::TlsConfiguration::File {
             certificate: certificate.to_string(),
             key: key.to_string(),
         });
         self
     }
 
+    /// 使用可热更新的证书，证书轮换后无需重启监听器
+    pub fn set_tls_cert_resolver(mut self, resolver: Arc<tls::ReloadableCertResolver>) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::Reloadable(resolver));
+        self
+    }
+
     pub async fn build(self, addr: &str, shutdown_condition: impl Future) -> anyhow::Result<()> {
         let (notify_shutdown, _) = broadcast::channel::<()>(1);
         let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
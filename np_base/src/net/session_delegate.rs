@@ -1,3 +1,4 @@
+use crate::net::tls::ClientIdentities;
 use crate::net::WriterMessage;
 use async_trait::async_trait;
 use bytes::{Bytes, BytesMut};
@@ -56,6 +57,12 @@ where
     async fn is_ready_for_read(&self) -> bool {
         true
     }
+
+    /// 透明代理模式下连接（数据包）的原始目标地址，在 on_session_start 之前调用
+    fn set_original_dst(&mut self, _addr: SocketAddr) {}
+
+    /// TLS 客户端证书中的身份（主题 CN 与 SAN），客户端出示证书时在 on_session_start 之前调用
+    fn set_client_identities(&mut self, _identities: ClientIdentities) {}
 }
 
 pub type CreateSessionDelegateCallback = Box<dyn Fn() -> Box<dyn SessionDelegate> + Send + Sync>;
@@ -1,5 +1,5 @@
 use crate::net::session_delegate::CreateSessionDelegateCallback;
-use crate::net::{net_session, tls};
+use crate::net::{net_session, proxy_protocol, tls};
 use log::{debug, error};
 use log::{info, trace};
 use std::future::Future;
@@ -9,7 +9,6 @@ use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
 use tokio::select;
 use tokio::sync::{broadcast, mpsc};
-use tokio_rustls::TlsAcceptor;
 
 pub type StreamInitCallbackType = Arc<
     dyn Fn(TcpStream) -> Pin<Box<dyn Future<Output = anyhow::Result<TcpStream>> + Send>>
@@ -23,14 +22,20 @@ struct Server {
 }
 
 impl Server {
+    #[allow(clippy::too_many_arguments)]
     async fn start_server(
         &self,
         listener: TcpListener,
         on_create_session_delegate_callback: CreateSessionDelegateCallback,
         on_stream_init_callback: Option<StreamInitCallbackType>,
         tls_configuration: Option<tls::TlsConfiguration>,
+        tls_client_auth: Option<tls::ClientAuthConfiguration>,
+        proxy_protocol: bool,
+        #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] transparent: bool,
     ) -> anyhow::Result<()> {
-        let tls_acceptor = tls_configuration.map(TlsAcceptor::try_from).transpose()?;
+        let tls_acceptor = tls_configuration
+            .map(|tls_configuration| tls::server_acceptor(tls_configuration, tls_client_auth))
+            .transpose()?;
 
         loop {
             let (mut stream, addr) = listener.accept().await?;
@@ -48,7 +53,18 @@ impl Server {
             }
 
             let tls_acceptor = tls_acceptor.clone();
-            let delegate = on_create_session_delegate_callback();
+            let mut delegate = on_create_session_delegate_callback();
+
+            #[cfg(target_os = "linux")]
+            if transparent {
+                match crate::net::transparent::original_dst(&stream) {
+                    Ok(dst) => delegate.set_original_dst(dst),
+                    Err(error) => {
+                        error!("TCP Server original dst error:{}", error);
+                        continue;
+                    }
+                }
+            }
             let shutdown = self.notify_shutdown.subscribe();
             let shutdown_complete = self.shutdown_complete_tx.clone();
 
@@ -56,9 +72,26 @@ impl Server {
             tokio::spawn(async move {
                 trace!("TCP Server new connection: {}", addr);
 
+                // 位于负载均衡之后时，以 PROXY protocol 头部中的地址作为客户端地址
+                let addr = if proxy_protocol {
+                    match proxy_protocol::read_header(&mut stream).await {
+                        Ok(src) => src.unwrap_or(addr),
+                        Err(err) => {
+                            debug!("TCP Server proxy protocol error: {err}, peer addr: {addr}");
+                            return;
+                        }
+                    }
+                } else {
+                    addr
+                };
+
                 if let Some(tls_acceptor) = tls_acceptor {
                     match tls::try_tls(stream, tls_acceptor).await {
                         Ok(stream) => {
+                            let identities = tls::client_identities(&stream);
+                            if !identities.is_empty() {
+                                delegate.set_client_identities(identities);
+                            }
                             net_session::run(
                                 net_session::create_session_id(),
                                 addr,
@@ -94,7 +127,10 @@ impl Server {
 pub struct Builder {
     create_session_delegate_callback: CreateSessionDelegateCallback,
     tls_configuration: Option<tls::TlsConfiguration>,
+    tls_client_auth: Option<tls::ClientAuthConfiguration>,
+    proxy_protocol: bool,
     stream_init_callback: Option<StreamInitCallbackType>,
+    transparent: bool,
 }
 
 impl Builder {
@@ -102,7 +138,10 @@ impl Builder {
         Self {
             create_session_delegate_callback,
             tls_configuration: None,
+            tls_client_auth: None,
+            proxy_protocol: false,
             stream_init_callback: None,
+            transparent: false,
         }
     }
 
@@ -114,14 +153,42 @@ impl Builder {
         self
     }
 
+    /// 透明代理模式，会话开始前读取连接的原始目标地址
+    #[cfg(target_os = "linux")]
+    pub fn set_transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// 连接开头必须携带 PROXY protocol v1/v2 头部，用于位于负载均衡之后的监听器
+    pub fn set_proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
     pub fn set_tls_configuration<A: ToString>(mut self, certificate: A, key: A) -> Self {
-        self.tls_configuration = Some(tls::TlsConfiguration {
+        self.tls_configuration = Some(tls::TlsConfiguration::File {
             certificate: certificate.to_string(),
             key: key.to_string(),
         });
         self
     }
 
+    /// 使用可热更新的证书，证书轮换后无需重启监听器
+    pub fn set_tls_cert_resolver(mut self, resolver: Arc<tls::ReloadableCertResolver>) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::Reloadable(resolver));
+        self
+    }
+
+    /// 校验客户端证书（mTLS），`required` 为 false 时未出示证书的客户端也可以连接
+    pub fn set_tls_client_auth<A: ToString>(mut self, ca: A, required: bool) -> Self {
+        self.tls_client_auth = Some(tls::ClientAuthConfiguration {
+            ca: ca.to_string(),
+            required,
+        });
+        self
+    }
+
     pub async fn build_with_listener(
         self,
         listener: TcpListener,
@@ -136,7 +203,7 @@ impl Builder {
         };
 
         select! {
-            res = server.start_server(listener, self.create_session_delegate_callback, self.stream_init_callback, self.tls_configuration) => {
+            res = server.start_server(listener, self.create_session_delegate_callback, self.stream_init_callback, self.tls_configuration, self.tls_client_auth, self.proxy_protocol, self.transparent) => {
                 if let Err(err) = res {
                     error!("TCP Server error: {}", err);
                 }
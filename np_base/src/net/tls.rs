@@ -1,30 +1,389 @@
-use std::sync::Arc;
+use anyhow::anyhow;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_rustls::rustls;
+use tokio_rustls::rustls::crypto::CryptoProvider;
 use tokio_rustls::rustls::pki_types::pem::PemObject;
 use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::RootCertStore;
 use tokio_rustls::TlsAcceptor;
 
-pub struct TlsConfiguration {
-    pub certificate: String,
-    pub key: String,
+pub enum TlsConfiguration {
+    /// 启动时读取一次证书文件
+    File { certificate: String, key: String },
+    /// 可运行时替换的证书
+    Reloadable(Arc<ReloadableCertResolver>),
 }
 
-impl TryFrom<TlsConfiguration> for TlsAcceptor {
-    type Error = anyhow::Error;
-    fn try_from(tls_configuration: TlsConfiguration) -> Result<Self, Self::Error> {
-        let cert_path = std::path::PathBuf::from(&tls_configuration.certificate);
-        let key_path = std::path::PathBuf::from(&tls_configuration.key);
+impl TlsConfiguration {
+    /// 当前使用的证书与私钥路径
+    pub fn paths(&self) -> (String, String) {
+        match self {
+            TlsConfiguration::File { certificate, key } => (certificate.clone(), key.clone()),
+            TlsConfiguration::Reloadable(resolver) => resolver.paths(),
+        }
+    }
+}
+
+/// 客户端证书校验（mTLS）
+pub struct ClientAuthConfiguration {
+    /// 签发客户端证书的 CA（PEM 文件）
+    pub ca: String,
+    /// 是否要求客户端必须出示证书，否则未出示证书的客户端仍可使用密码登录
+    pub required: bool,
+}
+
+/// 创建服务端 TLS 接收器，`client_auth` 不为空时校验客户端证书
+pub fn server_acceptor(
+    tls_configuration: TlsConfiguration,
+    client_auth: Option<ClientAuthConfiguration>,
+) -> anyhow::Result<TlsAcceptor> {
+    Ok(TlsAcceptor::from(Arc::new(server_config(
+        tls_configuration,
+        client_auth,
+    )?)))
+}
+
+/// 创建服务端 TLS 配置，需要设置 ALPN 等选项时使用
+pub fn server_config(
+    tls_configuration: TlsConfiguration,
+    client_auth: Option<ClientAuthConfiguration>,
+) -> anyhow::Result<rustls::ServerConfig> {
+    let builder = rustls::ServerConfig::builder();
+    let builder = match client_auth {
+        Some(client_auth) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(&client_auth.ca)? {
+                roots.add(cert?)?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if client_auth.required {
+                verifier.build()?
+            } else {
+                verifier.allow_unauthenticated().build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = match tls_configuration {
+        TlsConfiguration::File { certificate, key } => {
+            let (certs, key) = load_pem_files(&certificate, &key)?;
+            builder.with_single_cert(certs, key)?
+        }
+        TlsConfiguration::Reloadable(resolver) => builder.with_cert_resolver(resolver),
+    };
+
+    Ok(config)
+}
+
+/// 客户端证书中的身份
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientIdentities {
+    /// 主题 CN
+    pub common_name: Option<String>,
+    /// SAN 中的 DNS、URI 名称
+    pub alt_names: Vec<String>,
+}
+
+impl ClientIdentities {
+    pub fn is_empty(&self) -> bool {
+        self.common_name.is_none() && self.alt_names.is_empty()
+    }
+
+    /// 全部身份，CN 在前
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.common_name.iter().chain(&self.alt_names)
+    }
+}
+
+/// 读取客户端证书中的身份
+///
+/// 客户端未出示证书时返回空身份。证书链已在握手时由 CA 校验。
+pub fn client_identities<IO>(stream: &tokio_rustls::TlsStream<IO>) -> ClientIdentities {
+    let tokio_rustls::TlsStream::Server(stream) = stream else {
+        return ClientIdentities::default();
+    };
+    let Some(end_entity) = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+    else {
+        return ClientIdentities::default();
+    };
+    let Ok(cert) = webpki::EndEntityCert::try_from(end_entity) else {
+        return ClientIdentities::default();
+    };
+
+    let mut alt_names: Vec<String> = Vec::new();
+    for name in cert.valid_dns_names().chain(cert.valid_uri_names()) {
+        if !alt_names.iter().any(|identity| identity == name) {
+            alt_names.push(name.to_owned());
+        }
+    }
+    ClientIdentities {
+        common_name: subject_common_name(cert.subject()),
+        alt_names,
+    }
+}
 
-        let certs = CertificateDer::pem_file_iter(cert_path)?.collect::<Result<Vec<_>, _>>()?;
-        let key = PrivateKeyDer::from_pem_file(key_path)?;
+/// 从 DER 编码的证书主题中读取 CN
+fn subject_common_name(mut subject: &[u8]) -> Option<String> {
+    // 2.5.4.3
+    const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
 
-        let config = rustls::ServerConfig::builder()
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+    while let Some((_, mut set, rest)) = der_element(subject) {
+        subject = rest;
+        while let Some((_, attribute, rest)) = der_element(set) {
+            set = rest;
+            let (_, oid, value) = der_element(attribute)?;
+            if oid == OID_COMMON_NAME {
+                let (_, value, _) = der_element(value)?;
+                return String::from_utf8(value.to_vec()).ok();
+            }
+        }
+    }
+    None
+}
+
+/// 读取一个 DER 元素，返回 (标签, 内容, 剩余数据)
+fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&len, mut rest) = rest.split_first()?;
+    let len = if len & 0x80 == 0 {
+        len as usize
+    } else {
+        let count = (len & 0x7F) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, remain) = rest.split_at(count);
+        rest = remain;
+        bytes.iter().fold(0usize, |len, b| len << 8 | *b as usize)
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (content, rest) = rest.split_at(len);
+    Some((tag, content, rest))
+}
+
+fn load_pem_files(
+    certificate: &str,
+    key: &str,
+) -> anyhow::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certs = CertificateDer::pem_file_iter(certificate)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key)?;
+    Ok((certs, key))
+}
+
+struct LoadedCert {
+    certificate: String,
+    key: String,
+    certified_key: Arc<CertifiedKey>,
+}
+
+/// 可热更新的服务端证书
+///
+/// 多个监听器共享同一个实例，`reload` 成功后新的握手立即使用新证书，已建立的连接不受影响。
+pub struct ReloadableCertResolver {
+    provider: Arc<CryptoProvider>,
+    current: RwLock<LoadedCert>,
+}
+
+impl std::fmt::Debug for ReloadableCertResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (certificate, key) = self.paths();
+        f.debug_struct("ReloadableCertResolver")
+            .field("certificate", &certificate)
+            .field("key", &key)
+            .finish()
+    }
+}
+
+impl ReloadableCertResolver {
+    pub fn new(certificate: &str, key: &str) -> anyhow::Result<Arc<Self>> {
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        let certified_key = Self::load(&provider, certificate, key)?;
+        Ok(Arc::new(Self {
+            provider,
+            current: RwLock::new(LoadedCert {
+                certificate: certificate.to_string(),
+                key: key.to_string(),
+                certified_key,
+            }),
+        }))
+    }
+
+    /// 重新读取证书文件，读取失败时保留旧证书，返回证书链是否有变化
+    pub fn reload(&self, certificate: &str, key: &str) -> anyhow::Result<bool> {
+        let certified_key = self.load_cert(certificate, key)?;
+        Ok(self.replace(certificate, key, certified_key))
+    }
+
+    /// 只读取证书文件，不替换当前证书；多个证书需要一起更新时先全部读取再逐个 `replace`
+    pub fn load_cert(&self, certificate: &str, key: &str) -> anyhow::Result<Arc<CertifiedKey>> {
+        Self::load(&self.provider, certificate, key)
+    }
+
+    /// 替换为已读取的证书，返回证书链是否有变化
+    pub fn replace(&self, certificate: &str, key: &str, certified_key: Arc<CertifiedKey>) -> bool {
+        // 持锁期间只做赋值，不会 panic，锁中毒时直接取回内部数据
+        let mut current = self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let changed = current.certified_key.cert != certified_key.cert;
+        *current = LoadedCert {
+            certificate: certificate.to_string(),
+            key: key.to_string(),
+            certified_key,
+        };
+        changed
+    }
+
+    pub fn paths(&self) -> (String, String) {
+        match self.current.read() {
+            Ok(current) => (current.certificate.clone(), current.key.clone()),
+            Err(_) => (String::new(), String::new()),
+        }
+    }
+
+    fn load(
+        provider: &CryptoProvider,
+        certificate: &str,
+        key: &str,
+    ) -> anyhow::Result<Arc<CertifiedKey>> {
+        let (certs, key) = load_pem_files(certificate, key)?;
+        Ok(Arc::new(CertifiedKey::from_der(certs, key, provider)?))
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current
+            .read()
+            .ok()
+            .map(|current| current.certified_key.clone())
+    }
+}
+
+/// 读取 PEM 格式的证书链与私钥，私钥与证书不匹配时返回错误
+pub fn load_certified_key(
+    provider: &CryptoProvider,
+    certificate: &str,
+    key: &str,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certs = CertificateDer::pem_slice_iter(certificate.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| anyhow!("invalid certificate: {err}"))?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificate found"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key.as_bytes())
+        .map_err(|err| anyhow!("invalid private key: {err}"))?;
+    Ok(Arc::new(CertifiedKey::from_der(certs, key, provider)?))
+}
+
+/// 证书中的 DNS 域名（含通配域名）
+pub fn certificate_dns_names(certified_key: &CertifiedKey) -> anyhow::Result<Vec<String>> {
+    let end_entity = certified_key
+        .end_entity_cert()
+        .map_err(|err| anyhow!("invalid certificate: {err}"))?;
+    let cert = webpki::EndEntityCert::try_from(end_entity)
+        .map_err(|err| anyhow!("invalid certificate: {err}"))?;
+    Ok(cert
+        .valid_dns_names()
+        .map(|name| name.to_ascii_lowercase())
+        .collect())
+}
+
+/// 校验证书链与私钥，返回证书中的域名
+pub fn certificate_domains(certificate: &str, key: &str) -> anyhow::Result<Vec<String>> {
+    let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+    let certified_key = load_certified_key(&provider, certificate, key)?;
+    certificate_dns_names(&certified_key)
+}
+
+/// 按 SNI 选择证书
+///
+/// 通道入口终止 TLS 时使用，一个通道可以配置多张证书；
+/// 没有匹配的证书（或客户端未发送 SNI）时使用第一张。
+pub struct SniCertResolver {
+    certs: Vec<(Vec<String>, Arc<CertifiedKey>)>,
+}
+
+impl std::fmt::Debug for SniCertResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SniCertResolver")
+            .field(
+                "names",
+                &self
+                    .certs
+                    .iter()
+                    .map(|(names, _)| names)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl SniCertResolver {
+    /// `certificates` 为 (证书链 PEM, 私钥 PEM) 列表
+    pub fn new(certificates: &[(String, String)]) -> anyhow::Result<Arc<Self>> {
+        if certificates.is_empty() {
+            return Err(anyhow!("no certificate configured"));
+        }
+        let provider = rustls::ServerConfig::builder().crypto_provider().clone();
+        let certs = certificates
+            .iter()
+            .map(|(certificate, key)| {
+                let certified_key = load_certified_key(&provider, certificate, key)?;
+                Ok((certificate_dns_names(&certified_key)?, certified_key))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Arc::new(Self { certs }))
+    }
+
+    /// 使用该证书集合的服务端配置
+    pub fn server_config(self: Arc<Self>) -> Arc<rustls::ServerConfig> {
+        let mut config = rustls::ServerConfig::builder()
             .with_no_client_auth()
-            .with_single_cert(certs, key)?;
+            .with_cert_resolver(self);
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
 
-        Ok(TlsAcceptor::from(Arc::new(config)))
+    fn find(&self, server_name: &str) -> Option<&Arc<CertifiedKey>> {
+        let server_name = server_name.to_ascii_lowercase();
+        let wildcard = server_name
+            .split_once('.')
+            .map(|(_, parent)| format!("*.{parent}"));
+        self.certs
+            .iter()
+            .find(|(names, _)| names.contains(&server_name))
+            .or_else(|| {
+                let wildcard = wildcard.as_ref()?;
+                self.certs
+                    .iter()
+                    .find(|(names, _)| names.contains(wildcard))
+            })
+            .map(|(_, certified_key)| certified_key)
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        client_hello
+            .server_name()
+            .and_then(|server_name| self.find(server_name))
+            .or_else(|| self.certs.first().map(|(_, certified_key)| certified_key))
+            .cloned()
     }
 }
 
@@ -45,3 +404,107 @@ where
     .await??;
     Ok(tokio_rustls::TlsStream::Server(stream))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::ClientConfig;
+    use tokio_rustls::TlsConnector;
+
+    #[tokio::test]
+    async fn test_client_identities() {
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server_cert = CertificateParams::new(vec!["server.test".to_owned()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(vec!["alice".to_owned()]).unwrap();
+        client_params
+            .distinguished_name
+            .push(DnType::CommonName, "device-01");
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client_cert = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("np_tls_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str, content: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let ca_path = path("ca.pem", ca.pem());
+        let tls_configuration = || TlsConfiguration::File {
+            certificate: path("server.pem", server_cert.pem()),
+            key: path("server.key", server_key.serialize_pem()),
+        };
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let connect = |acceptor: TlsAcceptor, config: ClientConfig| async move {
+            let (client, server) = tokio::io::duplex(4096);
+            let connector = TlsConnector::from(Arc::new(config));
+            let server_name = ServerName::try_from("server.test").unwrap();
+            let (client, server) = tokio::join!(
+                connector.connect(server_name, client),
+                try_tls(server, acceptor)
+            );
+            client.ok()?;
+            Some(client_identities(&server.ok()?))
+        };
+
+        let acceptor = server_acceptor(
+            tls_configuration(),
+            Some(ClientAuthConfiguration {
+                ca: ca_path.clone(),
+                required: false,
+            }),
+        )
+        .unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots.clone())
+            .with_client_auth_cert(
+                vec![client_cert.der().clone()],
+                PrivateKeyDer::from_pem_slice(client_key.serialize_pem().as_bytes()).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            connect(acceptor.clone(), config).await,
+            Some(ClientIdentities {
+                common_name: Some("device-01".to_owned()),
+                alt_names: vec!["alice".to_owned()],
+            })
+        );
+
+        // 未出示证书时可以连接，但没有身份
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots.clone())
+            .with_no_client_auth();
+        assert_eq!(
+            connect(acceptor, config.clone()).await,
+            Some(ClientIdentities::default())
+        );
+
+        // 要求证书时拒绝握手
+        let acceptor = server_acceptor(
+            tls_configuration(),
+            Some(ClientAuthConfiguration {
+                ca: ca_path,
+                required: true,
+            }),
+        )
+        .unwrap();
+        assert_eq!(connect(acceptor, config).await, None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
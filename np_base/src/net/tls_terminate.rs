@@ -0,0 +1,224 @@
+//! 在会话层终止 TLS
+//!
+//! 包装一个明文会话：收到的数据先经 rustls 解密再交给内部会话，
+//! 内部会话发出的数据加密后再写回连接。与 `tcp_server` 的 TLS 监听不同，
+//! 这里不需要独占监听端口，可以用于虚拟主机按域名分发后的会话。
+
+use crate::net::session_delegate::{CreateSessionDelegateCallback, SessionDelegate};
+use crate::net::WriterMessage;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_rustls::rustls::{ServerConfig, ServerConnection};
+
+/// 创建终止 TLS 的会话，内部会话由 `create_session_delegate` 创建
+pub fn wrap(
+    create_session_delegate: CreateSessionDelegateCallback,
+    config: Arc<ServerConfig>,
+) -> CreateSessionDelegateCallback {
+    Box::new(move || -> Box<dyn SessionDelegate> {
+        Box::new(TlsTerminateSession {
+            config: config.clone(),
+            conn: None,
+            inner: create_session_delegate(),
+            tx: None,
+        })
+    })
+}
+
+struct TlsTerminateSession {
+    config: Arc<ServerConfig>,
+    conn: Option<Arc<Mutex<ServerConnection>>>,
+    inner: Box<dyn SessionDelegate>,
+    tx: Option<UnboundedSender<WriterMessage>>,
+}
+
+/// 取出 rustls 待发送的密文
+fn take_tls_output(conn: &mut ServerConnection) -> std::io::Result<Bytes> {
+    let mut output = Vec::new();
+    while conn.wants_write() {
+        conn.write_tls(&mut output)?;
+    }
+    Ok(Bytes::from(output))
+}
+
+/// 加密内部会话发出的明文
+fn encrypt(conn: &Mutex<ServerConnection>, data: &[u8]) -> std::io::Result<Bytes> {
+    let mut conn = conn.lock().unwrap();
+    conn.writer().write_all(data)?;
+    take_tls_output(&mut conn)
+}
+
+fn close_notify(conn: &Mutex<ServerConnection>) -> std::io::Result<Bytes> {
+    let mut conn = conn.lock().unwrap();
+    conn.send_close_notify();
+    take_tls_output(&mut conn)
+}
+
+#[async_trait]
+impl SessionDelegate for TlsTerminateSession {
+    async fn on_session_start(
+        &mut self,
+        session_id: u32,
+        addr: &SocketAddr,
+        tx: UnboundedSender<WriterMessage>,
+    ) -> anyhow::Result<()> {
+        let conn = Arc::new(Mutex::new(ServerConnection::new(self.config.clone())?));
+        let (inner_tx, mut inner_rx) = mpsc::unbounded_channel::<WriterMessage>();
+
+        // 内部会话的发送通道关闭（会话结束）时任务退出
+        let writer_conn = conn.clone();
+        let writer_tx = tx.clone();
+        tokio::spawn(async move {
+            while let Some(message) = inner_rx.recv().await {
+                let message = match message {
+                    WriterMessage::Send(data, flush) => match encrypt(&writer_conn, &data) {
+                        Ok(data) => WriterMessage::Send(data, flush),
+                        Err(_) => WriterMessage::Close,
+                    },
+                    WriterMessage::SendAndThen(data, callback) => {
+                        match encrypt(&writer_conn, &data) {
+                            Ok(data) => WriterMessage::SendAndThen(data, callback),
+                            Err(_) => WriterMessage::Close,
+                        }
+                    }
+                    WriterMessage::Close | WriterMessage::CloseDelayed(_) => {
+                        if let Ok(data) = close_notify(&writer_conn) {
+                            let _ = writer_tx.send(WriterMessage::Send(data, true));
+                        }
+                        message
+                    }
+                    WriterMessage::Flush => WriterMessage::Flush,
+                    WriterMessage::SendTo(..) => continue,
+                };
+                if writer_tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.conn = Some(conn);
+        self.tx = Some(tx);
+        self.inner
+            .on_session_start(session_id, addr, inner_tx)
+            .await
+    }
+
+    async fn on_session_close(&mut self) -> anyhow::Result<()> {
+        self.inner.on_session_close().await
+    }
+
+    async fn on_recv_frame(&mut self, frame: Bytes) -> anyhow::Result<()> {
+        let conn = self.conn.as_ref().unwrap();
+        let (plaintext, output, result) = {
+            let mut conn = conn.lock().unwrap();
+            let mut reader = frame.as_ref();
+            let mut result = Ok(false);
+            while !reader.is_empty() {
+                if let Err(err) = conn.read_tls(&mut reader) {
+                    result = Err(anyhow!("tls read error: {err}"));
+                    break;
+                }
+                if let Err(err) = conn.process_new_packets() {
+                    result = Err(anyhow!("tls error: {err}"));
+                    break;
+                }
+            }
+
+            let mut plaintext = Vec::new();
+            if result.is_ok() {
+                result = match conn.reader().read_to_end(&mut plaintext) {
+                    // 对端发送了 close_notify
+                    Ok(_) => Ok(true),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+                    Err(err) => Err(anyhow!("tls read error: {err}")),
+                };
+            }
+            // 握手回复或出错时的告警
+            (plaintext, take_tls_output(&mut conn)?, result)
+        };
+
+        if !output.is_empty() {
+            self.tx
+                .as_ref()
+                .unwrap()
+                .send(WriterMessage::Send(output, true))?;
+        }
+        if !plaintext.is_empty() {
+            self.inner.on_recv_frame(Bytes::from(plaintext)).await?;
+        }
+        if result? {
+            return Err(anyhow!("tls session closed by peer"));
+        }
+        Ok(())
+    }
+
+    async fn is_ready_for_read(&self) -> bool {
+        self.inner.is_ready_for_read().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::inlet::{InletDataEx, InletProxyType};
+    use crate::proxy::tests::{assert_echo, start_echo_server, start_local_inlet};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use std::sync::Arc;
+    use tokio::net::TcpStream;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    #[tokio::test]
+    async fn test_tls_termination_by_sni() {
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let certificates = ["a.test", "*.b.test"]
+            .into_iter()
+            .map(|name| {
+                let key = KeyPair::generate().unwrap();
+                let cert = CertificateParams::new(vec![name.to_string()])
+                    .unwrap()
+                    .signed_by(&key, &ca, &ca_key)
+                    .unwrap();
+                (cert.pem(), key.serialize_pem())
+            })
+            .collect();
+
+        let echo_addr = start_echo_server().await;
+        let listen_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        start_local_inlet(
+            InletProxyType::TCP,
+            listen_addr,
+            echo_addr.to_string(),
+            InletDataEx::new(String::new(), String::new()).with_tls_certificates(certificates),
+        )
+        .await;
+
+        let mut roots = RootCertStore::empty();
+        roots.add(CertificateDer::from(ca.der().to_vec())).unwrap();
+        let connector = TlsConnector::from(Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        ));
+        // 证书校验通过说明按 SNI 选中了对应证书
+        for name in ["a.test", "www.b.test"] {
+            let stream = TcpStream::connect(listen_addr).await.unwrap();
+            let mut stream = connector
+                .connect(ServerName::try_from(name).unwrap(), stream)
+                .await
+                .unwrap();
+            assert_echo(&mut stream).await;
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Linux 透明代理（iptables REDIRECT / TPROXY）相关的 socket 操作
+
+use log::warn;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::fd::{AsRawFd, RawFd};
+use tokio::io::Interest;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+/// 创建透明代理 TCP 监听
+///
+/// 设置 IP_TRANSPARENT 以接收 TPROXY 转发的连接（需要 CAP_NET_ADMIN），
+/// 设置失败时仍然可以配合 iptables REDIRECT 使用。
+pub fn bind_tcp_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if let Err(err) = set_transparent(socket.as_raw_fd(), addr.is_ipv6()) {
+        warn!("IP_TRANSPARENT unavailable on {addr}, only iptables REDIRECT works: {err}");
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// 创建透明代理 UDP 监听，接收数据包时可以取得原始目标地址
+pub fn bind_udp_socket(addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    if let Err(err) = set_transparent(socket.as_raw_fd(), addr.is_ipv6()) {
+        warn!("IP_TRANSPARENT unavailable on {addr}, UDP TPROXY will not work: {err}");
+    }
+    let (level, name) = if addr.is_ipv6() {
+        (libc::SOL_IPV6, libc::IPV6_RECVORIGDSTADDR)
+    } else {
+        (libc::SOL_IP, libc::IP_RECVORIGDSTADDR)
+    };
+    setsockopt_int(socket.as_raw_fd(), level, name, 1)?;
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// 创建绑定在原始目标地址上的 UDP socket，用于以原始目标地址的身份回复客户端
+pub fn bind_udp_reply_socket(dst: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(dst), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    set_transparent(socket.as_raw_fd(), dst.is_ipv6())?;
+    socket.bind(&dst.into())?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// TCP 连接的原始目标地址
+///
+/// REDIRECT 通过 SO_ORIGINAL_DST 读取 NAT 之前的目标地址；
+/// TPROXY 不做地址转换，本地地址即原始目标地址。
+pub fn original_dst(stream: &TcpStream) -> io::Result<SocketAddr> {
+    let local_addr = stream.local_addr()?;
+    let (level, name) = if local_addr.is_ipv6() {
+        (libc::SOL_IPV6, libc::IP6T_SO_ORIGINAL_DST)
+    } else {
+        (libc::SOL_IP, libc::SO_ORIGINAL_DST)
+    };
+
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            level,
+            name,
+            &mut storage as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret == 0 {
+        if let Ok(addr) = sockaddr_to_std(&storage) {
+            return Ok(addr);
+        }
+    }
+    Ok(local_addr)
+}
+
+/// 接收一个数据包，返回 (长度, 来源地址, 原始目标地址)
+pub async fn recv_from_with_dst(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+    let fd = socket.as_raw_fd();
+    socket
+        .async_io(Interest::READABLE, || recvmsg_with_dst(fd, buf))
+        .await
+}
+
+fn recvmsg_with_dst(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, SocketAddr)> {
+    let mut src: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // u64 保证控制消息缓冲区按 cmsghdr 对齐
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut src as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let src = sockaddr_to_std(&src)?;
+
+    let mut dst = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let (level, ty) = ((*cmsg).cmsg_level, (*cmsg).cmsg_type);
+            if (level == libc::SOL_IP && ty == libc::IP_ORIGDSTADDR)
+                || (level == libc::SOL_IPV6 && ty == libc::IPV6_ORIGDSTADDR)
+            {
+                let mut storage: libc::sockaddr_storage = mem::zeroed();
+                let len = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    .min(mem::size_of::<libc::sockaddr_storage>());
+                std::ptr::copy_nonoverlapping(
+                    libc::CMSG_DATA(cmsg),
+                    &mut storage as *mut _ as *mut u8,
+                    len,
+                );
+                dst = Some(sockaddr_to_std(&storage)?);
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    let dst = dst.ok_or_else(|| io::Error::other("missing original destination address"))?;
+    Ok((n as usize, src, dst))
+}
+
+fn set_transparent(fd: RawFd, is_ipv6: bool) -> io::Result<()> {
+    if is_ipv6 {
+        setsockopt_int(fd, libc::SOL_IPV6, libc::IPV6_TRANSPARENT, 1)
+    } else {
+        setsockopt_int(fd, libc::SOL_IP, libc::IP_TRANSPARENT, 1)
+    }
+}
+
+fn setsockopt_int(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn sockaddr_to_std(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        _ => Err(io::Error::other("unsupported address family")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_original_dst_without_redirect() {
+        // 未经 iptables 转发时，原始目标地址即监听地址
+        let listener = bind_tcp_listener("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listen_addr = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(listen_addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        assert_eq!(original_dst(&stream).unwrap(), listen_addr);
+
+        let socket = bind_udp_socket("127.0.0.1:0".parse().unwrap()).unwrap();
+        let socket_addr = socket.local_addr().unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"ping", socket_addr).await.unwrap();
+        let mut buf = [0u8; 16];
+        let (n, src, dst) = recv_from_with_dst(&socket, &mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        assert_eq!(src, client.local_addr().unwrap());
+        assert_eq!(dst, socket_addr);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::net::session_delegate::CreateSessionDelegateCallback;
 use crate::net::{net_session, udp_session};
 use bytes::Bytes;
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use log::{error, info, trace, warn};
 use std::future::Future;
@@ -20,12 +21,65 @@ pub async fn run_server(
     socket: UdpSocket,
     on_create_session_delegate_callback: CreateSessionDelegateCallback,
     shutdown: impl Future,
+) {
+    run_server_internal(socket, on_create_session_delegate_callback, shutdown, false).await
+}
+
+/// 透明代理（TPROXY）UDP 服务器
+///
+/// 会话按 (来源地址, 原始目标地址) 区分，socket 需由 `transparent::bind_udp_socket` 创建。
+/// 每个会话使用绑定在原始目标地址上的 socket 回复，客户端看到的回复来自原始目标地址。
+#[cfg(target_os = "linux")]
+pub async fn run_transparent_server(
+    socket: UdpSocket,
+    on_create_session_delegate_callback: CreateSessionDelegateCallback,
+    shutdown: impl Future,
+) {
+    run_server_internal(socket, on_create_session_delegate_callback, shutdown, true).await
+}
+
+/// 接收一个数据包，透明代理模式下同时返回原始目标地址
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+async fn recv_from(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+    transparent: bool,
+) -> std::io::Result<(usize, SocketAddr, Option<SocketAddr>)> {
+    #[cfg(target_os = "linux")]
+    if transparent {
+        let (amt, addr, dst) = crate::net::transparent::recv_from_with_dst(socket, buf).await?;
+        return Ok((amt, addr, Some(dst)));
+    }
+    let (amt, addr) = socket.recv_from(buf).await?;
+    Ok((amt, addr, None))
+}
+
+/// 会话使用的发送 socket，透明代理需要以原始目标地址的身份回复
+fn session_socket(
+    socket: &Arc<UdpSocket>,
+    dst: Option<SocketAddr>,
+) -> std::io::Result<Arc<UdpSocket>> {
+    match dst {
+        #[cfg(target_os = "linux")]
+        Some(dst) => Ok(Arc::new(crate::net::transparent::bind_udp_reply_socket(
+            dst,
+        )?)),
+        _ => Ok(socket.clone()),
+    }
+}
+
+async fn run_server_internal(
+    socket: UdpSocket,
+    on_create_session_delegate_callback: CreateSessionDelegateCallback,
+    shutdown: impl Future,
+    transparent: bool,
 ) {
     let (notify_shutdown, receiver_shutdown) = broadcast::channel::<()>(1);
     let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
 
     let recv_task = async {
-        let session_map: Arc<DashMap<SocketAddr, mpsc::Sender<Bytes>>> = Arc::new(DashMap::new());
+        type SessionKey = (SocketAddr, Option<SocketAddr>);
+        let session_map: Arc<DashMap<SessionKey, mpsc::Sender<Bytes>>> = Arc::new(DashMap::new());
         let mut buf = [0u8; 65535];
         let socket = Arc::new(socket);
         /// 连续错误超过此值视为 socket 不可恢复，退出接收循环
@@ -33,7 +87,7 @@ pub async fn run_server(
         let mut consecutive_errors: u32 = 0;
 
         loop {
-            let (amt, addr) = match socket.recv_from(&mut buf).await {
+            let (amt, addr, dst) = match recv_from(&socket, &mut buf, transparent).await {
                 Ok(v) => {
                     consecutive_errors = 0; // 成功后重置错误计数
                     v
@@ -66,43 +120,56 @@ pub async fn run_server(
 
             let received_data = Bytes::copy_from_slice(&buf[..amt]);
 
-            let sender = session_map.entry(addr).or_insert_with(|| {
-                // 全局唯一 session_id，与所有协议的会话共用同一空间
-                let session_id = net_session::create_session_id();
-                // 每个会话独立的 delegate 实例，避免跨会话状态干扰
-                let delegate = on_create_session_delegate_callback();
-                // 通知会话结束
-                let shutdown = receiver_shutdown.resubscribe();
-                // 反向通知会话结束完毕
-                let shutdown_complete = shutdown_complete_tx.clone();
-                // UDP 会话直接使用共享 socket，避免每个会话创建独立 socket 的资源开销和端口占用问题
-                let socket_cloned = socket.clone();
-
-                // 创建有界通道，限制积压消息数量
-                let (udp_recv_sender, udp_recv_receiver) = mpsc::channel::<Bytes>(128);
-
-                // clone session_map 供会话任务使用
-                let session_map_cloned = session_map.clone();
-                // 新连接单独起一个异步任务处理
-                tokio::spawn(async move {
-                    trace!("UDP Server new connection: {addr}, session_id: {session_id}");
-                    udp_session::run(
-                        session_id,
-                        addr,
-                        delegate,
-                        Some(udp_recv_receiver),
-                        shutdown,
-                        socket_cloned,
-                    )
-                    .await;
-                    session_map_cloned.remove(&addr);
-                    trace!("UDP Server disconnect: {addr}");
-                    // 反向通知会话结束
-                    drop(shutdown_complete);
-                });
-
-                udp_recv_sender
-            });
+            let key = (addr, dst);
+            let sender = match session_map.entry(key) {
+                Entry::Occupied(entry) => entry.into_ref(),
+                Entry::Vacant(entry) => {
+                    // UDP 会话直接使用共享 socket，避免每个会话创建独立 socket 的资源开销和端口占用问题
+                    let socket_cloned = match session_socket(&socket, dst) {
+                        Ok(socket) => socket,
+                        Err(e) => {
+                            warn!("UDP session socket error, addr: {addr}, dst: {dst:?}: {e}");
+                            continue;
+                        }
+                    };
+                    // 全局唯一 session_id，与所有协议的会话共用同一空间
+                    let session_id = net_session::create_session_id();
+                    // 每个会话独立的 delegate 实例，避免跨会话状态干扰
+                    let mut delegate = on_create_session_delegate_callback();
+                    if let Some(dst) = dst {
+                        delegate.set_original_dst(dst);
+                    }
+                    // 通知会话结束
+                    let shutdown = receiver_shutdown.resubscribe();
+                    // 反向通知会话结束完毕
+                    let shutdown_complete = shutdown_complete_tx.clone();
+
+                    // 创建有界通道，限制积压消息数量
+                    let (udp_recv_sender, udp_recv_receiver) = mpsc::channel::<Bytes>(128);
+
+                    // clone session_map 供会话任务使用
+                    let session_map_cloned = session_map.clone();
+                    // 新连接单独起一个异步任务处理
+                    tokio::spawn(async move {
+                        trace!("UDP Server new connection: {addr}, session_id: {session_id}");
+                        udp_session::run(
+                            session_id,
+                            addr,
+                            delegate,
+                            Some(udp_recv_receiver),
+                            shutdown,
+                            socket_cloned,
+                        )
+                        .await;
+                        session_map_cloned.remove(&key);
+                        trace!("UDP Server disconnect: {addr}");
+                        // 反向通知会话结束
+                        drop(shutdown_complete);
+                    });
+
+                    entry.insert(udp_recv_sender)
+                }
+            };
 
             match sender.try_send(received_data) {
                 Ok(_) => {}
@@ -1,16 +1,19 @@
 use crate::net::session_delegate::CreateSessionDelegateCallback;
 use crate::net::ws_async_io::WebSocketAsyncIo;
-use crate::net::{net_session, tls};
+use crate::net::{net_session, proxy_protocol, tls};
 use log::{debug, error};
 use log::{info, trace};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
 use tokio::select;
 use tokio::sync::{broadcast, mpsc};
-use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, HeaderValue, StatusCode};
+use tokio_tungstenite::WebSocketStream;
 
 pub type StreamInitCallbackType = Arc<
     dyn Fn(TcpStream) -> Pin<Box<dyn Future<Output = anyhow::Result<TcpStream>> + Send>>
@@ -18,20 +21,106 @@ pub type StreamInitCallbackType = Arc<
         + Sync,
 >;
 
+/// WebSocket 升级请求的校验规则
+///
+/// 路径或请求头不符合时返回 404，看起来与普通 Web 服务一致
+#[derive(Default)]
+struct UpgradeRules {
+    path: Option<String>,
+    headers: Vec<(String, String)>,
+    subprotocols: Vec<String>,
+}
+
+impl UpgradeRules {
+    // 返回类型由 tungstenite 的握手回调决定
+    #[allow(clippy::result_large_err)]
+    fn check(&self, request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+        let not_found = || {
+            let mut response = ErrorResponse::new(None);
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        };
+
+        if let Some(path) = &self.path {
+            if request.uri().path() != path {
+                return Err(not_found());
+            }
+        }
+        for (name, value) in &self.headers {
+            let matched = request
+                .headers()
+                .get_all(name.as_str())
+                .iter()
+                .any(|v| v.as_bytes() == value.as_bytes());
+            if !matched {
+                return Err(not_found());
+            }
+        }
+
+        // 选择客户端提供的第一个受支持的子协议
+        if !self.subprotocols.is_empty() {
+            let selected = request
+                .headers()
+                .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(str::trim)
+                .find(|protocol| self.subprotocols.iter().any(|p| p == protocol));
+            if let Some(protocol) = selected.and_then(|p| HeaderValue::from_str(p).ok()) {
+                response
+                    .headers_mut()
+                    .insert(header::SEC_WEBSOCKET_PROTOCOL, protocol);
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// 完成 WebSocket 握手，被拒绝的升级请求只记录调试日志
+#[allow(clippy::result_large_err)]
+async fn accept<S>(stream: S, rules: &UpgradeRules) -> Option<WebSocketStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response| {
+        rules.check(request, response)
+    })
+    .await
+    {
+        Ok(stream) => Some(stream),
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            debug!("Websocket Server rejected upgrade: {}", response.status());
+            None
+        }
+        Err(err) => {
+            error!("Websocket Server accept error: {err}");
+            None
+        }
+    }
+}
+
 struct Server {
     notify_shutdown: broadcast::Sender<()>,
     shutdown_complete_tx: mpsc::Sender<()>,
 }
 
 impl Server {
+    #[allow(clippy::too_many_arguments)]
     async fn start_server(
         &self,
         listener: TcpListener,
         on_create_session_delegate_callback: CreateSessionDelegateCallback,
         on_stream_init_callback: Option<StreamInitCallbackType>,
         tls_configuration: Option<tls::TlsConfiguration>,
+        tls_client_auth: Option<tls::ClientAuthConfiguration>,
+        proxy_protocol: bool,
+        upgrade_rules: UpgradeRules,
     ) -> anyhow::Result<()> {
-        let tls_acceptor = tls_configuration.map(TlsAcceptor::try_from).transpose()?;
+        let tls_acceptor = tls_configuration
+            .map(|tls_configuration| tls::server_acceptor(tls_configuration, tls_client_auth))
+            .transpose()?;
+        let upgrade_rules = Arc::new(upgrade_rules);
 
         loop {
             let (mut stream, addr) = listener.accept().await?;
@@ -49,7 +138,8 @@ impl Server {
             }
 
             let tls_acceptor = tls_acceptor.clone();
-            let delegate = on_create_session_delegate_callback();
+            let upgrade_rules = upgrade_rules.clone();
+            let mut delegate = on_create_session_delegate_callback();
             let shutdown = self.notify_shutdown.subscribe();
             let shutdown_complete = self.shutdown_complete_tx.clone();
 
@@ -57,10 +147,29 @@ impl Server {
             tokio::spawn(async move {
                 trace!("Websocket Server new connection: {}", addr);
 
+                // 位于负载均衡之后时，以 PROXY protocol 头部中的地址作为客户端地址
+                let addr = if proxy_protocol {
+                    match proxy_protocol::read_header(&mut stream).await {
+                        Ok(src) => src.unwrap_or(addr),
+                        Err(err) => {
+                            debug!(
+                                "Websocket Server proxy protocol error: {err}, peer addr: {addr}"
+                            );
+                            return;
+                        }
+                    }
+                } else {
+                    addr
+                };
+
                 if let Some(tls_acceptor) = tls_acceptor {
                     match tls::try_tls(stream, tls_acceptor).await {
-                        Ok(stream) => match tokio_tungstenite::accept_async(stream).await {
-                            Ok(stream) => {
+                        Ok(stream) => {
+                            let identities = tls::client_identities(&stream);
+                            if !identities.is_empty() {
+                                delegate.set_client_identities(identities);
+                            }
+                            if let Some(stream) = accept(stream, &upgrade_rules).await {
                                 net_session::run(
                                     net_session::create_session_id(),
                                     addr,
@@ -70,30 +179,20 @@ impl Server {
                                 )
                                 .await;
                             }
-                            Err(err) => {
-                                error!("Websocket Server accept error: {err}");
-                            }
-                        },
-                        Err(err) => {
-                            debug!("Websocket Server tls error: {err}");
-                        }
-                    }
-                } else {
-                    match tokio_tungstenite::accept_async(stream).await {
-                        Ok(stream) => {
-                            net_session::run(
-                                net_session::create_session_id(),
-                                addr,
-                                delegate,
-                                shutdown,
-                                WebSocketAsyncIo::new(stream),
-                            )
-                            .await;
                         }
                         Err(err) => {
-                            error!("Websocket Server accept error: {err}");
+                            debug!("Websocket Server tls error: {err}");
                         }
                     }
+                } else if let Some(stream) = accept(stream, &upgrade_rules).await {
+                    net_session::run(
+                        net_session::create_session_id(),
+                        addr,
+                        delegate,
+                        shutdown,
+                        WebSocketAsyncIo::new(stream),
+                    )
+                    .await;
                 }
 
                 trace!("Websocket Server disconnect: {}", addr);
@@ -107,6 +206,9 @@ impl Server {
 pub struct Builder {
     create_session_delegate_callback: CreateSessionDelegateCallback,
     tls_configuration: Option<tls::TlsConfiguration>,
+    tls_client_auth: Option<tls::ClientAuthConfiguration>,
+    proxy_protocol: bool,
+    upgrade_rules: UpgradeRules,
     steam_init_callback: Option<StreamInitCallbackType>,
 }
 
@@ -115,6 +217,9 @@ impl Builder {
         Self {
             create_session_delegate_callback,
             tls_configuration: None,
+            tls_client_auth: None,
+            proxy_protocol: false,
+            upgrade_rules: UpgradeRules::default(),
             steam_init_callback: None,
         }
     }
@@ -127,14 +232,55 @@ impl Builder {
         self
     }
 
+    /// 连接开头必须携带 PROXY protocol v1/v2 头部，用于位于负载均衡之后的监听器
+    pub fn set_proxy_protocol(mut self, proxy_protocol: bool) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// 只接受指定路径的升级请求，其余路径返回 404
+    pub fn set_path<A: ToString>(mut self, path: A) -> Self {
+        self.upgrade_rules.path = Some(path.to_string());
+        self
+    }
+
+    /// 升级请求必须携带指定的请求头（如鉴权 token），否则返回 404
+    pub fn add_required_header<A: ToString>(mut self, name: A, value: A) -> Self {
+        self.upgrade_rules
+            .headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// 支持的子协议，握手时回应客户端提供的第一个受支持的子协议
+    pub fn set_subprotocols(mut self, subprotocols: Vec<String>) -> Self {
+        self.upgrade_rules.subprotocols = subprotocols;
+        self
+    }
+
     pub fn set_tls_configuration<A: ToString>(mut self, certificate: A, key: A) -> Self {
-        self.tls_configuration = Some(tls::TlsConfiguration {
+        self.tls_configuration = Some(tls::TlsConfiguration::File {
             certificate: certificate.to_string(),
             key: key.to_string(),
         });
         self
     }
 
+    /// 使用可热更新的证书，证书轮换后无需重启监听器
+    pub fn set_tls_cert_resolver(mut self, resolver: Arc<tls::ReloadableCertResolver>) -> Self {
+        self.tls_configuration = Some(tls::TlsConfiguration::Reloadable(resolver));
+        self
+    }
+
+    /// 校验客户端证书（mTLS），`required` 为 false 时未出示证书的客户端也可以连接
+    pub fn set_tls_client_auth<A: ToString>(mut self, ca: A, required: bool) -> Self {
+        self.tls_client_auth = Some(tls::ClientAuthConfiguration {
+            ca: ca.to_string(),
+            required,
+        });
+        self
+    }
+
     pub async fn build_with_listener(
         self,
         listener: TcpListener,
@@ -149,7 +295,7 @@ impl Builder {
         };
 
         select! {
-            res = server.start_server(listener, self.create_session_delegate_callback, self.steam_init_callback, self.tls_configuration) => {
+            res = server.start_server(listener, self.create_session_delegate_callback, self.steam_init_callback, self.tls_configuration, self.tls_client_auth, self.proxy_protocol, self.upgrade_rules) => {
                 if let Err(err) = res {
                     error!("Websocket Server error: {}", err);
                 }
@@ -197,3 +343,48 @@ impl Builder {
         self.build_with_listener(listener, shutdown_condition).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_rules() {
+        let rules = UpgradeRules {
+            path: Some("/api/stream".to_owned()),
+            headers: vec![("Authorization".to_owned(), "Bearer token".to_owned())],
+            subprotocols: vec!["np.v1".to_owned()],
+        };
+        let request = |path: &str, token: &str| {
+            Request::builder()
+                .uri(path)
+                .header("Authorization", token)
+                .header("Sec-WebSocket-Protocol", "chat, np.v1")
+                .body(())
+                .unwrap()
+        };
+
+        let response = rules
+            .check(&request("/api/stream", "Bearer token"), Response::new(()))
+            .unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get(header::SEC_WEBSOCKET_PROTOCOL)
+                .unwrap(),
+            "np.v1"
+        );
+
+        for (path, token) in [("/", "Bearer token"), ("/api/stream", "Bearer other")] {
+            let response = rules
+                .check(&request(path, token), Response::new(()))
+                .unwrap_err();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        // 未配置规则时接受任意升级请求
+        assert!(UpgradeRules::default()
+            .check(&request("/", ""), Response::new(()))
+            .is_ok());
+    }
+}
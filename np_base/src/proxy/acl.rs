@@ -0,0 +1,296 @@
+//! 代理通道的目标访问控制
+//!
+//! 每条规则的格式为 `主机[:端口]`：
+//!
+//! - 主机：`*` 任意主机，`example.com` 精确域名，`*.example.com` 子域名，
+//!   `10.0.0.0/8`、`192.168.1.1`、`fd00::/8` IP 或网段
+//! - 端口：`443` 或 `8000-9000`，省略时匹配任意端口；IPv6 带端口时写作 `[fd00::/8]:22`
+//!
+//! 命中拒绝列表的目标一律拒绝；允许列表非空时，只允许命中允许列表的目标。
+//! 目标为域名时网段规则要解析后才能判断，入口只拒绝能确定的目标，
+//! 出口解析域名后对每个地址再完整检查一次。
+
+use anyhow::anyhow;
+use log::error;
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Debug)]
+enum Host {
+    Any,
+    Domain(String),
+    /// 子域名后缀，包含开头的 `.`
+    Suffix(String),
+    Cidr(IpAddr, u8),
+}
+
+#[derive(Debug)]
+struct Rule {
+    host: Host,
+    ports: Option<(u16, u16)>,
+}
+
+/// 一个通道的允许列表与拒绝列表
+#[derive(Default, Debug)]
+pub struct AccessList {
+    allow: Vec<Rule>,
+    deny: Vec<Rule>,
+}
+
+impl AccessList {
+    /// 解析规则，格式错误时返回错误
+    pub fn parse(allow: &[String], deny: &[String]) -> anyhow::Result<Self> {
+        let parse_rules = |rules: &[String]| -> anyhow::Result<Vec<Rule>> {
+            rules
+                .iter()
+                .map(|rule| rule.trim())
+                .filter(|rule| !rule.is_empty())
+                .map(Rule::parse)
+                .collect()
+        };
+        Ok(Self {
+            allow: parse_rules(allow)?,
+            deny: parse_rules(deny)?,
+        })
+    }
+
+    /// 解析规则，格式错误时拒绝所有目标
+    ///
+    /// 服务端保存通道时已校验过规则，仍然失败说明版本不一致或数据被绕过校验写入，
+    /// 此时不能放行任何目标。
+    pub(crate) fn from_rules(allow: &[String], deny: &[String]) -> Self {
+        Self::parse(allow, deny).unwrap_or_else(|err| {
+            error!("{err}, denying all targets");
+            Self::deny_all()
+        })
+    }
+
+    fn deny_all() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: vec![Rule {
+                host: Host::Any,
+                ports: None,
+            }],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// 入口检查 `主机:端口` 形式的目标，只有确定被拒绝时返回 false
+    pub(crate) fn permits_target(&self, target: &str) -> bool {
+        match split_target(target) {
+            Some((host, port)) => self.decide(host, port, None) != Some(false),
+            None => self.is_empty(),
+        }
+    }
+
+    /// 出口检查解析后的目标地址，host 为解析前的主机名
+    pub(crate) fn permits_addr(&self, host: &str, addr: SocketAddr) -> bool {
+        self.decide(host, addr.port(), Some(addr.ip())) == Some(true)
+    }
+
+    /// 返回 None 表示需要解析域名后才能判断
+    fn decide(&self, host: &str, port: u16, resolved: Option<IpAddr>) -> Option<bool> {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.');
+        let ip = host.parse::<IpAddr>().ok().or(resolved);
+
+        let mut undecided = false;
+        for rule in &self.deny {
+            match rule.matches(host, ip, port) {
+                Some(true) => return Some(false),
+                Some(false) => {}
+                None => undecided = true,
+            }
+        }
+        if self.allow.is_empty() {
+            return if undecided { None } else { Some(true) };
+        }
+
+        let mut allowed = Some(false);
+        for rule in &self.allow {
+            match rule.matches(host, ip, port) {
+                Some(true) => {
+                    allowed = Some(true);
+                    break;
+                }
+                Some(false) => {}
+                None => allowed = None,
+            }
+        }
+        if undecided && allowed == Some(true) {
+            None
+        } else {
+            allowed
+        }
+    }
+}
+
+impl Rule {
+    fn parse(rule: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow!("invalid access rule '{rule}'");
+
+        // 拆分主机与端口，IPv6 只有写在方括号中时才能带端口
+        let (host, ports) = if let Some(rest) = rule.strip_prefix('[') {
+            let (host, rest) = rest.split_once(']').ok_or_else(invalid)?;
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid)?)),
+            }
+        } else if rule.matches(':').count() == 1 {
+            let (host, ports) = rule.split_once(':').unwrap();
+            (host, Some(ports))
+        } else {
+            (rule, None)
+        };
+
+        let ports = match ports {
+            Some(ports) => {
+                let (start, end) = ports.split_once('-').unwrap_or((ports, ports));
+                let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+                let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                Some((start, end))
+            }
+            None => None,
+        };
+
+        let host = host.trim().to_ascii_lowercase();
+        let host = if host == "*" {
+            Host::Any
+        } else if let Some((ip, prefix)) = host.split_once('/') {
+            let ip: IpAddr = ip.parse().map_err(|_| invalid())?;
+            let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+            if prefix > if ip.is_ipv4() { 32 } else { 128 } {
+                return Err(invalid());
+            }
+            Host::Cidr(ip, prefix)
+        } else if let Ok(ip) = host.parse::<IpAddr>() {
+            Host::Cidr(ip, if ip.is_ipv4() { 32 } else { 128 })
+        } else if let Some(suffix) = host.strip_prefix("*.") {
+            if !is_domain(suffix) {
+                return Err(invalid());
+            }
+            Host::Suffix(format!(".{suffix}"))
+        } else if is_domain(&host) {
+            Host::Domain(host)
+        } else {
+            return Err(invalid());
+        };
+
+        Ok(Self { host, ports })
+    }
+
+    fn matches(&self, host: &str, ip: Option<IpAddr>, port: u16) -> Option<bool> {
+        if let Some((start, end)) = self.ports {
+            if port < start || port > end {
+                return Some(false);
+            }
+        }
+        match &self.host {
+            Host::Any => Some(true),
+            Host::Domain(domain) => Some(host.eq_ignore_ascii_case(domain)),
+            Host::Suffix(suffix) => Some(
+                host.len() > suffix.len()
+                    && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix),
+            ),
+            Host::Cidr(network, prefix) => ip.map(|ip| in_network(ip, *network, *prefix)),
+        }
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    // IPv4 映射的 IPv6 地址按 IPv4 比较
+    match (ip.to_canonical(), network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+fn is_domain(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_')
+}
+
+/// 拆分 `主机:端口`，主机可以是 `[IPv6]`
+pub(crate) fn split_target(target: &str) -> Option<(&str, u16)> {
+    let (host, port) = target.rsplit_once(':')?;
+    Some((host, port.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> Vec<String> {
+        rules.iter().map(|rule| rule.to_string()).collect()
+    }
+
+    #[test]
+    fn test_access_list() {
+        let acl = AccessList::parse(
+            &rules(&[
+                "*.office.lan",
+                "10.0.0.0/8:22",
+                "[fd00::/8]:443",
+                "*:80-443",
+            ]),
+            &rules(&["secret.office.lan", "10.0.0.1"]),
+        )
+        .unwrap();
+
+        // 入口：能确定的目标直接判断，域名遇到网段规则时交给出口
+        assert!(acl.permits_target("git.office.lan:22"));
+        assert!(!acl.permits_target("secret.office.lan:22"));
+        assert!(acl.permits_target("10.1.2.3:22"));
+        assert!(!acl.permits_target("10.1.2.3:23"));
+        assert!(!acl.permits_target("10.0.0.1:80"));
+        assert!(acl.permits_target("[fd00::1]:443"));
+        assert!(acl.permits_target("example.com:443"));
+        assert!(!acl.permits_target("example.com:8080"));
+
+        // 出口：按解析后的地址判断
+        let addr = |addr: &str| addr.parse::<SocketAddr>().unwrap();
+        assert!(!acl.permits_addr("example.com", addr("93.184.216.34:8080")));
+        assert!(acl.permits_addr("example.com", addr("93.184.216.34:443")));
+        assert!(!acl.permits_addr("example.com", addr("10.0.0.1:443")));
+        assert!(acl.permits_addr("db.internal", addr("10.9.9.9:22")));
+        assert!(acl.permits_addr("[::ffff:10.9.9.9]", addr("[::ffff:10.9.9.9]:22")));
+
+        // 只有拒绝列表时其余目标都允许
+        let acl = AccessList::parse(&[], &rules(&["192.168.0.0/16"])).unwrap();
+        assert!(acl.permits_target("example.com:443"));
+        assert!(!acl.permits_addr("router.lan", addr("192.168.1.1:80")));
+
+        for rule in [
+            "10.0.0.0/33",
+            "host:99999",
+            "a b",
+            "fd00::1:22:x",
+            "[fd00::1]22",
+        ] {
+            assert!(AccessList::parse(&rules(&[rule]), &[]).is_err(), "{rule}");
+        }
+
+        // 规则无法解析时拒绝所有目标
+        let acl = AccessList::from_rules(&rules(&["*:80"]), &rules(&["10.0.0.0/33"]));
+        assert!(!acl.is_empty());
+        assert!(!acl.permits_target("example.com:80"));
+        assert!(!acl.permits_addr("example.com", addr("93.184.216.34:80")));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::net::WriterMessage;
-use crate::proxy::crypto::EncryptionMethod;
+use crate::proxy::crypto::{EncryptionMethod, KeyExchange};
+use crate::proxy::rate_limit::RateLimiters;
 use crate::proxy::{crypto, OutputFuncType, ProxyMessage};
 use anyhow::anyhow;
 use bytes::Bytes;
 use std::borrow::Cow;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::{Receiver, UnboundedSender};
 use tokio::sync::Semaphore;
 
@@ -53,50 +56,253 @@ impl FlowController {
     }
 }
 
+/// 会话运行状态，用于查询活跃会话
+///
+/// 流量按原始数据（编码前/解码后）统计，上行为客户端发往目标地址，下行为目标地址返回客户端。
+#[derive(Default)]
+pub(crate) struct SessionStats {
+    client_addr: Mutex<String>,
+    target_addr: Mutex<String>,
+    // 会话开始时间（Unix 秒）
+    start_time: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl SessionStats {
+    /// 记录会话开始时间与两端地址
+    pub(crate) fn start(&self, client_addr: String, target_addr: String) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        self.start_time.store(now, Ordering::Relaxed);
+        *self.client_addr.lock().unwrap() = client_addr;
+        *self.target_addr.lock().unwrap() = target_addr;
+    }
+
+    /// SOCKS5/HTTP 代理在握手后才能确定目标地址
+    pub(crate) fn set_target_addr(&self, target_addr: String) {
+        *self.target_addr.lock().unwrap() = target_addr;
+    }
+
+    fn add(&self, size: usize, is_upload: bool) {
+        let counter = if is_upload {
+            &self.bytes_in
+        } else {
+            &self.bytes_out
+        };
+        counter.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, session_id: u32) -> SessionSnapshot {
+        SessionSnapshot {
+            session_id,
+            client_addr: self.client_addr.lock().unwrap().clone(),
+            target_addr: self.target_addr.lock().unwrap().clone(),
+            start_time: self.start_time.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 活跃会话快照
+#[derive(Clone, Debug)]
+pub struct SessionSnapshot {
+    pub session_id: u32,
+    // 客户端地址（连接到输入端的地址）
+    pub client_addr: String,
+    // 目标地址
+    pub target_addr: String,
+    // 会话开始时间（Unix 秒）
+    pub start_time: u64,
+    // 上行字节数（客户端 -> 目标地址）
+    pub bytes_in: u64,
+    // 下行字节数（目标地址 -> 客户端）
+    pub bytes_out: u64,
+}
+
 #[derive(Clone)]
 pub struct SessionCommonInfo {
+    // 是否为输入端，决定限速时扣除上行还是下行额度
+    is_inlet: bool,
     // 是否压缩数据
     pub is_compressed: bool,
     // 加密方法
     pub encryption_method: EncryptionMethod,
-    // 加密key (Arc 避免 Clone 时复制 Vec)
+    // 加密key (Arc 避免 Clone 时复制 Vec)，AEAD 加密方式下为本端 X25519 公钥
     pub encryption_key: Arc<Vec<u8>>,
+    // AEAD 加密方式的密钥协商状态
+    key_exchange: Option<Arc<KeyExchange>>,
     // 流量控制器
     pub flow_controller: FlowController,
+    // 带宽限速器
+    rate_limiters: RateLimiters,
+    // 会话运行状态
+    pub(crate) stats: Arc<SessionStats>,
 }
 
 impl SessionCommonInfo {
     pub fn new(
-        _is_inlet: bool,
+        is_inlet: bool,
         is_compressed: bool,
         encryption_method: EncryptionMethod,
         encryption_key: Vec<u8>,
     ) -> Self {
         Self {
+            is_inlet,
             is_compressed,
             encryption_method,
             encryption_key: Arc::new(encryption_key),
+            key_exchange: None,
             flow_controller: FlowController::new(4 * 1024 * 1024), // 默认最大4MB未处理数据
+            rate_limiters: RateLimiters::default(),
+            stats: Arc::new(SessionStats::default()),
         }
     }
 
+    /// 设置带宽限速器
+    pub fn with_rate_limiters(mut self, rate_limiters: RateLimiters) -> Self {
+        self.rate_limiters = rate_limiters;
+        self
+    }
+
     pub fn from_method_name(
         is_inlet: bool,
         is_compressed: bool,
         encryption_method: String,
     ) -> Self {
         let encryption_method = crypto::get_method(encryption_method.as_str());
+        if encryption_method.is_aead() {
+            return Self::with_key_exchange(is_inlet, is_compressed, encryption_method);
+        }
         let encryption_key = crypto::generate_key(&encryption_method);
         Self::new(is_inlet, is_compressed, encryption_method, encryption_key)
     }
 
+    /// 输出端根据 I2oConnect 中携带的 key 创建会话信息
+    ///
+    /// 旧加密方式直接使用对端发来的 key；AEAD 加密方式下 `peer_key` 为输入端公钥，
+    /// 在这里生成本端临时密钥并完成协商，`encryption_key` 中保存本端公钥用于回复 O2iConnect。
+    /// `is_tcp` 为 false 时按数据报会话检查重放。
+    pub fn from_peer_key(
+        is_inlet: bool,
+        is_compressed: bool,
+        is_tcp: bool,
+        encryption_method: EncryptionMethod,
+        peer_key: Vec<u8>,
+    ) -> anyhow::Result<Self> {
+        if !encryption_method.is_aead() {
+            return Ok(Self::new(
+                is_inlet,
+                is_compressed,
+                encryption_method,
+                peer_key,
+            ));
+        }
+        let info = Self::with_key_exchange(is_inlet, is_compressed, encryption_method);
+        info.set_datagram(!is_tcp);
+        info.complete_key_exchange(&peer_key)?;
+        Ok(info)
+    }
+
+    fn with_key_exchange(
+        is_inlet: bool,
+        is_compressed: bool,
+        encryption_method: EncryptionMethod,
+    ) -> Self {
+        let key_exchange = KeyExchange::new(encryption_method.clone(), is_inlet);
+        let mut info = Self::new(
+            is_inlet,
+            is_compressed,
+            encryption_method,
+            key_exchange.public_key().to_vec(),
+        );
+        info.key_exchange = Some(Arc::new(key_exchange));
+        info
+    }
+
+    /// 同一客户端连接上新建上游会话使用的会话信息
+    ///
+    /// 加密方式、压缩、限速器与会话统计沿用当前会话，密钥与流量控制独立。
+    pub(crate) fn new_upstream(&self) -> Self {
+        let mut info = if self.encryption_method.is_aead() {
+            Self::with_key_exchange(
+                self.is_inlet,
+                self.is_compressed,
+                self.encryption_method.clone(),
+            )
+        } else {
+            let encryption_key = crypto::generate_key(&self.encryption_method);
+            Self::new(
+                self.is_inlet,
+                self.is_compressed,
+                self.encryption_method.clone(),
+                encryption_key,
+            )
+        };
+        info.rate_limiters = self.rate_limiters.clone();
+        info.stats = self.stats.clone();
+        info
+    }
+
+    /// 标记为数据报（UDP）会话，AEAD 加密方式下允许乱序到达，需要在完成密钥协商之前设置
+    pub fn set_datagram(&self, datagram: bool) {
+        if let Some(key_exchange) = &self.key_exchange {
+            key_exchange.set_datagram(datagram);
+        }
+    }
+
+    /// 使用对端公钥完成密钥协商，非 AEAD 加密方式直接忽略
+    pub fn complete_key_exchange(&self, peer_public_key: &[u8]) -> anyhow::Result<()> {
+        match &self.key_exchange {
+            Some(key_exchange) => key_exchange.complete(peer_public_key),
+            None => Ok(()),
+        }
+    }
+
+    /// 需要回复给对端的公钥，非 AEAD 加密方式为空，避免旧 key 再次出现在链路上
+    pub fn public_key(&self) -> &[u8] {
+        match &self.key_exchange {
+            Some(key_exchange) => key_exchange.public_key(),
+            None => &[],
+        }
+    }
+
+    fn encrypt(&self, data: Cow<'_, [u8]>) -> anyhow::Result<Vec<u8>> {
+        match &self.key_exchange {
+            Some(key_exchange) => key_exchange.session()?.encrypt(&data),
+            None => crypto::encrypt(
+                &self.encryption_method,
+                self.encryption_key.as_slice(),
+                data,
+            ),
+        }
+    }
+
+    fn decrypt(&self, data: Cow<'_, [u8]>) -> anyhow::Result<Vec<u8>> {
+        match &self.key_exchange {
+            Some(key_exchange) => key_exchange.session()?.decrypt(&data),
+            None => crypto::decrypt(
+                &self.encryption_method,
+                self.encryption_key.as_slice(),
+                data,
+            ),
+        }
+    }
+
     /// 编码数据并申请背压许可
     ///
+    /// 先按原始数据长度申请限速额度（输入端计上行，输出端计下行），再进行编码。
+    ///
     /// 接受 `Bytes` 避免调用方 `to_vec()` 的无谓拷贝：
     /// - 无压缩无加密：直接返回入参，真正零拷贝
     /// - 有压缩：`compress_data(&data)` 接受 `&[u8]`，省去额外 Vec 分配
     /// - 仅加密：encrypt 需要 `Vec<u8>`，此处仍需一次 `to_vec()`（不可避免）
     pub async fn encode_data_and_limiting(&self, data: Bytes) -> anyhow::Result<Bytes> {
+        self.rate_limiters.acquire(data.len(), self.is_inlet).await;
+        self.stats.add(data.len(), self.is_inlet);
+
         // 快速路径：无压缩无加密 → 零拷贝，直接返回
         if self.encryption_method.is_none() && !self.is_compressed {
             self.flow_controller.acquire_read_permit(data.len()).await;
@@ -108,22 +314,14 @@ impl SessionCommonInfo {
             let compressed = crypto::compress_data(&data)?;
             if !self.encryption_method.is_none() {
                 // compressed 已是 Vec，Cow::Owned 让 Xor 原地修改（零额外分配）
-                crypto::encrypt(
-                    &self.encryption_method,
-                    self.encryption_key.as_slice(),
-                    Cow::Owned(compressed),
-                )?
+                self.encrypt(Cow::Owned(compressed))?
             } else {
                 compressed
             }
         } else {
             // 仅加密，无压缩：Cow::Borrowed 让 AES 直接借用 Bytes，不再需要 to_vec()
             // Xor 仍需 into_owned()（一次拷贝），不可避免
-            crypto::encrypt(
-                &self.encryption_method,
-                self.encryption_key.as_slice(),
-                Cow::Borrowed(&data),
-            )?
+            self.encrypt(Cow::Borrowed(&data))?
         };
 
         self.flow_controller
@@ -137,13 +335,15 @@ impl SessionCommonInfo {
     ///
     /// 接受 `Bytes`，返回解密/解压后的 `Bytes`。
     pub fn decode_data(&self, data: Bytes) -> anyhow::Result<Bytes> {
+        let data = self.decode_data_internal(data)?;
+        self.stats.add(data.len(), !self.is_inlet);
+        Ok(data)
+    }
+
+    fn decode_data_internal(&self, data: Bytes) -> anyhow::Result<Bytes> {
         let decoded = if !self.encryption_method.is_none() {
             // Cow::Borrowed：AES 直接借用，零拷贝；Xor 内部 into_owned() 一次拷贝
-            let decrypted = crypto::decrypt(
-                &self.encryption_method,
-                self.encryption_key.as_slice(),
-                Cow::Borrowed(&data),
-            )?;
+            let decrypted = self.decrypt(Cow::Borrowed(&data))?;
             if self.is_compressed {
                 crypto::decompress_data(decrypted.as_slice())?
             } else {
@@ -182,3 +382,45 @@ pub async fn parse_addr(host: &String) -> anyhow::Result<SocketAddr> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_stats() {
+        let inlet = SessionCommonInfo::from_method_name(true, true, "Xor".to_string());
+        let outlet = SessionCommonInfo::new(
+            false,
+            true,
+            inlet.encryption_method.clone(),
+            inlet.encryption_key.to_vec(),
+        );
+        inlet
+            .stats
+            .start("127.0.0.1:1000".to_string(), String::new());
+        inlet.stats.set_target_addr("example.com:443".to_string());
+
+        // 流量按原始数据长度统计，与压缩/加密后的长度无关
+        let up = inlet
+            .encode_data_and_limiting(Bytes::from_static(b"hello world"))
+            .await
+            .unwrap();
+        outlet.decode_data(up).unwrap();
+        let down = outlet
+            .encode_data_and_limiting(Bytes::from_static(b"hi"))
+            .await
+            .unwrap();
+        inlet.decode_data(down).unwrap();
+
+        for (info, session_id) in [(&inlet, 1), (&outlet, 2)] {
+            let snapshot = info.stats.snapshot(session_id);
+            assert_eq!(snapshot.session_id, session_id);
+            assert_eq!((snapshot.bytes_in, snapshot.bytes_out), (11, 2));
+        }
+        let snapshot = inlet.stats.snapshot(1);
+        assert_eq!(snapshot.client_addr, "127.0.0.1:1000");
+        assert_eq!(snapshot.target_addr, "example.com:443");
+        assert!(snapshot.start_time > 0);
+    }
+}
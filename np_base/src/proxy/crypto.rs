@@ -1,8 +1,17 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::Aes256Gcm;
 use anyhow::anyhow;
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
 use lz4_flex::block::{compress_prepend_size, decompress_size_prepended};
+use rand::rngs::OsRng;
 use rand::Rng;
+use sha2::Sha256;
 use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
 use std::{fmt, io};
+use x25519_dalek::{EphemeralSecret, PublicKey};
 
 // Function to compress data using Brotli
 pub fn compress_data(input: &[u8]) -> Result<Vec<u8>, io::Error> {
@@ -21,12 +30,22 @@ pub enum EncryptionMethod {
     None,
     Aes128,
     Xor,
+    Aes256Gcm,
+    ChaCha20Poly1305,
 }
 
 impl EncryptionMethod {
     pub fn is_none(&self) -> bool {
         matches!(self, EncryptionMethod::None)
     }
+
+    /// 是否是 AEAD 加密方式（密钥通过 X25519 协商，不在链路上传输）
+    pub fn is_aead(&self) -> bool {
+        matches!(
+            self,
+            EncryptionMethod::Aes256Gcm | EncryptionMethod::ChaCha20Poly1305
+        )
+    }
 }
 
 pub fn get_method(method: &str) -> EncryptionMethod {
@@ -34,6 +53,8 @@ pub fn get_method(method: &str) -> EncryptionMethod {
         "Aes128" => EncryptionMethod::Aes128,
         "None" => EncryptionMethod::None,
         "Xor" => EncryptionMethod::Xor,
+        "Aes256Gcm" => EncryptionMethod::Aes256Gcm,
+        "ChaCha20Poly1305" => EncryptionMethod::ChaCha20Poly1305,
         _ => EncryptionMethod::None,
     }
 }
@@ -50,6 +71,12 @@ impl fmt::Display for EncryptionMethod {
             EncryptionMethod::Xor => {
                 write!(f, "Xor")
             }
+            EncryptionMethod::Aes256Gcm => {
+                write!(f, "Aes256Gcm")
+            }
+            EncryptionMethod::ChaCha20Poly1305 => {
+                write!(f, "ChaCha20Poly1305")
+            }
         }
     }
 }
@@ -76,6 +103,8 @@ pub fn generate_key(method: &EncryptionMethod) -> Vec<u8> {
                 })
                 .collect::<Vec<u8>>()
         }
+        // AEAD 方式的会话密钥由 KeyExchange 协商得出
+        EncryptionMethod::Aes256Gcm | EncryptionMethod::ChaCha20Poly1305 => Vec::new(),
     }
 }
 
@@ -98,6 +127,9 @@ pub fn encrypt(
             // into_owned(): Cow::Owned → O(0) 直接取出 Vec；Cow::Borrowed → O(n) 复制一次
             Ok(xor_encrypt_decrypt(data.into_owned(), key))
         }
+        EncryptionMethod::Aes256Gcm | EncryptionMethod::ChaCha20Poly1305 => Err(anyhow!(
+            "{method} requires a negotiated session key, use KeyExchange"
+        )),
     }
 }
 
@@ -119,6 +151,9 @@ pub fn decrypt(
             }
             Ok(xor_encrypt_decrypt(data.into_owned(), key))
         }
+        EncryptionMethod::Aes256Gcm | EncryptionMethod::ChaCha20Poly1305 => Err(anyhow!(
+            "{method} requires a negotiated session key, use KeyExchange"
+        )),
     }
 }
 
@@ -133,3 +168,248 @@ fn xor_encrypt_decrypt(mut data: Vec<u8>, key: &[u8]) -> Vec<u8> {
     //     .map(|(&data_byte, &key_byte)| data_byte ^ key_byte)
     //     .collect()
 }
+
+// AEAD nonce 长度(96 bit)
+const AEAD_NONCE_LEN: usize = 12;
+// AEAD 认证标签长度
+const AEAD_TAG_LEN: usize = 16;
+
+enum AeadCipher {
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+impl AeadCipher {
+    fn new(method: &EncryptionMethod, key: &[u8]) -> anyhow::Result<Self> {
+        match method {
+            EncryptionMethod::Aes256Gcm => Ok(AeadCipher::Aes256Gcm(Box::new(
+                Aes256Gcm::new_from_slice(key).map_err(|_| anyhow!("invalid key length"))?,
+            ))),
+            EncryptionMethod::ChaCha20Poly1305 => Ok(AeadCipher::ChaCha20Poly1305(Box::new(
+                ChaCha20Poly1305::new_from_slice(key).map_err(|_| anyhow!("invalid key length"))?,
+            ))),
+            _ => Err(anyhow!("{method} is not an AEAD method")),
+        }
+    }
+
+    fn seal(&self, nonce: &[u8; AEAD_NONCE_LEN], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            AeadCipher::Aes256Gcm(cipher) => cipher.encrypt(nonce.into(), data),
+            AeadCipher::ChaCha20Poly1305(cipher) => cipher.encrypt(nonce.into(), data),
+        }
+        .map_err(|_| anyhow!("aead encrypt error"))
+    }
+
+    fn open(&self, nonce: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            AeadCipher::Aes256Gcm(cipher) => cipher.decrypt(nonce.into(), data),
+            AeadCipher::ChaCha20Poly1305(cipher) => cipher.decrypt(nonce.into(), data),
+        }
+        .map_err(|_| anyhow!("aead decrypt error: authentication failed"))
+    }
+}
+
+// 数据报会话重放窗口的大小（个数）
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+/// 接收方向的重放检查
+///
+/// `next` 为已接收的最大计数器 + 1，`bitmap` 第 i 位表示计数器 `next - 1 - i` 已接收。
+#[derive(Default)]
+struct ReplayWindow {
+    next: u64,
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    /// 流式会话要求严格等于下一个计数器，数据报会话允许窗口内乱序
+    fn check(&self, counter: u64, datagram: bool) -> bool {
+        if !datagram {
+            return counter == self.next;
+        }
+        if counter >= self.next {
+            return true;
+        }
+        let offset = self.next - 1 - counter;
+        offset < REPLAY_WINDOW_SIZE && self.bitmap & (1 << offset) == 0
+    }
+
+    fn update(&mut self, counter: u64) {
+        if counter >= self.next {
+            let shift = counter - self.next + 1;
+            self.bitmap = if shift >= REPLAY_WINDOW_SIZE {
+                0
+            } else {
+                self.bitmap << shift
+            };
+            self.bitmap |= 1;
+            self.next = counter + 1;
+        } else {
+            self.bitmap |= 1 << (self.next - 1 - counter);
+        }
+    }
+}
+
+/// AEAD 会话密钥
+///
+/// 两个方向使用不同的密钥，nonce 为本方向单调递增的 64 位计数器，
+/// 随密文一起发送：`nonce(12) | ciphertext | tag(16)`。
+/// 流式（TCP）会话的数据按序到达，只接受下一个计数器，重放与乱序都视为错误；
+/// 数据报（UDP）会话使用滑动窗口，允许乱序/丢包，拒绝重复与过旧的计数器。
+pub struct AeadSession {
+    seal: AeadCipher,
+    open: AeadCipher,
+    counter: AtomicU64,
+    datagram: bool,
+    replay: Mutex<ReplayWindow>,
+}
+
+impl AeadSession {
+    fn new(
+        method: &EncryptionMethod,
+        seal_key: &[u8],
+        open_key: &[u8],
+        datagram: bool,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            seal: AeadCipher::new(method, seal_key)?,
+            open: AeadCipher::new(method, open_key)?,
+            counter: AtomicU64::new(0),
+            datagram,
+            replay: Mutex::new(ReplayWindow::default()),
+        })
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        if counter == u64::MAX {
+            return Err(anyhow!("aead nonce exhausted"));
+        }
+        let mut nonce = [0u8; AEAD_NONCE_LEN];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+
+        let sealed = self.seal.seal(&nonce, data)?;
+        let mut output = Vec::with_capacity(AEAD_NONCE_LEN + sealed.len());
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&sealed);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if data.len() < AEAD_NONCE_LEN + AEAD_TAG_LEN {
+            return Err(anyhow!("aead frame too short: {}", data.len()));
+        }
+        let (nonce, sealed) = data.split_at(AEAD_NONCE_LEN);
+        if nonce[..4] != [0u8; 4] {
+            return Err(anyhow!("aead invalid nonce"));
+        }
+        let counter = u64::from_be_bytes(nonce[4..].try_into()?);
+
+        let mut replay = self
+            .replay
+            .lock()
+            .map_err(|_| anyhow!("aead replay lock poisoned"))?;
+        if !replay.check(counter, self.datagram) {
+            return Err(anyhow!(
+                "aead replayed or out-of-order frame: counter {counter}, expected {}",
+                replay.next
+            ));
+        }
+        // 认证通过后才更新窗口，伪造的帧不会影响后续数据
+        let plain = self.open.open(nonce, sealed)?;
+        replay.update(counter);
+        Ok(plain)
+    }
+}
+
+/// X25519 临时密钥交换
+///
+/// 输入端在 I2oConnect 中携带自己的公钥，输出端在 O2iConnect 中回复自己的公钥，
+/// 双方用 HKDF-SHA256 从共享密钥派生出两个方向的会话密钥，密钥本身不在链路上传输。
+pub struct KeyExchange {
+    method: EncryptionMethod,
+    is_inlet: bool,
+    // 是否为数据报（UDP）会话，决定接收方向的重放检查方式
+    datagram: AtomicBool,
+    public_key: [u8; 32],
+    secret: Mutex<Option<EphemeralSecret>>,
+    session: OnceLock<AeadSession>,
+}
+
+impl KeyExchange {
+    pub fn new(method: EncryptionMethod, is_inlet: bool) -> Self {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public_key = PublicKey::from(&secret).to_bytes();
+        Self {
+            method,
+            is_inlet,
+            datagram: AtomicBool::new(false),
+            public_key,
+            secret: Mutex::new(Some(secret)),
+            session: OnceLock::new(),
+        }
+    }
+
+    /// 本端公钥
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// 标记为数据报（UDP）会话，需要在完成密钥协商之前设置
+    pub fn set_datagram(&self, datagram: bool) {
+        self.datagram.store(datagram, Ordering::Relaxed);
+    }
+
+    /// 使用对端公钥完成密钥协商，每个 KeyExchange 只能完成一次
+    pub fn complete(&self, peer_public_key: &[u8]) -> anyhow::Result<()> {
+        let peer_public_key: [u8; 32] = peer_public_key
+            .try_into()
+            .map_err(|_| anyhow!("invalid public key length: {}", peer_public_key.len()))?;
+
+        let secret = self
+            .secret
+            .lock()
+            .map_err(|_| anyhow!("key exchange lock poisoned"))?
+            .take()
+            .ok_or_else(|| anyhow!("key exchange already completed"))?;
+
+        let shared = secret.diffie_hellman(&PublicKey::from(peer_public_key));
+        if !shared.was_contributory() {
+            return Err(anyhow!("invalid peer public key"));
+        }
+
+        let (inlet_public_key, outlet_public_key) = if self.is_inlet {
+            (&self.public_key, &peer_public_key)
+        } else {
+            (&peer_public_key, &self.public_key)
+        };
+        let mut salt = [0u8; 64];
+        salt[..32].copy_from_slice(inlet_public_key);
+        salt[32..].copy_from_slice(outlet_public_key);
+
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes());
+        let mut i2o_key = [0u8; 32];
+        let mut o2i_key = [0u8; 32];
+        hkdf.expand(b"npipe i2o", &mut i2o_key)
+            .map_err(|_| anyhow!("hkdf expand error"))?;
+        hkdf.expand(b"npipe o2i", &mut o2i_key)
+            .map_err(|_| anyhow!("hkdf expand error"))?;
+
+        let datagram = self.datagram.load(Ordering::Relaxed);
+        let session = if self.is_inlet {
+            AeadSession::new(&self.method, &i2o_key, &o2i_key, datagram)?
+        } else {
+            AeadSession::new(&self.method, &o2i_key, &i2o_key, datagram)?
+        };
+        self.session
+            .set(session)
+            .map_err(|_| anyhow!("key exchange already completed"))
+    }
+
+    /// 协商完成后的会话密钥
+    pub fn session(&self) -> anyhow::Result<&AeadSession> {
+        self.session
+            .get()
+            .ok_or_else(|| anyhow!("key exchange not completed"))
+    }
+}
@@ -0,0 +1,213 @@
+//! HTTP 头改写规则
+//!
+//! 规则写在通道的 `custom_mapping` 中，键为 `request.<动作>:<头名称>` 或
+//! `response.<动作>:<头名称>`，值为头的内容（`remove` 忽略值）：
+//!
+//! - `set` 删除同名头后写入一个新值（可用于改写 `Host`）
+//! - `add` 追加一个头，保留已有的同名头
+//! - `remove` 删除所有同名头
+//!
+//! 值中可以引用变量：`$remote_addr` 客户端 IP，`$remote_port` 客户端端口，
+//! `$proxy_add_x_forwarded_for` 客户端请求原有的 `X-Forwarded-For` 追加客户端 IP。
+//! 同一个头的规则按 remove、set、add 的顺序执行，与书写顺序无关。
+
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// 请求与响应头，按原始顺序保存
+pub(crate) type Headers = Vec<(String, Vec<u8>)>;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Action {
+    Remove,
+    Set,
+    Add,
+}
+
+#[derive(Debug)]
+struct Rule {
+    action: Action,
+    name: String,
+    value: String,
+}
+
+/// 一个通道的头改写规则
+#[derive(Default, Debug)]
+pub struct HeaderRules {
+    request: Vec<Rule>,
+    response: Vec<Rule>,
+}
+
+/// 改写时可用的变量
+pub(crate) struct Variables<'a> {
+    pub(crate) peer_addr: &'a SocketAddr,
+    /// 客户端请求原有的 X-Forwarded-For
+    pub(crate) forwarded_for: Option<&'a str>,
+}
+
+/// `custom_mapping` 中的键是否为头改写规则
+///
+/// 域名不能包含冒号，所以规则不会与虚拟主机域名混淆
+pub fn is_header_rule_key(key: &str) -> bool {
+    (key.starts_with("request.") || key.starts_with("response.")) && key.contains(':')
+}
+
+impl HeaderRules {
+    /// 解析 `custom_mapping` 中的规则，格式错误时返回错误
+    pub fn parse(custom_mapping: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut rules = Self::default();
+        for (key, value) in custom_mapping {
+            if !is_header_rule_key(key) {
+                continue;
+            }
+            let (kind, rest) = key.split_once('.').unwrap();
+            let (action, name) = rest.split_once(':').unwrap();
+            let action = match action {
+                "set" => Action::Set,
+                "add" => Action::Add,
+                "remove" => Action::Remove,
+                _ => return Err(anyhow!("header rule '{key}' has unknown action")),
+            };
+            let name = name.trim();
+            if name.is_empty() || !name.bytes().all(is_token_byte) {
+                return Err(anyhow!("header rule '{key}' has invalid header name"));
+            }
+            if value.contains(['\r', '\n']) {
+                return Err(anyhow!("header rule '{key}' has invalid value"));
+            }
+            let rule = Rule {
+                action,
+                name: name.to_owned(),
+                value: value.clone(),
+            };
+            match kind {
+                "request" => rules.request.push(rule),
+                _ => rules.response.push(rule),
+            }
+        }
+        // HashMap 无序，排序后执行顺序固定
+        for list in [&mut rules.request, &mut rules.response] {
+            list.sort_by(|a, b| {
+                (a.action, a.name.to_ascii_lowercase())
+                    .cmp(&(b.action, b.name.to_ascii_lowercase()))
+            });
+        }
+        Ok(rules)
+    }
+
+    /// 解析规则，格式错误时不改写（服务端保存通道时已校验）
+    pub(crate) fn from_mapping(custom_mapping: &HashMap<String, String>) -> Self {
+        Self::parse(custom_mapping).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.request.is_empty() && self.response.is_empty()
+    }
+
+    pub(crate) fn has_response_rules(&self) -> bool {
+        !self.response.is_empty()
+    }
+
+    pub(crate) fn apply_request(&self, headers: &mut Headers, vars: &Variables) {
+        apply(&self.request, headers, vars);
+    }
+
+    pub(crate) fn apply_response(&self, headers: &mut Headers, vars: &Variables) {
+        apply(&self.response, headers, vars);
+    }
+}
+
+fn apply(rules: &[Rule], headers: &mut Headers, vars: &Variables) {
+    for rule in rules {
+        if matches!(rule.action, Action::Remove | Action::Set) {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case(&rule.name));
+        }
+        if matches!(rule.action, Action::Set | Action::Add) {
+            headers.push((rule.name.clone(), expand(&rule.value, vars).into_bytes()));
+        }
+    }
+}
+
+/// 替换值中的变量
+fn expand(value: &str, vars: &Variables) -> String {
+    if !value.contains('$') {
+        return value.to_owned();
+    }
+    let remote_addr = vars.peer_addr.ip().to_string();
+    let forwarded_for = match vars.forwarded_for {
+        Some(forwarded_for) if !forwarded_for.trim().is_empty() => {
+            format!("{}, {}", forwarded_for.trim(), remote_addr)
+        }
+        _ => remote_addr.clone(),
+    };
+    // 较长的变量名先替换，避免前缀相同的变量被误替换
+    value
+        .replace("$proxy_add_x_forwarded_for", &forwarded_for)
+        .replace("$remote_addr", &remote_addr)
+        .replace("$remote_port", &vars.peer_addr.port().to_string())
+}
+
+/// RFC 7230 token 字符
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_rules() {
+        let rules = HeaderRules::parse(&HashMap::from([
+            ("request.set:Host".to_owned(), "backend.local".to_owned()),
+            (
+                "request.set:X-Forwarded-For".to_owned(),
+                "$proxy_add_x_forwarded_for".to_owned(),
+            ),
+            (
+                "request.add:X-Real-IP".to_owned(),
+                "$remote_addr".to_owned(),
+            ),
+            ("request.remove:Cookie".to_owned(), String::new()),
+            ("response.remove:Server".to_owned(), String::new()),
+            ("app.example.com".to_owned(), String::new()),
+        ]))
+        .unwrap();
+
+        let peer_addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let mut headers: Headers = vec![
+            ("Host".to_owned(), b"example.com".to_vec()),
+            ("cookie".to_owned(), b"a=1".to_vec()),
+            ("Accept".to_owned(), b"*/*".to_vec()),
+        ];
+        rules.apply_request(
+            &mut headers,
+            &Variables {
+                peer_addr: &peer_addr,
+                forwarded_for: Some("1.1.1.1"),
+            },
+        );
+        assert_eq!(
+            headers,
+            vec![
+                ("Accept".to_owned(), b"*/*".to_vec()),
+                ("Host".to_owned(), b"backend.local".to_vec()),
+                ("X-Forwarded-For".to_owned(), b"1.1.1.1, 10.0.0.1".to_vec()),
+                ("X-Real-IP".to_owned(), b"10.0.0.1".to_vec()),
+            ]
+        );
+        assert!(rules.has_response_rules());
+
+        assert!(HeaderRules::parse(&HashMap::from([(
+            "request.replace:Host".to_owned(),
+            String::new()
+        )]))
+        .is_err());
+        assert!(HeaderRules::parse(&HashMap::from([(
+            "request.set:X Y".to_owned(),
+            String::new()
+        )]))
+        .is_err());
+    }
+}
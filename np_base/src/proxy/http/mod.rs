@@ -1,4 +1,6 @@
-use crate::net::{SendMessageFuncType, WriterMessage};
+use crate::net::{net_session, SendMessageFuncType, WriterMessage};
+use crate::proxy::common::SessionCommonInfo;
+use crate::proxy::header_rules::{HeaderRules, Headers, Variables};
 use crate::proxy::inlet::InletProxyType;
 use crate::proxy::proxy_context::{ProxyContext, ProxyContextData};
 use crate::proxy::ProxyMessage;
@@ -8,33 +10,103 @@ use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use bytes::Bytes;
 use log::error;
-use std::collections::HashSet;
-use std::fmt::Write as FmtWrite;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 
 enum Status {
+    /// 等待请求头
     Free,
-    Connecting,
-    Running,
+    /// 转发请求体
+    Body(BodyState),
+    /// CONNECT 或协议升级之后直接转发
+    Tunnel,
     Invalid,
 }
 
+enum BodyState {
+    /// 剩余字节数
+    Length(u64),
+    Chunked(ChunkedState),
+}
+
+#[derive(Clone, Copy)]
+enum ChunkedState {
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailer,
+}
+
+/// 上游连接，每个目标主机一个，使用独立的会话 id
+struct Upstream {
+    target: String,
+    common_info: SessionCommonInfo,
+    connected: bool,
+    /// 连接建立前缓存的请求数据
+    pending: Vec<u8>,
+    /// CONNECT 请求在连接建立后回复客户端的响应
+    connect_response: Option<Vec<u8>>,
+    /// 配置了响应头改写规则时解析上游响应
+    response: Option<ResponseRewriter>,
+}
+
+/// 按响应头改写规则解析上游返回的数据
+struct ResponseRewriter {
+    status: ResponseStatus,
+    cache: Vec<u8>,
+    /// 等待响应的请求是否为 HEAD，按发送顺序排列
+    pending_requests: VecDeque<bool>,
+}
+
+enum ResponseStatus {
+    /// 等待响应头
+    Head,
+    Body(BodyState),
+    /// 协议升级或没有长度的响应体，之后的数据原样转发
+    Raw,
+}
+
+/// 解析后的请求头
+struct RequestHead {
+    head_size: usize,
+    target: String,
+    is_connect: bool,
+    is_upgrade: bool,
+    is_head: bool,
+    authorized: bool,
+    version: &'static str,
+    /// 去掉代理相关 header 之后的请求头，CONNECT 请求为空
+    data: Vec<u8>,
+    body: Option<BodyState>,
+}
+
 pub struct HttpContext {
     status: Status,
     cache_data: Vec<u8>,
     write_to_peer_tx: Option<UnboundedSender<WriterMessage>>,
     peer_addr: Option<SocketAddr>,
     ctx_data: Option<Arc<ProxyContextData>>,
-    is_connect_method: bool,
+    authorized: bool,
+    upstreams: HashMap<u32, Upstream>,
+    /// 当前请求使用的上游会话 id
+    active: Option<u32>,
+    header_rules: Arc<HeaderRules>,
 }
 
 const PROXY_AUTH_HEADER: &str = "Proxy-Authorization";
 const PROXY_AUTH_REQUIRED_RESPONSE: &[u8] = b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"Proxy\"\r\n\r\n";
 const BAD_GATEWAY_HEADER: &[u8] =
     b"HTTP/1.1 502 Bad Gateway\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n";
+const FORBIDDEN_RESPONSE: &[u8] =
+    b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+const BAD_REQUEST_RESPONSE: &[u8] =
+    b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+/// 请求头最大长度
+const MAX_REQUEST_HEAD_LEN: usize = 64 * 1024;
+
 #[async_trait]
 impl ProxyContext for HttpContext {
     async fn on_start(
@@ -45,171 +117,85 @@ impl ProxyContext for HttpContext {
     ) -> anyhow::Result<()> {
         self.write_to_peer_tx = Some(write_to_peer_tx);
         self.peer_addr = Some(peer_addr);
+        self.header_rules = ctx_data.data_ex.header_rules.clone();
         self.ctx_data = Some(ctx_data);
         Ok(())
     }
 
+    /// 逐个解析 keep-alive 连接上的请求，按目标主机转发到对应的上游会话
     async fn on_recv_peer_data(
         &mut self,
         ctx_data: Arc<ProxyContextData>,
         data: Bytes,
     ) -> anyhow::Result<()> {
-        match self.status {
-            Status::Free => {
-                self.cache_data.extend_from_slice(&data);
-
-                let mut headers = [httparse::EMPTY_HEADER; 64];
-                let mut req = httparse::Request::new(&mut headers);
-                let res: httparse::Status<usize> = req.parse(&self.cache_data)?;
+        self.cache_data.extend_from_slice(&data);
 
-                match res {
-                    httparse::Status::Partial => {
-                        // 继续接收数据
+        loop {
+            match &mut self.status {
+                Status::Free => {
+                    if self.cache_data.is_empty() || !self.on_request_head(&ctx_data).await? {
                         return Ok(());
                     }
-                    httparse::Status::Complete(head_size) => {
-                        if !http_authorization(&ctx_data, &req)? {
-                            self.status = Status::Invalid;
-                            self.send_and_close(PROXY_AUTH_REQUIRED_RESPONSE.to_vec())
-                                .await?;
-                            return Ok(());
-                        }
-
-                        // let method = req.method.ok_or_else(|| anyhow!("parse http method error"))?.to_string();
-                        let mut path = req
-                            .path
-                            .ok_or_else(|| anyhow!("parse http path error"))?
-                            .to_string();
-
-                        // 如果输入没有协议，先添加一个假的协议以便解析
-                        if !path.starts_with("http://") && !path.starts_with("https://") {
-                            path = format!("http://{}", path);
-                        }
-
-                        let url =
-                            url::Url::parse(&path).map_err(|_| anyhow!("parse http path error"))?;
-
-                        if req.method == Some("CONNECT") {
-                            self.is_connect_method = true;
-                            let response = format!(
-                                "HTTP/1.1 200 Connection Established\r\nProxy-Agent: npipe/{}\r\n\r\n",
-                                format_httparse_request_version(req.version)
-                            );
-                            self.cache_data = response.into_bytes();
-                        } else {
-                            // 修改header
-                            let proxy_headers: HashSet<&'static [u8]> = HashSet::from([
-                                // b"proxy-connection".as_slice(),
-                                // b"proxy-authenticate".as_slice(),
-                                // b"proxy-authorization".as_slice(),
-                                b"forwarded".as_slice(),
-                                b"x-forwarded-for".as_slice(),
-                                b"x-forwarded-host".as_slice(),
-                                b"x-forwarded-proto".as_slice(),
-                                b"via".as_slice(),
-                                // b"connection".as_slice(), // 有时也需要移除
-                            ]);
-
-                            let mut headers_vec: Vec<_> = req.headers.to_vec();
-                            headers_vec.retain(|header| {
-                                let binding = header.name.to_ascii_lowercase();
-                                let name = binding.as_bytes();
-                                !proxy_headers.contains(name) && !name.starts_with(b"proxy-")
-                            });
-                            req.headers = &mut *headers_vec;
-
-                            let mut new_data = format_httparse_request(&req).into_bytes();
-                            new_data.extend_from_slice(&self.cache_data[head_size..]);
-                            self.cache_data = new_data;
-                        }
-
-                        let host = url
-                            .host_str()
-                            .ok_or_else(|| anyhow!("parse http host error"))?;
-
-                        let port = url
-                            .port_or_known_default()
-                            .ok_or_else(|| anyhow!("parse http port error"))?;
-
-                        self.status = Status::Connecting;
-                        // println!("connect: {}:{}", host, port);
-
-                        // 发送连接请求
-                        ctx_data
-                            .output
-                            .send(ProxyMessage::I2oConnect(
-                                ctx_data.get_session_id(),
-                                InletProxyType::HTTP.to_u8(),
-                                true,
-                                ctx_data.common_data.is_compressed,
-                                format!("{}:{}", host, port),
-                                ctx_data.common_data.encryption_method.to_string(),
-                                BASE64_STANDARD
-                                    .encode(ctx_data.common_data.encryption_key.as_slice()),
-                                self.peer_addr.as_ref().unwrap().to_string(),
-                            ))
-                            .await?;
+                }
+                Status::Body(BodyState::Length(remaining)) => {
+                    let size = (*remaining).min(self.cache_data.len() as u64);
+                    *remaining -= size;
+                    if *remaining == 0 {
+                        self.status = Status::Free;
                     }
+                    if size == 0 {
+                        return Ok(());
+                    }
+                    self.forward_cache_data(size as usize).await?;
+                }
+                Status::Body(BodyState::Chunked(state)) => {
+                    let (size, finished) = scan_chunked_body(state, &self.cache_data)?;
+                    if finished {
+                        self.status = Status::Free;
+                    }
+                    if size > 0 {
+                        self.forward_cache_data(size).await?;
+                    }
+                    if !finished {
+                        return Ok(());
+                    }
+                }
+                Status::Tunnel => {
+                    if !self.cache_data.is_empty() {
+                        self.forward_cache_data(self.cache_data.len()).await?;
+                    }
+                    return Ok(());
+                }
+                Status::Invalid => {
+                    self.cache_data.clear();
+                    return Ok(());
                 }
-            }
-            Status::Invalid => {}
-            Status::Connecting => {}
-            Status::Running => {
-                let encoded = ctx_data
-                    .common_data
-                    .encode_data_and_limiting(data) // data: Bytes，无需 to_vec()
-                    .await?;
-                ctx_data
-                    .output
-                    .send(ProxyMessage::I2oSendData(
-                        ctx_data.get_session_id(),
-                        encoded,
-                    ))
-                    .await?;
             }
         }
-
-        Ok(())
     }
 
     async fn on_recv_proxy_message(&mut self, proxy_message: ProxyMessage) -> anyhow::Result<()> {
         match proxy_message {
-            ProxyMessage::O2iConnect(_session_id, success, error_msg) => {
+            ProxyMessage::O2iConnect(session_id, success, error_msg, ..) => {
+                let Some(upstream) = self.upstreams.get_mut(&session_id) else {
+                    return Ok(());
+                };
                 if success {
-                    self.status = Status::Running;
-                    if self.is_connect_method {
-                        // mem::take: O(1) 转移所有权，不拷贝数据
+                    upstream.connected = true;
+                    if let Some(response) = upstream.connect_response.take() {
                         self.write_to_peer_tx
                             .as_ref()
                             .unwrap()
-                            .send(WriterMessage::Send(
-                                Bytes::from(std::mem::take(&mut self.cache_data)),
-                                true,
-                            ))?;
-                    } else {
-                        let data = self
-                            .ctx_data
-                            .as_ref()
-                            .unwrap()
-                            .common_data
-                            // mem::take 是 O(1)，Bytes::from(Vec) 也是 O(1)，合计零拷贝
-                            .encode_data_and_limiting(Bytes::from(std::mem::take(
-                                &mut self.cache_data,
-                            )))
-                            .await?;
-                        self.ctx_data
-                            .as_ref()
-                            .unwrap()
-                            .output
-                            .send(ProxyMessage::I2oSendData(
-                                self.ctx_data.as_ref().unwrap().get_session_id(),
-                                data,
-                            ))
+                            .send(WriterMessage::Send(Bytes::from(response), true))?;
+                    }
+                    // mem::take: O(1) 转移所有权，不拷贝数据
+                    let pending = std::mem::take(&mut upstream.pending);
+                    if !pending.is_empty() {
+                        self.send_to_upstream(session_id, Bytes::from(pending))
                             .await?;
                     }
-                    // mem::take 已经将 cache_data 置为空 Vec，无需再 clear/shrink
                 } else {
-                    error!("http proxy connect error: {error_msg}");
+                    error!("http proxy connect {} error: {error_msg}", upstream.target);
                     self.status = Status::Invalid;
 
                     let mut response = BAD_GATEWAY_HEADER.to_vec();
@@ -227,16 +213,29 @@ impl ProxyContext for HttpContext {
                 }
             }
             ProxyMessage::O2iRecvData(session_id, data) => {
+                let Some(upstream) = self.upstreams.get_mut(&session_id) else {
+                    return Ok(());
+                };
                 let data_len = data.len();
-                let decoded = self
-                    .ctx_data
-                    .as_ref()
-                    .unwrap()
-                    .common_data
-                    .decode_data(data)?;
+                let mut decoded = upstream.common_info.decode_data(data)?;
+                let output = self.ctx_data.as_ref().unwrap().output.clone();
+
+                if let Some(response) = upstream.response.as_mut() {
+                    let vars = Variables {
+                        peer_addr: self.peer_addr.as_ref().unwrap(),
+                        forwarded_for: None,
+                    };
+                    decoded = Bytes::from(response.feed(&decoded, &self.header_rules, &vars)?);
+                    // 响应头还不完整，没有数据写给客户端，直接确认
+                    if decoded.is_empty() {
+                        output
+                            .send(ProxyMessage::I2oRecvDataResult(session_id, data_len))
+                            .await?;
+                        return Ok(());
+                    }
+                }
 
                 // 写入完毕回调
-                let output = self.ctx_data.as_ref().unwrap().output.clone();
                 let callback: SendMessageFuncType = Box::new(move || {
                     let output = output.clone();
                     Box::pin(async move {
@@ -251,7 +250,18 @@ impl ProxyContext for HttpContext {
                     .unwrap()
                     .send(WriterMessage::SendAndThen(decoded, callback))?;
             }
-            ProxyMessage::O2iDisconnect(_) => {
+            ProxyMessage::O2iDisconnect(session_id) => {
+                if self.upstreams.remove(&session_id).is_some() {
+                    self.ctx_data
+                        .as_ref()
+                        .unwrap()
+                        .upstreams
+                        .unregister(session_id);
+                    // 空闲的上游断开不影响客户端连接，下次请求该主机时重新连接
+                    if self.active != Some(session_id) {
+                        return Ok(());
+                    }
+                }
                 self.write_to_peer_tx
                     .as_ref()
                     .unwrap()
@@ -262,8 +272,25 @@ impl ProxyContext for HttpContext {
         Ok(())
     }
 
+    async fn on_stop(&mut self, ctx_data: Arc<ProxyContextData>) -> anyhow::Result<()> {
+        for (session_id, _) in self.upstreams.drain() {
+            ctx_data.upstreams.unregister(session_id);
+            ctx_data
+                .output
+                .send(ProxyMessage::I2oDisconnect(session_id))
+                .await?;
+        }
+        Ok(())
+    }
+
     fn is_ready_for_read(&self) -> bool {
-        !matches!(self.status, Status::Connecting | Status::Invalid)
+        if matches!(self.status, Status::Invalid) {
+            return false;
+        }
+        // 当前上游连接建立之前暂停读取
+        self.active
+            .and_then(|session_id| self.upstreams.get(&session_id))
+            .is_none_or(|upstream| upstream.connected)
     }
 }
 
@@ -275,10 +302,172 @@ impl HttpContext {
             write_to_peer_tx: None,
             peer_addr: None,
             ctx_data: None,
-            is_connect_method: false,
+            authorized: false,
+            upstreams: HashMap::new(),
+            active: None,
+            header_rules: Arc::new(HeaderRules::default()),
+        }
+    }
+
+    /// 处理一个请求头，请求头不完整时返回 false
+    async fn on_request_head(&mut self, ctx_data: &Arc<ProxyContextData>) -> anyhow::Result<bool> {
+        let head = match parse_request_head(
+            ctx_data,
+            &self.cache_data,
+            &self.header_rules,
+            self.peer_addr.as_ref().unwrap(),
+        ) {
+            Ok(Some(head)) => head,
+            Ok(None) if self.cache_data.len() <= MAX_REQUEST_HEAD_LEN => return Ok(false),
+            Ok(None) | Err(_) => {
+                self.status = Status::Invalid;
+                self.send_and_close(BAD_REQUEST_RESPONSE.to_vec()).await?;
+                return Ok(false);
+            }
+        };
+
+        // 同一连接只在第一个请求校验代理认证
+        if !self.authorized {
+            if !head.authorized {
+                self.status = Status::Invalid;
+                self.send_and_close(PROXY_AUTH_REQUIRED_RESPONSE.to_vec())
+                    .await?;
+                return Ok(false);
+            }
+            self.authorized = true;
+        }
+
+        // 目标不在访问控制允许范围内
+        if !ctx_data.data_ex.access_list.permits_target(&head.target) {
+            self.status = Status::Invalid;
+            self.send_and_close(FORBIDDEN_RESPONSE.to_vec()).await?;
+            return Ok(false);
+        }
+
+        self.cache_data.drain(..head.head_size);
+        ctx_data
+            .common_data
+            .stats
+            .set_target_addr(head.target.clone());
+
+        if head.is_connect {
+            // CONNECT 之后连接只用于隧道，总是新建上游会话
+            let response = format!(
+                "HTTP/1.1 200 Connection Established\r\nProxy-Agent: npipe/{}\r\n\r\n",
+                head.version
+            );
+            let session_id = self.open_upstream(ctx_data, head.target, false).await?;
+            if let Some(upstream) = self.upstreams.get_mut(&session_id) {
+                upstream.connect_response = Some(response.into_bytes());
+            }
+            self.active = Some(session_id);
+            self.status = Status::Tunnel;
+            return Ok(true);
+        }
+
+        // 同一主机复用已有的上游会话
+        let session_id = match self
+            .upstreams
+            .iter()
+            .find(|(_, upstream)| upstream.target == head.target)
+        {
+            Some((session_id, _)) => *session_id,
+            None => {
+                let rewrite_response = self.header_rules.has_response_rules();
+                self.open_upstream(ctx_data, head.target, rewrite_response)
+                    .await?
+            }
+        };
+        if let Some(response) = self
+            .upstreams
+            .get_mut(&session_id)
+            .and_then(|upstream| upstream.response.as_mut())
+        {
+            response.pending_requests.push_back(head.is_head);
+        }
+        self.active = Some(session_id);
+        self.send_to_upstream(session_id, Bytes::from(head.data))
+            .await?;
+
+        self.status = if head.is_upgrade {
+            Status::Tunnel
+        } else {
+            match head.body {
+                Some(body) => Status::Body(body),
+                None => Status::Free,
+            }
+        };
+        Ok(true)
+    }
+
+    /// 新建到目标主机的上游会话
+    async fn open_upstream(
+        &mut self,
+        ctx_data: &Arc<ProxyContextData>,
+        target: String,
+        rewrite_response: bool,
+    ) -> anyhow::Result<u32> {
+        let session_id = net_session::create_session_id();
+        let common_info = ctx_data.common_data.new_upstream();
+        ctx_data.upstreams.register(session_id, common_info.clone());
+
+        ctx_data
+            .output
+            .send(ProxyMessage::I2oConnect(
+                session_id,
+                InletProxyType::HTTP.to_u8(),
+                true,
+                common_info.is_compressed,
+                target.clone(),
+                common_info.encryption_method.to_string(),
+                BASE64_STANDARD.encode(common_info.encryption_key.as_slice()),
+                self.peer_addr.as_ref().unwrap().to_string(),
+                false,
+            ))
+            .await?;
+
+        self.upstreams.insert(
+            session_id,
+            Upstream {
+                target,
+                common_info,
+                connected: false,
+                pending: Vec::new(),
+                connect_response: None,
+                response: rewrite_response.then(ResponseRewriter::new),
+            },
+        );
+        Ok(session_id)
+    }
+
+    /// 把缓存中前 size 个字节转发给当前上游会话
+    async fn forward_cache_data(&mut self, size: usize) -> anyhow::Result<()> {
+        let remaining = self.cache_data.split_off(size);
+        let data = std::mem::replace(&mut self.cache_data, remaining);
+        match self.active {
+            Some(session_id) => self.send_to_upstream(session_id, Bytes::from(data)).await,
+            None => Ok(()),
         }
     }
 
+    async fn send_to_upstream(&mut self, session_id: u32, data: Bytes) -> anyhow::Result<()> {
+        let Some(upstream) = self.upstreams.get_mut(&session_id) else {
+            return Ok(());
+        };
+        if !upstream.connected {
+            upstream.pending.extend_from_slice(&data);
+            return Ok(());
+        }
+        let encoded = upstream.common_info.encode_data_and_limiting(data).await?;
+        self.ctx_data
+            .as_ref()
+            .unwrap()
+            .output
+            .send(ProxyMessage::I2oSendData(session_id, encoded))
+            .await?;
+        Ok(())
+    }
+
     async fn send_and_close(&self, data: Vec<u8>) -> anyhow::Result<()> {
         self.write_to_peer_tx
             .as_ref()
@@ -292,6 +481,183 @@ impl HttpContext {
     }
 }
 
+/// 解析请求头，数据不完整时返回 None
+fn parse_request_head(
+    ctx_data: &Arc<ProxyContextData>,
+    data: &[u8],
+    header_rules: &HeaderRules,
+    peer_addr: &SocketAddr,
+) -> anyhow::Result<Option<RequestHead>> {
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers);
+    let head_size = match req.parse(data)? {
+        httparse::Status::Partial => return Ok(None),
+        httparse::Status::Complete(head_size) => head_size,
+    };
+
+    let authorized = http_authorization(ctx_data, &req)?;
+    let is_connect = req.method == Some("CONNECT");
+    let version = format_httparse_request_version(req.version);
+
+    let mut path = req
+        .path
+        .ok_or_else(|| anyhow!("parse http path error"))?
+        .to_string();
+
+    // 如果输入没有协议，先添加一个假的协议以便解析
+    if !path.starts_with("http://") && !path.starts_with("https://") {
+        path = format!("http://{}", path);
+    }
+
+    let url = url::Url::parse(&path).map_err(|_| anyhow!("parse http path error"))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("parse http host error"))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("parse http port error"))?;
+
+    // chunked 优先于 Content-Length
+    let mut content_length = None;
+    let mut is_chunked = false;
+    let mut connection_upgrade = false;
+    let mut has_upgrade = false;
+    for header in req.headers.iter() {
+        let value = std::str::from_utf8(header.value).unwrap_or("");
+        if header.name.eq_ignore_ascii_case("transfer-encoding") {
+            is_chunked |= value.to_ascii_lowercase().contains("chunked");
+        } else if header.name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("parse http content-length error"))?,
+            );
+        } else if header.name.eq_ignore_ascii_case("connection") {
+            connection_upgrade |= value.to_ascii_lowercase().contains("upgrade");
+        } else if header.name.eq_ignore_ascii_case("upgrade") {
+            has_upgrade = true;
+        }
+    }
+    let body = if is_chunked {
+        Some(BodyState::Chunked(ChunkedState::Size))
+    } else {
+        content_length
+            .filter(|length| *length > 0)
+            .map(BodyState::Length)
+    };
+
+    let data = if is_connect {
+        Vec::new()
+    } else {
+        // 修改header
+        let proxy_headers: HashSet<&'static [u8]> = HashSet::from([
+            // b"proxy-connection".as_slice(),
+            // b"proxy-authenticate".as_slice(),
+            // b"proxy-authorization".as_slice(),
+            b"forwarded".as_slice(),
+            b"x-forwarded-for".as_slice(),
+            b"x-forwarded-host".as_slice(),
+            b"x-forwarded-proto".as_slice(),
+            b"via".as_slice(),
+            // b"connection".as_slice(), // 有时也需要移除
+        ]);
+
+        let forwarded_for = req
+            .headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case("x-forwarded-for"))
+            .and_then(|header| std::str::from_utf8(header.value).ok());
+        let mut headers: Headers = req
+            .headers
+            .iter()
+            .filter(|header| {
+                let binding = header.name.to_ascii_lowercase();
+                let name = binding.as_bytes();
+                !proxy_headers.contains(name) && !name.starts_with(b"proxy-")
+            })
+            .map(|header| (header.name.to_owned(), header.value.to_vec()))
+            .collect();
+        header_rules.apply_request(
+            &mut headers,
+            &Variables {
+                peer_addr,
+                forwarded_for,
+            },
+        );
+        format_request_head(
+            req.method.unwrap_or("GET"),
+            req.path.unwrap_or("/"),
+            version,
+            &headers,
+        )
+    };
+
+    Ok(Some(RequestHead {
+        head_size,
+        target: format!("{}:{}", host, port),
+        is_connect,
+        is_upgrade: connection_upgrade && has_upgrade,
+        is_head: req.method == Some("HEAD"),
+        authorized,
+        version,
+        data,
+        body,
+    }))
+}
+
+/// 扫描 chunked 请求体，返回属于请求体的字节数以及请求体是否结束
+fn scan_chunked_body(state: &mut ChunkedState, data: &[u8]) -> anyhow::Result<(usize, bool)> {
+    let mut pos = 0;
+    loop {
+        let rest = &data[pos..];
+        match *state {
+            ChunkedState::Size => match httparse::parse_chunk_size(rest)
+                .map_err(|_| anyhow!("parse http chunk size error"))?
+            {
+                httparse::Status::Partial => return Ok((pos, false)),
+                httparse::Status::Complete((line_size, 0)) => {
+                    pos += line_size;
+                    *state = ChunkedState::Trailer;
+                }
+                httparse::Status::Complete((line_size, chunk_size)) => {
+                    pos += line_size;
+                    *state = ChunkedState::Data(chunk_size);
+                }
+            },
+            ChunkedState::Data(remaining) => {
+                let size = remaining.min(rest.len() as u64);
+                pos += size as usize;
+                if size < remaining {
+                    *state = ChunkedState::Data(remaining - size);
+                    return Ok((pos, false));
+                }
+                *state = ChunkedState::DataEnd;
+            }
+            ChunkedState::DataEnd => {
+                if rest.len() < 2 {
+                    return Ok((pos, false));
+                }
+                if !rest.starts_with(b"\r\n") {
+                    return Err(anyhow!("parse http chunk data error"));
+                }
+                pos += 2;
+                *state = ChunkedState::Size;
+            }
+            ChunkedState::Trailer => {
+                // trailer 逐行跳过，空行表示请求体结束
+                let Some(line_end) = rest.windows(2).position(|w| w == b"\r\n") else {
+                    return Ok((pos, false));
+                };
+                pos += line_end + 2;
+                if line_end == 0 {
+                    return Ok((pos, true));
+                }
+            }
+        }
+    }
+}
+
 fn http_authorization(
     ctx_data: &Arc<ProxyContextData>,
     request: &httparse::Request,
@@ -327,28 +693,317 @@ fn format_httparse_request_version(version: Option<u8>) -> &'static str {
     }
 }
 
-fn format_httparse_request(req: &httparse::Request) -> String {
-    let version = format_httparse_request_version(req.version);
-
+fn format_request_head(method: &str, path: &str, version: &str, headers: &Headers) -> Vec<u8> {
     // 预估容量：请求行约 64B + 每个头约 40B，减少 realloc 次数
-    let estimated = 64 + req.headers.len() * 40 + 2;
-    let mut result = String::with_capacity(estimated);
-
-    // 请求行直接写入，避免中间 String 分配
-    let _ = write!(
-        result,
-        "{} {} {}\r\n",
-        req.method.unwrap_or("GET"),
-        req.path.unwrap_or("/"),
-        version
-    );
-
-    // 每个头用 write! 直接追加，消除原来 &format!(...) 的 N 次临时堆分配
-    for h in req.headers.iter() {
-        let name = std::str::from_utf8(h.name.as_bytes()).unwrap_or("");
-        let value = std::str::from_utf8(h.value).unwrap_or("");
-        let _ = write!(result, "{}: {}\r\n", name, value);
-    }
-    result.push_str("\r\n");
+    let mut result = Vec::with_capacity(64 + headers.len() * 40 + 2);
+    result.extend_from_slice(format!("{} {} {}\r\n", method, path, version).as_bytes());
+    write_headers(&mut result, headers);
     result
 }
+
+fn write_headers(result: &mut Vec<u8>, headers: &Headers) {
+    for (name, value) in headers {
+        result.extend_from_slice(name.as_bytes());
+        result.extend_from_slice(b": ");
+        result.extend_from_slice(value);
+        result.extend_from_slice(b"\r\n");
+    }
+    result.extend_from_slice(b"\r\n");
+}
+
+impl ResponseRewriter {
+    fn new() -> Self {
+        Self {
+            status: ResponseStatus::Head,
+            cache: Vec::new(),
+            pending_requests: VecDeque::new(),
+        }
+    }
+
+    /// 处理上游返回的数据，返回改写后发给客户端的数据
+    fn feed(
+        &mut self,
+        data: &[u8],
+        header_rules: &HeaderRules,
+        vars: &Variables,
+    ) -> anyhow::Result<Vec<u8>> {
+        if matches!(self.status, ResponseStatus::Raw) {
+            return Ok(data.to_vec());
+        }
+        self.cache.extend_from_slice(data);
+
+        let mut output = Vec::with_capacity(self.cache.len());
+        loop {
+            match &mut self.status {
+                ResponseStatus::Head => {
+                    let Some(head) = self.on_response_head(header_rules, vars)? else {
+                        break;
+                    };
+                    output.extend_from_slice(&head);
+                }
+                ResponseStatus::Body(BodyState::Length(remaining)) => {
+                    let size = (*remaining).min(self.cache.len() as u64);
+                    *remaining -= size;
+                    output.extend(self.cache.drain(..size as usize));
+                    if *remaining > 0 {
+                        break;
+                    }
+                    self.status = ResponseStatus::Head;
+                }
+                ResponseStatus::Body(BodyState::Chunked(state)) => {
+                    let (size, finished) = scan_chunked_body(state, &self.cache)?;
+                    output.extend(self.cache.drain(..size));
+                    if !finished {
+                        break;
+                    }
+                    self.status = ResponseStatus::Head;
+                }
+                ResponseStatus::Raw => {
+                    output.append(&mut self.cache);
+                    break;
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// 解析并改写一个响应头，响应头不完整时返回 None
+    fn on_response_head(
+        &mut self,
+        header_rules: &HeaderRules,
+        vars: &Variables,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut resp = httparse::Response::new(&mut headers);
+        let head_size = match resp.parse(&self.cache)? {
+            httparse::Status::Partial if self.cache.len() <= MAX_REQUEST_HEAD_LEN => {
+                return Ok(None)
+            }
+            httparse::Status::Partial => return Err(anyhow!("http response head too long")),
+            httparse::Status::Complete(head_size) => head_size,
+        };
+
+        let code = resp.code.unwrap_or(0);
+        let mut headers: Headers = resp
+            .headers
+            .iter()
+            .map(|header| (header.name.to_owned(), header.value.to_vec()))
+            .collect();
+        // 1xx（101 除外）是中间响应，之后还有最终响应
+        let interim = (100..200).contains(&code) && code != 101;
+        self.status = if interim {
+            ResponseStatus::Head
+        } else {
+            let is_head = self.pending_requests.pop_front().unwrap_or(false);
+            header_rules.apply_response(&mut headers, vars);
+            if code == 101 {
+                ResponseStatus::Raw
+            } else if is_head || code == 204 || code == 304 {
+                ResponseStatus::Head
+            } else {
+                response_body(&headers)?
+            }
+        };
+
+        let mut head = format!(
+            "{} {} {}\r\n",
+            format_httparse_request_version(resp.version),
+            code,
+            resp.reason.unwrap_or("")
+        )
+        .into_bytes();
+        write_headers(&mut head, &headers);
+        self.cache.drain(..head_size);
+        Ok(Some(head))
+    }
+}
+
+/// 根据响应头判断响应体长度，没有长度时读到连接关闭
+fn response_body(headers: &Headers) -> anyhow::Result<ResponseStatus> {
+    let mut content_length = None;
+    for (name, value) in headers {
+        let value = std::str::from_utf8(value).unwrap_or("");
+        if name.eq_ignore_ascii_case("transfer-encoding")
+            && value.to_ascii_lowercase().contains("chunked")
+        {
+            return Ok(ResponseStatus::Body(BodyState::Chunked(ChunkedState::Size)));
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| anyhow!("parse http content-length error"))?,
+            );
+        }
+    }
+    Ok(match content_length {
+        Some(0) => ResponseStatus::Head,
+        Some(length) => ResponseStatus::Body(BodyState::Length(length)),
+        None => ResponseStatus::Raw,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::inlet::{InletDataEx, InletProxyType};
+    use crate::proxy::tests::{start_local_inlet, start_local_proxy};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// 读取一个完整的 HTTP 消息（Content-Length 或 chunked），返回 (消息头, 原始消息体)
+    async fn read_message<R: AsyncRead + Unpin>(stream: &mut R) -> Option<(String, Vec<u8>)> {
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            if stream.read(&mut byte).await.ok()? == 0 {
+                return None;
+            }
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        let lower = head.to_ascii_lowercase();
+        let mut body = Vec::new();
+        if lower.contains("transfer-encoding: chunked") {
+            while !body.ends_with(b"0\r\n\r\n") {
+                let mut byte = [0u8; 1];
+                stream.read_exact(&mut byte).await.ok()?;
+                body.push(byte[0]);
+            }
+        } else if let Some(line) = lower.lines().find(|l| l.starts_with("content-length:")) {
+            let length: usize = line[15..].trim().parse().unwrap();
+            body.resize(length, 0);
+            stream.read_exact(&mut body).await.ok()?;
+        }
+        Some((head, body))
+    }
+
+    /// keep-alive HTTP 服务器，响应内容为 "{name} {请求行} {请求体}"
+    async fn start_http_server(name: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    while let Some((head, body)) = read_message(&mut stream).await {
+                        let request_line = head.lines().next().unwrap().to_string();
+                        let content =
+                            format!("{name} {request_line} {}", String::from_utf8_lossy(&body));
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{content}",
+                            content.len()
+                        );
+                        stream.write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn request(stream: &mut TcpStream, request: String) -> String {
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let (head, body) = read_message(stream).await.unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"));
+        String::from_utf8(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_keep_alive_multiple_hosts() {
+        let addr_a = start_http_server("a").await;
+        let addr_b = start_http_server("b").await;
+        let proxy_addr = start_local_proxy(InletProxyType::HTTP, "").await;
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+
+        let body = request(
+            &mut stream,
+            format!("GET http://{addr_a}/1 HTTP/1.1\r\nHost: {addr_a}\r\nProxy-Connection: keep-alive\r\n\r\n"),
+        )
+        .await;
+        assert_eq!(body, format!("a GET http://{addr_a}/1 HTTP/1.1 "));
+
+        // 同一连接上切换到另一个主机，Content-Length 请求体
+        let body = request(
+            &mut stream,
+            format!("POST http://{addr_b}/2 HTTP/1.1\r\nHost: {addr_b}\r\nContent-Length: 5\r\n\r\nhello"),
+        )
+        .await;
+        assert_eq!(body, format!("b POST http://{addr_b}/2 HTTP/1.1 hello"));
+
+        // 回到第一个主机，chunked 请求体分多次发送
+        stream
+            .write_all(
+                format!("POST http://{addr_a}/3 HTTP/1.1\r\nHost: {addr_a}\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhe")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let body = request(&mut stream, "llo\r\n6\r\n world\r\n0\r\n\r\n".to_string()).await;
+        assert_eq!(
+            body,
+            format!("a POST http://{addr_a}/3 HTTP/1.1 5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_header_rules() {
+        // 响应内容为收到的请求头
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            while let Some((head, _)) = read_message(&mut stream).await {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nServer: test\r\nContent-Length: {}\r\n\r\n{head}",
+                    head.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let proxy_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let custom_mapping = HashMap::from([
+            ("request.set:Host", "backend.local"),
+            ("request.set:X-Forwarded-For", "$proxy_add_x_forwarded_for"),
+            ("request.set:X-Real-IP", "$remote_addr"),
+            ("response.remove:Server", ""),
+            ("response.add:X-Proxy", "npipe"),
+        ])
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        start_local_inlet(
+            InletProxyType::HTTP,
+            proxy_addr,
+            String::new(),
+            InletDataEx::new(String::new(), String::new()).with_custom_mapping(custom_mapping),
+        )
+        .await;
+
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        for path in ["/1", "/2"] {
+            stream
+                .write_all(
+                    format!("GET http://{server_addr}{path} HTTP/1.1\r\nHost: {server_addr}\r\nX-Forwarded-For: 1.1.1.1\r\n\r\n")
+                        .as_bytes(),
+                )
+                .await
+                .unwrap();
+            let (head, body) = read_message(&mut stream).await.unwrap();
+            assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(head.contains("X-Proxy: npipe\r\n"));
+            assert!(!head.contains("Server:"));
+
+            let body = String::from_utf8(body).unwrap();
+            assert!(body.starts_with(&format!("GET http://{server_addr}{path} HTTP/1.1\r\n")));
+            assert!(body.contains("Host: backend.local\r\n"));
+            assert!(body.contains("X-Forwarded-For: 1.1.1.1, 127.0.0.1\r\n"));
+            assert!(body.contains("X-Real-IP: 127.0.0.1\r\n"));
+            assert!(!body.contains(&format!("Host: {server_addr}")));
+        }
+    }
+}
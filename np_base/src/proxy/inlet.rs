@@ -1,16 +1,28 @@
-use crate::net::session_delegate::SessionDelegate;
+use crate::net::session_delegate::{CreateSessionDelegateCallback, SessionDelegate};
+use crate::net::tls::SniCertResolver;
+#[cfg(target_os = "linux")]
+use crate::net::transparent;
 use crate::net::WriterMessage;
-use crate::net::{tcp_server, udp_server};
-use crate::proxy::common::SessionCommonInfo;
+use crate::net::{tcp_server, tls_terminate, udp_server};
+use crate::proxy::acl::AccessList;
+use crate::proxy::common::{SessionCommonInfo, SessionSnapshot};
+use crate::proxy::header_rules::{is_header_rule_key, HeaderRules};
 use crate::proxy::http::HttpContext;
+use crate::proxy::mixed::MixedContext;
 use crate::proxy::proxy_context::{ProxyContext, ProxyContextData, UniversalProxy};
+use crate::proxy::rate_limit::RateLimiters;
+use crate::proxy::socks4::Socks4Context;
 use crate::proxy::socks5::Socks5Context;
+use crate::proxy::vhost;
 use crate::proxy::{common, OutputFuncType, ProxyMessage};
 use anyhow::anyhow;
 use async_trait::async_trait;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
 use bytes::Bytes;
 use dashmap::DashMap;
 use log::{error, trace};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -26,6 +38,14 @@ pub enum InletProxyType {
     UDP,
     SOCKS5,
     HTTP,
+    /// SOCKS4/4a，同一端口兼容 SOCKS5
+    SOCKS4,
+    /// HTTP 与 SOCKS5 共用端口，按首字节自动识别
+    MIXED,
+    /// Linux 透明代理（iptables REDIRECT / TPROXY），同时监听 TCP 与 UDP
+    TRANSPARENT,
+    /// 虚拟主机，多个通道共用监听端口，按 HTTP Host / TLS SNI 分发
+    VHOST,
     UNKNOWN,
 }
 
@@ -36,6 +56,10 @@ impl InletProxyType {
             1 => InletProxyType::UDP,
             2 => InletProxyType::SOCKS5,
             3 => InletProxyType::HTTP,
+            4 => InletProxyType::SOCKS4,
+            5 => InletProxyType::MIXED,
+            6 => InletProxyType::TRANSPARENT,
+            7 => InletProxyType::VHOST,
             _ => InletProxyType::UNKNOWN,
         }
     }
@@ -46,6 +70,10 @@ impl InletProxyType {
             InletProxyType::UDP => 1,
             InletProxyType::SOCKS5 => 2,
             InletProxyType::HTTP => 3,
+            InletProxyType::SOCKS4 => 4,
+            InletProxyType::MIXED => 5,
+            InletProxyType::TRANSPARENT => 6,
+            InletProxyType::VHOST => 7,
             InletProxyType::UNKNOWN => 255,
         }
     }
@@ -57,11 +85,35 @@ impl InletProxyType {
     pub fn is_tcp(&self) -> bool {
         matches!(self, InletProxyType::TCP)
     }
+
+    /// 目标地址是否由代理协议握手（或透明代理的原始目标地址）决定
+    pub fn is_dynamic_target(&self) -> bool {
+        matches!(
+            self,
+            InletProxyType::SOCKS5
+                | InletProxyType::HTTP
+                | InletProxyType::SOCKS4
+                | InletProxyType::MIXED
+                | InletProxyType::TRANSPARENT
+        )
+    }
+
+    /// 监听端口使用的协议 (tcp, udp)
+    pub fn listen_protocols(&self) -> (bool, bool) {
+        match self {
+            InletProxyType::UDP => (false, true),
+            InletProxyType::TRANSPARENT => (true, true),
+            InletProxyType::UNKNOWN => (false, false),
+            _ => (true, false),
+        }
+    }
 }
 
 struct SessionInfo {
     proxy_message_tx: UnboundedSender<ProxyMessage>,
     common_info: SessionCommonInfo,
+    /// 客户端连接上额外建立的上游会话，不计入会话列表
+    is_upstream: bool,
 }
 
 /// 使用 DashMap 替代 Arc<RwLock<HashMap>>:
@@ -70,12 +122,41 @@ struct SessionInfo {
 /// - 读操作不阻塞其他 shard 的读写
 type SessionInfoMap = Arc<DashMap<u32, SessionInfo>>;
 
+/// 同一客户端连接上的上游会话登记
+///
+/// HTTP 代理按请求的目标主机建立多个上游会话，每个上游会话有独立的会话 id 与密钥，
+/// 登记后输出端发来的消息会路由回同一个 ProxyContext。
+#[derive(Clone)]
+pub(crate) struct UpstreamRegistry {
+    session_info_map: SessionInfoMap,
+    proxy_message_tx: UnboundedSender<ProxyMessage>,
+}
+
+impl UpstreamRegistry {
+    pub(crate) fn register(&self, session_id: u32, common_info: SessionCommonInfo) {
+        self.session_info_map.insert(
+            session_id,
+            SessionInfo {
+                proxy_message_tx: self.proxy_message_tx.clone(),
+                common_info,
+                is_upstream: true,
+            },
+        );
+    }
+
+    pub(crate) fn unregister(&self, session_id: u32) {
+        self.session_info_map.remove(&session_id);
+    }
+}
+
 pub struct Inlet {
     is_running: Arc<AtomicBool>,
     input: Option<UnboundedSender<ProxyMessage>>,
     session_info_map: SessionInfoMap,
     description: String,
     on_output_callback: OutputFuncType,
+    /// 带宽限速器，所有会话共享
+    rate_limiters: RateLimiters,
     /// stop() 等待服务停止时使用的通知, 替代 yield_now() spin loop
     stopped_notify: Arc<Notify>,
 }
@@ -83,22 +164,58 @@ pub struct Inlet {
 pub struct InletDataEx {
     pub(crate) username: String,
     pub(crate) password: String,
+    /// 虚拟主机域名 -> 目标地址（为空时使用通道目标地址），以及 HTTP 头改写规则
+    pub(crate) custom_mapping: HashMap<String, String>,
+    /// 从 custom_mapping 解析出的 HTTP 头改写规则
+    pub(crate) header_rules: Arc<HeaderRules>,
+    /// 入口终止 TLS 使用的证书 (证书链 PEM, 私钥 PEM)，为空时不终止
+    pub(crate) tls_certificates: Vec<(String, String)>,
+    /// 代理类通道允许访问的目标
+    pub(crate) access_list: Arc<AccessList>,
 }
 
 impl InletDataEx {
     pub fn new(username: String, password: String) -> Self {
-        Self { username, password }
+        Self {
+            username,
+            password,
+            custom_mapping: HashMap::new(),
+            header_rules: Arc::new(HeaderRules::default()),
+            tls_certificates: Vec::new(),
+            access_list: Arc::new(AccessList::default()),
+        }
+    }
+
+    pub fn with_custom_mapping(mut self, custom_mapping: HashMap<String, String>) -> Self {
+        self.header_rules = Arc::new(HeaderRules::from_mapping(&custom_mapping));
+        self.custom_mapping = custom_mapping;
+        self
+    }
+
+    pub fn with_tls_certificates(mut self, tls_certificates: Vec<(String, String)>) -> Self {
+        self.tls_certificates = tls_certificates;
+        self
+    }
+
+    pub fn with_access_list(mut self, allow: &[String], deny: &[String]) -> Self {
+        self.access_list = Arc::new(AccessList::from_rules(allow, deny));
+        self
     }
 }
 
 impl Inlet {
-    pub fn new(on_output_callback: OutputFuncType, description: String) -> Self {
+    pub fn new(
+        on_output_callback: OutputFuncType,
+        description: String,
+        rate_limiters: RateLimiters,
+    ) -> Self {
         Self {
             is_running: Arc::new(AtomicBool::new(false)),
             session_info_map: Arc::new(DashMap::new()),
             input: None,
             description,
             on_output_callback,
+            rate_limiters,
             stopped_notify: Arc::new(Notify::new()),
         }
     }
@@ -123,20 +240,51 @@ impl Inlet {
         self.input = Some(input_tx);
 
         let session_info_map = self.session_info_map.clone();
-        let inlet_proxy_type_cloned = inlet_proxy_type.clone();
+        let vhost_routes = data_ex.custom_mapping.clone();
+        // TLS 终止只用于转发 HTTP 服务的 TCP 与虚拟主机通道
+        let tls_config = if data_ex.tls_certificates.is_empty() {
+            None
+        } else if matches!(
+            inlet_proxy_type,
+            InletProxyType::TCP | InletProxyType::VHOST
+        ) {
+            Some(SniCertResolver::new(&data_ex.tls_certificates)?.server_config())
+        } else {
+            return Err(anyhow!(
+                "TLS termination is only supported by tcp and vhost tunnels"
+            ));
+        };
         let data_ex = Arc::new(data_ex);
-
-        let create_session_delegate_func = Box::new(move || -> Box<dyn SessionDelegate> {
-            Box::new(InletSession::new(
-                inlet_proxy_type.clone(),
-                output_addr.clone(),
-                session_info_map.clone(),
-                is_compressed,
-                encryption_method.clone(),
-                output_tx.clone(),
-                data_ex.clone(),
-            ))
-        });
+        let rate_limiters = self.rate_limiters.clone();
+
+        // 透明代理同时监听 TCP 和 UDP，每个会话按实际协议创建；
+        // 虚拟主机每个域名可以有单独的目标地址
+        let new_session_delegate_func = move |inlet_proxy_type: InletProxyType,
+                                              output_addr: String|
+              -> CreateSessionDelegateCallback {
+            let session_info_map = session_info_map.clone();
+            let encryption_method = encryption_method.clone();
+            let output_tx = output_tx.clone();
+            let data_ex = data_ex.clone();
+            let rate_limiters = rate_limiters.clone();
+            let create_session_delegate: CreateSessionDelegateCallback =
+                Box::new(move || -> Box<dyn SessionDelegate> {
+                    Box::new(InletSession::new(
+                        inlet_proxy_type.clone(),
+                        output_addr.clone(),
+                        session_info_map.clone(),
+                        is_compressed,
+                        encryption_method.clone(),
+                        output_tx.clone(),
+                        data_ex.clone(),
+                        rate_limiters.clone(),
+                    ))
+                });
+            match &tls_config {
+                Some(config) => tls_terminate::wrap(create_session_delegate, config.clone()),
+                None => create_session_delegate,
+            }
+        };
 
         let on_output_callback = self.on_output_callback.clone();
         let session_info_map = self.session_info_map.clone();
@@ -144,9 +292,15 @@ impl Inlet {
         let stopped_notify = self.stopped_notify.clone();
         is_running.store(true, Ordering::Relaxed);
 
-        match inlet_proxy_type_cloned {
-            InletProxyType::TCP | InletProxyType::SOCKS5 | InletProxyType::HTTP => {
+        match inlet_proxy_type {
+            InletProxyType::TCP
+            | InletProxyType::SOCKS5
+            | InletProxyType::HTTP
+            | InletProxyType::SOCKS4
+            | InletProxyType::MIXED => {
                 let listener = TcpListener::bind(&listen_addr).await?;
+                let create_session_delegate_func =
+                    new_session_delegate_func(inlet_proxy_type, output_addr);
 
                 tokio::spawn(async move {
                     let server_task = tcp_server::Builder::new(create_session_delegate_func)
@@ -172,6 +326,8 @@ impl Inlet {
             }
             InletProxyType::UDP => {
                 let socket = UdpSocket::bind(&listen_addr).await?;
+                let create_session_delegate_func =
+                    new_session_delegate_func(inlet_proxy_type, output_addr);
 
                 tokio::spawn(async move {
                     let server_task = udp_server::run_server(
@@ -189,6 +345,91 @@ impl Inlet {
                     stopped_notify.notify_waiters();
                 });
             }
+            #[cfg(target_os = "linux")]
+            InletProxyType::TRANSPARENT => {
+                let addr = common::parse_addr(&listen_addr).await?;
+                let listener = transparent::bind_tcp_listener(addr)?;
+                let socket = transparent::bind_udp_socket(addr)?;
+                let tcp_session_delegate_func =
+                    new_session_delegate_func(InletProxyType::TCP, output_addr.clone());
+                let udp_session_delegate_func =
+                    new_session_delegate_func(InletProxyType::UDP, output_addr);
+
+                tokio::spawn(async move {
+                    // 输入通道关闭时同时停止 TCP 与 UDP 服务
+                    let shutdown_token = CancellationToken::new();
+                    let udp_shutdown = shutdown_token.clone().cancelled_owned();
+                    let tcp_task = tcp_server::Builder::new(tcp_session_delegate_func)
+                        .set_on_stream_init_callback(Arc::new(|stream: TcpStream| {
+                            Box::pin(async move {
+                                stream.set_nodelay(true)?;
+                                Ok(stream)
+                            })
+                        }))
+                        .set_transparent(true)
+                        .build_with_listener(listener, async move {
+                            Self::async_receive_input(input_rx, session_info_map).await;
+                            shutdown_token.cancel();
+                        });
+                    let udp_task = udp_server::run_transparent_server(
+                        socket,
+                        udp_session_delegate_func,
+                        udp_shutdown,
+                    );
+
+                    select! {
+                        _= async { tokio::join!(tcp_task, udp_task) } => {},
+                        _= common::async_receive_output(output_rx, on_output_callback) => {}
+                    }
+
+                    is_running.store(false, Ordering::Relaxed);
+                    stopped_notify.notify_waiters();
+                });
+            }
+            #[cfg(not(target_os = "linux"))]
+            InletProxyType::TRANSPARENT => {
+                return Err(anyhow!("Transparent proxy is only supported on Linux"));
+            }
+            InletProxyType::VHOST => {
+                let addr = common::parse_addr(&listen_addr).await?;
+                let routes = vhost_routes
+                    .into_iter()
+                    .filter(|(domain, _)| !is_header_rule_key(domain))
+                    .map(|(domain, target)| {
+                        let target = if target.is_empty() {
+                            output_addr.clone()
+                        } else {
+                            target
+                        };
+                        (
+                            domain,
+                            new_session_delegate_func(InletProxyType::TCP, target),
+                        )
+                    })
+                    .collect();
+                let registration = vhost::register(addr, routes)?;
+
+                tokio::spawn(async move {
+                    select! {
+                        _= Self::async_receive_input(input_rx, session_info_map.clone()) => {},
+                        _= common::async_receive_output(output_rx, on_output_callback) => {}
+                    }
+                    registration.close().await;
+
+                    // 共享监听不随通道关闭，需要主动断开本通道的会话
+                    for entry in session_info_map.iter() {
+                        if !entry.value().is_upstream {
+                            let _ = entry
+                                .value()
+                                .proxy_message_tx
+                                .send(ProxyMessage::O2iDisconnect(*entry.key()));
+                        }
+                    }
+
+                    is_running.store(false, Ordering::Relaxed);
+                    stopped_notify.notify_waiters();
+                });
+            }
             InletProxyType::UNKNOWN => {
                 return Err(anyhow!("Unknown inlet proxy type"));
             }
@@ -226,6 +467,36 @@ impl Inlet {
         &self.description
     }
 
+    /// 当前会话数量
+    pub fn session_count(&self) -> usize {
+        self.session_info_map
+            .iter()
+            .filter(|entry| !entry.value().is_upstream)
+            .count()
+    }
+
+    /// 当前活跃会话列表
+    pub fn sessions(&self) -> Vec<SessionSnapshot> {
+        self.session_info_map
+            .iter()
+            .filter(|entry| !entry.value().is_upstream)
+            .map(|entry| entry.value().common_info.stats.snapshot(*entry.key()))
+            .collect()
+    }
+
+    /// 强制关闭会话，会话不存在时返回 false
+    ///
+    /// 与输出端断开的处理相同：关闭客户端连接，随后通知输出端断开。
+    pub fn close_session(&self, session_id: u32) -> bool {
+        match self.session_info_map.get(&session_id) {
+            Some(session) if !session.is_upstream => session
+                .proxy_message_tx
+                .send(ProxyMessage::O2iDisconnect(session_id))
+                .is_ok(),
+            _ => false,
+        }
+    }
+
     async fn async_receive_input(
         mut input: UnboundedReceiver<ProxyMessage>,
         session_info_map: SessionInfoMap,
@@ -242,10 +513,31 @@ impl Inlet {
         session_info_map: &SessionInfoMap,
     ) -> anyhow::Result<()> {
         match &message {
-            ProxyMessage::O2iConnect(session_id, success, error_msg) => {
+            ProxyMessage::O2iConnect(session_id, success, error_msg, encryption_key, _) => {
                 trace!(
                     "O2iConnect: session_id:{session_id}, success:{success}, error_msg:{error_msg}"
                 );
+                if let Some(session) = session_info_map.get(session_id) {
+                    // AEAD 加密方式需要先用输出端公钥完成密钥协商，失败则按连接失败处理
+                    let message = if *success {
+                        match Self::complete_key_exchange(&session.common_info, encryption_key) {
+                            Ok(()) => message,
+                            Err(err) => ProxyMessage::O2iConnect(
+                                *session_id,
+                                false,
+                                format!("key exchange error: {err}"),
+                                String::new(),
+                                String::new(),
+                            ),
+                        }
+                    } else {
+                        message
+                    };
+                    session.proxy_message_tx.send(message)?;
+                }
+            }
+            ProxyMessage::O2iBindAccept(session_id, remote_addr) => {
+                trace!("O2iBindAccept: session_id:{session_id}, remote_addr:{remote_addr}");
                 if let Some(session) = session_info_map.get(session_id) {
                     session.proxy_message_tx.send(message)?;
                 }
@@ -281,6 +573,17 @@ impl Inlet {
 
         Ok(())
     }
+
+    fn complete_key_exchange(
+        common_info: &SessionCommonInfo,
+        encryption_key: &str,
+    ) -> anyhow::Result<()> {
+        if !common_info.encryption_method.is_aead() {
+            return Ok(());
+        }
+        let peer_public_key = BASE64_STANDARD.decode(encryption_key.as_bytes())?;
+        common_info.complete_key_exchange(&peer_public_key)
+    }
 }
 
 struct InletSession {
@@ -290,10 +593,13 @@ struct InletSession {
     /// 内部状态更复杂而增加开销。Mutex 在 write-only 场景下更高效。
     proxy_ctx: Arc<Mutex<dyn ProxyContext + Send + Sync>>,
     proxy_ctx_data: Arc<ProxyContextData>,
+    proxy_message_tx: UnboundedSender<ProxyMessage>,
+    proxy_message_rx: Option<UnboundedReceiver<ProxyMessage>>,
     proxy_message_recv_task_cancel_token: Option<CancellationToken>,
 }
 
 impl InletSession {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         inlet_proxy_type: InletProxyType,
         output_addr: String,
@@ -302,13 +608,27 @@ impl InletSession {
         encryption_method: String,
         output: Sender<ProxyMessage>,
         data_ex: Arc<InletDataEx>,
+        rate_limiters: RateLimiters,
     ) -> Self {
         let proxy_ctx: Arc<Mutex<dyn ProxyContext + Send + Sync>> = match inlet_proxy_type {
             InletProxyType::SOCKS5 => Arc::new(Mutex::new(Socks5Context::new())),
             InletProxyType::HTTP => Arc::new(Mutex::new(HttpContext::new())),
+            InletProxyType::SOCKS4 => Arc::new(Mutex::new(Socks4Context::new())),
+            InletProxyType::MIXED => Arc::new(Mutex::new(MixedContext::new())),
             _ => Arc::new(Mutex::new(UniversalProxy::new())),
         };
 
+        let common_info =
+            SessionCommonInfo::from_method_name(true, is_compressed, encryption_method)
+                .with_rate_limiters(rate_limiters);
+        common_info.set_datagram(matches!(inlet_proxy_type, InletProxyType::UDP));
+
+        let (proxy_message_tx, proxy_message_rx) = mpsc::unbounded_channel::<ProxyMessage>();
+        let upstreams = UpstreamRegistry {
+            session_info_map: session_info_map.clone(),
+            proxy_message_tx: proxy_message_tx.clone(),
+        };
+
         Self {
             session_info_map,
             proxy_ctx,
@@ -316,9 +636,12 @@ impl InletSession {
                 inlet_proxy_type,
                 output_addr,
                 output,
-                SessionCommonInfo::from_method_name(true, is_compressed, encryption_method),
+                common_info,
                 data_ex,
+                upstreams,
             )),
+            proxy_message_tx,
+            proxy_message_rx: Some(proxy_message_rx),
             proxy_message_recv_task_cancel_token: None,
         }
     }
@@ -335,8 +658,20 @@ impl SessionDelegate for InletSession {
         trace!("inlet on session({session_id}) start {addr}");
 
         self.proxy_ctx_data.set_session_id(session_id);
+        let target_addr = if self.proxy_ctx_data.inlet_proxy_type.is_dynamic_target() {
+            String::new()
+        } else {
+            self.proxy_ctx_data.output_addr.clone()
+        };
+        self.proxy_ctx_data
+            .common_data
+            .stats
+            .start(addr.to_string(), target_addr);
 
-        let (proxy_msg_tx, mut proxy_msg_rx) = mpsc::unbounded_channel::<ProxyMessage>();
+        let mut proxy_msg_rx = self
+            .proxy_message_rx
+            .take()
+            .ok_or_else(|| anyhow!("Repeated session start"))?;
 
         let token = CancellationToken::new();
         let cloned_token = token.clone();
@@ -371,8 +706,9 @@ impl SessionDelegate for InletSession {
         self.session_info_map.insert(
             session_id,
             SessionInfo {
-                proxy_message_tx: proxy_msg_tx,
+                proxy_message_tx: self.proxy_message_tx.clone(),
                 common_info: self.proxy_ctx_data.common_data.clone(),
+                is_upstream: false,
             },
         );
 
@@ -413,4 +749,11 @@ impl SessionDelegate for InletSession {
     async fn is_ready_for_read(&self) -> bool {
         self.proxy_ctx.lock().await.is_ready_for_read()
     }
+
+    fn set_original_dst(&mut self, addr: SocketAddr) {
+        // 会话开始前 proxy_ctx_data 尚未共享，可以直接修改
+        if let Some(ctx_data) = Arc::get_mut(&mut self.proxy_ctx_data) {
+            ctx_data.output_addr = addr.to_string();
+        }
+    }
 }
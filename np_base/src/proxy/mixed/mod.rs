@@ -0,0 +1,143 @@
+use crate::net::WriterMessage;
+use crate::proxy::http::HttpContext;
+use crate::proxy::proxy_context::{ProxyContext, ProxyContextData};
+use crate::proxy::socks5::{Socks5Context, SOCKS5_VERSION};
+use crate::proxy::ProxyMessage;
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// HTTP 与 SOCKS5 共用端口
+///
+/// 根据客户端发来的第一个字节选择协议：0x05 为 SOCKS5，其余按 HTTP 代理处理。
+/// 选定后所有回调都交给对应的 ProxyContext。
+pub struct MixedContext {
+    inner: Option<Box<dyn ProxyContext>>,
+    write_to_peer_tx: Option<UnboundedSender<WriterMessage>>,
+    peer_addr: Option<SocketAddr>,
+}
+
+#[async_trait]
+impl ProxyContext for MixedContext {
+    async fn on_start(
+        &mut self,
+        _ctx_data: Arc<ProxyContextData>,
+        peer_addr: SocketAddr,
+        write_to_peer_tx: UnboundedSender<WriterMessage>,
+    ) -> anyhow::Result<()> {
+        self.write_to_peer_tx = Some(write_to_peer_tx);
+        self.peer_addr = Some(peer_addr);
+        Ok(())
+    }
+
+    async fn on_recv_peer_data(
+        &mut self,
+        ctx_data: Arc<ProxyContextData>,
+        data: Bytes,
+    ) -> anyhow::Result<()> {
+        if self.inner.is_none() {
+            if data.is_empty() {
+                return Ok(());
+            }
+            let mut inner: Box<dyn ProxyContext> = if data[0] == SOCKS5_VERSION {
+                Box::new(Socks5Context::new())
+            } else {
+                Box::new(HttpContext::new())
+            };
+            inner
+                .on_start(
+                    ctx_data.clone(),
+                    self.peer_addr.unwrap(),
+                    self.write_to_peer_tx.take().unwrap(),
+                )
+                .await?;
+            self.inner = Some(inner);
+        }
+        self.inner
+            .as_mut()
+            .unwrap()
+            .on_recv_peer_data(ctx_data, data)
+            .await
+    }
+
+    async fn on_recv_proxy_message(&mut self, proxy_message: ProxyMessage) -> anyhow::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.on_recv_proxy_message(proxy_message).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn on_stop(&mut self, ctx_data: Arc<ProxyContextData>) -> anyhow::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.on_stop(ctx_data).await,
+            None => {
+                ctx_data
+                    .output
+                    .send(ProxyMessage::I2oDisconnect(ctx_data.get_session_id()))
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    fn is_ready_for_read(&self) -> bool {
+        self.inner
+            .as_ref()
+            .is_none_or(|inner| inner.is_ready_for_read())
+    }
+}
+
+impl MixedContext {
+    pub fn new() -> Self {
+        Self {
+            inner: None,
+            write_to_peer_tx: None,
+            peer_addr: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::inlet::InletProxyType;
+    use crate::proxy::tests::{assert_echo, start_echo_server, start_local_proxy};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn test_mixed_proxy() {
+        let echo_addr = start_echo_server().await;
+        let proxy_addr = start_local_proxy(InletProxyType::MIXED, "").await;
+
+        // HTTP CONNECT
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream
+            .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", echo_addr).as_bytes())
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            response.push(byte[0]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200"));
+        assert_echo(&mut stream).await;
+
+        // SOCKS5 CONNECT
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x00]);
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&echo_addr.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x05, 0x00]);
+        assert_echo(&mut stream).await;
+    }
+}
@@ -3,22 +3,32 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
+pub mod acl;
 pub(crate) mod common;
 pub mod crypto;
+pub mod header_rules;
 mod http;
 pub mod inlet;
+mod mixed;
 pub mod outlet;
 mod proxy_context;
+pub mod rate_limit;
+pub(crate) mod socks4;
 pub(crate) mod socks5;
+mod vhost;
+
+pub use common::SessionSnapshot;
 
 /// 代理消息枚举
 ///
 /// 数据字段使用 `Bytes` 实现零拷贝：克隆时只增加引用计数，不复制底层数据。
 pub enum ProxyMessage {
-    // 向输出端请求发起连接(u32:会话id  u8:通道类型 bool 是否TCP bool:是否压缩数据 String:目标地址 String:加密方式 String:加密密码 String:客户端地址)
-    I2oConnect(u32, u8, bool, bool, String, String, String, String),
-    // 连接结果(u32:会话id  bool:是否是成功 String:错误信息)
-    O2iConnect(u32, bool, String),
+    // 向输出端请求发起连接(u32:会话id  u8:通道类型 bool 是否TCP bool:是否压缩数据 String:目标地址 String:加密方式 String:加密密码(AEAD加密方式为输入端公钥) String:客户端地址 bool:是否为SOCKS5 BIND)
+    I2oConnect(u32, u8, bool, bool, String, String, String, String, bool),
+    // 连接结果(u32:会话id  bool:是否是成功 String:错误信息 String:AEAD加密方式下的输出端公钥 String:SOCKS5 BIND监听地址)
+    O2iConnect(u32, bool, String, String, String),
+    // SOCKS5 BIND监听地址收到连接(u32:会话id  String:连入方地址)
+    O2iBindAccept(u32, String),
     // 向输出端请求发送数据(u32:会话id  Bytes:数据)
     I2oSendData(u32, Bytes),
     // 向输出端请求发送数据(u32:会话id  Bytes:数据 String:udp包目标地址)
@@ -42,9 +52,104 @@ pub type OutputFuncType =
     Arc<dyn Fn(ProxyMessage) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::proxy::crypto;
+    use crate::proxy::inlet::{Inlet, InletDataEx, InletProxyType};
+    use crate::proxy::outlet::{Outlet, OutletDataEx};
+    use crate::proxy::rate_limit::RateLimiters;
+    use crate::proxy::{OutputFuncType, ProxyMessage};
     use std::borrow::Cow;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// 在本机启动入口，并与出口直接对接，返回入口监听地址
+    pub(crate) async fn start_local_proxy(
+        inlet_proxy_type: InletProxyType,
+        username: &str,
+    ) -> SocketAddr {
+        let proxy_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        start_local_inlet(
+            inlet_proxy_type,
+            proxy_addr,
+            String::new(),
+            InletDataEx::new(username.to_string(), String::new()),
+        )
+        .await;
+        proxy_addr
+    }
+
+    /// 在指定地址启动入口，并与出口直接对接
+    pub(crate) async fn start_local_inlet(
+        inlet_proxy_type: InletProxyType,
+        listen_addr: SocketAddr,
+        output_addr: String,
+        data_ex: InletDataEx,
+    ) {
+        let (to_inlet_tx, mut to_inlet_rx) = mpsc::unbounded_channel();
+        let outlet_output: OutputFuncType = Arc::new(move |message: ProxyMessage| {
+            let to_inlet_tx = to_inlet_tx.clone();
+            Box::pin(async move {
+                let _ = to_inlet_tx.send(message);
+            })
+        });
+        let outlet = Outlet::new(
+            outlet_output,
+            String::new(),
+            RateLimiters::default(),
+            OutletDataEx::new(),
+        );
+        let inlet_output: OutputFuncType = Arc::new(move |message: ProxyMessage| {
+            let outlet = outlet.clone();
+            Box::pin(async move {
+                outlet.input(message).await;
+            })
+        });
+        let mut inlet = Inlet::new(inlet_output, String::new(), RateLimiters::default());
+        inlet
+            .start(
+                inlet_proxy_type,
+                listen_addr.to_string(),
+                output_addr,
+                false,
+                "None".to_string(),
+                data_ex,
+            )
+            .await
+            .unwrap();
+        let inlet = Arc::new(inlet);
+        tokio::spawn(async move {
+            while let Some(message) = to_inlet_rx.recv().await {
+                inlet.input(message).await;
+            }
+        });
+    }
+
+    pub(crate) async fn start_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    pub(crate) async fn assert_echo<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) {
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
 
     #[test]
     fn test_crypto() {
@@ -100,4 +205,170 @@ mod tests {
             String::from_utf8_lossy(plain_text.as_slice())
         );
     }
+    #[test]
+    fn test_aead_key_exchange() {
+        let raw = b"authenticated tunnel payload";
+
+        for name in ["Aes256Gcm", "ChaCha20Poly1305"] {
+            let inlet = crypto::KeyExchange::new(crypto::get_method(name), true);
+            let outlet = crypto::KeyExchange::new(crypto::get_method(name), false);
+            outlet.complete(inlet.public_key()).unwrap();
+            inlet.complete(outlet.public_key()).unwrap();
+
+            // 两个方向使用不同密钥，nonce 不重复
+            let first = inlet.session().unwrap().encrypt(raw).unwrap();
+            let second = inlet.session().unwrap().encrypt(raw).unwrap();
+            assert_ne!(first, second);
+            assert_eq!(outlet.session().unwrap().decrypt(&first).unwrap(), raw);
+            assert_eq!(outlet.session().unwrap().decrypt(&second).unwrap(), raw);
+            assert!(inlet.session().unwrap().decrypt(&first).is_err());
+
+            let reply = outlet.session().unwrap().encrypt(raw).unwrap();
+            assert_eq!(inlet.session().unwrap().decrypt(&reply).unwrap(), raw);
+
+            // 篡改密文后认证失败
+            let mut tampered = reply.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(inlet.session().unwrap().decrypt(&tampered).is_err());
+        }
+    }
+
+    #[test]
+    fn test_aead_replay() {
+        let raw = b"replayed payload";
+
+        for datagram in [false, true] {
+            let inlet = crypto::KeyExchange::new(crypto::get_method("Aes256Gcm"), true);
+            let outlet = crypto::KeyExchange::new(crypto::get_method("Aes256Gcm"), false);
+            outlet.set_datagram(datagram);
+            outlet.complete(inlet.public_key()).unwrap();
+            inlet.complete(outlet.public_key()).unwrap();
+            let sender = inlet.session().unwrap();
+            let receiver = outlet.session().unwrap();
+
+            let frames: Vec<_> = (0..3).map(|_| sender.encrypt(raw).unwrap()).collect();
+            assert!(receiver.decrypt(&frames[0]).is_ok());
+            // 重放总是被拒绝
+            assert!(receiver.decrypt(&frames[0]).is_err());
+            // 流式会话拒绝乱序，数据报会话允许窗口内乱序
+            assert_eq!(receiver.decrypt(&frames[2]).is_ok(), datagram);
+            assert!(receiver.decrypt(&frames[1]).is_ok());
+            assert!(receiver.decrypt(&frames[1]).is_err());
+        }
+
+        // 数据报会话拒绝超出窗口的旧计数器
+        let inlet = crypto::KeyExchange::new(crypto::get_method("ChaCha20Poly1305"), true);
+        let outlet = crypto::KeyExchange::new(crypto::get_method("ChaCha20Poly1305"), false);
+        outlet.set_datagram(true);
+        outlet.complete(inlet.public_key()).unwrap();
+        inlet.complete(outlet.public_key()).unwrap();
+        let old = inlet.session().unwrap().encrypt(raw).unwrap();
+        let mut latest = Vec::new();
+        for _ in 0..100 {
+            latest = inlet.session().unwrap().encrypt(raw).unwrap();
+        }
+        assert!(outlet.session().unwrap().decrypt(&latest).is_ok());
+        assert!(outlet.session().unwrap().decrypt(&old).is_err());
+    }
+
+    /// 直接驱动出口处理 BIND 请求，返回出口的输出
+    async fn outlet_bind(
+        data_ex: OutletDataEx,
+        addr: SocketAddr,
+    ) -> (Arc<Outlet>, mpsc::UnboundedReceiver<ProxyMessage>) {
+        let (output_tx, output_rx) = mpsc::unbounded_channel();
+        let output: OutputFuncType = Arc::new(move |message: ProxyMessage| {
+            let output_tx = output_tx.clone();
+            Box::pin(async move {
+                let _ = output_tx.send(message);
+            })
+        });
+        let outlet = Outlet::new(output, String::new(), RateLimiters::default(), data_ex);
+        outlet
+            .input(ProxyMessage::I2oConnect(
+                1,
+                InletProxyType::SOCKS5.to_u8(),
+                true,
+                false,
+                addr.to_string(),
+                "None".to_string(),
+                String::new(),
+                "127.0.0.1:1".to_string(),
+                true,
+            ))
+            .await;
+        (outlet, output_rx)
+    }
+
+    #[tokio::test]
+    async fn test_outlet_bind() {
+        let target: SocketAddr = "127.0.0.1:21".parse().unwrap();
+
+        // 访问控制拒绝的目标不能 BIND
+        let data_ex = OutletDataEx::new().with_access_list(&[], &["127.0.0.1".to_string()]);
+        let (_outlet, mut output) = outlet_bind(data_ex, target).await;
+        match output.recv().await.unwrap() {
+            ProxyMessage::O2iConnect(_, success, _, _, _) => assert!(!success),
+            _ => panic!("unexpected message"),
+        }
+
+        // 只接受来自目标地址的连接
+        let (_outlet, mut output) = outlet_bind(OutletDataEx::new(), target).await;
+        let bind_addr: SocketAddr = match output.recv().await.unwrap() {
+            ProxyMessage::O2iConnect(_, true, _, _, bind_addr) => bind_addr.parse().unwrap(),
+            _ => panic!("unexpected message"),
+        };
+        assert_eq!(bind_addr.ip(), target.ip());
+
+        // 非目标地址的连接被直接断开，其他系统默认只有 127.0.0.1 一个回环地址
+        #[cfg(target_os = "linux")]
+        {
+            let other = tokio::net::TcpSocket::new_v4().unwrap();
+            other.bind("127.0.0.2:0".parse().unwrap()).unwrap();
+            let mut other = other.connect(bind_addr).await.unwrap();
+            let mut buf = [0u8; 1];
+            assert_eq!(other.read(&mut buf).await.unwrap_or(0), 0);
+        }
+
+        let _stream = tokio::net::TcpStream::connect(bind_addr).await.unwrap();
+        match output.recv().await.unwrap() {
+            ProxyMessage::O2iBindAccept(_, peer_addr) => {
+                assert_eq!(peer_addr.parse::<SocketAddr>().unwrap().ip(), target.ip())
+            }
+            _ => panic!("unexpected message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outlet_bind_any_peer() {
+        // 未指定目标地址时接受访问控制允许的任意连入方
+        let target: SocketAddr = "0.0.0.0:0".parse().unwrap();
+        let bind_port = |message: ProxyMessage| match message {
+            ProxyMessage::O2iConnect(_, true, _, _, bind_addr) => {
+                bind_addr.parse::<SocketAddr>().unwrap().port()
+            }
+            _ => panic!("unexpected message"),
+        };
+
+        let (_outlet, mut output) = outlet_bind(OutletDataEx::new(), target).await;
+        let port = bind_port(output.recv().await.unwrap());
+        let _stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        assert!(matches!(
+            output.recv().await.unwrap(),
+            ProxyMessage::O2iBindAccept(..)
+        ));
+
+        // 访问控制拒绝的连入方被直接断开
+        let data_ex = OutletDataEx::new().with_access_list(&[], &["127.0.0.1".to_string()]);
+        let (_outlet, mut output) = outlet_bind(data_ex, target).await;
+        let port = bind_port(output.recv().await.unwrap());
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).await.unwrap_or(0), 0);
+        assert!(output.try_recv().is_err());
+    }
 }
@@ -1,8 +1,11 @@
+use crate::net::proxy_protocol::{self, ProxyProtocolVersion};
 use crate::net::session_delegate::SessionDelegate;
 use crate::net::{net_session, udp_session, SendMessageFuncType, WriterMessage};
-use crate::proxy::common::{InputSenderType, SessionCommonInfo};
+use crate::proxy::acl::{split_target, AccessList};
+use crate::proxy::common::{InputSenderType, SessionCommonInfo, SessionSnapshot};
 use crate::proxy::crypto::get_method;
 use crate::proxy::inlet::InletProxyType;
+use crate::proxy::rate_limit::RateLimiters;
 use crate::proxy::ProxyMessage;
 use crate::proxy::{common, OutputFuncType};
 use anyhow::anyhow;
@@ -12,11 +15,12 @@ use bytes::Bytes;
 use dashmap::DashMap;
 use log::{debug, error, info, trace};
 use socket2::{SockRef, TcpKeepalive};
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::select;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::{broadcast, mpsc, Notify, RwLock};
@@ -28,6 +32,50 @@ struct SessionInfo {
 
 type SessionInfoMap = Arc<DashMap<u32, SessionInfo>>;
 
+/// SOCKS5 BIND 等待目标地址连入的超时时间
+const BIND_ACCEPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 出口的附加配置
+#[derive(Default)]
+pub struct OutletDataEx {
+    /// 连接目标后先发送的 PROXY protocol 头部版本，为空时不发送
+    pub(crate) proxy_protocol: Option<ProxyProtocolVersion>,
+    /// 代理类通道允许访问的目标，入口检查之后再检查一次
+    pub(crate) access_list: AccessList,
+}
+
+impl OutletDataEx {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 0 为不发送，1 为 v1，2 为 v2
+    pub fn with_proxy_protocol(mut self, version: u32) -> Self {
+        self.proxy_protocol = ProxyProtocolVersion::from_u32(version);
+        self
+    }
+
+    pub fn with_access_list(mut self, allow: &[String], deny: &[String]) -> Self {
+        self.access_list = AccessList::from_rules(allow, deny);
+        self
+    }
+
+    /// 解析目标地址，只保留访问控制允许的地址
+    ///
+    /// 按解析结果检查，域名指向被拒绝的网段时同样拒绝。
+    async fn permitted_addrs(&self, addr: &str) -> anyhow::Result<Vec<SocketAddr>> {
+        let (host, _) = split_target(addr).ok_or_else(|| anyhow!("invalid target address"))?;
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host(addr)
+            .await?
+            .filter(|target| self.access_list.permits_addr(host, *target))
+            .collect();
+        if addrs.is_empty() {
+            return Err(anyhow!("target denied by access list"));
+        }
+        Ok(addrs)
+    }
+}
+
 pub struct Outlet {
     session_info_map: SessionInfoMap,
     description: String,
@@ -39,10 +87,18 @@ pub struct Outlet {
     session_count: Arc<AtomicUsize>,
     /// 每当 session_count 降为 0 时通知 stop()
     all_sessions_closed: Arc<Notify>,
+    /// 带宽限速器，所有会话共享
+    rate_limiters: RateLimiters,
+    data_ex: Arc<OutletDataEx>,
 }
 
 impl Outlet {
-    pub fn new(on_output_callback: OutputFuncType, description: String) -> Arc<Self> {
+    pub fn new(
+        on_output_callback: OutputFuncType,
+        description: String,
+        rate_limiters: RateLimiters,
+        data_ex: OutletDataEx,
+    ) -> Arc<Self> {
         let (notify_shutdown, mut receiver_shutdown) = broadcast::channel::<()>(1);
         let (input_tx, input_rx) = mpsc::unbounded_channel();
         let (output_tx, output_rx) = mpsc::channel::<ProxyMessage>(1000);
@@ -56,6 +112,8 @@ impl Outlet {
             input: input_tx,
             session_count: Arc::new(AtomicUsize::new(0)),
             all_sessions_closed: Arc::new(Notify::new()),
+            rate_limiters,
+            data_ex: Arc::new(data_ex),
         });
 
         let outlet_cloned = outlet.clone();
@@ -108,6 +166,29 @@ impl Outlet {
         &self.description
     }
 
+    /// 当前会话数量
+    pub fn session_count(&self) -> usize {
+        self.session_count.load(Ordering::Acquire)
+    }
+
+    /// 当前活跃会话列表
+    pub fn sessions(&self) -> Vec<SessionSnapshot> {
+        self.session_info_map
+            .iter()
+            .map(|entry| entry.value().common_info.stats.snapshot(*entry.key()))
+            .collect()
+    }
+
+    /// 强制关闭会话，会话不存在时返回 false
+    ///
+    /// 关闭与目标地址的连接，会话结束时会通知输入端断开。
+    pub fn close_session(&self, session_id: u32) -> bool {
+        match self.session_info_map.get(&session_id) {
+            Some(session) => session.sender.send(WriterMessage::Close).is_ok(),
+            None => false,
+        }
+    }
+
     async fn async_receive_input(&self, mut input: UnboundedReceiver<ProxyMessage>) {
         while let Some(message) = input.recv().await {
             if let Err(err) = self.input_internal(message).await {
@@ -127,6 +208,7 @@ impl Outlet {
                 encryption_method,
                 encryption_key,
                 client_addr,
+                is_bind,
             ) => {
                 trace!(
                     "I2oConnect: session_id:{session_id}, addr:{addr}, tunnel_type:{tunnel_type}"
@@ -137,6 +219,8 @@ impl Outlet {
                 let output = self.output.clone();
                 let session_count = self.session_count.clone();
                 let all_sessions_closed = self.all_sessions_closed.clone();
+                let rate_limiters = self.rate_limiters.clone();
+                let data_ex = self.data_ex.clone();
                 tokio::spawn(async move {
                     if let Err(err) = Self::on_i2o_connect(
                         session_info_map,
@@ -147,10 +231,14 @@ impl Outlet {
                         addr.clone(),
                         encryption_method,
                         encryption_key,
+                        client_addr.clone(),
+                        is_bind,
                         shutdown_receiver,
                         output.clone(),
                         session_count,
                         all_sessions_closed,
+                        rate_limiters,
+                        data_ex,
                     )
                     .await
                     {
@@ -166,7 +254,13 @@ impl Outlet {
                         };
 
                         let _ = output
-                            .send(ProxyMessage::O2iConnect(session_id, false, err_info))
+                            .send(ProxyMessage::O2iConnect(
+                                session_id,
+                                false,
+                                err_info,
+                                String::new(),
+                                String::new(),
+                            ))
                             .await;
                     } else {
                         info!(
@@ -242,8 +336,17 @@ impl Outlet {
             }
         };
 
-        let target_addr = common::parse_addr(&target_addr).await?;
-        sender.send(WriterMessage::SendTo(decoded, target_addr))?;
+        let resolved_addr = common::parse_addr(&target_addr).await?;
+        // UDP 包逐个检查目标，被拒绝的包直接丢弃
+        let permitted = self.data_ex.access_list.is_empty()
+            || split_target(&target_addr).is_some_and(|(host, _)| {
+                self.data_ex.access_list.permits_addr(host, resolved_addr)
+            });
+        if permitted {
+            sender.send(WriterMessage::SendTo(decoded, resolved_addr))?;
+        } else {
+            debug!("udp packet to {target_addr} denied by access list");
+        }
 
         let _ = self
             .output
@@ -284,10 +387,14 @@ impl Outlet {
         mut addr: String,
         encryption_method: String,
         encryption_key: String,
+        client_addr: String,
+        is_bind: bool,
         shutdown_receiver: broadcast::Receiver<()>,
         output: mpsc::Sender<ProxyMessage>,
         session_count: Arc<AtomicUsize>,
         all_sessions_closed: Arc<Notify>,
+        rate_limiters: RateLimiters,
+        data_ex: Arc<OutletDataEx>,
     ) -> anyhow::Result<()> {
         if session_info_map.contains_key(&session_id) {
             return Err(anyhow!("repeated connection: session_id:{session_id}"));
@@ -295,8 +402,43 @@ impl Outlet {
 
         let encryption_method = get_method(&encryption_method);
         let encryption_key = BASE64_STANDARD.decode(encryption_key.as_bytes())?;
-        let common_info =
-            SessionCommonInfo::new(false, is_compressed, encryption_method, encryption_key);
+        let common_info = SessionCommonInfo::from_peer_key(
+            false,
+            is_compressed,
+            is_tcp,
+            encryption_method,
+            encryption_key,
+        )?
+        .with_rate_limiters(rate_limiters);
+        let src_addr = client_addr.parse::<SocketAddr>().ok();
+        common_info.stats.start(client_addr, addr.clone());
+
+        if is_bind {
+            // BIND 的目标同样受访问控制限制，解析结果用于核对连入方地址；
+            // 目标为未指定地址时客户端不知道连入方，改为对每个连入方检查访问控制
+            let targets: Vec<SocketAddr> = tokio::net::lookup_host(&addr).await?.collect();
+            let any_peer = targets.iter().all(|target| target.ip().is_unspecified());
+            let targets = if any_peer || data_ex.access_list.is_empty() {
+                targets
+            } else {
+                data_ex.permitted_addrs(&addr).await?
+            };
+            return Self::on_i2o_bind(
+                session_info_map,
+                session_id,
+                tunnel_type,
+                addr,
+                targets,
+                any_peer,
+                common_info,
+                shutdown_receiver,
+                output,
+                session_count,
+                all_sessions_closed,
+                data_ex,
+            )
+            .await;
+        }
 
         let connect_with_tcp = match tunnel_type {
             InletProxyType::UDP => false,
@@ -311,7 +453,11 @@ impl Outlet {
 
         if connect_with_tcp {
             debug!("tcp_connect: {}", addr);
-            let stream = TcpStream::connect(&addr).await?;
+            let mut stream = if data_ex.access_list.is_empty() {
+                TcpStream::connect(&addr).await?
+            } else {
+                TcpStream::connect(data_ex.permitted_addrs(&addr).await?.as_slice()).await?
+            };
 
             let ka = TcpKeepalive::new().with_time(Duration::from_secs(30));
             let sf = SockRef::from(&stream);
@@ -319,6 +465,13 @@ impl Outlet {
 
             let addr = stream.peer_addr()?;
 
+            // 向目标传递真实客户端地址
+            if let Some(version) = data_ex.proxy_protocol {
+                stream
+                    .write_all(&proxy_protocol::encode(version, src_addr, addr))
+                    .await?;
+            }
+
             tokio::spawn(async move {
                 net_session::run(
                     session_id,
@@ -330,6 +483,7 @@ impl Outlet {
                         tunnel_type,
                         session_count,
                         all_sessions_closed,
+                        false,
                     )),
                     shutdown_receiver,
                     stream,
@@ -360,6 +514,7 @@ impl Outlet {
                         tunnel_type,
                         session_count,
                         all_sessions_closed,
+                        false,
                     )),
                     None,
                     shutdown_receiver,
@@ -372,6 +527,163 @@ impl Outlet {
 
         Ok(())
     }
+
+    /// SOCKS5 BIND：监听本机端口，第一次回复监听地址，目标地址连入后第二次回复其地址
+    ///
+    /// 只接受来自 targets 中 IP 的连接，`any_peer` 为 true 时接受访问控制允许的任意连入方，
+    /// 其他连入方直接断开并继续等待。
+    #[allow(clippy::too_many_arguments)]
+    async fn on_i2o_bind(
+        session_info_map: SessionInfoMap,
+        session_id: u32,
+        tunnel_type: InletProxyType,
+        addr: String,
+        targets: Vec<SocketAddr>,
+        any_peer: bool,
+        common_info: SessionCommonInfo,
+        mut shutdown_receiver: broadcast::Receiver<()>,
+        output: mpsc::Sender<ProxyMessage>,
+        session_count: Arc<AtomicUsize>,
+        all_sessions_closed: Arc<Notify>,
+        data_ex: Arc<OutletDataEx>,
+    ) -> anyhow::Result<()> {
+        let (listener, bind_addr) = Self::bind_listen(&targets).await?;
+        debug!("tcp_bind: {}, listen: {}", addr, bind_addr);
+
+        // 连入前先占位，输入端断开（I2oDisconnect）时可以取消等待
+        let (cancel_tx, mut cancel_rx) = mpsc::unbounded_channel();
+        session_info_map.insert(
+            session_id,
+            SessionInfo {
+                sender: cancel_tx,
+                common_info: common_info.clone(),
+            },
+        );
+
+        let public_key = common_info.public_key();
+        let public_key = if public_key.is_empty() {
+            String::new()
+        } else {
+            BASE64_STANDARD.encode(public_key)
+        };
+        if let Err(err) = output
+            .send(ProxyMessage::O2iConnect(
+                session_id,
+                true,
+                "".to_string(),
+                public_key,
+                bind_addr.to_string(),
+            ))
+            .await
+        {
+            session_info_map.remove(&session_id);
+            return Err(anyhow!("on_i2o_bind: {}", err));
+        }
+
+        let cancelled = async {
+            while let Some(message) = cancel_rx.recv().await {
+                if matches!(message, WriterMessage::Close) {
+                    break;
+                }
+            }
+        };
+        let accept = async {
+            loop {
+                let (stream, peer_addr) = listener.accept().await?;
+                let peer_ip = peer_addr.ip().to_canonical();
+                let permitted = if any_peer {
+                    data_ex
+                        .access_list
+                        .permits_addr(&peer_ip.to_string(), peer_addr)
+                } else {
+                    targets
+                        .iter()
+                        .any(|target| target.ip().to_canonical() == peer_ip)
+                };
+                if permitted {
+                    return std::io::Result::Ok((stream, peer_addr));
+                }
+                debug!("tcp_bind: drop unexpected peer {peer_addr}, expected {addr}");
+            }
+        };
+        let accepted = select! {
+            result = tokio::time::timeout(BIND_ACCEPT_TIMEOUT, accept) => result,
+            _ = cancelled => {
+                debug!("tcp_bind cancelled: session_id:{session_id}");
+                session_info_map.remove(&session_id);
+                return Ok(());
+            }
+            _ = shutdown_receiver.recv() => {
+                session_info_map.remove(&session_id);
+                return Ok(());
+            }
+        };
+        let (stream, peer_addr) = match accepted {
+            Ok(Ok(accepted)) => accepted,
+            Ok(Err(err)) => {
+                session_info_map.remove(&session_id);
+                return Err(err.into());
+            }
+            Err(_) => {
+                session_info_map.remove(&session_id);
+                return Err(anyhow!("accept timeout"));
+            }
+        };
+
+        let ka = TcpKeepalive::new().with_time(Duration::from_secs(30));
+        let sf = SockRef::from(&stream);
+        sf.set_tcp_keepalive(&ka)?;
+
+        tokio::spawn(async move {
+            net_session::run(
+                session_id,
+                peer_addr,
+                Box::new(OutletSession::new(
+                    session_info_map,
+                    common_info,
+                    output,
+                    tunnel_type,
+                    session_count,
+                    all_sessions_closed,
+                    true,
+                )),
+                shutdown_receiver,
+                stream,
+            )
+            .await;
+            trace!("tcp bind client stop, peer addr: {}", peer_addr);
+        });
+        Ok(())
+    }
+
+    /// 在本机访问目标地址时使用的网卡上监听随机端口
+    ///
+    /// 获取网卡地址失败时在所有网卡上监听，回复给客户端的地址为未指定地址。
+    async fn bind_listen(targets: &[SocketAddr]) -> anyhow::Result<(TcpListener, SocketAddr)> {
+        let target = targets
+            .iter()
+            .find(|target| !target.ip().is_unspecified())
+            .copied();
+        let any_ip: IpAddr = match target {
+            Some(target) if target.is_ipv6() => Ipv6Addr::UNSPECIFIED.into(),
+            _ => Ipv4Addr::UNSPECIFIED.into(),
+        };
+
+        let mut ip = any_ip;
+        if let Some(target) = target {
+            // UDP connect 不发送数据，只用于查询路由选择的本机地址
+            if let Ok(socket) = UdpSocket::bind((any_ip, 0)).await {
+                if socket.connect(target).await.is_ok() {
+                    if let Ok(local_addr) = socket.local_addr() {
+                        ip = local_addr.ip();
+                    }
+                }
+            }
+        }
+        let listener = TcpListener::bind((ip, 0)).await?;
+        let bind_addr = listener.local_addr()?;
+        Ok((listener, bind_addr))
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////////// OutletSession ////////////////////////////////////////////////////////////////////////////////////
@@ -383,6 +695,8 @@ struct OutletSession {
     tunnel_type: InletProxyType,
     session_count: Arc<AtomicUsize>,
     all_sessions_closed: Arc<Notify>,
+    /// SOCKS5 BIND 连入的会话，建立时通知 O2iBindAccept
+    is_bind: bool,
 }
 
 impl OutletSession {
@@ -393,6 +707,7 @@ impl OutletSession {
         tunnel_type: InletProxyType,
         session_count: Arc<AtomicUsize>,
         all_sessions_closed: Arc<Notify>,
+        is_bind: bool,
     ) -> Self {
         Self {
             session_info_map,
@@ -402,6 +717,7 @@ impl OutletSession {
             tunnel_type,
             session_count,
             all_sessions_closed,
+            is_bind,
         }
     }
 }
@@ -426,11 +742,19 @@ impl SessionDelegate for OutletSession {
 
         // 先发送成功通知再计数；若发送失败则 net_session::run 不会调用 on_session_close，
         // 提前计数会导致 session_count 永久偏高，使 stop() 等满超时。
-        if let Err(err) = self
-            .output
-            .send(ProxyMessage::O2iConnect(session_id, true, "".to_string()))
-            .await
-        {
+        let message = if self.is_bind {
+            // BIND 已在监听时回复过连接结果
+            ProxyMessage::O2iBindAccept(session_id, addr.to_string())
+        } else {
+            let public_key = self.common_data.public_key();
+            let public_key = if public_key.is_empty() {
+                String::new()
+            } else {
+                BASE64_STANDARD.encode(public_key)
+            };
+            ProxyMessage::O2iConnect(session_id, true, "".to_string(), public_key, String::new())
+        };
+        if let Err(err) = self.output.send(message).await {
             self.session_info_map.remove(&session_id);
             tokio::time::sleep(Duration::from_secs(5)).await;
             Err(anyhow!("on_session_start: {}", err))
@@ -1,6 +1,6 @@
 use crate::net::{SendMessageFuncType, WriterMessage};
 use crate::proxy::common::SessionCommonInfo;
-use crate::proxy::inlet::{InletDataEx, InletProxyType};
+use crate::proxy::inlet::{InletDataEx, InletProxyType, UpstreamRegistry};
 use crate::proxy::ProxyMessage;
 use async_trait::async_trait;
 use base64::prelude::BASE64_STANDARD;
@@ -21,6 +21,7 @@ pub(crate) struct ProxyContextData {
     pub(crate) output: Sender<ProxyMessage>,
     pub(crate) common_data: SessionCommonInfo,
     pub(crate) data_ex: Arc<InletDataEx>,
+    pub(crate) upstreams: UpstreamRegistry,
 }
 
 impl ProxyContextData {
@@ -30,6 +31,7 @@ impl ProxyContextData {
         output: Sender<ProxyMessage>,
         common_data: SessionCommonInfo,
         data_ex: Arc<InletDataEx>,
+        upstreams: UpstreamRegistry,
     ) -> Self {
         Self {
             inlet_proxy_type,
@@ -38,6 +40,7 @@ impl ProxyContextData {
             output,
             common_data,
             data_ex,
+            upstreams,
         }
     }
 
@@ -106,6 +109,7 @@ impl ProxyContext for UniversalProxy {
                 ctx_data.common_data.encryption_method.to_string(),
                 BASE64_STANDARD.encode(ctx_data.common_data.encryption_key.as_slice()),
                 peer_addr.to_string(),
+                false,
             ))
             .await?;
         self.write_to_peer_tx = Some(write_to_peer_tx);
@@ -135,7 +139,7 @@ impl ProxyContext for UniversalProxy {
 
     async fn on_recv_proxy_message(&mut self, proxy_message: ProxyMessage) -> anyhow::Result<()> {
         match proxy_message {
-            ProxyMessage::O2iConnect(_session_id, success, error_msg) => {
+            ProxyMessage::O2iConnect(_session_id, success, error_msg, ..) => {
                 if success {
                     self.is_connected.store(true, Ordering::Relaxed);
                 } else {
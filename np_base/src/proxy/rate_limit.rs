@@ -0,0 +1,228 @@
+use dashmap::DashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 令牌桶限速器
+///
+/// - 速率为 0 表示不限速
+/// - 桶容量为 1 秒的速率，允许短时突发
+/// - 令牌允许透支，大数据帧先放行，之后按透支量等待，避免单帧超过桶容量时永远拿不到令牌
+pub struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+}
+
+struct TokenBucket {
+    // 每秒令牌数（字节）
+    rate: u64,
+    // 当前令牌数，可以为负数（透支）
+    tokens: f64,
+    // 上次补充令牌的时间
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            bucket: Mutex::new(TokenBucket {
+                rate,
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().unwrap().rate
+    }
+
+    /// 修改速率，已有会话立即生效
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.rate == rate {
+            return;
+        }
+        bucket.rate = rate;
+        bucket.tokens = rate as f64;
+        bucket.last_refill = Instant::now();
+    }
+
+    /// 消耗 size 字节令牌，返回需要等待的时长
+    fn reserve(&self, size: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        if bucket.rate == 0 {
+            return Duration::ZERO;
+        }
+        bucket.refill(Instant::now());
+        bucket.tokens -= size as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate as f64)
+        }
+    }
+
+    /// 申请 size 字节的发送额度，超出速率时等待
+    pub async fn acquire(&self, size: usize) {
+        let delay = self.reserve(size);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// 上行/下行带宽限速
+///
+/// 上行为输入端读取并发往输出端的数据，下行为输出端读取并返回输入端的数据。
+pub struct BandwidthLimiter {
+    upload: RateLimiter,
+    download: RateLimiter,
+}
+
+impl BandwidthLimiter {
+    pub fn new(upload_limit: u64, download_limit: u64) -> Arc<Self> {
+        Arc::new(Self {
+            upload: RateLimiter::new(upload_limit),
+            download: RateLimiter::new(download_limit),
+        })
+    }
+
+    pub fn set_limits(&self, upload_limit: u64, download_limit: u64) {
+        self.upload.set_rate(upload_limit);
+        self.download.set_rate(download_limit);
+    }
+
+    pub fn limits(&self) -> (u64, u64) {
+        (self.upload.rate(), self.download.rate())
+    }
+}
+
+/// 会话需要同时遵守的一组限速器（如通道限速 + 用户限速）
+#[derive(Clone, Default)]
+pub struct RateLimiters(Vec<Arc<BandwidthLimiter>>);
+
+impl RateLimiters {
+    pub fn new(limiters: Vec<Arc<BandwidthLimiter>>) -> Self {
+        Self(limiters)
+    }
+
+    /// 所有限速器同时扣除令牌，按等待最久的一个等待
+    pub async fn acquire(&self, size: usize, is_upload: bool) {
+        let delay = self
+            .0
+            .iter()
+            .map(|limiter| {
+                if is_upload {
+                    limiter.upload.reserve(size)
+                } else {
+                    limiter.download.reserve(size)
+                }
+            })
+            .max()
+            .unwrap_or(Duration::ZERO);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// 按通道、用户维护共享的限速器
+///
+/// 同一通道的所有会话共享一个限速器，同一用户的所有通道共享一个限速器。
+/// 限速修改时原地更新速率，无需重启入口/出口。
+#[derive(Default)]
+pub struct RateLimiterRegistry {
+    tunnels: DashMap<u32, Arc<BandwidthLimiter>>,
+    users: DashMap<u32, Arc<BandwidthLimiter>>,
+}
+
+impl RateLimiterRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 获取通道限速器，不存在时创建，存在时更新速率
+    pub fn tunnel(
+        &self,
+        tunnel_id: u32,
+        upload_limit: u64,
+        download_limit: u64,
+    ) -> Arc<BandwidthLimiter> {
+        Self::get_or_update(&self.tunnels, tunnel_id, upload_limit, download_limit)
+    }
+
+    /// 获取用户限速器，不存在时创建，存在时更新速率
+    pub fn user(
+        &self,
+        user_id: u32,
+        upload_limit: u64,
+        download_limit: u64,
+    ) -> Arc<BandwidthLimiter> {
+        Self::get_or_update(&self.users, user_id, upload_limit, download_limit)
+    }
+
+    /// 清理不再使用的限速器
+    pub fn retain(&self, tunnel_ids: &[u32], user_ids: &[u32]) {
+        self.tunnels.retain(|id, _| tunnel_ids.contains(id));
+        self.users.retain(|id, _| user_ids.contains(id));
+    }
+
+    fn get_or_update(
+        map: &DashMap<u32, Arc<BandwidthLimiter>>,
+        id: u32,
+        upload_limit: u64,
+        download_limit: u64,
+    ) -> Arc<BandwidthLimiter> {
+        map.entry(id)
+            .and_modify(|limiter| limiter.set_limits(upload_limit, download_limit))
+            .or_insert_with(|| BandwidthLimiter::new(upload_limit, download_limit))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_reserve() {
+        let limiter = RateLimiter::new(1000);
+        // 初始满桶，允许 1 秒突发
+        assert!(limiter.reserve(1000).is_zero());
+        // 透支 500 字节需要等待约 0.5 秒
+        let delay = limiter.reserve(500);
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+
+        // 不限速
+        let unlimited = RateLimiter::new(0);
+        assert!(unlimited.reserve(usize::MAX).is_zero());
+
+        // 修改速率后重新装满
+        limiter.set_rate(2000);
+        assert!(limiter.reserve(2000).is_zero());
+        limiter.set_rate(0);
+        assert!(limiter.reserve(1 << 30).is_zero());
+    }
+
+    #[test]
+    fn test_registry_shares_limiter() {
+        let registry = RateLimiterRegistry::new();
+        let first = registry.user(1, 100, 200);
+        let second = registry.user(1, 300, 400);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first.limits(), (300, 400));
+
+        registry.retain(&[], &[]);
+        let third = registry.user(1, 300, 400);
+        assert!(!Arc::ptr_eq(&first, &third));
+    }
+}
@@ -0,0 +1,460 @@
+use crate::net::{SendMessageFuncType, WriterMessage};
+use crate::proxy::inlet::InletProxyType;
+use crate::proxy::proxy_context::{ProxyContext, ProxyContextData};
+use crate::proxy::socks5::target_addr::TargetAddr;
+use crate::proxy::socks5::{Socks5Context, SOCKS5_VERSION};
+use crate::proxy::ProxyMessage;
+use async_trait::async_trait;
+use base64::prelude::BASE64_STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use log::{error, warn};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+const SOCKS4_VERSION: u8 = 0x04;
+
+const SOCKS4_CMD_CONNECT: u8 = 0x01;
+const SOCKS4_CMD_BIND: u8 = 0x02;
+
+const SOCKS4_REP_GRANTED: u8 = 0x5a;
+const SOCKS4_REP_REJECTED: u8 = 0x5b;
+const SOCKS4_REP_USER_ID_MISMATCH: u8 = 0x5d;
+
+// 请求头 8 字节 + USERID + 域名（SOCKS4a），超过此长度仍未收完视为非法请求
+const SOCKS4_MAX_REQUEST_LEN: usize = 1024;
+
+///
+///connect流程:
+///               Init -> Connecting -> Run
+///
+///bind流程:
+///               Init -> Binding -> BindWaiting -> Run
+///
+///首字节为 0x05 时按 SOCKS5 处理，之后的数据全部交给 Socks5Context
+///
+#[derive(Debug)]
+enum Status {
+    Init,
+    Connecting,
+    /// 等待输出端监听端口
+    Binding,
+    /// 等待目标地址连入输出端的监听端口
+    BindWaiting,
+    /// 运行中
+    Run,
+}
+
+pub struct Socks4Context {
+    status: Status,
+    buffer: Vec<u8>,
+    write_to_peer_tx: Option<UnboundedSender<WriterMessage>>,
+    ctx_data: Option<Arc<ProxyContextData>>,
+    peer_addr: Option<SocketAddr>,
+    /// 同一端口上的 SOCKS5 客户端
+    socks5: Option<Socks5Context>,
+}
+
+#[async_trait]
+impl ProxyContext for Socks4Context {
+    async fn on_start(
+        &mut self,
+        ctx_data: Arc<ProxyContextData>,
+        peer_addr: SocketAddr,
+        write_to_peer_tx: UnboundedSender<WriterMessage>,
+    ) -> anyhow::Result<()> {
+        self.write_to_peer_tx = Some(write_to_peer_tx);
+        self.ctx_data = Some(ctx_data);
+        self.peer_addr = Some(peer_addr);
+        Ok(())
+    }
+
+    async fn on_recv_peer_data(
+        &mut self,
+        ctx_data: Arc<ProxyContextData>,
+        data: Bytes,
+    ) -> anyhow::Result<()> {
+        if let Some(socks5) = self.socks5.as_mut() {
+            return socks5.on_recv_peer_data(ctx_data, data).await;
+        }
+
+        match &self.status {
+            Status::Init => {
+                if self.buffer.is_empty() && data.first() == Some(&SOCKS5_VERSION) {
+                    let mut socks5 = Socks5Context::new();
+                    socks5
+                        .on_start(
+                            ctx_data.clone(),
+                            self.peer_addr.unwrap(),
+                            self.write_to_peer_tx.as_ref().unwrap().clone(),
+                        )
+                        .await?;
+                    socks5.on_recv_peer_data(ctx_data, data).await?;
+                    self.socks5 = Some(socks5);
+                    return Ok(());
+                }
+                self.buffer.extend_from_slice(&data);
+                self.on_socks4_request().await?;
+            }
+            Status::Connecting | Status::Binding | Status::BindWaiting => {
+                warn!("Socks4 should not receive data before connected");
+            }
+            Status::Run => {
+                let encoded = ctx_data.common_data.encode_data_and_limiting(data).await?;
+                ctx_data
+                    .output
+                    .send(ProxyMessage::I2oSendData(
+                        ctx_data.get_session_id(),
+                        encoded,
+                    ))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn on_recv_proxy_message(&mut self, proxy_message: ProxyMessage) -> anyhow::Result<()> {
+        if let Some(socks5) = self.socks5.as_mut() {
+            return socks5.on_recv_proxy_message(proxy_message).await;
+        }
+
+        match proxy_message {
+            ProxyMessage::O2iConnect(_session_id, success, error_msg, _, bind_addr) => {
+                if !success {
+                    error!("socks4 connect error: {error_msg}");
+                }
+                self.on_recv_o2i_connect(success, bind_addr)?;
+            }
+            ProxyMessage::O2iBindAccept(_session_id, remote_addr) => {
+                if let Status::BindWaiting = self.status {
+                    self.status = Status::Run;
+                    self.send_reply(SOCKS4_REP_GRANTED, remote_addr.parse().ok())?;
+                }
+            }
+            ProxyMessage::O2iRecvData(session_id, data) => {
+                self.on_recv_o2i_recv_data(session_id, data)?;
+            }
+            ProxyMessage::O2iDisconnect(_) => {
+                self.write_to_peer_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(WriterMessage::Close)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn on_stop(&mut self, ctx_data: Arc<ProxyContextData>) -> anyhow::Result<()> {
+        if let Some(socks5) = self.socks5.as_mut() {
+            return socks5.on_stop(ctx_data).await;
+        }
+        ctx_data
+            .output
+            .send(ProxyMessage::I2oDisconnect(ctx_data.get_session_id()))
+            .await?;
+        Ok(())
+    }
+
+    fn is_ready_for_read(&self) -> bool {
+        true
+    }
+}
+
+impl Socks4Context {
+    pub fn new() -> Self {
+        Self {
+            status: Status::Init,
+            buffer: vec![],
+            write_to_peer_tx: None,
+            ctx_data: None,
+            peer_addr: None,
+            socks5: None,
+        }
+    }
+
+    async fn on_socks4_request(&mut self) -> anyhow::Result<()> {
+        // +----+----+----+----+----+----+----+----+----+----+....+----+
+        // | VN | CD | DSTPORT |      DSTIP        | USERID       |NULL|
+        // +----+----+----+----+----+----+----+----+----+----+....+----+
+        // | 1  | 1  |    2    |         4         | Variable     | 1  |
+        // +----+----+----+----+----+----+----+----+----+----+....+----+
+        // SOCKS4a: DSTIP 为 0.0.0.x（x 非 0），USERID 的 NULL 之后跟随目标域名与 NULL，由输出端解析
+        if self.buffer.len() < 9 {
+            return Ok(());
+        }
+        if self.buffer[0] != SOCKS4_VERSION {
+            return self.reject(SOCKS4_REP_REJECTED);
+        }
+
+        let Some(user_id_len) = self.buffer[8..].iter().position(|&b| b == 0) else {
+            return self.check_request_len();
+        };
+        let port = u16::from_be_bytes([self.buffer[2], self.buffer[3]]);
+        let ip = Ipv4Addr::new(
+            self.buffer[4],
+            self.buffer[5],
+            self.buffer[6],
+            self.buffer[7],
+        );
+
+        let octets = ip.octets();
+        let target_addr = if octets[..3] == [0, 0, 0] && octets[3] != 0 {
+            let host_start = 8 + user_id_len + 1;
+            let Some(host_len) = self.buffer[host_start..].iter().position(|&b| b == 0) else {
+                return self.check_request_len();
+            };
+            match std::str::from_utf8(&self.buffer[host_start..host_start + host_len]) {
+                Ok(host) if !host.is_empty() => TargetAddr::Domain(host.to_string(), port),
+                _ => return self.reject(SOCKS4_REP_REJECTED),
+            }
+        } else {
+            TargetAddr::Ip(SocketAddr::from((ip, port)))
+        };
+
+        // SOCKS4 没有密码，USERID 与通道用户名一致即可；未设置用户名时不校验。
+        // 通道设置了密码时只允许同一端口上的 SOCKS5 客户端认证后使用
+        let ctx_data = self.ctx_data.as_ref().unwrap().clone();
+        if !ctx_data.data_ex.password.is_empty() {
+            warn!("socks4 request rejected: tunnel requires a password, use SOCKS5");
+            return self.reject(SOCKS4_REP_REJECTED);
+        }
+        let username = &ctx_data.data_ex.username;
+        if !username.is_empty() && &self.buffer[8..8 + user_id_len] != username.as_bytes() {
+            return self.reject(SOCKS4_REP_USER_ID_MISMATCH);
+        }
+
+        let cmd = self.buffer[1];
+        if cmd != SOCKS4_CMD_CONNECT && cmd != SOCKS4_CMD_BIND {
+            return self.reject(SOCKS4_REP_REJECTED);
+        }
+        let is_bind = cmd == SOCKS4_CMD_BIND;
+        if !ctx_data
+            .data_ex
+            .access_list
+            .permits_target(&target_addr.to_string())
+        {
+            return self.reject(SOCKS4_REP_REJECTED);
+        }
+
+        ctx_data
+            .common_data
+            .stats
+            .set_target_addr(target_addr.to_string());
+        ctx_data
+            .output
+            .send(ProxyMessage::I2oConnect(
+                ctx_data.get_session_id(),
+                InletProxyType::SOCKS4.to_u8(),
+                true,
+                ctx_data.common_data.is_compressed,
+                target_addr.to_string(),
+                ctx_data.common_data.encryption_method.to_string(),
+                BASE64_STANDARD.encode(ctx_data.common_data.encryption_key.as_slice()),
+                self.peer_addr.as_ref().unwrap().to_string(),
+                is_bind,
+            ))
+            .await?;
+
+        self.buffer.clear();
+        self.status = if is_bind {
+            Status::Binding
+        } else {
+            Status::Connecting
+        };
+        Ok(())
+    }
+
+    fn on_recv_o2i_connect(&mut self, success: bool, bind_addr: String) -> anyhow::Result<()> {
+        match self.status {
+            Status::Connecting | Status::Binding if success => {
+                // BIND 第一次回复输出端监听的地址
+                let addr = if let Status::Binding = self.status {
+                    self.status = Status::BindWaiting;
+                    bind_addr.parse().ok()
+                } else {
+                    self.status = Status::Run;
+                    None
+                };
+                self.send_reply(SOCKS4_REP_GRANTED, addr)?;
+            }
+            Status::Connecting | Status::Binding | Status::BindWaiting => {
+                self.reject(SOCKS4_REP_REJECTED)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_recv_o2i_recv_data(&self, session_id: u32, data: Bytes) -> anyhow::Result<()> {
+        if let Status::Run = self.status {
+            let data_len = data.len();
+            let decoded = self
+                .ctx_data
+                .as_ref()
+                .unwrap()
+                .common_data
+                .decode_data(data)?;
+
+            // 写入完毕回调
+            let output = self.ctx_data.as_ref().unwrap().output.clone();
+            let callback: SendMessageFuncType = Box::new(move || {
+                let output = output.clone();
+                Box::pin(async move {
+                    let _ = output
+                        .send(ProxyMessage::I2oRecvDataResult(session_id, data_len))
+                        .await;
+                })
+            });
+
+            self.write_to_peer_tx
+                .as_ref()
+                .unwrap()
+                .send(WriterMessage::SendAndThen(decoded, callback))?;
+        } else {
+            warn!(
+                "on_recv_o2i_recv_data Socks4 error status: {:?}",
+                self.status
+            );
+        }
+        Ok(())
+    }
+
+    fn send_reply(&self, rep: u8, addr: Option<SocketAddr>) -> anyhow::Result<()> {
+        // +----+----+----+----+----+----+----+----+
+        // | VN | CD | DSTPORT |      DSTIP        |
+        // +----+----+----+----+----+----+----+----+
+        // | 1  | 1  |    2    |         4         |
+        // +----+----+----+----+----+----+----+----+
+        // VN 为 0，只有 BIND 回复需要填写地址
+        let mut response: Vec<u8> = vec![0x00, rep];
+        match addr {
+            Some(SocketAddr::V4(addr)) => {
+                response.extend_from_slice(&addr.port().to_be_bytes());
+                response.extend_from_slice(&addr.ip().octets());
+            }
+            _ => response.extend_from_slice(&[0x00; 6]),
+        }
+        self.write_to_peer_tx
+            .as_ref()
+            .unwrap()
+            .send(WriterMessage::Send(Bytes::from(response), true))?;
+        Ok(())
+    }
+
+    fn reject(&self, rep: u8) -> anyhow::Result<()> {
+        self.send_reply(rep, None)?;
+        self.write_to_peer_tx
+            .as_ref()
+            .unwrap()
+            .send(WriterMessage::CloseDelayed(Duration::from_millis(10)))?;
+        Ok(())
+    }
+
+    fn check_request_len(&self) -> anyhow::Result<()> {
+        if self.buffer.len() > SOCKS4_MAX_REQUEST_LEN {
+            return self.reject(SOCKS4_REP_REJECTED);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::inlet::{InletDataEx, InletProxyType};
+    use crate::proxy::tests::{
+        assert_echo, start_echo_server, start_local_inlet, start_local_proxy,
+    };
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    async fn socks4a_connect(proxy_addr: SocketAddr, user_id: &str, port: u16) -> TcpStream {
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut request = vec![0x04, 0x01];
+        request.extend_from_slice(&port.to_be_bytes());
+        request.extend_from_slice(&[0, 0, 0, 1]);
+        request.extend_from_slice(user_id.as_bytes());
+        request.push(0);
+        request.extend_from_slice(b"localhost\0");
+        stream.write_all(&request).await.unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_socks4() {
+        let echo_addr = start_echo_server().await;
+        let proxy_addr = start_local_proxy(InletProxyType::SOCKS4, "bob").await;
+
+        // SOCKS4a 由输出端解析域名
+        let mut stream = socks4a_connect(proxy_addr, "bob", echo_addr.port()).await;
+        let mut reply = [0u8; 8];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x00, 0x5a]);
+        assert_echo(&mut stream).await;
+
+        // USERID 与通道用户名不一致
+        let mut stream = socks4a_connect(proxy_addr, "alice", echo_addr.port()).await;
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x00, 0x5d]);
+
+        // 同一端口兼容 SOCKS5
+        let proxy_addr = start_local_proxy(InletProxyType::SOCKS4, "").await;
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x00]);
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&echo_addr.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x05, 0x00]);
+        assert_echo(&mut stream).await;
+    }
+
+    #[tokio::test]
+    async fn test_socks4_rejected_with_password() {
+        let echo_addr = start_echo_server().await;
+        let proxy_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        start_local_inlet(
+            InletProxyType::SOCKS4,
+            proxy_addr,
+            String::new(),
+            InletDataEx::new("bob".to_string(), "secret".to_string()),
+        )
+        .await;
+
+        // SOCKS4 无法携带密码，即使 USERID 正确也拒绝
+        let mut stream = socks4a_connect(proxy_addr, "bob", echo_addr.port()).await;
+        let mut reply = [0u8; 8];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x00, 0x5b]);
+
+        // SOCKS5 客户端仍可使用用户名密码认证
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream.write_all(&[0x05, 0x01, 0x02]).await.unwrap();
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x02]);
+        stream
+            .write_all(&[&[0x01, 3][..], b"bob", &[6], b"secret"].concat())
+            .await
+            .unwrap();
+        stream.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x01, 0x00]);
+        let mut request = vec![0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+        request.extend_from_slice(&echo_addr.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[..2], [0x05, 0x00]);
+        assert_echo(&mut stream).await;
+    }
+}
@@ -20,7 +20,7 @@ use tokio::select;
 use tokio::sync::mpsc::{Sender, UnboundedSender};
 use tokio_util::sync::CancellationToken;
 
-const SOCKS5_VERSION: u8 = 0x05;
+pub(crate) const SOCKS5_VERSION: u8 = 0x05;
 
 const SOCKS5_AUTH_METHOD_NONE: u8 = 0x00;
 // const SOCKS5_AUTH_METHOD_GSSAPI: u8 = 0x01; // not support
@@ -31,6 +31,10 @@ const SOCKS5_CMD_TCP_CONNECT: u8 = 0x01;
 const SOCKS5_CMD_TCP_BIND: u8 = 0x02;
 const SOCKS5_CMD_UDP_ASSOCIATE: u8 = 0x03;
 
+// 规则不允许连接
+const SOCKS5_REP_NOT_ALLOWED: u8 = 0x02;
+const SOCKS5_REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+
 const SOCKS5_ADDR_TYPE_IPV4: u8 = 0x01;
 const SOCKS5_ADDR_TYPE_DOMAIN_NAME: u8 = 0x03;
 const SOCKS5_ADDR_TYPE_IPV6: u8 = 0x04;
@@ -48,12 +52,22 @@ const SOCKS5_ADDR_TYPE_IPV6: u8 = 0x04;
 ///       无密码模式
 ///               Init -> Connect -> Connecting -> RunWithUdp
 ///
+///bind流程:
+///       有密码模式
+///               Init -> Verification -> Connect -> Binding -> BindWaiting -> RunWithTcp
+///       无密码模式
+///               Init -> Connect -> Binding -> BindWaiting -> RunWithTcp
+///
 #[derive(Debug)]
 enum Status {
     Init,
     Verification,
     Connect,
     Connecting(bool),
+    /// 等待输出端监听端口
+    Binding,
+    /// 等待目标地址连入输出端的监听端口
+    BindWaiting,
     /// 运行中
     RunWithTcp,
     /// 运行中
@@ -109,6 +123,9 @@ impl ProxyContext for Socks5Context {
             Status::Connecting(_) => {
                 warn!("Status::Connecting should not receive other data");
             }
+            Status::Binding | Status::BindWaiting => {
+                warn!("SOCKS5_CMD_TCP_BIND should not receive data before connected");
+            }
             Status::RunWithTcp => {
                 let encoded = ctx_data
                     .common_data
@@ -132,11 +149,14 @@ impl ProxyContext for Socks5Context {
 
     async fn on_recv_proxy_message(&mut self, proxy_message: ProxyMessage) -> anyhow::Result<()> {
         match proxy_message {
-            ProxyMessage::O2iConnect(_session_id, success, error_msg) => {
+            ProxyMessage::O2iConnect(_session_id, success, error_msg, _, bind_addr) => {
                 if !success {
                     error!("socks5 connect error: {error_msg}");
                 }
-                self.on_recv_o2i_connect(success).await?;
+                self.on_recv_o2i_connect(success, bind_addr).await?;
+            }
+            ProxyMessage::O2iBindAccept(_session_id, remote_addr) => {
+                self.on_recv_o2i_bind_accept(remote_addr)?;
             }
 
             ProxyMessage::O2iRecvData(session_id, data) => {
@@ -330,13 +350,13 @@ impl Socks5Context {
 
         if support {
             match cmd {
-                SOCKS5_CMD_TCP_CONNECT | SOCKS5_CMD_UDP_ASSOCIATE => {
+                SOCKS5_CMD_TCP_CONNECT | SOCKS5_CMD_TCP_BIND | SOCKS5_CMD_UDP_ASSOCIATE => {
                     // 头部已跳过 4 字节（不 drain）→ 传 &self.buffer[4..]
                     let addr_result = target_addr::read_address(&self.buffer[4..], address_type)?;
 
                     if let Some((mut target_addr, addr_data_len)) = addr_result {
                         let is_tcp = match cmd {
-                            SOCKS5_CMD_TCP_CONNECT => true,
+                            SOCKS5_CMD_TCP_CONNECT | SOCKS5_CMD_TCP_BIND => true,
                             SOCKS5_CMD_UDP_ASSOCIATE => {
                                 let mut addr: SocketAddr = *self.peer_addr.as_ref().unwrap();
                                 addr.set_port(target_addr.port());
@@ -347,7 +367,24 @@ impl Socks5Context {
                                 panic!("unknown cmd:{cmd}")
                             }
                         };
+                        let is_bind = cmd == SOCKS5_CMD_TCP_BIND;
+
+                        // UDP 的目标在每个数据包中，由出口逐包检查
+                        if is_tcp
+                            && !self
+                                .ctx_data
+                                .as_ref()
+                                .unwrap()
+                                .data_ex
+                                .access_list
+                                .permits_target(&target_addr.to_string())
+                        {
+                            return self.reply_and_close(SOCKS5_REP_NOT_ALLOWED);
+                        }
 
+                        let common_data = &self.ctx_data.as_ref().unwrap().common_data;
+                        common_data.set_datagram(!is_tcp);
+                        common_data.stats.set_target_addr(target_addr.to_string());
                         self.ctx_data
                             .as_ref()
                             .unwrap()
@@ -373,6 +410,7 @@ impl Socks5Context {
                                         .as_slice(),
                                 ),
                                 self.peer_addr.as_ref().unwrap().to_string(),
+                                is_bind,
                             ))
                             .await?;
 
@@ -384,20 +422,28 @@ impl Socks5Context {
                         }
 
                         self.buffer.clear();
-                        self.status = Status::Connecting(is_tcp);
+                        self.status = if is_bind {
+                            Status::Binding
+                        } else {
+                            Status::Connecting(is_tcp)
+                        };
                     }
                     // addr_result 为 None（数据不足）时 buffer 保持不变，等待下一次数据到来
                     return Ok(());
                 }
                 // not support
-                SOCKS5_CMD_TCP_BIND => {}
                 _ => {}
             }
         }
 
+        self.reply_and_close(SOCKS5_REP_COMMAND_NOT_SUPPORTED)
+    }
+
+    /// 回复失败并关闭连接
+    fn reply_and_close(&self, rep: u8) -> anyhow::Result<()> {
         let response: Vec<u8> = vec![
             SOCKS5_VERSION,
-            0x07, // 0x07不支持的命令
+            rep,
             0x00,
             0x01,
             0x00,
@@ -506,8 +552,39 @@ impl Socks5Context {
         Ok(())
     }
 
-    async fn on_recv_o2i_connect(&mut self, success: bool) -> anyhow::Result<()> {
+    async fn on_recv_o2i_connect(
+        &mut self,
+        success: bool,
+        bind_addr: String,
+    ) -> anyhow::Result<()> {
         match self.status {
+            // BIND 第一次回复：输出端监听的地址
+            Status::Binding => {
+                let response = if success {
+                    self.status = Status::BindWaiting;
+                    bind_reply(0x00, &bind_addr)
+                } else {
+                    bind_reply(0x04, "") // 0x04主机不可达
+                };
+                let _ = self
+                    .write_to_peer_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(WriterMessage::Send(Bytes::from(response), true));
+            }
+            // 等待连入超时或失败
+            Status::BindWaiting if !success => {
+                let _ = self
+                    .write_to_peer_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(WriterMessage::Send(Bytes::from(bind_reply(0x01, "")), true)); // 0x01普通SOCKS服务器连接失败
+                let _ = self
+                    .write_to_peer_tx
+                    .as_ref()
+                    .unwrap()
+                    .send(WriterMessage::CloseDelayed(Duration::from_millis(10)));
+            }
             Status::Connecting(is_tcp) if self.target_addr.is_some() => {
                 let response: Vec<u8> = if success {
                     if is_tcp {
@@ -565,6 +642,26 @@ impl Socks5Context {
         Ok(())
     }
 
+    /// BIND 第二次回复：连入的地址
+    fn on_recv_o2i_bind_accept(&mut self, remote_addr: String) -> anyhow::Result<()> {
+        if let Status::BindWaiting = self.status {
+            self.status = Status::RunWithTcp;
+            self.write_to_peer_tx
+                .as_ref()
+                .unwrap()
+                .send(WriterMessage::Send(
+                    Bytes::from(bind_reply(0x00, &remote_addr)),
+                    true,
+                ))?;
+        } else {
+            warn!(
+                "on_recv_o2i_bind_accept Socks5 error status: {:?}",
+                self.status
+            );
+        }
+        Ok(())
+    }
+
     async fn udp_bind(&mut self) -> anyhow::Result<Vec<u8>> {
         let socket = UdpSocket::bind("0.0.0.0:0").await?;
         if let Some(ref target_addr) = self.target_addr {
@@ -632,6 +729,20 @@ impl Socks5Context {
     }
 }
 
+/// 构造 BIND 回复，地址无法解析时回复全 0 的 IPv4 地址
+fn bind_reply(rep: u8, addr: &str) -> Vec<u8> {
+    let mut response = vec![SOCKS5_VERSION, rep, 0x00];
+    match addr
+        .parse::<SocketAddr>()
+        .ok()
+        .and_then(|addr| TargetAddr::Ip(addr).to_be_bytes().ok())
+    {
+        Some(addr_bytes) => response.extend(addr_bytes),
+        None => response.extend_from_slice(&[SOCKS5_ADDR_TYPE_IPV4, 0, 0, 0, 0, 0, 0]),
+    }
+    response
+}
+
 #[inline]
 async fn recv_udp_data(
     session_id: u32,
@@ -672,3 +783,87 @@ async fn recv_udp_data(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::proxy::inlet::InletProxyType;
+    use crate::proxy::tests::start_local_proxy;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn read_reply(stream: &mut TcpStream) -> SocketAddr {
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply[..4], &[0x05, 0x00, 0x00, 0x01]);
+        SocketAddr::from((
+            [reply[4], reply[5], reply[6], reply[7]],
+            u16::from_be_bytes([reply[8], reply[9]]),
+        ))
+    }
+
+    async fn socks5_request(proxy_addr: SocketAddr, cmd: u8, addr: SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        let mut method = [0u8; 2];
+        stream.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x00]);
+
+        let SocketAddr::V4(addr) = addr else {
+            panic!("ipv4 only");
+        };
+        let mut request = vec![0x05, cmd, 0x00, 0x01];
+        request.extend_from_slice(&addr.ip().octets());
+        request.extend_from_slice(&addr.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_socks5_bind() {
+        // 类 FTP 主动模式的服务端：控制连接收到 "PORT ip:port" 后主动连接数据端口
+        let ftp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ftp_addr = ftp_listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (control, _) = ftp_listener.accept().await.unwrap();
+            let mut control = BufReader::new(control);
+            let mut line = String::new();
+            control.read_line(&mut line).await.unwrap();
+            let data_addr = line.trim().strip_prefix("PORT ").unwrap().to_string();
+
+            let mut data = TcpStream::connect(data_addr).await.unwrap();
+            data.write_all(b"file content").await.unwrap();
+            let mut ack = [0u8; 2];
+            data.read_exact(&mut ack).await.unwrap();
+            assert_eq!(&ack, b"ok");
+            control.get_mut().write_all(b"226 done\r\n").await.unwrap();
+        });
+
+        let proxy_addr = start_local_proxy(InletProxyType::SOCKS5, "").await;
+
+        // 控制连接走 CONNECT，数据连接走 BIND
+        let mut control = socks5_request(proxy_addr, super::SOCKS5_CMD_TCP_CONNECT, ftp_addr).await;
+        read_reply(&mut control).await;
+        let mut data = socks5_request(proxy_addr, super::SOCKS5_CMD_TCP_BIND, ftp_addr).await;
+        let bind_addr = read_reply(&mut data).await;
+        assert_eq!(bind_addr.ip(), ftp_addr.ip());
+        assert_ne!(bind_addr.port(), 0);
+
+        control
+            .write_all(format!("PORT {}\r\n", bind_addr).as_bytes())
+            .await
+            .unwrap();
+        let remote_addr = read_reply(&mut data).await;
+        assert_eq!(remote_addr.ip(), ftp_addr.ip());
+
+        let mut content = [0u8; 12];
+        data.read_exact(&mut content).await.unwrap();
+        assert_eq!(&content, b"file content");
+        data.write_all(b"ok").await.unwrap();
+
+        let mut control = BufReader::new(control);
+        let mut line = String::new();
+        control.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "226 done\r\n");
+    }
+}
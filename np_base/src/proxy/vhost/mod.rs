@@ -0,0 +1,537 @@
+//! 虚拟主机：多个通道共用一个 TCP 监听，按 HTTP Host 或 TLS SNI 分发到对应通道
+//!
+//! TLS 流量不解密，原样转发给通道的目标地址。
+
+use crate::net::session_delegate::{CreateSessionDelegateCallback, SessionDelegate};
+use crate::net::{tcp_server, WriterMessage};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bytes::Bytes;
+use dashmap::DashMap;
+use log::{debug, error};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// 识别域名最多缓存的字节数
+const MAX_SNIFF_LEN: usize = 16 * 1024;
+/// 等待通道连接建立的最长时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const NOT_FOUND_RESPONSE: &[u8] =
+    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// 域名 -> 创建通道入口会话
+type RouteMap = Arc<DashMap<String, CreateSessionDelegateCallback>>;
+
+struct VhostListener {
+    routes: RouteMap,
+    shutdown: CancellationToken,
+    /// 监听关闭后触发
+    closed: CancellationToken,
+}
+
+static VHOST_LISTENERS: LazyLock<Mutex<HashMap<SocketAddr, Arc<VhostListener>>>> =
+    LazyLock::new(Default::default);
+
+/// 通道在共享监听上登记的域名
+///
+/// 调用 [`VhostRegistration::close`] 移除，最后一个通道移除后关闭监听。
+pub(crate) struct VhostRegistration {
+    addr: SocketAddr,
+    domains: Vec<String>,
+}
+
+impl VhostRegistration {
+    pub(crate) async fn close(self) {
+        let closed = {
+            let mut listeners = VHOST_LISTENERS.lock().unwrap();
+            let Some(listener) = listeners.get(&self.addr).cloned() else {
+                return;
+            };
+            for domain in &self.domains {
+                listener.routes.remove(domain);
+            }
+            if !listener.routes.is_empty() {
+                return;
+            }
+            listeners.remove(&self.addr);
+            listener.shutdown.cancel();
+            listener.closed.clone()
+        };
+        // 等待端口释放，通道重启时可以立即重新监听
+        closed.cancelled().await;
+    }
+}
+
+/// 在共享监听上登记域名，监听不存在时创建
+///
+/// 域名支持精确匹配、`*.example.com` 通配子域名以及 `*` 兜底。
+pub(crate) fn register(
+    addr: SocketAddr,
+    routes: Vec<(String, CreateSessionDelegateCallback)>,
+) -> anyhow::Result<VhostRegistration> {
+    if routes.is_empty() {
+        return Err(anyhow!("vhost tunnel has no domain"));
+    }
+
+    let mut listeners = VHOST_LISTENERS.lock().unwrap();
+    if let Some(listener) = listeners.get(&addr) {
+        if let Some((domain, _)) = routes
+            .iter()
+            .find(|(domain, _)| listener.routes.contains_key(&normalize_domain(domain)))
+        {
+            return Err(anyhow!("vhost domain {domain} already in use on {addr}"));
+        }
+    } else {
+        listeners.insert(addr, start_listener(addr)?);
+    }
+
+    let listener = &listeners[&addr];
+    let mut domains = Vec::with_capacity(routes.len());
+    for (domain, create_session_delegate) in routes {
+        let domain = normalize_domain(&domain);
+        listener
+            .routes
+            .insert(domain.clone(), create_session_delegate);
+        domains.push(domain);
+    }
+    Ok(VhostRegistration { addr, domains })
+}
+
+fn start_listener(addr: SocketAddr) -> anyhow::Result<Arc<VhostListener>> {
+    let std_listener = std::net::TcpListener::bind(addr)?;
+    std_listener.set_nonblocking(true)?;
+    let tcp_listener = TcpListener::from_std(std_listener)?;
+
+    let listener = Arc::new(VhostListener {
+        routes: Arc::new(DashMap::new()),
+        shutdown: CancellationToken::new(),
+        closed: CancellationToken::new(),
+    });
+
+    let routes = listener.routes.clone();
+    let shutdown = listener.shutdown.clone();
+    let closed = listener.closed.clone();
+    tokio::spawn(async move {
+        let server_task = tcp_server::Builder::new(Box::new(move || {
+            Box::new(VhostSession::new(routes.clone()))
+        }))
+        .set_on_stream_init_callback(Arc::new(|stream: TcpStream| {
+            Box::pin(async move {
+                stream.set_nodelay(true)?;
+                Ok(stream)
+            })
+        }))
+        .build_with_listener(tcp_listener, shutdown.cancelled_owned());
+        if let Err(err) = server_task.await {
+            error!("vhost listener {addr} error: {err}");
+        }
+        debug!("vhost listener {addr} closed");
+        closed.cancel();
+    });
+    Ok(listener)
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// 按域名查找通道：精确匹配 > 通配子域名（由近到远）> `*`
+fn find_route<'a>(
+    routes: &'a RouteMap,
+    host: &str,
+) -> Option<dashmap::mapref::one::Ref<'a, String, CreateSessionDelegateCallback>> {
+    let host = normalize_domain(host);
+    if let Some(route) = routes.get(&host) {
+        return Some(route);
+    }
+    let mut rest = host.as_str();
+    while let Some((_, parent)) = rest.split_once('.') {
+        if let Some(route) = routes.get(&format!("*.{parent}")) {
+            return Some(route);
+        }
+        rest = parent;
+    }
+    routes.get("*")
+}
+
+enum Sniff {
+    /// 数据不完整
+    Incomplete,
+    Http(String),
+    Tls(String),
+    /// 无法识别或没有域名
+    Unknown,
+}
+
+fn sniff_host(data: &[u8]) -> Sniff {
+    if data.is_empty() {
+        return Sniff::Incomplete;
+    }
+    // TLS handshake record
+    if data[0] == 0x16 {
+        return sniff_tls(data);
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut req = httparse::Request::new(&mut headers);
+    match req.parse(data) {
+        Ok(httparse::Status::Partial) => Sniff::Incomplete,
+        Ok(httparse::Status::Complete(_)) => {
+            let host = req
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case("host"))
+                .and_then(|header| std::str::from_utf8(header.value).ok())
+                .map(strip_port)
+                .unwrap_or_default();
+            Sniff::Http(host.to_string())
+        }
+        Err(_) => Sniff::Unknown,
+    }
+}
+
+/// ClientHello 可能被拆分到多个 TLS 记录中，拼接记录内容后再解析
+fn sniff_tls(mut data: &[u8]) -> Sniff {
+    let mut handshake = Vec::new();
+    loop {
+        if handshake.len() >= 4 {
+            let handshake_len = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]);
+            let handshake_len = 4 + handshake_len as usize;
+            // 超过缓存上限的 ClientHello 不再等待
+            if handshake_len > MAX_SNIFF_LEN {
+                return Sniff::Unknown;
+            }
+            if handshake.len() >= handshake_len {
+                return match parse_client_hello_sni(&handshake[..handshake_len]) {
+                    Some(host) => Sniff::Tls(host),
+                    None => Sniff::Unknown,
+                };
+            }
+        }
+
+        if data.len() < 5 {
+            return Sniff::Incomplete;
+        }
+        // ClientHello 完整之前只能出现 handshake 记录
+        if data[0] != 0x16 {
+            return Sniff::Unknown;
+        }
+        let record_len = u16::from_be_bytes([data[3], data[4]]) as usize;
+        if data.len() < 5 + record_len {
+            return Sniff::Incomplete;
+        }
+        handshake.extend_from_slice(&data[5..5 + record_len]);
+        data = &data[5 + record_len..];
+    }
+}
+
+/// 去掉 Host 中的端口，IPv6 地址保留方括号
+fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+    if host.starts_with('[') {
+        return host.split_inclusive(']').next().unwrap_or(host);
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (data, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(data)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|data| data[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|data| u16::from_be_bytes([data[0], data[1]]))
+    }
+}
+
+/// 从 ClientHello 中读取 server_name 扩展
+fn parse_client_hello_sni(handshake: &[u8]) -> Option<String> {
+    let mut reader = Reader(handshake);
+    // ClientHello
+    if reader.u8()? != 0x01 {
+        return None;
+    }
+    // 长度 + 版本 + 随机数
+    reader.take(3 + 2 + 32)?;
+    let session_id_len = reader.u8()? as usize;
+    reader.take(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.take(cipher_suites_len)?;
+    let compression_methods_len = reader.u8()? as usize;
+    reader.take(compression_methods_len)?;
+    let extensions_len = reader.u16()? as usize;
+    let mut extensions = Reader(reader.take(extensions_len)?);
+
+    while !extensions.0.is_empty() {
+        let extension_type = extensions.u16()?;
+        let extension_len = extensions.u16()? as usize;
+        let extension = extensions.take(extension_len)?;
+        if extension_type != 0x0000 {
+            continue;
+        }
+        let mut server_names = Reader(extension);
+        server_names.u16()?;
+        // host_name
+        if server_names.u8()? != 0x00 {
+            return None;
+        }
+        let name_len = server_names.u16()? as usize;
+        return String::from_utf8(server_names.take(name_len)?.to_vec()).ok();
+    }
+    None
+}
+
+/// 共享监听上的会话，识别出域名后交给对应通道的入口会话
+struct VhostSession {
+    routes: RouteMap,
+    session: Option<(u32, SocketAddr, UnboundedSender<WriterMessage>)>,
+    cache_data: Vec<u8>,
+    inner: Option<Box<dyn SessionDelegate>>,
+}
+
+impl VhostSession {
+    fn new(routes: RouteMap) -> Self {
+        Self {
+            routes,
+            session: None,
+            cache_data: Vec::new(),
+            inner: None,
+        }
+    }
+
+    fn close(&self, response: Option<&'static [u8]>) -> anyhow::Result<()> {
+        let (_, _, tx) = self.session.as_ref().unwrap();
+        if let Some(response) = response {
+            tx.send(WriterMessage::Send(Bytes::from_static(response), true))?;
+        }
+        tx.send(WriterMessage::Close)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionDelegate for VhostSession {
+    async fn on_session_start(
+        &mut self,
+        session_id: u32,
+        addr: &SocketAddr,
+        tx: UnboundedSender<WriterMessage>,
+    ) -> anyhow::Result<()> {
+        self.session = Some((session_id, *addr, tx));
+        Ok(())
+    }
+
+    async fn on_session_close(&mut self) -> anyhow::Result<()> {
+        match self.inner.as_mut() {
+            Some(inner) => inner.on_session_close().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn on_recv_frame(&mut self, frame: Bytes) -> anyhow::Result<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            return inner.on_recv_frame(frame).await;
+        }
+
+        self.cache_data.extend_from_slice(&frame);
+        let (host, is_http) = match sniff_host(&self.cache_data) {
+            Sniff::Incomplete if self.cache_data.len() < MAX_SNIFF_LEN => return Ok(()),
+            Sniff::Http(host) => (host, true),
+            Sniff::Tls(host) => (host, false),
+            Sniff::Incomplete | Sniff::Unknown => {
+                debug!(
+                    "vhost unrecognized request from {}",
+                    self.session.as_ref().unwrap().1
+                );
+                return self.close(None);
+            }
+        };
+
+        let Some(mut inner) = find_route(&self.routes, &host).map(|create| create()) else {
+            debug!("vhost no route for host '{host}'");
+            return self.close(is_http.then_some(NOT_FOUND_RESPONSE));
+        };
+
+        let (session_id, addr, tx) = self.session.clone().unwrap();
+        inner
+            .on_session_start(session_id, &addr, tx.clone())
+            .await?;
+        self.inner = Some(inner);
+
+        // 通道连接建立前不能发送数据，与读取时的背压检查一样退避等待
+        let inner = self.inner.as_mut().unwrap();
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        let mut backoff_ms = 1u64;
+        while !inner.is_ready_for_read().await {
+            if tx.is_closed() || Instant::now() >= deadline {
+                return Err(anyhow!("vhost '{host}' connect failed"));
+            }
+            tokio::time::sleep(Duration::from_millis(backoff_ms)).await;
+            backoff_ms = (backoff_ms * 2).min(32);
+        }
+        let data = Bytes::from(std::mem::take(&mut self.cache_data));
+        inner.on_recv_frame(data).await
+    }
+
+    async fn is_ready_for_read(&self) -> bool {
+        match self.inner.as_ref() {
+            Some(inner) => inner.is_ready_for_read().await,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::inlet::{InletDataEx, InletProxyType};
+    use crate::proxy::tests::start_local_inlet;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// 返回固定内容后关闭连接的服务器
+    async fn start_named_server(name: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(name.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    /// 只带 server_name 扩展的 ClientHello
+    fn client_hello(server_name: &str) -> Vec<u8> {
+        let name = server_name.as_bytes();
+        let mut sni = Vec::new();
+        sni.extend_from_slice(&((name.len() + 3) as u16).to_be_bytes());
+        sni.push(0x00);
+        sni.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        sni.extend_from_slice(name);
+
+        let mut extensions = vec![0x00, 0x00];
+        extensions.extend_from_slice(&(sni.len() as u16).to_be_bytes());
+        extensions.extend_from_slice(&sni);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0u8; 32]);
+        body.push(0x00);
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    async fn request(addr: SocketAddr, data: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(data).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_sniff_host() {
+        assert!(matches!(
+            sniff_host(b"GET / HTTP/1.1\r\nHost: a"),
+            Sniff::Incomplete
+        ));
+        assert!(
+            matches!(sniff_host(b"GET / HTTP/1.1\r\nHost: A.example.com:8080\r\n\r\n"), Sniff::Http(host) if host == "A.example.com")
+        );
+        let hello = client_hello("b.example.com");
+        assert!(matches!(sniff_host(&hello[..20]), Sniff::Incomplete));
+        assert!(matches!(sniff_host(&hello), Sniff::Tls(host) if host == "b.example.com"));
+        // ClientHello 拆分到两个记录中
+        let (header, handshake) = hello.split_at(5);
+        let (first, second) = handshake.split_at(10);
+        let mut split = Vec::new();
+        for fragment in [first, second] {
+            split.extend_from_slice(&header[..3]);
+            split.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            split.extend_from_slice(fragment);
+        }
+        assert!(matches!(sniff_host(&split[..20]), Sniff::Incomplete));
+        assert!(matches!(sniff_host(&split), Sniff::Tls(host) if host == "b.example.com"));
+        assert_eq!(strip_port("[::1]:443"), "[::1]");
+    }
+
+    #[tokio::test]
+    async fn test_vhost_routing() {
+        let server_a = start_named_server("a").await;
+        let server_b = start_named_server("b").await;
+        let listen_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        start_local_inlet(
+            InletProxyType::VHOST,
+            listen_addr,
+            server_a.to_string(),
+            InletDataEx::new(String::new(), String::new()).with_custom_mapping(HashMap::from([(
+                "a.example.com".to_string(),
+                String::new(),
+            )])),
+        )
+        .await;
+        start_local_inlet(
+            InletProxyType::VHOST,
+            listen_addr,
+            String::new(),
+            InletDataEx::new(String::new(), String::new()).with_custom_mapping(HashMap::from([(
+                "*.example.org".to_string(),
+                server_b.to_string(),
+            )])),
+        )
+        .await;
+
+        let response = request(
+            listen_addr,
+            b"GET / HTTP/1.1\r\nHost: a.example.com\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response, "a");
+        assert_eq!(
+            request(listen_addr, &client_hello("www.example.org")).await,
+            "b"
+        );
+        let response = request(
+            listen_addr,
+            b"GET / HTTP/1.1\r\nHost: c.example.com\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
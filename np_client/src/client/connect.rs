@@ -1,18 +1,26 @@
 //! 协议连接建立。
 //!
-//! 每种协议（TCP、KCP、WS、QUIC）提供一个 `ForwardConnector` 闭包，
+//! 每种协议（TCP、KCP、WS、HTTP/2、QUIC）提供一个 `ForwardConnector` 闭包，
 //! 传输层连接池据此按需创建新的转发连接。
+//! 配置了上游代理时，TCP、WS 与 HTTP/2 连接（包括转发连接）都经由代理建立。
 
+use super::metrics::CLIENT_METRICS;
 use super::session::ClientSession;
 use super::transport::{ClientTransport, ForwardConnector};
+use super::upstream_proxy::UpstreamProxy;
 use crate::client::now_secs;
 use crate::CommonArgs;
 use anyhow::anyhow;
 use dashmap::DashMap;
 use http::Uri;
-use log::info;
+#[cfg(feature = "ws")]
+use http::{header, HeaderName, HeaderValue};
+use log::{info, warn};
+#[cfg(feature = "h2")]
+use np_base::net::h2_client::H2Client;
 #[cfg(feature = "ws")]
 use np_base::net::ws_async_io::WebSocketAsyncIo;
+use np_base::proxy::rate_limit::RateLimiterRegistry;
 #[cfg(feature = "quic")]
 use s2n_quic::{client::Connect, Client as QUICClient};
 #[cfg(feature = "tcp")]
@@ -29,32 +37,44 @@ use tokio::io::{AsyncRead, AsyncWrite};
 #[cfg(feature = "tcp")]
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex};
-#[cfg(any(feature = "tcp", feature = "kcp"))]
+#[cfg(any(feature = "tcp", feature = "kcp", feature = "h2"))]
 use tokio::time::timeout;
 #[cfg(feature = "kcp")]
 use tokio_kcp::{KcpConfig, KcpNoDelayConfig, KcpStream};
 use tokio_rustls::rustls::pki_types::pem::PemObject;
-use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::{ClientConfig, RootCertStore};
-#[cfg(any(feature = "tcp", feature = "kcp"))]
+#[cfg(any(feature = "tcp", feature = "kcp", feature = "h2"))]
 use tokio_rustls::TlsConnector;
+#[cfg(feature = "ws")]
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, handshake::client::Request};
 use webpki_roots::TLS_SERVER_ROOTS;
 
 /// TLS 握手超时时间（秒）。
-#[cfg(any(feature = "tcp", feature = "kcp"))]
+#[cfg(any(feature = "tcp", feature = "kcp", feature = "h2"))]
 const TIMEOUT_TLS: u64 = 30;
 
+/// `grpc://` 地址未指定路径时使用的 gRPC 方法路径。
+#[cfg(feature = "h2")]
+const DEFAULT_GRPC_PATH: &str = "/npipe.Tunnel/Stream";
+
 // ─── 底层连接辅助函数 ─────────────────────────────────────────────────────────
 
 #[cfg(feature = "tcp")]
-async fn connect_with_tcp(request: &Uri) -> anyhow::Result<TcpStream> {
+async fn connect_with_tcp(
+    request: &Uri,
+    upstream_proxy: Option<&UpstreamProxy>,
+) -> anyhow::Result<TcpStream> {
     let host = request
         .host()
         .ok_or_else(|| anyhow!("invalid URI: missing host"))?;
     let port = request.port_u16().unwrap_or(80);
 
-    info!("connecting via TCP to {}:{}", host, port);
-    let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+    match upstream_proxy {
+        Some(proxy) => info!("connecting via TCP to {}:{} through {}", host, port, proxy),
+        None => info!("connecting via TCP to {}:{}", host, port),
+    }
+    let stream = super::upstream_proxy::connect_tcp(upstream_proxy, host, port).await?;
     info!("TCP connected to {}:{}", host, port);
 
     let ka = TcpKeepalive::new().with_time(Duration::from_secs(30));
@@ -63,6 +83,80 @@ async fn connect_with_tcp(request: &Uri) -> anyhow::Result<TcpStream> {
     Ok(stream)
 }
 
+/// 建立 WebSocket 底层的 TCP 连接，配置了上游代理时经由代理连接。
+#[cfg(feature = "ws")]
+async fn connect_ws_tcp(
+    request: &Uri,
+    upstream_proxy: Option<&UpstreamProxy>,
+) -> anyhow::Result<tokio::net::TcpStream> {
+    let host = request
+        .host()
+        .ok_or_else(|| anyhow!("invalid URI: missing host"))?;
+    let default_port = if request.scheme_str() == Some("wss") {
+        443
+    } else {
+        80
+    };
+    let port = request.port_u16().unwrap_or(default_port);
+    super::upstream_proxy::connect_tcp(upstream_proxy, host, port).await
+}
+
+/// WebSocket 握手请求的自定义 Host、请求头与子协议。
+///
+/// 请求路径取自服务端地址，如 `ws://example.com/api/stream`。
+#[cfg(feature = "ws")]
+struct WsRequestOptions {
+    host: Option<HeaderValue>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    subprotocol: Option<HeaderValue>,
+}
+
+#[cfg(feature = "ws")]
+impl WsRequestOptions {
+    fn from_args(common_args: &CommonArgs) -> anyhow::Result<Self> {
+        let optional = |value: &str| -> anyhow::Result<Option<HeaderValue>> {
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(HeaderValue::from_str(value)?))
+            }
+        };
+        let headers = common_args
+            .ws_header
+            .iter()
+            .map(|line| {
+                let (name, value) = line.split_once(':').ok_or_else(|| {
+                    anyhow!("invalid --ws-header '{}', expected 'Name: value'", line)
+                })?;
+                Ok((
+                    HeaderName::from_bytes(name.trim().as_bytes())?,
+                    HeaderValue::from_str(value.trim())?,
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            host: optional(&common_args.ws_host)?,
+            headers,
+            subprotocol: optional(&common_args.ws_subprotocol)?,
+        })
+    }
+
+    fn build(&self, request: &Uri) -> anyhow::Result<Request> {
+        let mut request = request.clone().into_client_request()?;
+        let headers = request.headers_mut();
+        if let Some(host) = &self.host {
+            headers.insert(header::HOST, host.clone());
+        }
+        for (name, value) in &self.headers {
+            headers.append(name.clone(), value.clone());
+        }
+        if let Some(subprotocol) = &self.subprotocol {
+            headers.insert(header::SEC_WEBSOCKET_PROTOCOL, subprotocol.clone());
+        }
+        Ok(request)
+    }
+}
+
 #[cfg(feature = "kcp")]
 async fn connect_with_kcp(request: &Uri) -> anyhow::Result<KcpStream> {
     let host = request
@@ -112,17 +206,34 @@ async fn connect_with_kcp(request: &Uri) -> anyhow::Result<KcpStream> {
 /// 根据 URI scheme 选择协议，构建 `ForwardConnector`，然后启动会话。
 pub async fn run(common_args: &CommonArgs, request: Uri) -> anyhow::Result<()> {
     info!("connecting to server: {}", request);
+    CLIENT_METRICS.on_connecting(&request, &common_args.username);
+
+    let upstream_proxy = if common_args.upstream_proxy.is_empty() {
+        None
+    } else {
+        if matches!(request.scheme_str(), Some("kcp") | Some("quic")) {
+            warn!(
+                "--upstream-proxy only applies to TCP, WS and HTTP/2, connecting to {} directly",
+                request
+            );
+        }
+        Some(Arc::new(UpstreamProxy::parse(&common_args.upstream_proxy)?))
+    };
 
     if common_args.enable_tls {
-        run_with_tls(common_args, request).await
+        run_with_tls(common_args, request, upstream_proxy).await
     } else {
-        run_without_tls(common_args, request).await
+        run_without_tls(common_args, request, upstream_proxy).await
     }
 }
 
 // ─── TLS 路径 ──────────────────────────────────────────────────────────────────
 
-async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<()> {
+async fn run_with_tls(
+    common_args: &CommonArgs,
+    request: Uri,
+    upstream_proxy: Option<Arc<UpstreamProxy>>,
+) -> anyhow::Result<()> {
     let mut root_cert_store = RootCertStore::empty();
     if !common_args.ca_cert.is_empty() {
         // 加载自定义 CA 证书
@@ -134,12 +245,30 @@ async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<
     root_cert_store.extend(TLS_SERVER_ROOTS.iter().cloned());
 
     // 创建TLS客户端配置
-    let mut config = ClientConfig::builder()
-        .with_root_certificates(root_cert_store)
-        .with_no_client_auth();
+    let builder = ClientConfig::builder().with_root_certificates(root_cert_store);
+    let mut config = if common_args.client_cert.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        // 加载客户端证书（mTLS），未指定私钥文件时从证书文件中读取
+        let certs = CertificateDer::pem_file_iter(&common_args.client_cert)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = PrivateKeyDer::from_pem_file(if common_args.client_key.is_empty() {
+            &common_args.client_cert
+        } else {
+            &common_args.client_key
+        })?;
+        builder.with_client_auth_cert(certs, key)?
+    };
 
-    // TLS 验证禁用
-    if common_args.insecure {
+    // 固定证书指纹时只校验指纹，QUIC 也使用同一配置
+    if !common_args.pin_sha256.is_empty() {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(super::tls::PinnedCertVerifier::new(
+                &common_args.pin_sha256,
+            )?));
+    } else if common_args.insecure {
+        // TLS 验证禁用
         config
             .dangerous()
             .set_certificate_verifier(Arc::new(super::tls::NoCertificateVerification::default()));
@@ -159,11 +288,13 @@ async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<
                     let request = request.clone();
                     let domain = domain.clone();
                     let config = config.clone();
+                    let upstream_proxy = upstream_proxy.clone();
                     Box::pin(async move {
                         let tls = TlsConnector::from(config);
+                        let stream = connect_with_tcp(&request, upstream_proxy.as_deref()).await?;
                         match timeout(
                             Duration::from_secs(TIMEOUT_TLS),
-                            tls.connect(domain, connect_with_tcp(&request).await?),
+                            tls.connect(domain, stream),
                         )
                         .await
                         {
@@ -209,18 +340,22 @@ async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<
             let request = Uri::from_str(&request.to_string().replace("ws://", "wss://"))?;
             info!("using WSS");
             let config = Arc::new(config);
+            let options = Arc::new(WsRequestOptions::from_args(common_args)?);
             let connector: ForwardConnector<_> = {
                 let request = request.clone();
                 let config = config.clone();
                 Arc::new(move || {
                     let request = request.clone();
                     let config = config.clone();
+                    let options = options.clone();
+                    let upstream_proxy = upstream_proxy.clone();
                     Box::pin(async move {
+                        let stream = connect_ws_tcp(&request, upstream_proxy.as_deref()).await?;
                         let tls_connector = tokio_tungstenite::Connector::Rustls(config);
-                        let (stream, _) = tokio_tungstenite::connect_async_tls_with_config(
-                            request,
+                        let (stream, _) = tokio_tungstenite::client_async_tls_with_config(
+                            options.build(&request)?,
+                            stream,
                             None,
-                            false,
                             Some(tls_connector),
                         )
                         .await?;
@@ -230,6 +365,11 @@ async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<
             };
             run_client(common_args, connector).await
         }
+        #[cfg(feature = "h2")]
+        Some("h2") | Some("grpc") => {
+            info!("using HTTP/2+TLS");
+            run_h2_client(common_args, &request, Some(config), upstream_proxy).await
+        }
         #[cfg(feature = "quic")]
         Some("quic") => {
             info!("using QUIC");
@@ -241,7 +381,11 @@ async fn run_with_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<
 
 // ─── 非 TLS 路径 ──────────────────────────────────────────────────────────────
 
-async fn run_without_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Result<()> {
+async fn run_without_tls(
+    common_args: &CommonArgs,
+    request: Uri,
+    upstream_proxy: Option<Arc<UpstreamProxy>>,
+) -> anyhow::Result<()> {
     match request.scheme_str() {
         #[cfg(feature = "tcp")]
         Some("tcp") => {
@@ -250,7 +394,10 @@ async fn run_without_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Resu
                 let request = request.clone();
                 Arc::new(move || {
                     let request = request.clone();
-                    Box::pin(async move { connect_with_tcp(&request).await })
+                    let upstream_proxy = upstream_proxy.clone();
+                    Box::pin(
+                        async move { connect_with_tcp(&request, upstream_proxy.as_deref()).await },
+                    )
                 })
             };
             run_client(common_args, connector).await
@@ -270,24 +417,95 @@ async fn run_without_tls(common_args: &CommonArgs, request: Uri) -> anyhow::Resu
         #[cfg(feature = "ws")]
         Some("ws") => {
             info!("using WS (no TLS)");
+            let options = Arc::new(WsRequestOptions::from_args(common_args)?);
             let connector: ForwardConnector<_> = {
                 let request = request.clone();
                 Arc::new(move || {
                     let request = request.clone();
+                    let options = options.clone();
+                    let upstream_proxy = upstream_proxy.clone();
                     Box::pin(async move {
-                        let (stream, _) = tokio_tungstenite::connect_async(request).await?;
+                        let stream = connect_ws_tcp(&request, upstream_proxy.as_deref()).await?;
+                        let (stream, _) =
+                            tokio_tungstenite::client_async(options.build(&request)?, stream)
+                                .await?;
                         Ok(WebSocketAsyncIo::new(stream))
                     })
                 })
             };
             run_client(common_args, connector).await
         }
+        #[cfg(feature = "h2")]
+        Some("h2") | Some("grpc") => {
+            info!("using HTTP/2 (no TLS)");
+            run_h2_client(common_args, &request, None, upstream_proxy).await
+        }
         #[cfg(feature = "quic")]
         Some("quic") => Err(anyhow!("QUIC requires TLS (--enable-tls)")),
         _ => Err(anyhow!("unsupported URL scheme: {}", request)),
     }
 }
 
+// ─── HTTP/2 客户端 ────────────────────────────────────────────────────────────
+
+/// 建立一条 HTTP/2 连接，每个转发连接是连接上的一条流。
+///
+/// `grpc://` 以 gRPC 双向流的形式收发数据，`h2://` 直接使用请求与响应的 body。
+/// 未启用 TLS 时使用 h2c（prior knowledge）。
+#[cfg(feature = "h2")]
+async fn run_h2_client(
+    common_args: &CommonArgs,
+    request: &Uri,
+    config: Option<ClientConfig>,
+    upstream_proxy: Option<Arc<UpstreamProxy>>,
+) -> anyhow::Result<()> {
+    let grpc = request.scheme_str() == Some("grpc");
+    let host = request
+        .host()
+        .ok_or_else(|| anyhow!("invalid URI: missing host"))?;
+    let port = request
+        .port_u16()
+        .unwrap_or(if config.is_some() { 443 } else { 80 });
+    let authority = request
+        .authority()
+        .map_or(host, |authority| authority.as_str());
+    let path = match request.path() {
+        "/" if grpc => DEFAULT_GRPC_PATH,
+        path => path,
+    };
+
+    info!("connecting via HTTP/2 to {}:{}", host, port);
+    let stream = super::upstream_proxy::connect_tcp(upstream_proxy.as_deref(), host, port).await?;
+
+    let client = match config {
+        Some(mut config) => {
+            // 经由 CDN 或反向代理时需要通过 ALPN 协商 h2
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            let domain = super::tls_server_name(common_args, request)?;
+            let tls = TlsConnector::from(Arc::new(config));
+            let stream = timeout(
+                Duration::from_secs(TIMEOUT_TLS),
+                tls.connect(domain, stream),
+            )
+            .await
+            .map_err(|_| anyhow!("TLS handshake timeout ({}s)", TIMEOUT_TLS))??;
+            H2Client::handshake(stream, true, authority, path, grpc).await?
+        }
+        None => H2Client::handshake(stream, false, authority, path, grpc).await?,
+    };
+
+    info!("HTTP/2 connected to {}:{}", host, port);
+
+    // 构建 connector 闭包：在同一 HTTP/2 连接上打开新的流
+    let client = Arc::new(client);
+    let connector: ForwardConnector<_> = Arc::new(move || {
+        let client = client.clone();
+        Box::pin(async move { client.open_stream().await })
+    });
+
+    run_client(common_args, connector).await
+}
+
 // ─── QUIC 客户端 ──────────────────────────────────────────────────────────────
 
 #[cfg(feature = "quic")]
@@ -378,12 +596,18 @@ where
         transport,
         username: common_args.username.clone(),
         password: common_args.password.clone(),
+        legacy_login: common_args.legacy_login,
+        // 配置了客户端证书且未提供密码时使用证书登录
+        certificate_login: common_args.enable_tls
+            && !common_args.client_cert.is_empty()
+            && common_args.password.is_empty(),
         transport_max_connections: common_args.transport_max_connections,
         transport_idle_timeout_secs: common_args.transport_idle_timeout_secs,
         player_id: 0,
         outlets: Arc::new(DashMap::new()),
         inlets: Arc::new(DashMap::new()),
         tunnels: HashMap::new(),
+        rate_limiters: RateLimiterRegistry::new(),
     };
 
     session
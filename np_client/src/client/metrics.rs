@@ -0,0 +1,197 @@
+//! 本地 Prometheus 指标接口：连接状态与本机入口/出口的会话数量。
+
+use dashmap::DashMap;
+use http::Uri;
+use log::{error, info};
+use np_base::metrics::{tunnel_type_label, MetricsWriter, METRICS_CONTENT_TYPE};
+use np_base::proxy::inlet::Inlet;
+use np_base::proxy::outlet::Outlet;
+use np_proto::class_def::Tunnel;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 全局客户端指标，跨重连保留计数。
+pub(crate) static CLIENT_METRICS: Lazy<ClientMetrics> = Lazy::new(ClientMetrics::default);
+
+type OutletMap = Arc<DashMap<u32, Arc<Outlet>>>;
+type InletMap = Arc<DashMap<u32, Inlet>>;
+
+#[derive(Default)]
+pub(crate) struct ClientMetrics {
+    /// 当前连接的服务端地址与协议（URI scheme）。
+    server: Mutex<(String, String)>,
+    /// 登录用户名。
+    username: Mutex<String>,
+    /// 是否已登录。
+    connected: AtomicBool,
+    /// 登录成功次数。
+    logins: AtomicU64,
+    /// 会话断开次数。
+    disconnects: AtomicU64,
+    /// 当前会话的入口、出口集合。
+    proxies: Mutex<Option<(InletMap, OutletMap)>>,
+    /// 本机托管的隧道类型，key 为隧道 ID。
+    tunnel_types: Mutex<HashMap<u32, u32>>,
+}
+
+impl ClientMetrics {
+    /// 开始连接服务端。
+    pub(crate) fn on_connecting(&self, request: &Uri, username: &str) {
+        let protocol = request.scheme_str().unwrap_or("tcp").to_string();
+        *self.server.lock().unwrap() = (request.to_string(), protocol);
+        *self.username.lock().unwrap() = username.to_string();
+    }
+
+    /// 登录成功，登记当前会话的入口、出口集合。
+    pub(crate) fn on_login(&self, inlets: InletMap, outlets: OutletMap) {
+        *self.proxies.lock().unwrap() = Some((inlets, outlets));
+        self.connected.store(true, Ordering::Relaxed);
+        self.logins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 隧道同步完成，记录本机托管的隧道。
+    pub(crate) fn on_tunnels_synced(&self, tunnels: &[&Tunnel]) {
+        *self.tunnel_types.lock().unwrap() = tunnels
+            .iter()
+            .map(|tunnel| (tunnel.id, tunnel.tunnel_type as u32))
+            .collect();
+    }
+
+    /// 会话结束。
+    pub(crate) fn on_disconnect(&self) {
+        if self.connected.swap(false, Ordering::Relaxed) {
+            self.disconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        self.proxies.lock().unwrap().take();
+        self.tunnel_types.lock().unwrap().clear();
+    }
+
+    fn render(&self) -> String {
+        let (server, protocol) = self.server.lock().unwrap().clone();
+        let username = self.username.lock().unwrap().clone();
+        let tunnel_types = self.tunnel_types.lock().unwrap().clone();
+
+        let mut sessions: Vec<(u32, &'static str, usize)> = Vec::new();
+        if let Some((inlets, outlets)) = self.proxies.lock().unwrap().as_ref() {
+            sessions.extend(
+                inlets
+                    .iter()
+                    .map(|entry| (*entry.key(), "inlet", entry.value().session_count())),
+            );
+            sessions.extend(
+                outlets
+                    .iter()
+                    .map(|entry| (*entry.key(), "outlet", entry.value().session_count())),
+            );
+        }
+        sessions.sort();
+
+        let mut writer = MetricsWriter::new();
+        writer
+            .family(
+                "npipe_client_connected",
+                "gauge",
+                "Whether the client is logged in to the server",
+            )
+            .sample(
+                "npipe_client_connected",
+                &[
+                    ("user", &username),
+                    ("server", &server),
+                    ("protocol", &protocol),
+                ],
+                self.connected.load(Ordering::Relaxed) as u8,
+            );
+        writer.single(
+            "npipe_client_logins_total",
+            "counter",
+            "Number of successful logins since client start",
+            self.logins.load(Ordering::Relaxed),
+        );
+        writer.single(
+            "npipe_client_disconnects_total",
+            "counter",
+            "Number of lost server sessions since client start",
+            self.disconnects.load(Ordering::Relaxed),
+        );
+        writer.single(
+            "npipe_client_tunnels",
+            "gauge",
+            "Number of enabled tunnels with an inlet or outlet on this client",
+            tunnel_types.len(),
+        );
+        writer.family(
+            "npipe_client_tunnel_sessions",
+            "gauge",
+            "Number of active sessions of tunnel inlets/outlets on this client",
+        );
+        for (tunnel_id, side, count) in sessions {
+            let tunnel_type = tunnel_types
+                .get(&tunnel_id)
+                .map_or("", |tunnel_type| tunnel_type_label(*tunnel_type));
+            writer.sample(
+                "npipe_client_tunnel_sessions",
+                &[
+                    ("tunnel", &tunnel_id.to_string()),
+                    ("type", tunnel_type),
+                    ("side", side),
+                ],
+                count,
+            );
+        }
+        writer.finish()
+    }
+}
+
+/// 启动本地指标监听，只响应 `GET /metrics`。
+pub async fn run_metrics_server(addr: String) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    info!("metrics listening on http://{}/metrics", addr);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(err) = handle_connection(stream).await {
+                error!("metrics connection error: {}", err);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) -> anyhow::Result<()> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    // 只需要请求头，最多读取 8KB
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < 8192 {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let request_line = String::from_utf8_lossy(&buf);
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = if method == "GET" && path == "/metrics" {
+        ("200 OK", METRICS_CONTENT_TYPE, CLIENT_METRICS.render())
+    } else {
+        ("404 Not Found", "text/plain", "not found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -2,15 +2,19 @@
 
 mod connect;
 mod io;
+mod metrics;
 mod session;
 mod tls;
 mod transport;
+mod upstream_proxy;
 
 use crate::CommonArgs;
 use anyhow::anyhow;
 pub use connect::run;
 use http::Uri;
+pub use metrics::run_metrics_server;
 use std::time::{SystemTime, UNIX_EPOCH};
+pub use upstream_proxy::UpstreamProxy;
 
 /// 当前 Unix 时间戳（秒）。
 #[inline(always)]
@@ -22,7 +26,7 @@ pub(super) fn now_secs() -> u64 {
 }
 
 /// 从命令行参数或请求 URI 解析 TLS 服务端名称。
-#[cfg(any(feature = "tcp", feature = "kcp", feature = "quic"))]
+#[cfg(any(feature = "tcp", feature = "kcp", feature = "h2", feature = "quic"))]
 pub(super) fn tls_server_name(
     common_args: &CommonArgs,
     request: &Uri,
@@ -1,18 +1,30 @@
 //! 客户端会话：登录、事件循环、隧道同步与代理消息路由。
 
 use anyhow::anyhow;
+use argon2::{Algorithm, Argon2, Params, Version};
 use byteorder::{BigEndian, ByteOrder};
 use dashmap::DashMap;
 use log::{debug, error, info, warn};
 use np_base::proxy::inlet::{Inlet, InletDataEx, InletProxyType};
-use np_base::proxy::outlet::Outlet;
+use np_base::proxy::outlet::{Outlet, OutletDataEx};
+use np_base::proxy::rate_limit::{RateLimiterRegistry, RateLimiters};
+use np_base::proxy::SessionSnapshot;
 use np_base::proxy::{OutputFuncType, ProxyMessage};
-use np_proto::class_def::{Tunnel, TunnelPoint};
-use np_proto::client_server::LoginReq;
+use np_proto::class_def::{SessionInfo, Tunnel, TunnelPoint};
+use np_proto::client_server::{LoginChallengeReq, LoginReq, SessionCloseAck, SessionListAck};
+use np_proto::generic::ErrorCode;
 use np_proto::message_map::{self, MessageType};
-use np_proto::server_client::ModifyTunnelNtf;
+use np_proto::server_client::{
+    LoginChallengeAck, ModifyTunnelNtf, SessionCloseReq, SessionListReq,
+};
+use np_proto::utils::login::{
+    login_proof, verify_server_signature, LOGIN_KEY_LEN, LOGIN_MAX_M_COST, LOGIN_MAX_P_COST,
+    LOGIN_MAX_T_COST, LOGIN_PROTOCOL_VERSION_CHALLENGE,
+};
 use np_proto::utils::message_bridge;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use std::time::Duration;
@@ -21,6 +33,7 @@ use tokio::select;
 use tokio::sync::mpsc;
 
 use super::io::ping_forever;
+use super::metrics::CLIENT_METRICS;
 use super::transport::{ClientTransport, IncomingFrame, TransportEvent};
 
 /// 客户端会话状态：登录、隧道同步与代理消息路由。
@@ -36,6 +49,10 @@ where
     pub username: String,
     /// 登录密码。
     pub password: String,
+    /// 使用旧版明文密码登录（兼容不支持挑战应答登录的服务端）。
+    pub legacy_login: bool,
+    /// 使用 TLS 客户端证书登录，不发送密码。
+    pub certificate_login: bool,
     /// 客户端期望的最大转发连接数（上报至 LoginReq）。
     pub transport_max_connections: u32,
     /// 客户端期望的转发连接空闲超时秒数（上报至 LoginReq）。
@@ -48,11 +65,31 @@ where
     pub inlets: Arc<DashMap<u32, Inlet>>,
     /// 服务端下发的隧道快照，key 为隧道 ID。
     pub tunnels: HashMap<u32, Tunnel>,
+    /// 按隧道、用户共享的带宽限速器。
+    pub rate_limiters: RateLimiterRegistry,
 }
 
 /// 登录超时时间（秒）。
 const LOGIN_TIMEOUT_SECS: u64 = 30;
 
+/// 服务端不支持挑战应答登录（旧版服务端无法识别 LoginChallengeReq）。
+///
+/// 该回复未经认证，可能被链路上的攻击者伪造，因此不会自动退回明文密码登录。
+#[derive(Debug)]
+pub struct ChallengeLoginUnsupported;
+
+impl std::fmt::Display for ChallengeLoginUnsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "server does not support challenge-response login, \
+             pass --legacy-login to use the plaintext password login"
+        )
+    }
+}
+
+impl std::error::Error for ChallengeLoginUnsupported {}
+
 impl<S> ClientSession<S>
 where
     S: AsyncRead + AsyncWrite + Send + 'static,
@@ -80,6 +117,7 @@ where
 
         // 2. 发送登录并等待回复
         self.login_with_timeout(&mut event_rx).await?;
+        CLIENT_METRICS.on_login(self.inlets.clone(), self.outlets.clone());
 
         // 3. 预热转发路径
         self.transport.warm_up().await;
@@ -101,29 +139,156 @@ where
 
         // 5. 清理隧道
         self.sync_tunnels(&[]).await;
+        CLIENT_METRICS.on_disconnect();
         result
     }
 
     /// 发送登录请求并在超时时间内等待服务端回复。
+    ///
+    /// 默认先请求登录挑战，使用服务端下发的盐值计算应答，密码不在链路上传输，
+    /// 服务端不认识挑战请求时返回 `ChallengeLoginUnsupported`；
+    /// `legacy_login` 为 true 时直接使用旧版明文密码登录；
+    /// `certificate_login` 为 true 时由服务端根据客户端证书确定用户，不需要挑战。
     async fn login_with_timeout(
         &mut self,
         event_rx: &mut mpsc::UnboundedReceiver<TransportEvent>,
     ) -> anyhow::Result<()> {
+        // 挑战登录使用的 Argon2id 输出与随机数，用于校验服务端签名
+        let mut challenge_key = None;
+        let login_req = if self.certificate_login {
+            LoginReq {
+                version: "0.0.0".to_string(),
+                username: self.username.clone(),
+                password: String::new(),
+                transport_max_connections: self.transport_max_connections,
+                transport_idle_timeout_secs: self.transport_idle_timeout_secs,
+                protocol_version: LOGIN_PROTOCOL_VERSION_CHALLENGE,
+                proof: Vec::new(),
+            }
+        } else if self.legacy_login {
+            LoginReq {
+                version: "0.0.0".to_string(),
+                username: self.username.clone(),
+                password: self.password.clone(),
+                transport_max_connections: self.transport_max_connections,
+                transport_idle_timeout_secs: self.transport_idle_timeout_secs,
+                protocol_version: 0,
+                proof: Vec::new(),
+            }
+        } else {
+            info!("sending login challenge request");
+            self.transport
+                .send_control_message(
+                    -1,
+                    &MessageType::ClientServerLoginChallengeReq(LoginChallengeReq {
+                        protocol_version: LOGIN_PROTOCOL_VERSION_CHALLENGE,
+                        username: self.username.clone(),
+                    }),
+                )
+                .await?;
+
+            let challenge = match Self::decode_login_frame(Self::recv_login_frame(event_rx).await?)?
+            {
+                MessageType::ServerClientLoginChallengeAck(msg) => msg,
+                // 旧版服务端回复接口不存在或消息解码失败，随后关闭连接
+                MessageType::GenericError(err)
+                    if err.number == i32::from(ErrorCode::InterfaceAbsent)
+                        || (err.number == i32::from(ErrorCode::InternalError)
+                            && err.message.contains("unknown message id")) =>
+                {
+                    return Err(ChallengeLoginUnsupported.into())
+                }
+                MessageType::GenericError(err) => {
+                    return Err(anyhow!(
+                        "login failed: {} (code={})",
+                        err.message,
+                        err.number
+                    ))
+                }
+                _ => return Err(anyhow!("login failed: unexpected message type")),
+            };
+
+            let nonce = challenge.nonce.clone();
+            let salted_password = self.compute_salted_password(challenge).await?;
+            let proof = login_proof(&salted_password, &nonce, &self.username);
+            challenge_key = Some((salted_password, nonce));
+            LoginReq {
+                version: "0.0.0".to_string(),
+                username: self.username.clone(),
+                password: String::new(),
+                transport_max_connections: self.transport_max_connections,
+                transport_idle_timeout_secs: self.transport_idle_timeout_secs,
+                protocol_version: LOGIN_PROTOCOL_VERSION_CHALLENGE,
+                proof,
+            }
+        };
+
         info!("sending login request");
         self.transport
-            .send_control_message(
-                -1,
-                &MessageType::ClientServerLoginReq(LoginReq {
-                    version: "0.0.0".to_string(),
-                    username: self.username.clone(),
-                    password: self.password.clone(),
-                    transport_max_connections: self.transport_max_connections,
-                    transport_idle_timeout_secs: self.transport_idle_timeout_secs,
-                }),
-            )
+            .send_control_message(-1, &MessageType::ClientServerLoginReq(login_req))
             .await?;
 
-        // 等待登录响应
+        let message = Self::decode_login_frame(Self::recv_login_frame(event_rx).await?)?;
+        // 挑战登录成功时确认服务端持有本用户的口令校验值
+        if let (Some((salted_password, nonce)), MessageType::ServerClientLoginAck(msg)) =
+            (&challenge_key, &message)
+        {
+            if !verify_server_signature(
+                salted_password,
+                nonce,
+                &self.username,
+                &msg.server_signature,
+            ) {
+                return Err(anyhow!("login failed: invalid server signature"));
+            }
+        }
+        self.handle_login_message(message).await
+    }
+
+    /// 根据登录挑战计算 Argon2id 输出，Argon2 计算较慢，放到阻塞线程中执行。
+    ///
+    /// 服务端下发的参数超过上限时直接失败，不会按过大的参数分配内存。
+    async fn compute_salted_password(
+        &self,
+        challenge: LoginChallengeAck,
+    ) -> anyhow::Result<[u8; LOGIN_KEY_LEN]> {
+        if challenge.m_cost > LOGIN_MAX_M_COST
+            || challenge.t_cost > LOGIN_MAX_T_COST
+            || challenge.p_cost > LOGIN_MAX_P_COST
+        {
+            return Err(anyhow!(
+                "login failed: server requested excessive argon2 params (m_cost={}, t_cost={}, p_cost={}, max {}/{}/{})",
+                challenge.m_cost,
+                challenge.t_cost,
+                challenge.p_cost,
+                LOGIN_MAX_M_COST,
+                LOGIN_MAX_T_COST,
+                LOGIN_MAX_P_COST
+            ));
+        }
+
+        let password = self.password.clone();
+        tokio::task::spawn_blocking(move || {
+            let params = Params::new(
+                challenge.m_cost,
+                challenge.t_cost,
+                challenge.p_cost,
+                Some(LOGIN_KEY_LEN),
+            )
+            .map_err(|e| anyhow!("invalid login challenge params: {}", e))?;
+            let mut key = [0u8; LOGIN_KEY_LEN];
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), &challenge.salt, &mut key)
+                .map_err(|e| anyhow!("failed to derive login key: {}", e))?;
+            Ok(key)
+        })
+        .await?
+    }
+
+    /// 在超时时间内等待控制连接上的下一个登录响应帧。
+    async fn recv_login_frame(
+        event_rx: &mut mpsc::UnboundedReceiver<TransportEvent>,
+    ) -> anyhow::Result<IncomingFrame> {
         let timeout_duration = Duration::from_secs(LOGIN_TIMEOUT_SECS);
         loop {
             let event = tokio::time::timeout(timeout_duration, event_rx.recv())
@@ -138,7 +303,7 @@ where
                         debug!("ignoring forward path frame during login");
                         continue;
                     }
-                    return self.handle_login_frame(incoming).await;
+                    return Ok(incoming);
                 }
                 TransportEvent::Closed { path_id, reason } => {
                     if path_id.is_none() {
@@ -154,8 +319,8 @@ where
         }
     }
 
-    /// 解析登录响应帧，成功则配置传输层。
-    async fn handle_login_frame(&mut self, incoming: IncomingFrame) -> anyhow::Result<()> {
+    /// 解析登录响应帧。
+    fn decode_login_frame(incoming: IncomingFrame) -> anyhow::Result<MessageType> {
         let frame = incoming.frame;
         if frame.len() < 8 {
             return Err(anyhow!("login response frame too short"));
@@ -168,7 +333,11 @@ where
         if serial <= 0 {
             return Err(anyhow!("login failed: unexpected serial={}", serial));
         }
+        Ok(message)
+    }
 
+    /// 处理登录响应，成功则配置传输层。
+    async fn handle_login_message(&mut self, message: MessageType) -> anyhow::Result<()> {
         match message {
             MessageType::ServerClientLoginAck(msg) => {
                 info!("login successful, player_id={}", msg.player_id);
@@ -278,6 +447,12 @@ where
             MessageType::ServerClientModifyTunnelNtf(msg) => {
                 self.on_modify_tunnel_ntf(msg).await;
             }
+            MessageType::ServerClientSessionListReq(msg) => {
+                self.on_session_list_req(msg).await?;
+            }
+            MessageType::ServerClientSessionCloseReq(msg) => {
+                self.on_session_close_req(msg).await?;
+            }
             _ => {
                 if let Some((proxy_msg, tunnel_id)) = message_bridge::pb_2_proxy_message(message) {
                     if let Some(tunnel) = self.tunnels.get(&tunnel_id) {
@@ -318,10 +493,82 @@ where
         }
     }
 
+    /// 上报本机入口、出口的活跃会话。
+    async fn on_session_list_req(&self, msg: SessionListReq) -> anyhow::Result<()> {
+        let to_info = |snapshot: SessionSnapshot, is_inlet: bool| SessionInfo {
+            tunnel_id: msg.tunnel_id,
+            session_id: snapshot.session_id,
+            is_inlet,
+            client_addr: snapshot.client_addr,
+            target_addr: snapshot.target_addr,
+            start_time: snapshot.start_time,
+            bytes_in: snapshot.bytes_in,
+            bytes_out: snapshot.bytes_out,
+        };
+        let mut sessions = Vec::new();
+        if let Some(inlet) = self.inlets.get(&msg.tunnel_id) {
+            sessions.extend(inlet.sessions().into_iter().map(|s| to_info(s, true)));
+        }
+        if let Some(outlet) = self.outlets.get(&msg.tunnel_id) {
+            sessions.extend(outlet.sessions().into_iter().map(|s| to_info(s, false)));
+        }
+        self.transport
+            .send_control_message(
+                0,
+                &MessageType::ClientServerSessionListAck(SessionListAck {
+                    request_id: msg.request_id,
+                    sessions,
+                }),
+            )
+            .await
+    }
+
+    /// 强制关闭本机入口或出口的会话。
+    async fn on_session_close_req(&self, msg: SessionCloseReq) -> anyhow::Result<()> {
+        let closed = if msg.is_inlet {
+            self.inlets
+                .get(&msg.tunnel_id)
+                .is_some_and(|inlet| inlet.close_session(msg.session_id))
+        } else {
+            self.outlets
+                .get(&msg.tunnel_id)
+                .is_some_and(|outlet| outlet.close_session(msg.session_id))
+        };
+        if closed {
+            info!(
+                "session {} of tunnel {} closed by server",
+                msg.session_id, msg.tunnel_id
+            );
+        }
+        self.transport
+            .send_control_message(
+                0,
+                &MessageType::ClientServerSessionCloseAck(SessionCloseAck {
+                    request_id: msg.request_id,
+                    closed,
+                }),
+            )
+            .await
+    }
+
     // ─── 隧道同步 ──────────────────────────────────────────────────────────────
 
     /// 根据隧道列表同步入口和出口。
     pub async fn sync_tunnels(&mut self, tunnels: &[Tunnel]) {
+        // 限速变更原地生效，无需重启入口和出口
+        let active: Vec<&Tunnel> = tunnels
+            .iter()
+            .filter(|t| t.enabled && (t.sender == self.player_id || t.receiver == self.player_id))
+            .collect();
+        for tunnel in &active {
+            self.tunnel_rate_limiters(tunnel);
+        }
+        self.rate_limiters.retain(
+            &active.iter().map(|t| t.id).collect::<Vec<_>>(),
+            &active.iter().map(|t| t.receiver).collect::<Vec<_>>(),
+        );
+        CLIENT_METRICS.on_tunnels_synced(&active);
+
         // 收集无效的出口
         let keys_to_remove: Vec<u32> = self
             .outlets
@@ -414,7 +661,14 @@ where
                 debug!("+ outlet({})", outlet_description(tunnel));
                 self.outlets.insert(
                     tunnel_id,
-                    Outlet::new(outlet_output, outlet_description(tunnel)),
+                    Outlet::new(
+                        outlet_output,
+                        outlet_description(tunnel),
+                        self.tunnel_rate_limiters(tunnel),
+                        OutletDataEx::new()
+                            .with_proxy_protocol(tunnel.proxy_protocol)
+                            .with_access_list(&tunnel.acl_allow, &tunnel.acl_deny),
+                    ),
                 );
             }
         }
@@ -475,7 +729,11 @@ where
                         tunnel.tunnel_type, source
                     );
                 } else {
-                    let mut inlet = Inlet::new(inlet_output, inlet_description(tunnel));
+                    let mut inlet = Inlet::new(
+                        inlet_output,
+                        inlet_description(tunnel),
+                        self.tunnel_rate_limiters(tunnel),
+                    );
                     if let Err(err) = inlet
                         .start(
                             inlet_proxy_type,
@@ -483,7 +741,18 @@ where
                             endpoint.clone(),
                             tunnel.is_compressed,
                             tunnel.encryption_method.clone(),
-                            InletDataEx::new(tunnel.username.clone(), tunnel.password.clone()),
+                            InletDataEx::new(tunnel.username.clone(), tunnel.password.clone())
+                                .with_custom_mapping(tunnel.custom_mapping.clone())
+                                .with_tls_certificates(
+                                    tunnel
+                                        .tls_certificates
+                                        .iter()
+                                        .map(|cert| {
+                                            (cert.certificate.clone(), cert.private_key.clone())
+                                        })
+                                        .collect(),
+                                )
+                                .with_access_list(&tunnel.acl_allow, &tunnel.acl_deny),
                         )
                         .await
                     {
@@ -497,6 +766,19 @@ where
         }
     }
 
+    /// 隧道的带宽限速器：隧道限速 + 接收方用户限速。
+    fn tunnel_rate_limiters(&self, tunnel: &Tunnel) -> RateLimiters {
+        RateLimiters::new(vec![
+            self.rate_limiters
+                .tunnel(tunnel.id, tunnel.upload_limit, tunnel.download_limit),
+            self.rate_limiters.user(
+                tunnel.receiver,
+                tunnel.user_upload_limit,
+                tunnel.user_download_limit,
+            ),
+        ])
+    }
+
     // ─── 代理消息路由 ──────────────────────────────────────────────────────────
 
     /// 路由代理消息：本机直接投递，远端通过传输层发送。
@@ -541,8 +823,13 @@ fn fmt_point(point: &Option<TunnelPoint>) -> String {
 
 fn outlet_description(tunnel: &Tunnel) -> String {
     format!(
-        "tunnel#{} sender:{} enabled:{}",
-        tunnel.id, tunnel.sender, tunnel.enabled
+        "tunnel#{} sender:{} enabled:{} proxy_protocol:{} acl:{:?}/{:?}",
+        tunnel.id,
+        tunnel.sender,
+        tunnel.enabled,
+        tunnel.proxy_protocol,
+        tunnel.acl_allow,
+        tunnel.acl_deny
     )
 }
 
@@ -552,6 +839,10 @@ fn fmt_tunnel_type(t: i32) -> &'static str {
         1 => "udp",
         2 => "socks5",
         3 => "http",
+        4 => "socks4",
+        5 => "mixed",
+        6 => "transparent",
+        7 => "vhost",
         _ => "unknown",
     }
 }
@@ -567,9 +858,15 @@ fn inlet_description(tunnel: &Tunnel) -> String {
             .collect::<Vec<_>>()
             .join(", ")
     };
+    // 证书只记录摘要，既能识别证书变化又不会把私钥写进日志
+    let mut hasher = DefaultHasher::new();
+    for cert in &tunnel.tls_certificates {
+        cert.certificate.hash(&mut hasher);
+        cert.private_key.hash(&mut hasher);
+    }
 
     format!(
-        "tunnel#{}[{}] {} -> {} sender:{} receiver:{} enabled:{} compressed:{} encrypt:{} auth:{}:{} mapping:[{}]",
+        "tunnel#{}[{}] {} -> {} sender:{} receiver:{} enabled:{} compressed:{} encrypt:{} auth:{}:{} mapping:[{}] tls:{}/{:x} acl:{:?}/{:?}",
         tunnel.id,
         fmt_tunnel_type(tunnel.tunnel_type),
         fmt_point(&tunnel.source),
@@ -582,5 +879,9 @@ fn inlet_description(tunnel: &Tunnel) -> String {
         tunnel.username,
         tunnel.password,
         custom_mapping,
+        tunnel.tls_certificates.len(),
+        hasher.finish(),
+        tunnel.acl_allow,
+        tunnel.acl_deny,
     )
 }
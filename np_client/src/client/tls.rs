@@ -1,11 +1,16 @@
+use anyhow::anyhow;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use log::warn;
 use rustls::crypto::aws_lc_rs as provider;
+use sha2::{Digest, Sha256};
 use tokio_rustls::rustls;
 use tokio_rustls::rustls::client::danger::{
     HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
 };
 use tokio_rustls::rustls::crypto::CryptoProvider;
 use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
-use tokio_rustls::rustls::DigitallySignedStruct;
+use tokio_rustls::rustls::{CertificateError, DigitallySignedStruct};
 
 /// 跳过服务端证书验证的验证器。
 ///
@@ -67,3 +72,121 @@ impl ServerCertVerifier for NoCertificateVerification {
         self.0.signature_verification_algorithms.supported_schemes()
     }
 }
+
+/// 按 SHA-256 指纹固定服务端证书的验证器。
+///
+/// 服务端证书（链中的第一张）的公钥（SPKI）或整张证书的指纹与任一固定指纹一致即通过，
+/// 不再校验 CA、域名与有效期。固定公钥指纹时，服务端续期证书只要沿用原私钥就不受影响。
+#[derive(Debug)]
+pub struct PinnedCertVerifier {
+    provider: CryptoProvider,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedCertVerifier {
+    /// `pins` 为逗号分隔的指纹，每个指纹为十六进制（可带冒号）或 base64，可带 `sha256/` 前缀
+    pub fn new(pins: &str) -> anyhow::Result<Self> {
+        let pins = pins
+            .split(',')
+            .map(str::trim)
+            .filter(|pin| !pin.is_empty())
+            .map(parse_pin)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if pins.is_empty() {
+            return Err(anyhow!("no certificate fingerprint configured"));
+        }
+        Ok(Self {
+            provider: provider::default_provider(),
+            pins,
+        })
+    }
+}
+
+fn parse_pin(pin: &str) -> anyhow::Result<[u8; 32]> {
+    let value = pin.strip_prefix("sha256/").unwrap_or(pin);
+    let hex: String = value.chars().filter(|c| *c != ':').collect();
+    let bytes = if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        (0..64)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        STANDARD
+            .decode(value)
+            .map_err(|_| anyhow!("invalid sha256 fingerprint '{pin}'"))?
+    };
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("invalid sha256 fingerprint '{pin}'"))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let cert = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let spki_hash: [u8; 32] = Sha256::digest(cert.subject_public_key_info().as_ref()).into();
+        let cert_hash: [u8; 32] = Sha256::digest(end_entity.as_ref()).into();
+
+        if self
+            .pins
+            .iter()
+            .any(|pin| *pin == spki_hash || *pin == cert_hash)
+        {
+            return Ok(ServerCertVerified::assertion());
+        }
+        warn!(
+            "server certificate does not match the pinned fingerprints, spki sha256: {} ({}), certificate sha256: {}",
+            to_hex(&spki_hash),
+            STANDARD.encode(spki_hash),
+            to_hex(&cert_hash)
+        );
+        Err(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        ))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
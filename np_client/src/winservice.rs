@@ -199,8 +199,18 @@ pub fn install_service(common_args: CommonArgs) -> anyhow::Result<()> {
         OsString::from(format!("--base-log-level={}", common_args.base_log_level)),
         OsString::from(format!("--log-dir={}", common_args.log_dir)),
         OsString::from(format!("--ca-cert={}", common_args.ca_cert)),
+        OsString::from(format!("--pin-sha256={}", common_args.pin_sha256)),
+        OsString::from(format!("--client-cert={}", common_args.client_cert)),
+        OsString::from(format!("--client-key={}", common_args.client_key)),
         OsString::from(format!("--tls-server-name={}", common_args.tls_server_name)),
+        OsString::from(format!("--metrics-addr={}", common_args.metrics_addr)),
+        OsString::from(format!("--upstream-proxy={}", common_args.upstream_proxy)),
+        OsString::from(format!("--ws-host={}", common_args.ws_host)),
+        OsString::from(format!("--ws-subprotocol={}", common_args.ws_subprotocol)),
     ];
+    for header in &common_args.ws_header {
+        service_binary_arguments.push(OsString::from(format!("--ws-header={}", header)));
+    }
 
     if common_args.enable_tls {
         service_binary_arguments.push(OsString::from("--enable-tls"));
@@ -211,6 +221,9 @@ pub fn install_service(common_args: CommonArgs) -> anyhow::Result<()> {
     if common_args.quiet {
         service_binary_arguments.push(OsString::from("--quiet"));
     }
+    if common_args.legacy_login {
+        service_binary_arguments.push(OsString::from("--legacy-login"));
+    }
 
     // Run the current service as `System` type
     let service_info = ServiceInfo {
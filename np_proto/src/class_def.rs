@@ -52,6 +52,72 @@ pub struct Tunnel {
         ::prost::alloc::string::String,
         ::prost::alloc::string::String,
     >,
+    /// 通道上行限速（字节/秒，0为不限速）
+    #[prost(uint64, tag = "13")]
+    pub upload_limit: u64,
+    /// 通道下行限速（字节/秒，0为不限速）
+    #[prost(uint64, tag = "14")]
+    pub download_limit: u64,
+    /// 接收方用户上行限速（字节/秒，0为不限速）
+    #[prost(uint64, tag = "15")]
+    pub user_upload_limit: u64,
+    /// 接收方用户下行限速（字节/秒，0为不限速）
+    #[prost(uint64, tag = "16")]
+    pub user_download_limit: u64,
+    /// 入口终止 TLS 使用的证书，按 SNI 选择
+    #[prost(message, repeated, tag = "17")]
+    pub tls_certificates: ::prost::alloc::vec::Vec<TlsCertificate>,
+    /// 出口连接目标时发送的 PROXY protocol 版本（0为不发送，1为v1，2为v2）
+    #[prost(uint32, tag = "18")]
+    pub proxy_protocol: u32,
+    /// 代理类通道允许访问的目标（域名、网段、端口），为空时不限制
+    #[prost(string, repeated, tag = "19")]
+    pub acl_allow: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// 代理类通道禁止访问的目标，优先于允许列表
+    #[prost(string, repeated, tag = "20")]
+    pub acl_deny: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// TLS 证书
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TlsCertificate {
+    /// PEM 证书链
+    #[prost(string, tag = "1")]
+    pub certificate: ::prost::alloc::string::String,
+    /// PEM 私钥
+    #[prost(string, tag = "2")]
+    pub private_key: ::prost::alloc::string::String,
+}
+/// 活跃会话
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionInfo {
+    /// 通道id
+    #[prost(uint32, tag = "1")]
+    pub tunnel_id: u32,
+    /// 会话id
+    #[prost(uint32, tag = "2")]
+    pub session_id: u32,
+    /// 是否为入口会话（否则为出口会话）
+    #[prost(bool, tag = "3")]
+    pub is_inlet: bool,
+    /// 客户端地址
+    #[prost(string, tag = "4")]
+    pub client_addr: ::prost::alloc::string::String,
+    /// 目标地址
+    #[prost(string, tag = "5")]
+    pub target_addr: ::prost::alloc::string::String,
+    /// 会话开始时间（Unix 秒）
+    #[prost(uint64, tag = "6")]
+    pub start_time: u64,
+    /// 上行字节数（客户端 -> 目标地址）
+    #[prost(uint64, tag = "7")]
+    pub bytes_in: u64,
+    /// 下行字节数（目标地址 -> 客户端）
+    #[prost(uint64, tag = "8")]
+    pub bytes_out: u64,
 }
 /// 通道类型
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
@@ -20,6 +20,12 @@ pub struct LoginReq {
     /// 客户端请求的转发连接空闲关闭时间（秒）
     #[prost(uint32, tag = "5")]
     pub transport_idle_timeout_secs: u32,
+    /// 登录协议版本，0 表示旧版明文密码登录
+    #[prost(uint32, tag = "6")]
+    pub protocol_version: u32,
+    /// 挑战应答 HMAC-SHA256，使用挑战登录时 password 为空
+    #[prost(bytes = "vec", tag = "7")]
+    pub proof: ::prost::alloc::vec::Vec<u8>,
 }
 /// 注册请求
 /// return Success | Error
@@ -64,3 +70,43 @@ pub struct BindTransportReq {
     #[prost(uint32, tag = "3")]
     pub connection_type: u32,
 }
+/// 登录挑战请求，在 LoginReq 之前发送
+/// return LoginChallengeAck | Error
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LoginChallengeReq {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1015;}
+    /// 登录协议版本
+    #[prost(uint32, tag = "1")]
+    pub protocol_version: u32,
+    /// 用户名
+    #[prost(string, tag = "2")]
+    pub username: ::prost::alloc::string::String,
+}
+/// 活跃会话查询结果（推送）
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionListAck {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1017;}
+    /// 请求id
+    #[prost(uint32, tag = "1")]
+    pub request_id: u32,
+    /// 会话列表
+    #[prost(message, repeated, tag = "2")]
+    pub sessions: ::prost::alloc::vec::Vec<super::class_def::SessionInfo>,
+}
+/// 强制关闭会话结果（推送）
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionCloseAck {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1019;}
+    /// 请求id
+    #[prost(uint32, tag = "1")]
+    pub request_id: u32,
+    /// 会话是否存在并已关闭
+    #[prost(bool, tag = "2")]
+    pub closed: bool,
+}
@@ -79,6 +79,9 @@ pub struct I2oConnect {
     /// 客户端地址
     #[prost(string, tag = "9")]
     pub client_addr: ::prost::alloc::string::String,
+    /// 是否为 SOCKS5 BIND 请求（输出端监听并等待目标地址主动连入）
+    #[prost(bool, tag = "10")]
+    pub is_bind: bool,
 }
 /// 连接结果
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
@@ -98,6 +101,12 @@ pub struct O2iConnect {
     /// 错误信息
     #[prost(string, tag = "4")]
     pub error_info: ::prost::alloc::string::String,
+    /// AEAD加密方式下输出端的X25519公钥(base64)
+    #[prost(string, tag = "5")]
+    pub encryption_key: ::prost::alloc::string::String,
+    /// SOCKS5 BIND 请求时输出端的监听地址
+    #[prost(string, tag = "6")]
+    pub bind_addr: ::prost::alloc::string::String,
 }
 /// 输出端收到数据返回给输入端
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
@@ -227,6 +236,22 @@ pub struct O2iRecvDataFrom {
     #[prost(string, tag = "4")]
     pub remote_addr: ::prost::alloc::string::String,
 }
+/// SOCKS5 BIND 监听地址收到连接
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct O2iBindAccept {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 150016;}
+    /// 通道id
+    #[prost(uint32, tag = "1")]
+    pub tunnel_id: u32,
+    /// 会话id
+    #[prost(uint32, tag = "2")]
+    pub session_id: u32,
+    /// 连入方地址
+    #[prost(string, tag = "3")]
+    pub remote_addr: ::prost::alloc::string::String,
+}
 /// 通用错误码
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
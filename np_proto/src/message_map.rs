@@ -8,11 +8,17 @@ pub enum MessageType {
     ClientServerRegisterReq(super::client_server::RegisterReq),
     ClientServerManagementLoginReq(super::client_server::ManagementLoginReq),
     ClientServerBindTransportReq(super::client_server::BindTransportReq),
+    ClientServerLoginChallengeReq(super::client_server::LoginChallengeReq),
+    ClientServerSessionListAck(super::client_server::SessionListAck),
+    ClientServerSessionCloseAck(super::client_server::SessionCloseAck),
     ServerClientLoginAck(super::server_client::LoginAck),
     ServerClientManagementLoginAck(super::server_client::ManagementLoginAck),
     ServerClientModifyTunnelNtf(super::server_client::ModifyTunnelNtf),
     ServerClientBindTransportAck(super::server_client::BindTransportAck),
     ServerClientDisconnectNtf(super::server_client::DisconnectNtf),
+    ServerClientLoginChallengeAck(super::server_client::LoginChallengeAck),
+    ServerClientSessionListReq(super::server_client::SessionListReq),
+    ServerClientSessionCloseReq(super::server_client::SessionCloseReq),
     GenericSuccess(super::generic::Success),
     GenericFail(super::generic::Fail),
     GenericError(super::generic::Error),
@@ -28,6 +34,7 @@ pub enum MessageType {
     GenericI2oRecvDataResult(super::generic::I2oRecvDataResult),
     GenericI2oSendToData(super::generic::I2oSendToData),
     GenericO2iRecvDataFrom(super::generic::O2iRecvDataFrom),
+    GenericO2iBindAccept(super::generic::O2iBindAccept),
 }
 
 impl MessageType {
@@ -42,11 +49,17 @@ pub fn get_message_id(message: &MessageType) -> Option<u32> {
         MessageType::ClientServerRegisterReq(_) => Some(1003u32),
         MessageType::ClientServerManagementLoginReq(_) => Some(1005u32),
         MessageType::ClientServerBindTransportReq(_) => Some(1011u32),
+        MessageType::ClientServerLoginChallengeReq(_) => Some(1015u32),
+        MessageType::ClientServerSessionListAck(_) => Some(1017u32),
+        MessageType::ClientServerSessionCloseAck(_) => Some(1019u32),
         MessageType::ServerClientLoginAck(_) => Some(1002u32),
         MessageType::ServerClientManagementLoginAck(_) => Some(1006u32),
         MessageType::ServerClientModifyTunnelNtf(_) => Some(1008u32),
         MessageType::ServerClientBindTransportAck(_) => Some(1012u32),
         MessageType::ServerClientDisconnectNtf(_) => Some(1014u32),
+        MessageType::ServerClientLoginChallengeAck(_) => Some(1016u32),
+        MessageType::ServerClientSessionListReq(_) => Some(1018u32),
+        MessageType::ServerClientSessionCloseReq(_) => Some(1020u32),
         MessageType::GenericSuccess(_) => Some(150001u32),
         MessageType::GenericFail(_) => Some(150002u32),
         MessageType::GenericError(_) => Some(150003u32),
@@ -62,6 +75,7 @@ pub fn get_message_id(message: &MessageType) -> Option<u32> {
         MessageType::GenericI2oRecvDataResult(_) => Some(150013u32),
         MessageType::GenericI2oSendToData(_) => Some(150014u32),
         MessageType::GenericO2iRecvDataFrom(_) => Some(150015u32),
+        MessageType::GenericO2iBindAccept(_) => Some(150016u32),
         _ => None,
     }
 }
@@ -84,6 +98,18 @@ pub fn decode_message(message_id: u32, bytes: &[u8]) -> Result<MessageType, Deco
             Ok(message) => Ok(MessageType::ClientServerBindTransportReq(message)),
             Err(err) => Err(err),
         },
+        1015u32 => match super::client_server::LoginChallengeReq::decode(bytes) {
+            Ok(message) => Ok(MessageType::ClientServerLoginChallengeReq(message)),
+            Err(err) => Err(err),
+        },
+        1017u32 => match super::client_server::SessionListAck::decode(bytes) {
+            Ok(message) => Ok(MessageType::ClientServerSessionListAck(message)),
+            Err(err) => Err(err),
+        },
+        1019u32 => match super::client_server::SessionCloseAck::decode(bytes) {
+            Ok(message) => Ok(MessageType::ClientServerSessionCloseAck(message)),
+            Err(err) => Err(err),
+        },
         1002u32 => match super::server_client::LoginAck::decode(bytes) {
             Ok(message) => Ok(MessageType::ServerClientLoginAck(message)),
             Err(err) => Err(err),
@@ -104,6 +130,18 @@ pub fn decode_message(message_id: u32, bytes: &[u8]) -> Result<MessageType, Deco
             Ok(message) => Ok(MessageType::ServerClientDisconnectNtf(message)),
             Err(err) => Err(err),
         },
+        1016u32 => match super::server_client::LoginChallengeAck::decode(bytes) {
+            Ok(message) => Ok(MessageType::ServerClientLoginChallengeAck(message)),
+            Err(err) => Err(err),
+        },
+        1018u32 => match super::server_client::SessionListReq::decode(bytes) {
+            Ok(message) => Ok(MessageType::ServerClientSessionListReq(message)),
+            Err(err) => Err(err),
+        },
+        1020u32 => match super::server_client::SessionCloseReq::decode(bytes) {
+            Ok(message) => Ok(MessageType::ServerClientSessionCloseReq(message)),
+            Err(err) => Err(err),
+        },
         150001u32 => match super::generic::Success::decode(bytes) {
             Ok(message) => Ok(MessageType::GenericSuccess(message)),
             Err(err) => Err(err),
@@ -164,6 +202,10 @@ pub fn decode_message(message_id: u32, bytes: &[u8]) -> Result<MessageType, Deco
             Ok(message) => Ok(MessageType::GenericO2iRecvDataFrom(message)),
             Err(err) => Err(err),
         },
+        150016u32 => match super::generic::O2iBindAccept::decode(bytes) {
+            Ok(message) => Ok(MessageType::GenericO2iBindAccept(message)),
+            Err(err) => Err(err),
+        },
         _ => Err(DecodeError::new("unknown message id")),
     }
 }
@@ -174,11 +216,17 @@ pub fn encode_message(message: &MessageType) -> Option<(u32, Vec<u8>)> {
         MessageType::ClientServerRegisterReq(msg) => Some((1003u32, msg.encode_to_vec())),
         MessageType::ClientServerManagementLoginReq(msg) => Some((1005u32, msg.encode_to_vec())),
         MessageType::ClientServerBindTransportReq(msg) => Some((1011u32, msg.encode_to_vec())),
+        MessageType::ClientServerLoginChallengeReq(msg) => Some((1015u32, msg.encode_to_vec())),
+        MessageType::ClientServerSessionListAck(msg) => Some((1017u32, msg.encode_to_vec())),
+        MessageType::ClientServerSessionCloseAck(msg) => Some((1019u32, msg.encode_to_vec())),
         MessageType::ServerClientLoginAck(msg) => Some((1002u32, msg.encode_to_vec())),
         MessageType::ServerClientManagementLoginAck(msg) => Some((1006u32, msg.encode_to_vec())),
         MessageType::ServerClientModifyTunnelNtf(msg) => Some((1008u32, msg.encode_to_vec())),
         MessageType::ServerClientBindTransportAck(msg) => Some((1012u32, msg.encode_to_vec())),
         MessageType::ServerClientDisconnectNtf(msg) => Some((1014u32, msg.encode_to_vec())),
+        MessageType::ServerClientLoginChallengeAck(msg) => Some((1016u32, msg.encode_to_vec())),
+        MessageType::ServerClientSessionListReq(msg) => Some((1018u32, msg.encode_to_vec())),
+        MessageType::ServerClientSessionCloseReq(msg) => Some((1020u32, msg.encode_to_vec())),
         MessageType::GenericSuccess(msg) => Some((150001u32, msg.encode_to_vec())),
         MessageType::GenericFail(msg) => Some((150002u32, msg.encode_to_vec())),
         MessageType::GenericError(msg) => Some((150003u32, msg.encode_to_vec())),
@@ -194,6 +242,7 @@ pub fn encode_message(message: &MessageType) -> Option<(u32, Vec<u8>)> {
         MessageType::GenericI2oRecvDataResult(msg) => Some((150013u32, msg.encode_to_vec())),
         MessageType::GenericI2oSendToData(msg) => Some((150014u32, msg.encode_to_vec())),
         MessageType::GenericO2iRecvDataFrom(msg) => Some((150015u32, msg.encode_to_vec())),
+        MessageType::GenericO2iBindAccept(msg) => Some((150016u32, msg.encode_to_vec())),
         _ => None,
     }
 }
@@ -204,11 +253,17 @@ pub fn get_message_size(message: &MessageType) -> usize {
         MessageType::ClientServerRegisterReq(msg) => msg.encoded_len(),
         MessageType::ClientServerManagementLoginReq(msg) => msg.encoded_len(),
         MessageType::ClientServerBindTransportReq(msg) => msg.encoded_len(),
+        MessageType::ClientServerLoginChallengeReq(msg) => msg.encoded_len(),
+        MessageType::ClientServerSessionListAck(msg) => msg.encoded_len(),
+        MessageType::ClientServerSessionCloseAck(msg) => msg.encoded_len(),
         MessageType::ServerClientLoginAck(msg) => msg.encoded_len(),
         MessageType::ServerClientManagementLoginAck(msg) => msg.encoded_len(),
         MessageType::ServerClientModifyTunnelNtf(msg) => msg.encoded_len(),
         MessageType::ServerClientBindTransportAck(msg) => msg.encoded_len(),
         MessageType::ServerClientDisconnectNtf(msg) => msg.encoded_len(),
+        MessageType::ServerClientLoginChallengeAck(msg) => msg.encoded_len(),
+        MessageType::ServerClientSessionListReq(msg) => msg.encoded_len(),
+        MessageType::ServerClientSessionCloseReq(msg) => msg.encoded_len(),
         MessageType::GenericSuccess(msg) => msg.encoded_len(),
         MessageType::GenericFail(msg) => msg.encoded_len(),
         MessageType::GenericError(msg) => msg.encoded_len(),
@@ -224,6 +279,7 @@ pub fn get_message_size(message: &MessageType) -> usize {
         MessageType::GenericI2oRecvDataResult(msg) => msg.encoded_len(),
         MessageType::GenericI2oSendToData(msg) => msg.encoded_len(),
         MessageType::GenericO2iRecvDataFrom(msg) => msg.encoded_len(),
+        MessageType::GenericO2iBindAccept(msg) => msg.encoded_len(),
         _ => 0,
     }
 }
@@ -234,11 +290,17 @@ pub fn encode_raw_message(message: &MessageType, buf: &mut impl BufMut) {
         MessageType::ClientServerRegisterReq(msg) => msg.encode_raw(buf),
         MessageType::ClientServerManagementLoginReq(msg) => msg.encode_raw(buf),
         MessageType::ClientServerBindTransportReq(msg) => msg.encode_raw(buf),
+        MessageType::ClientServerLoginChallengeReq(msg) => msg.encode_raw(buf),
+        MessageType::ClientServerSessionListAck(msg) => msg.encode_raw(buf),
+        MessageType::ClientServerSessionCloseAck(msg) => msg.encode_raw(buf),
         MessageType::ServerClientLoginAck(msg) => msg.encode_raw(buf),
         MessageType::ServerClientManagementLoginAck(msg) => msg.encode_raw(buf),
         MessageType::ServerClientModifyTunnelNtf(msg) => msg.encode_raw(buf),
         MessageType::ServerClientBindTransportAck(msg) => msg.encode_raw(buf),
         MessageType::ServerClientDisconnectNtf(msg) => msg.encode_raw(buf),
+        MessageType::ServerClientLoginChallengeAck(msg) => msg.encode_raw(buf),
+        MessageType::ServerClientSessionListReq(msg) => msg.encode_raw(buf),
+        MessageType::ServerClientSessionCloseReq(msg) => msg.encode_raw(buf),
         MessageType::GenericSuccess(msg) => msg.encode_raw(buf),
         MessageType::GenericFail(msg) => msg.encode_raw(buf),
         MessageType::GenericError(msg) => msg.encode_raw(buf),
@@ -254,6 +316,7 @@ pub fn encode_raw_message(message: &MessageType, buf: &mut impl BufMut) {
         MessageType::GenericI2oRecvDataResult(msg) => msg.encode_raw(buf),
         MessageType::GenericI2oSendToData(msg) => msg.encode_raw(buf),
         MessageType::GenericO2iRecvDataFrom(msg) => msg.encode_raw(buf),
+        MessageType::GenericO2iBindAccept(msg) => msg.encode_raw(buf),
         _ => {}
     }
 }
@@ -265,11 +328,17 @@ pub fn serialize_to_json(message: &MessageType) -> serde_json::Result<String> {
         MessageType::ClientServerRegisterReq(msg) => serde_json::to_string(&msg),
         MessageType::ClientServerManagementLoginReq(msg) => serde_json::to_string(&msg),
         MessageType::ClientServerBindTransportReq(msg) => serde_json::to_string(&msg),
+        MessageType::ClientServerLoginChallengeReq(msg) => serde_json::to_string(&msg),
+        MessageType::ClientServerSessionListAck(msg) => serde_json::to_string(&msg),
+        MessageType::ClientServerSessionCloseAck(msg) => serde_json::to_string(&msg),
         MessageType::ServerClientLoginAck(msg) => serde_json::to_string(&msg),
         MessageType::ServerClientManagementLoginAck(msg) => serde_json::to_string(&msg),
         MessageType::ServerClientModifyTunnelNtf(msg) => serde_json::to_string(&msg),
         MessageType::ServerClientBindTransportAck(msg) => serde_json::to_string(&msg),
         MessageType::ServerClientDisconnectNtf(msg) => serde_json::to_string(&msg),
+        MessageType::ServerClientLoginChallengeAck(msg) => serde_json::to_string(&msg),
+        MessageType::ServerClientSessionListReq(msg) => serde_json::to_string(&msg),
+        MessageType::ServerClientSessionCloseReq(msg) => serde_json::to_string(&msg),
         MessageType::GenericSuccess(msg) => serde_json::to_string(&msg),
         MessageType::GenericFail(msg) => serde_json::to_string(&msg),
         MessageType::GenericError(msg) => serde_json::to_string(&msg),
@@ -285,6 +354,7 @@ pub fn serialize_to_json(message: &MessageType) -> serde_json::Result<String> {
         MessageType::GenericI2oRecvDataResult(msg) => serde_json::to_string(&msg),
         MessageType::GenericI2oSendToData(msg) => serde_json::to_string(&msg),
         MessageType::GenericO2iRecvDataFrom(msg) => serde_json::to_string(&msg),
+        MessageType::GenericO2iBindAccept(msg) => serde_json::to_string(&msg),
         _ => Ok("null".into()),
     }
 }
@@ -20,6 +20,9 @@ pub struct LoginAck {
     /// 转发连接空闲关闭时间（秒）
     #[prost(uint32, tag = "5")]
     pub transport_idle_timeout_secs: u32,
+    /// 挑战登录的服务端签名，客户端据此确认服务端持有自己的口令校验值
+    #[prost(bytes = "vec", tag = "6")]
+    pub server_signature: ::prost::alloc::vec::Vec<u8>,
 }
 /// 管理员登录回复
 #[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
@@ -70,3 +73,57 @@ pub struct DisconnectNtf {
     #[prost(string, tag = "1")]
     pub reason: ::prost::alloc::string::String,
 }
+/// 登录挑战回复
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LoginChallengeAck {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1016;}
+    /// 服务端随机数，只对当前连接的下一次登录有效
+    #[prost(bytes = "vec", tag = "1")]
+    pub nonce: ::prost::alloc::vec::Vec<u8>,
+    /// 密码哈希盐值
+    #[prost(bytes = "vec", tag = "2")]
+    pub salt: ::prost::alloc::vec::Vec<u8>,
+    /// Argon2id 内存开销（KiB）
+    #[prost(uint32, tag = "3")]
+    pub m_cost: u32,
+    /// Argon2id 迭代次数
+    #[prost(uint32, tag = "4")]
+    pub t_cost: u32,
+    /// Argon2id 并行度
+    #[prost(uint32, tag = "5")]
+    pub p_cost: u32,
+}
+/// 查询客户端本机的活跃会话（推送，客户端回复 SessionListAck）
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionListReq {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1018;}
+    /// 请求id，原样返回
+    #[prost(uint32, tag = "1")]
+    pub request_id: u32,
+    /// 通道id
+    #[prost(uint32, tag = "2")]
+    pub tunnel_id: u32,
+}
+/// 强制关闭客户端本机的会话（推送，客户端回复 SessionCloseAck）
+#[cfg_attr(feature = "serde-serialize", derive(serde::Serialize, serde::Deserialize))]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionCloseReq {
+    /// @build_automatically_generate_message_id@  enum MsgId {None = 0; Id = 1020;}
+    /// 请求id，原样返回
+    #[prost(uint32, tag = "1")]
+    pub request_id: u32,
+    /// 通道id
+    #[prost(uint32, tag = "2")]
+    pub tunnel_id: u32,
+    /// 会话id
+    #[prost(uint32, tag = "3")]
+    pub session_id: u32,
+    /// 是否为入口会话
+    #[prost(bool, tag = "4")]
+    pub is_inlet: bool,
+}
@@ -0,0 +1,89 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 支持挑战应答登录的协议版本，LoginReq.protocol_version 低于该值时使用旧版明文密码登录。
+pub const LOGIN_PROTOCOL_VERSION_CHALLENGE: u32 = 1;
+
+/// 挑战应答与派生密钥的长度（SHA-256 输出）。
+pub const LOGIN_KEY_LEN: usize = 32;
+
+// 客户端接受的 Argon2id 参数上限，超出时登录失败，避免服务端下发的参数耗尽客户端内存或 CPU。
+/// 内存开销上限（KiB），即 256 MiB。
+pub const LOGIN_MAX_M_COST: u32 = 256 * 1024;
+/// 迭代次数上限。
+pub const LOGIN_MAX_T_COST: u32 = 16;
+/// 并行度上限。
+pub const LOGIN_MAX_P_COST: u32 = 16;
+
+// 挑战应答登录，参考 SCRAM（RFC 5802）：
+//
+//   salted_password = Argon2id(password, salt)
+//   ClientKey       = HMAC(salted_password, "Client Key")
+//   StoredKey       = SHA256(ClientKey)
+//   ServerKey       = HMAC(salted_password, "Server Key")
+//   AuthMessage     = nonce | username
+//   ClientProof     = ClientKey XOR HMAC(StoredKey, AuthMessage)
+//   ServerSignature = HMAC(ServerKey, AuthMessage)
+//
+// 服务端只保存 StoredKey 与 ServerKey，数据库泄露后无法直接用于登录。
+
+/// 由 Argon2id 的输出派生服务端保存的 `(StoredKey, ServerKey)`。
+pub fn derive_login_keys(salted_password: &[u8]) -> ([u8; LOGIN_KEY_LEN], [u8; LOGIN_KEY_LEN]) {
+    let stored_key = Sha256::digest(client_key(salted_password)).into();
+    (stored_key, hmac(salted_password, &[b"Server Key"]))
+}
+
+/// 客户端计算挑战应答 `ClientProof`。
+pub fn login_proof(salted_password: &[u8], nonce: &[u8], username: &str) -> Vec<u8> {
+    let client_key = client_key(salted_password);
+    let stored_key: [u8; LOGIN_KEY_LEN] = Sha256::digest(client_key).into();
+    let signature = hmac(&stored_key, &[nonce, username.as_bytes()]);
+    xor(&client_key, &signature).to_vec()
+}
+
+/// 服务端校验挑战应答：还原 ClientKey 后比较其哈希与保存的 StoredKey（常量时间比较）。
+pub fn verify_login_proof(stored_key: &[u8], nonce: &[u8], username: &str, proof: &[u8]) -> bool {
+    if proof.len() != LOGIN_KEY_LEN {
+        return false;
+    }
+    let signature = hmac(stored_key, &[nonce, username.as_bytes()]);
+    let client_key = xor(proof, &signature);
+    constant_time_eq(&Sha256::digest(client_key), stored_key)
+}
+
+/// 服务端签名，客户端用它确认服务端确实持有自己的密码校验值。
+pub fn server_signature(server_key: &[u8], nonce: &[u8], username: &str) -> Vec<u8> {
+    hmac(server_key, &[nonce, username.as_bytes()]).to_vec()
+}
+
+/// 客户端校验服务端签名（常量时间比较）。
+pub fn verify_server_signature(salted_password: &[u8], nonce: &[u8], username: &str, signature: &[u8]) -> bool {
+    let server_key = hmac(salted_password, &[b"Server Key"]);
+    constant_time_eq(&server_signature(&server_key, nonce, username), signature)
+}
+
+fn client_key(salted_password: &[u8]) -> [u8; LOGIN_KEY_LEN] {
+    hmac(salted_password, &[b"Client Key"])
+}
+
+fn hmac(key: &[u8], data: &[&[u8]]) -> [u8; LOGIN_KEY_LEN] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    for data in data {
+        mac.update(data);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn xor(a: &[u8], b: &[u8; LOGIN_KEY_LEN]) -> [u8; LOGIN_KEY_LEN] {
+    let mut output = [0u8; LOGIN_KEY_LEN];
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = a[i] ^ b[i];
+    }
+    output
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -5,7 +5,7 @@ use np_base::proxy::ProxyMessage;
 
 pub fn proxy_message_2_pb(proxy_message: ProxyMessage, tunnel_id: u32) -> MessageType {
     match proxy_message {
-        ProxyMessage::I2oConnect(session_id, tunnel_type, is_tcp, is_compressed, addr, encryption_method, encryption_key, client_addr) => {
+        ProxyMessage::I2oConnect(session_id, tunnel_type, is_tcp, is_compressed, addr, encryption_method, encryption_key, client_addr, is_bind) => {
             MessageType::GenericI2oConnect(generic::I2oConnect {
                 tunnel_id,
                 session_id,
@@ -16,13 +16,21 @@ pub fn proxy_message_2_pb(proxy_message: ProxyMessage, tunnel_id: u32) -> Messag
                 encryption_method,
                 encryption_key,
                 client_addr,
+                is_bind,
             })
         }
-        ProxyMessage::O2iConnect(session_id, success, error_info) => MessageType::GenericO2iConnect(generic::O2iConnect {
+        ProxyMessage::O2iConnect(session_id, success, error_info, encryption_key, bind_addr) => MessageType::GenericO2iConnect(generic::O2iConnect {
             tunnel_id,
             session_id,
             success,
             error_info,
+            encryption_key,
+            bind_addr,
+        }),
+        ProxyMessage::O2iBindAccept(session_id, remote_addr) => MessageType::GenericO2iBindAccept(generic::O2iBindAccept {
+            tunnel_id,
+            session_id,
+            remote_addr,
         }),
         ProxyMessage::I2oSendData(session_id, data) => MessageType::GenericI2oSendData(generic::I2oSendData {
             tunnel_id,
@@ -96,6 +104,10 @@ pub fn pb_2_proxy_message(message: MessageType) -> Option<(ProxyMessage, u32)> {
             let tunnel_id = msg.tunnel_id;
             Some((msg.into(), tunnel_id))
         }
+        MessageType::GenericO2iBindAccept(msg) => {
+            let tunnel_id = msg.tunnel_id;
+            Some((msg.into(), tunnel_id))
+        }
         MessageType::GenericO2iDisconnect(msg) => {
             let tunnel_id = msg.tunnel_id;
             Some((msg.into(), tunnel_id))
@@ -115,6 +127,7 @@ pub fn is_i2o_message(proxy_message: &ProxyMessage) -> bool {
         }
 
         ProxyMessage::O2iConnect(..)
+        | ProxyMessage::O2iBindAccept(..)
         | ProxyMessage::O2iSendDataResult(..)
         | ProxyMessage::O2iRecvData(..)
         | ProxyMessage::O2iRecvDataFrom(..)
@@ -135,6 +148,7 @@ pub fn pb_proxy_session_id(message: &MessageType) -> Option<u32> {
         MessageType::GenericI2oRecvDataResult(msg) => Some(msg.session_id),
         MessageType::GenericI2oSendToData(msg) => Some(msg.session_id),
         MessageType::GenericO2iRecvDataFrom(msg) => Some(msg.session_id),
+        MessageType::GenericO2iBindAccept(msg) => Some(msg.session_id),
         _ => None,
     }
 }
@@ -155,13 +169,20 @@ impl From<generic::I2oConnect> for ProxyMessage {
             msg.encryption_method,
             msg.encryption_key,
             msg.client_addr,
+            msg.is_bind,
         )
     }
 }
 
 impl From<generic::O2iConnect> for ProxyMessage {
     fn from(msg: generic::O2iConnect) -> Self {
-        ProxyMessage::O2iConnect(msg.session_id, msg.success, msg.error_info)
+        ProxyMessage::O2iConnect(msg.session_id, msg.success, msg.error_info, msg.encryption_key, msg.bind_addr)
+    }
+}
+
+impl From<generic::O2iBindAccept> for ProxyMessage {
+    fn from(msg: generic::O2iBindAccept) -> Self {
+        ProxyMessage::O2iBindAccept(msg.session_id, msg.remote_addr)
     }
 }
 
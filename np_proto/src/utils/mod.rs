@@ -1,2 +1,3 @@
+pub mod login;
 pub mod message_bridge;
 pub mod transport;
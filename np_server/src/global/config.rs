@@ -1,9 +1,16 @@
 use crate::global::forward_rule::ForwardRule;
 use crate::global::opts::GLOBAL_OPTS;
-use once_cell::sync::Lazy;
+use arc_swap::ArcSwap;
+use log::{error, info, warn};
+use np_base::net::tls::ReloadableCertResolver;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ForwardRuleConfig {
@@ -26,6 +33,18 @@ pub struct Config {
     pub tls_cert: String,
     /// tls秘钥
     pub tls_key: String,
+    /// 校验客户端证书（mTLS）的 CA 证书，为空时不校验客户端证书
+    #[serde(default = "default_config_empty_string_function")]
+    pub tls_client_ca: String,
+    /// 是否要求客户端必须使用证书登录
+    #[serde(default = "default_config_false_function")]
+    pub tls_client_cert_required: bool,
+    /// 客户端证书身份（CN 或 SAN）到用户名的映射
+    #[serde(default)]
+    pub tls_client_cert_users: HashMap<String, String>,
+    /// 未配置映射时是否以客户端证书的主题 CN 作为用户名
+    #[serde(default = "default_config_false_function")]
+    pub tls_client_cert_cn_as_username: bool,
     /// web监听地址
     pub web_addr: String,
     /// Web 管理后台是否启用 TLS
@@ -49,6 +68,18 @@ pub struct Config {
     pub web_password: String,
     /// web目录
     pub web_base_dir: String,
+    /// tcp/ws/h2 监听器是否要求连接携带 PROXY protocol 头部（位于负载均衡之后时开启）
+    #[serde(default = "default_config_false_function")]
+    pub proxy_protocol: bool,
+    /// ws 监听器只接受该路径的升级请求，为空时接受任意路径
+    #[serde(default = "default_config_empty_string_function")]
+    pub ws_path: String,
+    /// ws 升级请求必须携带的请求头（如鉴权 token），路径或请求头不符合时返回 404
+    #[serde(default)]
+    pub ws_required_headers: HashMap<String, String>,
+    /// ws 支持的子协议
+    #[serde(default)]
+    pub ws_subprotocols: Vec<String>,
     /// 非法流量转发地址
     #[serde(default = "default_config_empty_string_function")]
     pub illegal_traffic_forward: String,
@@ -67,10 +98,34 @@ pub struct Config {
     /// 转发连接空闲关闭时间（秒）
     #[serde(default = "default_config_transport_idle_timeout_secs_function")]
     pub transport_idle_timeout_secs: u32,
+    /// 是否允许旧版客户端使用明文密码登录
+    #[serde(default = "default_config_true_function")]
+    pub allow_plaintext_login: bool,
+    /// 配置文件与证书变更检测间隔（秒），0 表示只通过 SIGHUP 或管理接口重载
+    #[serde(default)]
+    pub config_watch_interval_secs: u32,
+    /// 用户与通道清单文件（JSON/TOML），启动与重载配置时同步到数据库
+    #[serde(default = "default_config_empty_string_function")]
+    pub manifest_file: String,
+    /// 是否在 Web 服务上开启 Prometheus 指标接口 /metrics
+    #[serde(default = "default_config_false_function")]
+    pub metrics_enabled: bool,
+    /// 指标接口访问令牌，非空时要求 `Authorization: Bearer <token>`
+    #[serde(default = "default_config_empty_string_function")]
+    pub metrics_token: String,
     #[serde(skip)]
     pub forward_rules: Vec<ForwardRule>,
 }
 
+impl Config {
+    /// 是否配置了 quic:// 监听器
+    pub fn has_quic_listener(&self) -> bool {
+        self.listen_addr
+            .split(',')
+            .any(|addr| addr.trim().starts_with("quic://"))
+    }
+}
+
 fn default_config_empty_string_function() -> String {
     "".to_string()
 }
@@ -80,6 +135,9 @@ fn default_config_quiet_function() -> bool {
 fn default_config_false_function() -> bool {
     false
 }
+fn default_config_true_function() -> bool {
+    true
+}
 fn default_config_log_dir_function() -> String {
     "logs".to_string()
 }
@@ -89,25 +147,316 @@ fn default_config_transport_max_connections_function() -> u32 {
 fn default_config_transport_idle_timeout_secs_function() -> u32 {
     60
 }
-pub static GLOBAL_CONFIG: Lazy<Config> = Lazy::new(|| {
-    let file = match File::open(&GLOBAL_OPTS.config_file) {
-        Ok(file) => file,
+/// 全局配置，重载时整体替换。
+///
+/// 读取方使用 `GLOBAL_CONFIG.load()` 取得当前快照，不要长时间持有。
+pub static GLOBAL_CONFIG: Lazy<ArcSwap<Config>> =
+    Lazy::new(|| match load_config(&GLOBAL_OPTS.config_file) {
+        Ok(config) => ArcSwap::from_pointee(config),
         Err(e) => {
-            eprintln!("Failed to open config file: {}", e);
+            eprintln!("{}", e);
             std::process::exit(1);
         }
-    };
+    });
+
+/// 隧道监听器共享的证书，重载配置时原地替换
+pub static TLS_CERT_RESOLVER: OnceCell<Arc<ReloadableCertResolver>> = OnceCell::new();
+
+/// Web 管理后台的证书（自签名证书除外），重载配置时原地替换
+pub static WEB_TLS_CERT_RESOLVER: OnceCell<Arc<ReloadableCertResolver>> = OnceCell::new();
 
+fn load_config(path: &str) -> anyhow::Result<Config> {
+    let file =
+        File::open(path).map_err(|e| anyhow::anyhow!("Failed to open config file: {}", e))?;
     let reader = BufReader::new(file);
-    let mut config: Config = match serde_json::from_reader(reader) {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("Failed to parse config file: {}", e);
-            std::process::exit(1);
+    let mut config: Config = serde_json::from_reader(reader)
+        .map_err(|e| anyhow::anyhow!("Failed to parse config file: {}", e))?;
+
+    config.forward_rules = crate::global::forward_rule::parse_config(&config);
+
+    Ok(config)
+}
+
+/// 取得隧道监听器证书，首次调用时读取证书文件
+pub fn tls_cert_resolver() -> anyhow::Result<Arc<ReloadableCertResolver>> {
+    TLS_CERT_RESOLVER
+        .get_or_try_init(|| {
+            let config = GLOBAL_CONFIG.load();
+            ReloadableCertResolver::new(&config.tls_cert, &config.tls_key)
+        })
+        .cloned()
+}
+
+/// 重新读取配置文件并替换全局配置。
+///
+/// 证书、非法流量转发规则、转发连接参数、管理员账号等立即生效；
+/// 监听地址、数据库、日志等启动参数变更只打印警告，需要重启后生效。
+/// 任一证书读取失败时保持旧配置不变。
+pub fn reload_config() -> anyhow::Result<()> {
+    let new_config = load_config(&GLOBAL_OPTS.config_file)?;
+    let old_config = GLOBAL_CONFIG.load();
+
+    // 先读取全部证书，都成功后再一起替换，避免只更新了其中一个
+    let tls_cert = match TLS_CERT_RESOLVER.get() {
+        Some(resolver) => Some((
+            resolver,
+            resolver.load_cert(&new_config.tls_cert, &new_config.tls_key)?,
+        )),
+        None => None,
+    };
+    let web_tls_cert = match WEB_TLS_CERT_RESOLVER.get() {
+        Some(resolver)
+            if !new_config.web_tls_cert.is_empty() && !new_config.web_tls_key.is_empty() =>
+        {
+            Some((
+                resolver,
+                resolver.load_cert(&new_config.web_tls_cert, &new_config.web_tls_key)?,
+            ))
         }
+        _ => None,
     };
 
-    config.forward_rules = crate::global::forward_rule::parse_config(&config);
+    let mut tls_cert_changed = false;
+    if let Some((resolver, certified_key)) = tls_cert {
+        tls_cert_changed =
+            resolver.replace(&new_config.tls_cert, &new_config.tls_key, certified_key);
+    }
+    if let Some((resolver, certified_key)) = web_tls_cert {
+        resolver.replace(
+            &new_config.web_tls_cert,
+            &new_config.web_tls_key,
+            certified_key,
+        );
+    }
+
+    for name in restart_required_changes(&old_config, &new_config) {
+        warn!("config '{}' changed, restart np_server to apply it", name);
+    }
+    // 证书路径不变、文件内容更新时，quic:// 监听器同样需要重启
+    if tls_cert_changed
+        && new_config.has_quic_listener()
+        && old_config.tls_cert == new_config.tls_cert
+        && old_config.tls_key == new_config.tls_key
+    {
+        warn!("certificate files changed, restart np_server to apply them to quic:// listeners");
+    }
+
+    let manifest_enabled = !new_config.manifest_file.is_empty();
+    GLOBAL_CONFIG.store(Arc::new(new_config));
+    info!("config reloaded from {}", GLOBAL_OPTS.config_file);
+
+    if manifest_enabled {
+        tokio::spawn(async move {
+            if let Err(e) = crate::global::manifest::reconcile_manifest().await {
+                error!("Failed to reconcile manifest: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn restart_required_changes(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut changes = Vec::new();
+    if old.database_url != new.database_url {
+        changes.push("database_url");
+    }
+    if old.listen_addr != new.listen_addr {
+        changes.push("listen_addr");
+    }
+    if old.enable_tls != new.enable_tls {
+        changes.push("enable_tls");
+    }
+    // s2n-quic 在监听器启动时读取证书文件，不使用可热更新的证书
+    if new.has_quic_listener() && old.tls_cert != new.tls_cert {
+        changes.push("tls_cert (quic://)");
+    }
+    if new.has_quic_listener() && old.tls_key != new.tls_key {
+        changes.push("tls_key (quic://)");
+    }
+    if old.tls_client_ca != new.tls_client_ca {
+        changes.push("tls_client_ca");
+    }
+    if old.tls_client_cert_required != new.tls_client_cert_required {
+        changes.push("tls_client_cert_required");
+    }
+    if old.proxy_protocol != new.proxy_protocol {
+        changes.push("proxy_protocol");
+    }
+    if old.ws_path != new.ws_path {
+        changes.push("ws_path");
+    }
+    if old.ws_required_headers != new.ws_required_headers {
+        changes.push("ws_required_headers");
+    }
+    if old.ws_subprotocols != new.ws_subprotocols {
+        changes.push("ws_subprotocols");
+    }
+    if old.web_addr != new.web_addr {
+        changes.push("web_addr");
+    }
+    if old.web_enable_tls != new.web_enable_tls {
+        changes.push("web_enable_tls");
+    }
+    if old.web_tls_auto_self_signed != new.web_tls_auto_self_signed {
+        changes.push("web_tls_auto_self_signed");
+    }
+    if old.web_cookie_secure != new.web_cookie_secure {
+        changes.push("web_cookie_secure");
+    }
+    if old.web_base_dir != new.web_base_dir {
+        changes.push("web_base_dir");
+    }
+    if old.quiet != new.quiet {
+        changes.push("quiet");
+    }
+    if old.log_dir != new.log_dir {
+        changes.push("log_dir");
+    }
+    changes
+}
+
+/// 启动配置重载任务：监听 SIGHUP，并按 `config_watch_interval_secs` 检测配置文件与证书的修改时间
+pub(crate) fn start_config_reload_loop() {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        let mut sighup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        {
+            Ok(sighup) => sighup,
+            Err(e) => {
+                error!("Failed to install SIGHUP handler: {}", e);
+                return;
+            }
+        };
+        while sighup.recv().await.is_some() {
+            info!("SIGHUP received, reloading config");
+            if let Err(e) = reload_config() {
+                error!("Failed to reload config: {}", e);
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut last_modified = watched_files_modified();
+        loop {
+            let interval = GLOBAL_CONFIG.load().config_watch_interval_secs;
+            if interval == 0 {
+                // 未开启时定期检查，以便重载后开启检测
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                last_modified = watched_files_modified();
+                continue;
+            }
+            tokio::time::sleep(Duration::from_secs(interval as u64)).await;
+
+            let modified = watched_files_modified();
+            if modified != last_modified {
+                last_modified = modified;
+                info!("config or certificate file changed, reloading config");
+                if let Err(e) = reload_config() {
+                    error!("Failed to reload config: {}", e);
+                }
+            }
+        }
+    });
+}
 
-    config
-});
+/// 配置文件与当前证书文件的修改时间
+fn watched_files_modified() -> Vec<Option<SystemTime>> {
+    let config = GLOBAL_CONFIG.load();
+    [
+        GLOBAL_OPTS.config_file.as_str(),
+        config.tls_cert.as_str(),
+        config.tls_key.as_str(),
+        config.web_tls_cert.as_str(),
+        config.web_tls_key.as_str(),
+    ]
+    .iter()
+    .map(|path| {
+        if path.is_empty() {
+            return None;
+        }
+        Path::new(path)
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_self_signed(dir: &Path, name: &str) -> (String, String) {
+        let certified_key = rcgen::generate_simple_self_signed(vec![name.to_owned()]).unwrap();
+        let cert = dir.join(format!("{}.pem", name));
+        let key = dir.join(format!("{}.key", name));
+        std::fs::write(&cert, certified_key.cert.pem()).unwrap();
+        std::fs::write(&key, certified_key.key_pair.serialize_pem()).unwrap();
+        (
+            cert.to_string_lossy().into_owned(),
+            key.to_string_lossy().into_owned(),
+        )
+    }
+
+    fn config_with(listen_addr: &str, tls_cert: &str) -> Config {
+        serde_json::from_value(serde_json::json!({
+            "database_url": "sqlite://npipe.db",
+            "listen_addr": listen_addr,
+            "enable_tls": true,
+            "tls_cert": tls_cert,
+            "tls_key": "key.pem",
+            "web_addr": "",
+            "web_username": "",
+            "web_password": "",
+            "web_base_dir": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let old = config_with("tcp://0.0.0.0:8118", "a.pem");
+        let new = config_with("tcp://0.0.0.0:8118", "b.pem");
+        assert!(restart_required_changes(&old, &new).is_empty());
+
+        // quic:// 监听器不支持证书热更新
+        let old = config_with("tcp://0.0.0.0:8118, quic://0.0.0.0:8118", "a.pem");
+        let new = config_with("tcp://0.0.0.0:8118, quic://0.0.0.0:8118", "b.pem");
+        assert_eq!(
+            restart_required_changes(&old, &new),
+            vec!["tls_cert (quic://)"]
+        );
+
+        // 监听器启动时读取 proxy_protocol；/metrics 的开关与令牌每次请求时读取，立即生效
+        let mut new = config_with("tcp://0.0.0.0:8118, quic://0.0.0.0:8118", "a.pem");
+        new.proxy_protocol = true;
+        new.metrics_enabled = true;
+        new.metrics_token = "secret".to_string();
+        assert_eq!(restart_required_changes(&old, &new), vec!["proxy_protocol"]);
+    }
+
+    #[test]
+    fn test_cert_resolver_reload_keeps_old_cert_on_error() {
+        let dir = std::env::temp_dir().join(format!("np_server_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert_a, key_a) = write_self_signed(&dir, "a.example");
+        let (cert_b, key_b) = write_self_signed(&dir, "b.example");
+
+        let resolver = ReloadableCertResolver::new(&cert_a, &key_a).unwrap();
+        assert!(resolver.reload(&cert_b, &key_b).unwrap());
+        assert!(!resolver.reload(&cert_b, &key_b).unwrap());
+        assert_eq!(resolver.paths(), (cert_b.clone(), key_b.clone()));
+
+        assert!(resolver.reload(&cert_a, &key_b).is_err());
+        assert!(resolver.reload("missing.pem", "missing.key").is_err());
+        assert_eq!(resolver.paths(), (cert_b.clone(), key_b.clone()));
+
+        // 只读取不替换，调用 replace 后才生效
+        let certified_key = resolver.load_cert(&cert_a, &key_a).unwrap();
+        assert_eq!(resolver.paths(), (cert_b, key_b));
+        assert!(resolver.replace(&cert_a, &key_a, certified_key));
+        assert_eq!(resolver.paths(), (cert_a, key_a));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,8 +1,10 @@
 use crate::global::config::GLOBAL_CONFIG;
 use crate::global::manager::GLOBAL_MANAGER;
 use crate::orm_entity::{
-    login_history, operation_log, schema_version, traffic_hourly, tunnel, user,
+    login_history, operation_log, schema_version, traffic_hourly, tunnel, tunnel_traffic_hourly,
+    user,
 };
+use crate::utils::password::{hash_password_async, is_password_hash, upgrade_password_hash};
 use chrono::Utc;
 use sea_orm::sea_query::{Index, MysqlQueryBuilder, PostgresQueryBuilder, SqliteQueryBuilder};
 use sea_orm::ActiveValue::{NotSet, Set};
@@ -13,12 +15,12 @@ use sea_orm::{
 use std::time::Duration;
 use tokio::sync::OnceCell;
 
-const CURRENT_SCHEMA_VERSION: i32 = 2;
+const CURRENT_SCHEMA_VERSION: i32 = 10;
 
 pub(crate) static GLOBAL_DB_POOL: OnceCell<DatabaseConnection> = OnceCell::const_new();
 
 pub(crate) async fn init_database() -> anyhow::Result<()> {
-    let mut opt = ConnectOptions::new(&GLOBAL_CONFIG.database_url);
+    let mut opt = ConnectOptions::new(&GLOBAL_CONFIG.load().database_url);
     opt.max_connections(100)
         .min_connections(5)
         .connect_timeout(Duration::from_secs(8))
@@ -67,6 +69,14 @@ pub(crate) async fn init_database() -> anyhow::Result<()> {
                     .to_string(MysqlQueryBuilder),
             ))
             .await?;
+            db.execute(Statement::from_string(
+                backend,
+                schema
+                    .create_table_from_entity(tunnel_traffic_hourly::Entity)
+                    .if_not_exists()
+                    .to_string(MysqlQueryBuilder),
+            ))
+            .await?;
             db.execute(Statement::from_string(
                 backend,
                 schema
@@ -117,6 +127,14 @@ pub(crate) async fn init_database() -> anyhow::Result<()> {
                     .to_string(PostgresQueryBuilder),
             ))
             .await?;
+            db.execute(Statement::from_string(
+                backend,
+                schema
+                    .create_table_from_entity(tunnel_traffic_hourly::Entity)
+                    .if_not_exists()
+                    .to_string(PostgresQueryBuilder),
+            ))
+            .await?;
             db.execute(Statement::from_string(
                 backend,
                 schema
@@ -167,6 +185,14 @@ pub(crate) async fn init_database() -> anyhow::Result<()> {
                     .to_string(SqliteQueryBuilder),
             ))
             .await?;
+            db.execute(Statement::from_string(
+                backend,
+                schema
+                    .create_table_from_entity(tunnel_traffic_hourly::Entity)
+                    .if_not_exists()
+                    .to_string(SqliteQueryBuilder),
+            ))
+            .await?;
             db.execute(Statement::from_string(
                 backend,
                 schema
@@ -217,6 +243,46 @@ async fn run_schema_migrations(db: &DatabaseConnection, backend: DbBackend) -> a
 
     if version < 2 {
         ensure_login_history_columns(db, backend).await?;
+        set_schema_version(db, 2).await?;
+    }
+
+    if version < 3 {
+        hash_plaintext_passwords(db).await?;
+        set_schema_version(db, 3).await?;
+    }
+
+    if version < 4 {
+        ensure_rate_limit_columns(db, backend).await?;
+        set_schema_version(db, 4).await?;
+    }
+
+    if version < 5 {
+        ensure_quota_columns(db, backend).await?;
+        set_schema_version(db, 5).await?;
+    }
+
+    if version < 6 {
+        ensure_tunnel_traffic_indexes(db, backend).await?;
+        set_schema_version(db, 6).await?;
+    }
+
+    if version < 7 {
+        ensure_tls_certificate_columns(db, backend).await?;
+        set_schema_version(db, 7).await?;
+    }
+
+    if version < 8 {
+        ensure_proxy_protocol_columns(db, backend).await?;
+        set_schema_version(db, 8).await?;
+    }
+
+    if version < 9 {
+        ensure_access_list_columns(db, backend).await?;
+        set_schema_version(db, 9).await?;
+    }
+
+    if version < 10 {
+        upgrade_password_hashes(db).await?;
         set_schema_version(db, CURRENT_SCHEMA_VERSION).await?;
     }
 
@@ -278,6 +344,26 @@ async fn ensure_indexes(db: &DatabaseConnection, backend: DbBackend) -> anyhow::
     Ok(())
 }
 
+async fn ensure_tunnel_traffic_indexes(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+) -> anyhow::Result<()> {
+    let index = Index::create()
+        .name("idx_tunnel_traffic_hourly_tunnel_id_hour")
+        .table(tunnel_traffic_hourly::Entity)
+        .col(tunnel_traffic_hourly::Column::TunnelId)
+        .col(tunnel_traffic_hourly::Column::Hour)
+        .if_not_exists()
+        .to_owned();
+    let sql = match backend {
+        DbBackend::MySql => index.to_string(MysqlQueryBuilder),
+        DbBackend::Postgres => index.to_string(PostgresQueryBuilder),
+        DbBackend::Sqlite => index.to_string(SqliteQueryBuilder),
+    };
+    db.execute(Statement::from_string(backend, sql)).await?;
+    Ok(())
+}
+
 async fn ensure_user_columns(db: &DatabaseConnection, backend: DbBackend) -> anyhow::Result<()> {
     let columns = match backend {
         DbBackend::MySql => vec![
@@ -294,18 +380,58 @@ async fn ensure_user_columns(db: &DatabaseConnection, backend: DbBackend) -> any
         ],
     };
 
-    for sql in columns {
-        if let Err(err) = db.execute(Statement::from_string(backend, sql)).await {
-            let msg = err.to_string().to_lowercase();
-            if !(msg.contains("duplicate")
-                || msg.contains("exists")
-                || msg.contains("duplicate column"))
-            {
-                return Err(err.into());
-            }
+    add_columns(db, backend, columns).await
+}
+
+/// 将旧版本保存的明文密码升级为 Argon2id 哈希
+async fn hash_plaintext_passwords(db: &DatabaseConnection) -> anyhow::Result<()> {
+    let users = user::Entity::find().all(db).await?;
+    let mut count = 0;
+    for model in users {
+        if is_password_hash(&model.password) {
+            continue;
         }
+        let password_hash = hash_password_async(model.password.clone()).await?;
+        let mut active: user::ActiveModel = model.into();
+        active.password = Set(password_hash);
+        active.update(db).await?;
+        count += 1;
+    }
+    if count > 0 {
+        log::info!("hashed {} plaintext user passwords", count);
     }
+    Ok(())
+}
 
+/// 将 Argon2id 哈希升级为只保存 StoredKey/ServerKey 的口令校验值
+///
+/// 升级只需要哈希本身，不需要原密码；无法升级的哈希保持不变，需要重新设置密码。
+async fn upgrade_password_hashes(db: &DatabaseConnection) -> anyhow::Result<()> {
+    let users = user::Entity::find().all(db).await?;
+    let mut count = 0;
+    for model in users {
+        if !model.password.starts_with("$argon2") {
+            continue;
+        }
+        let password_hash = match upgrade_password_hash(&model.password) {
+            Ok(password_hash) => password_hash,
+            Err(err) => {
+                log::warn!(
+                    "user '{}' password hash can not be upgraded, reset the password: {}",
+                    model.username,
+                    err
+                );
+                continue;
+            }
+        };
+        let mut active: user::ActiveModel = model.into();
+        active.password = Set(password_hash);
+        active.update(db).await?;
+        count += 1;
+    }
+    if count > 0 {
+        log::info!("upgraded {} user password hashes", count);
+    }
     Ok(())
 }
 
@@ -328,6 +454,136 @@ async fn ensure_login_history_columns(
         ],
     };
 
+    add_columns(db, backend, columns).await
+}
+
+async fn ensure_rate_limit_columns(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+) -> anyhow::Result<()> {
+    let columns = match backend {
+        DbBackend::MySql => vec![
+            "ALTER TABLE user ADD COLUMN upload_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN download_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN upload_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN download_limit BIGINT NOT NULL DEFAULT 0",
+        ],
+        DbBackend::Postgres => vec![
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS upload_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS download_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS upload_limit BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS download_limit BIGINT NOT NULL DEFAULT 0",
+        ],
+        DbBackend::Sqlite => vec![
+            "ALTER TABLE user ADD COLUMN upload_limit INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN download_limit INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN upload_limit INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN download_limit INTEGER NOT NULL DEFAULT 0",
+        ],
+    };
+
+    add_columns(db, backend, columns).await
+}
+
+async fn ensure_quota_columns(db: &DatabaseConnection, backend: DbBackend) -> anyhow::Result<()> {
+    let columns = match backend {
+        DbBackend::MySql => vec![
+            "ALTER TABLE user ADD COLUMN quota_bytes BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN quota_period TINYINT NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN quota_reset_day TINYINT NOT NULL DEFAULT 1",
+            "ALTER TABLE user ADD COLUMN quota_hard TINYINT NOT NULL DEFAULT 1",
+            "ALTER TABLE user ADD COLUMN quota_exceeded TINYINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN quota_suspended TINYINT NOT NULL DEFAULT 0",
+        ],
+        DbBackend::Postgres => vec![
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS quota_bytes BIGINT NOT NULL DEFAULT 0",
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS quota_period SMALLINT NOT NULL DEFAULT 0",
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS quota_reset_day SMALLINT NOT NULL DEFAULT 1",
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS quota_hard SMALLINT NOT NULL DEFAULT 1",
+            "ALTER TABLE \"user\" ADD COLUMN IF NOT EXISTS quota_exceeded SMALLINT NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS quota_suspended SMALLINT NOT NULL DEFAULT 0",
+        ],
+        DbBackend::Sqlite => vec![
+            "ALTER TABLE user ADD COLUMN quota_bytes INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN quota_period INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE user ADD COLUMN quota_reset_day INTEGER NOT NULL DEFAULT 1",
+            "ALTER TABLE user ADD COLUMN quota_hard INTEGER NOT NULL DEFAULT 1",
+            "ALTER TABLE user ADD COLUMN quota_exceeded INTEGER NOT NULL DEFAULT 0",
+            "ALTER TABLE tunnel ADD COLUMN quota_suspended INTEGER NOT NULL DEFAULT 0",
+        ],
+    };
+
+    add_columns(db, backend, columns).await
+}
+
+async fn ensure_tls_certificate_columns(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+) -> anyhow::Result<()> {
+    let columns = match backend {
+        // MySQL 的 TEXT 列不能有默认值，已有行会填充为空串
+        DbBackend::MySql => {
+            vec!["ALTER TABLE tunnel ADD COLUMN tls_certificates MEDIUMTEXT NOT NULL"]
+        }
+        DbBackend::Postgres => vec![
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS tls_certificates TEXT NOT NULL DEFAULT ''",
+        ],
+        DbBackend::Sqlite => {
+            vec!["ALTER TABLE tunnel ADD COLUMN tls_certificates TEXT NOT NULL DEFAULT ''"]
+        }
+    };
+
+    add_columns(db, backend, columns).await
+}
+
+async fn ensure_proxy_protocol_columns(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+) -> anyhow::Result<()> {
+    let columns = match backend {
+        DbBackend::MySql => {
+            vec!["ALTER TABLE tunnel ADD COLUMN proxy_protocol TINYINT NOT NULL DEFAULT 0"]
+        }
+        DbBackend::Postgres => vec![
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS proxy_protocol SMALLINT NOT NULL DEFAULT 0",
+        ],
+        DbBackend::Sqlite => {
+            vec!["ALTER TABLE tunnel ADD COLUMN proxy_protocol INTEGER NOT NULL DEFAULT 0"]
+        }
+    };
+
+    add_columns(db, backend, columns).await
+}
+
+async fn ensure_access_list_columns(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+) -> anyhow::Result<()> {
+    let columns = match backend {
+        // MySQL 的 TEXT 列不能有默认值，已有行会填充为空串
+        DbBackend::MySql => vec![
+            "ALTER TABLE tunnel ADD COLUMN acl_allow TEXT NOT NULL",
+            "ALTER TABLE tunnel ADD COLUMN acl_deny TEXT NOT NULL",
+        ],
+        DbBackend::Postgres => vec![
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS acl_allow TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE tunnel ADD COLUMN IF NOT EXISTS acl_deny TEXT NOT NULL DEFAULT ''",
+        ],
+        DbBackend::Sqlite => vec![
+            "ALTER TABLE tunnel ADD COLUMN acl_allow TEXT NOT NULL DEFAULT ''",
+            "ALTER TABLE tunnel ADD COLUMN acl_deny TEXT NOT NULL DEFAULT ''",
+        ],
+    };
+
+    add_columns(db, backend, columns).await
+}
+
+/// 逐条执行加列语句，忽略列已存在的错误
+async fn add_columns(
+    db: &DatabaseConnection,
+    backend: DbBackend,
+    columns: Vec<&str>,
+) -> anyhow::Result<()> {
     for sql in columns {
         if let Err(err) = db.execute(Statement::from_string(backend, sql)).await {
             let msg = err.to_string().to_lowercase();
@@ -412,5 +668,49 @@ async fn traffic_flush_loop() {
                 );
             }
         }
+
+        flush_tunnel_traffic(db, &hour).await;
+    }
+}
+
+/// 将通道内存中的流量计数刷入数据库，失败时归还计数等待下次刷库
+async fn flush_tunnel_traffic(db: &DatabaseConnection, hour: &str) {
+    for snapshot in GLOBAL_MANAGER.proxy_manager.take_tunnel_traffic() {
+        let (tunnel_id, bytes_in, bytes_out, sessions) = snapshot;
+        let save_result = match tunnel_traffic_hourly::Entity::find()
+            .filter(tunnel_traffic_hourly::Column::TunnelId.eq(tunnel_id))
+            .filter(tunnel_traffic_hourly::Column::Hour.eq(hour))
+            .one(db)
+            .await
+        {
+            Ok(Some(model)) => {
+                let mut active: tunnel_traffic_hourly::ActiveModel = model.into();
+                active.bytes_in = Set(active.bytes_in.unwrap() + bytes_in as i64);
+                active.bytes_out = Set(active.bytes_out.unwrap() + bytes_out as i64);
+                active.sessions = Set(active.sessions.unwrap() + sessions as i64);
+                active.update(db).await.map(|_| ())
+            }
+            Ok(None) => tunnel_traffic_hourly::ActiveModel {
+                id: NotSet,
+                tunnel_id: Set(tunnel_id),
+                bytes_in: Set(bytes_in as i64),
+                bytes_out: Set(bytes_out as i64),
+                sessions: Set(sessions as i64),
+                hour: Set(hour.to_owned()),
+            }
+            .insert(db)
+            .await
+            .map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = save_result {
+            GLOBAL_MANAGER.proxy_manager.add_tunnel_traffic(snapshot);
+            log::error!(
+                "tunnel traffic flush error, restored counters, tunnel_id:{}, error:{}",
+                tunnel_id,
+                e
+            );
+        }
     }
 }
@@ -11,7 +11,7 @@ pub(crate) fn init_logger() -> anyhow::Result<()> {
         env::set_var("RUST_BACKTRACE", "1");
     }
 
-    if GLOBAL_CONFIG.quiet {
+    if GLOBAL_CONFIG.load().quiet {
         return Ok(());
     }
 
@@ -22,7 +22,7 @@ pub(crate) fn init_logger() -> anyhow::Result<()> {
     ))?
     .log_to_file(
         FileSpec::default()
-            .directory(&GLOBAL_CONFIG.log_dir)
+            .directory(&GLOBAL_CONFIG.load().log_dir)
             .suppress_timestamp()
             .suffix("log"),
     )
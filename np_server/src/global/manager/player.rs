@@ -1,7 +1,9 @@
+use crate::global::quota::{check_quotas, PlayerQuota, QUOTA_PERIOD_MONTHLY};
 use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::prelude::User;
 use crate::orm_entity::user;
 use crate::player::{Player, PlayerId};
+use crate::utils::password::hash_password_async;
 use crate::utils::str::{is_valid_password, is_valid_username};
 use chrono::Utc;
 use dashmap::DashMap;
@@ -15,12 +17,15 @@ use tokio::sync::RwLock;
 
 pub struct PlayerManager {
     pub(crate) player_map: DashMap<PlayerId, Arc<RwLock<Player>>>,
+    /// 玩家带宽限速（上行, 下行），单位字节/秒，0为不限速
+    rate_limits: DashMap<PlayerId, (i64, i64)>,
 }
 
 impl PlayerManager {
     pub(crate) fn new() -> PlayerManager {
         PlayerManager {
             player_map: DashMap::new(),
+            rate_limits: DashMap::new(),
         }
     }
 
@@ -28,6 +33,8 @@ impl PlayerManager {
         let users = User::find().all(GLOBAL_DB_POOL.get().unwrap()).await?;
         for user in users {
             self.create_player(user.id);
+            self.rate_limits
+                .insert(user.id, (user.upload_limit, user.download_limit));
         }
         Ok(())
     }
@@ -42,6 +49,13 @@ impl PlayerManager {
         self.player_map.get(&player_id).map(|r| r.clone())
     }
 
+    /// 玩家带宽限速（上行, 下行），纯 DashMap 查询，无需 async。
+    pub fn rate_limit(&self, player_id: PlayerId) -> (i64, i64) {
+        self.rate_limits
+            .get(&player_id)
+            .map_or((0, 0), |entry| *entry.value())
+    }
+
     pub async fn get_player_by_transport_token(&self, token: &str) -> Option<Arc<RwLock<Player>>> {
         if token.is_empty() {
             return None;
@@ -102,6 +116,7 @@ impl PlayerManager {
         );
 
         // DashMap::remove: O(1)，只锁对应 shard，无需遍历
+        self.rate_limits.remove(&player_id);
         if let Some((_, player)) = self.player_map.remove(&player_id) {
             player.write().await.close_session();
         }
@@ -128,33 +143,71 @@ impl PlayerManager {
             return Ok((-2, "user already exists".into()));
         }
 
-        let mut count = 0;
-        loop {
-            count += 1;
-            if count > 10000 {
-                return Ok((-3, "too many cycles".into()));
-            }
+        let password_hash = hash_password_async(password.to_owned()).await?;
 
-            let id: u32 = rand::random_range(10000000..99999999);
-            if self.contain(id) {
-                continue;
+        match self.allocate_player_id() {
+            Some(id) => {
+                self.insert_player(id, username, password_hash, 1, 0)
+                    .await?;
+                Ok((0, "".into()))
             }
-
-            let new_user = user::ActiveModel {
-                id: Set(id),
-                username: Set(username.to_owned()),
-                password: Set(password.to_owned()),
-                create_time: Set(Utc::now().naive_utc()),
-                enabled: Set(1),
-                web_access: Set(0),
-            };
-
-            let _ = new_user.insert(GLOBAL_DB_POOL.get().unwrap()).await?;
-            self.create_player(id);
-            return Ok((0, "".into()));
+            None => Ok((-3, "too many cycles".into())),
         }
     }
 
+    /// 按已计算好的密码哈希新加玩家，返回玩家 ID
+    pub async fn provision_player(
+        &self,
+        username: &str,
+        password_hash: String,
+        enabled: u8,
+        web_access: u8,
+    ) -> anyhow::Result<PlayerId> {
+        anyhow::ensure!(is_valid_username(username), "username format error");
+        let id = self
+            .allocate_player_id()
+            .ok_or_else(|| anyhow::anyhow!("too many cycles"))?;
+        self.insert_player(id, username, password_hash, enabled, web_access)
+            .await?;
+        Ok(id)
+    }
+
+    /// 随机分配一个未使用的玩家 ID
+    fn allocate_player_id(&self) -> Option<PlayerId> {
+        (0..10000)
+            .map(|_| rand::random_range(10000000..99999999))
+            .find(|id| !self.contain(*id))
+    }
+
+    async fn insert_player(
+        &self,
+        id: PlayerId,
+        username: &str,
+        password_hash: String,
+        enabled: u8,
+        web_access: u8,
+    ) -> anyhow::Result<()> {
+        let new_user = user::ActiveModel {
+            id: Set(id),
+            username: Set(username.to_owned()),
+            password: Set(password_hash),
+            create_time: Set(Utc::now().naive_utc()),
+            enabled: Set(enabled),
+            web_access: Set(web_access),
+            upload_limit: Set(0),
+            download_limit: Set(0),
+            quota_bytes: Set(0),
+            quota_period: Set(QUOTA_PERIOD_MONTHLY),
+            quota_reset_day: Set(1),
+            quota_hard: Set(1),
+            quota_exceeded: Set(0),
+        };
+
+        let _ = new_user.insert(GLOBAL_DB_POOL.get().unwrap()).await?;
+        self.create_player(id);
+        Ok(())
+    }
+
     /// 修改玩家用户名
     pub async fn rename_player(&self, player_id: u32, username: &String) -> anyhow::Result<()> {
         anyhow::ensure!(is_valid_username(username), "username format error");
@@ -192,14 +245,33 @@ impl PlayerManager {
             .await?;
         anyhow::ensure!(user.is_some(), "can't find user: {}", player_id);
 
+        let password_hash = hash_password_async(password.to_owned()).await?;
         let mut user: user::ActiveModel = user.unwrap().into();
-        user.password = Set(password.to_owned());
+        user.password = Set(password_hash);
         user.web_access = Set(0);
 
         let _ = user.update(GLOBAL_DB_POOL.get().unwrap()).await?;
         Ok(())
     }
 
+    /// 直接设置玩家密码哈希
+    pub async fn set_player_password_hash(
+        &self,
+        player_id: u32,
+        password_hash: String,
+    ) -> anyhow::Result<()> {
+        let user = User::find_by_id(player_id)
+            .one(GLOBAL_DB_POOL.get().unwrap())
+            .await?;
+        anyhow::ensure!(user.is_some(), "can't find user: {}", player_id);
+
+        let mut user: user::ActiveModel = user.unwrap().into();
+        user.password = Set(password_hash);
+
+        let _ = user.update(GLOBAL_DB_POOL.get().unwrap()).await?;
+        Ok(())
+    }
+
     /// 修改玩家启用状态
     pub async fn update_player_status(&self, player_id: u32, enabled: u8) -> anyhow::Result<()> {
         let user = User::find_by_id(player_id)
@@ -237,6 +309,67 @@ impl PlayerManager {
 
         Ok(())
     }
+
+    /// 修改玩家带宽限速，0为不限速
+    ///
+    /// 限速作用于该玩家作为接收方（入口）的所有通道，修改后重新下发相关通道，
+    /// 各端原地更新限速器，已有连接无需断开。
+    pub async fn update_player_rate_limit(
+        &self,
+        player_id: u32,
+        upload_limit: i64,
+        download_limit: i64,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            upload_limit >= 0 && download_limit >= 0,
+            "rate limit must not be negative"
+        );
+        let user = User::find_by_id(player_id)
+            .one(GLOBAL_DB_POOL.get().unwrap())
+            .await?;
+        anyhow::ensure!(user.is_some(), "can't find user: {}", player_id);
+
+        let mut user: user::ActiveModel = user.unwrap().into();
+        user.upload_limit = Set(upload_limit);
+        user.download_limit = Set(download_limit);
+        let _ = user.update(GLOBAL_DB_POOL.get().unwrap()).await?;
+
+        self.rate_limits
+            .insert(player_id, (upload_limit, download_limit));
+        GLOBAL_MANAGER
+            .tunnel_manager
+            .refresh_receiver_tunnels(player_id)
+            .await;
+
+        Ok(())
+    }
+
+    /// 修改玩家流量配额，quota_bytes 为 0 表示不限额
+    ///
+    /// 修改后立即执行一次配额检查，超额的硬限额玩家通道会被暂停，不再超额的会被恢复。
+    pub async fn update_player_quota(
+        &self,
+        player_id: u32,
+        quota: &PlayerQuota,
+    ) -> anyhow::Result<()> {
+        quota.validate()?;
+        let user = User::find_by_id(player_id)
+            .one(GLOBAL_DB_POOL.get().unwrap())
+            .await?;
+        anyhow::ensure!(user.is_some(), "can't find user: {}", player_id);
+
+        let mut user: user::ActiveModel = user.unwrap().into();
+        user.quota_bytes = Set(quota.quota_bytes);
+        user.quota_period = Set(quota.quota_period);
+        user.quota_reset_day = Set(quota.quota_reset_day);
+        user.quota_hard = Set(quota.quota_hard as u8);
+        let _ = user.update(GLOBAL_DB_POOL.get().unwrap()).await?;
+
+        if let Err(err) = check_quotas().await {
+            log::error!("quota check error: {}", err);
+        }
+        Ok(())
+    }
 }
 
 pub(crate) fn start_transport_idle_cleanup_loop() {
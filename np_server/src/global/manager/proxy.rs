@@ -1,18 +1,46 @@
 use crate::global::manager::GLOBAL_MANAGER;
+use crate::orm_entity::tunnel;
 use crate::player::PlayerId;
 use dashmap::DashMap;
 use log::{debug, error};
 use np_base::proxy::inlet::{Inlet, InletDataEx, InletProxyType};
-use np_base::proxy::outlet::Outlet;
-use np_base::proxy::{OutputFuncType, ProxyMessage};
+use np_base::proxy::outlet::{Outlet, OutletDataEx};
+use np_base::proxy::rate_limit::{RateLimiterRegistry, RateLimiters};
+use np_base::proxy::{OutputFuncType, ProxyMessage, SessionSnapshot};
+use np_proto::class_def::SessionInfo;
 use np_proto::message_map::{get_message_size, MessageType};
+use np_proto::server_client::{SessionCloseReq, SessionListReq};
 use np_proto::utils::message_bridge;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 向客户端查询/关闭会话的超时时间
+const SESSION_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 通道流量计数，刷库时取走清零
+#[derive(Default)]
+struct TunnelTraffic {
+    /// 入口发往出口的数据字节数
+    bytes_in: AtomicU64,
+    /// 出口返回入口的数据字节数
+    bytes_out: AtomicU64,
+    /// 新建会话数
+    sessions: AtomicU64,
+}
+
+/// 通道流量快照 (通道 ID, 入口→出口字节数, 出口→入口字节数, 新建会话数)
+pub type TunnelTrafficSnapshot = (u32, u64, u64, u64);
 
 pub struct ProxyManager {
     outlets: Arc<DashMap<u32, Arc<Outlet>>>,
     inlets: Arc<DashMap<u32, Inlet>>,
+    rate_limiters: RateLimiterRegistry,
+    tunnel_traffic: DashMap<u32, TunnelTraffic>,
+    /// 等待客户端回复的会话查询/关闭请求，key 为请求 ID，value 为 (被请求的玩家, 回复通道)
+    session_requests: DashMap<u32, (PlayerId, oneshot::Sender<MessageType>)>,
+    next_session_request_id: AtomicU32,
 }
 
 impl ProxyManager {
@@ -20,13 +48,248 @@ impl ProxyManager {
         Self {
             outlets: Arc::new(DashMap::new()),
             inlets: Arc::new(DashMap::new()),
+            rate_limiters: RateLimiterRegistry::new(),
+            tunnel_traffic: DashMap::new(),
+            session_requests: DashMap::new(),
+            next_session_request_id: AtomicU32::new(1),
+        }
+    }
+    /// 服务端托管的入口、出口当前会话数量，返回 (通道 ID, 是否入口, 会话数)
+    pub fn session_counts(&self) -> Vec<(u32, bool, usize)> {
+        let mut counts: Vec<(u32, bool, usize)> = self
+            .inlets
+            .iter()
+            .map(|entry| (*entry.key(), true, entry.value().session_count()))
+            .collect();
+        counts.extend(
+            self.outlets
+                .iter()
+                .map(|entry| (*entry.key(), false, entry.value().session_count())),
+        );
+        counts
+    }
+
+    /// 服务端托管的入口、出口上某个通道的活跃会话
+    pub fn local_sessions(&self, tunnel_id: u32) -> Vec<SessionInfo> {
+        let to_info = |snapshot: SessionSnapshot, is_inlet: bool| SessionInfo {
+            tunnel_id,
+            session_id: snapshot.session_id,
+            is_inlet,
+            client_addr: snapshot.client_addr,
+            target_addr: snapshot.target_addr,
+            start_time: snapshot.start_time,
+            bytes_in: snapshot.bytes_in,
+            bytes_out: snapshot.bytes_out,
+        };
+        let mut sessions = Vec::new();
+        if let Some(inlet) = self.inlets.get(&tunnel_id) {
+            sessions.extend(inlet.sessions().into_iter().map(|s| to_info(s, true)));
+        }
+        if let Some(outlet) = self.outlets.get(&tunnel_id) {
+            sessions.extend(outlet.sessions().into_iter().map(|s| to_info(s, false)));
+        }
+        sessions
+    }
+
+    /// 强制关闭服务端托管的会话，会话不存在时返回 false
+    pub fn close_local_session(&self, tunnel_id: u32, session_id: u32, is_inlet: bool) -> bool {
+        if is_inlet {
+            self.inlets
+                .get(&tunnel_id)
+                .is_some_and(|inlet| inlet.close_session(session_id))
+        } else {
+            self.outlets
+                .get(&tunnel_id)
+                .is_some_and(|outlet| outlet.close_session(session_id))
+        }
+    }
+
+    /// 查询客户端上某个通道的活跃会话
+    pub async fn remote_sessions(
+        &self,
+        player_id: PlayerId,
+        tunnel_id: u32,
+    ) -> anyhow::Result<Vec<SessionInfo>> {
+        let response = self
+            .request_player(player_id, |request_id| {
+                MessageType::ServerClientSessionListReq(SessionListReq {
+                    request_id,
+                    tunnel_id,
+                })
+            })
+            .await?;
+        match response {
+            MessageType::ClientServerSessionListAck(ack) => Ok(ack.sessions),
+            _ => Err(anyhow::anyhow!("unexpected session list response")),
+        }
+    }
+
+    /// 强制关闭客户端上的会话，会话不存在时返回 false
+    pub async fn close_remote_session(
+        &self,
+        player_id: PlayerId,
+        tunnel_id: u32,
+        session_id: u32,
+        is_inlet: bool,
+    ) -> anyhow::Result<bool> {
+        let response = self
+            .request_player(player_id, |request_id| {
+                MessageType::ServerClientSessionCloseReq(SessionCloseReq {
+                    request_id,
+                    tunnel_id,
+                    session_id,
+                    is_inlet,
+                })
+            })
+            .await?;
+        match response {
+            MessageType::ClientServerSessionCloseAck(ack) => Ok(ack.closed),
+            _ => Err(anyhow::anyhow!("unexpected session close response")),
+        }
+    }
+
+    /// 向在线玩家推送请求并等待回复
+    async fn request_player(
+        &self,
+        player_id: PlayerId,
+        build_request: impl FnOnce(u32) -> MessageType,
+    ) -> anyhow::Result<MessageType> {
+        let player = GLOBAL_MANAGER
+            .player_manager
+            .get_player(player_id)
+            .ok_or_else(|| anyhow::anyhow!("player {} not found", player_id))?;
+
+        let request_id = self.next_session_request_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.session_requests.insert(request_id, (player_id, tx));
+
+        let result = async {
+            {
+                let player = player.read().await;
+                anyhow::ensure!(player.is_online(), "player {} is offline", player_id);
+                player.send_push(&build_request(request_id))?;
+            }
+            tokio::time::timeout(SESSION_REQUEST_TIMEOUT, rx)
+                .await
+                .map_err(|_| anyhow::anyhow!("player {} response timeout", player_id))?
+                .map_err(|_| anyhow::anyhow!("player {} request canceled", player_id))
+        }
+        .await;
+        self.session_requests.remove(&request_id);
+        result
+    }
+
+    /// 客户端回复会话查询/关闭请求，只接受被请求玩家的回复
+    pub(crate) fn on_session_response(
+        &self,
+        player_id: PlayerId,
+        request_id: u32,
+        message: MessageType,
+    ) {
+        if let Some((_, (_, tx))) = self
+            .session_requests
+            .remove_if(&request_id, |_, (request_player_id, _)| {
+                *request_player_id == player_id
+            })
+        {
+            let _ = tx.send(message);
+        }
+    }
+
+    /// 统计经过服务端的通道数据与新建会话
+    ///
+    /// 只统计数据负载，不含协议头；同一客户端内部的本机通道不经过服务端，无法统计。
+    fn record_tunnel_traffic(&self, tunnel_id: u32, message: &ProxyMessage) {
+        let (bytes_in, bytes_out, sessions) = match message {
+            ProxyMessage::I2oConnect(..) => (0, 0, 1),
+            ProxyMessage::I2oSendData(_, data) | ProxyMessage::I2oSendToData(_, data, _) => {
+                (data.len() as u64, 0, 0)
+            }
+            ProxyMessage::O2iRecvData(_, data) | ProxyMessage::O2iRecvDataFrom(_, data, _) => {
+                (0, data.len() as u64, 0)
+            }
+            _ => return,
+        };
+        let add = |traffic: &TunnelTraffic| {
+            traffic.bytes_in.fetch_add(bytes_in, Ordering::Relaxed);
+            traffic.bytes_out.fetch_add(bytes_out, Ordering::Relaxed);
+            traffic.sessions.fetch_add(sessions, Ordering::Relaxed);
+        };
+        if let Some(traffic) = self.tunnel_traffic.get(&tunnel_id) {
+            add(&traffic);
+        } else {
+            add(&self.tunnel_traffic.entry(tunnel_id).or_default());
         }
     }
+
+    /// 取走所有通道尚未刷库的流量计数
+    pub fn take_tunnel_traffic(&self) -> Vec<TunnelTrafficSnapshot> {
+        let snapshots: Vec<TunnelTrafficSnapshot> = self
+            .tunnel_traffic
+            .iter()
+            .map(|entry| {
+                let traffic = entry.value();
+                (
+                    *entry.key(),
+                    traffic.bytes_in.swap(0, Ordering::Relaxed),
+                    traffic.bytes_out.swap(0, Ordering::Relaxed),
+                    traffic.sessions.swap(0, Ordering::Relaxed),
+                )
+            })
+            .filter(|(_, bytes_in, bytes_out, sessions)| {
+                *bytes_in > 0 || *bytes_out > 0 || *sessions > 0
+            })
+            .collect();
+        // 清理空闲计数，已删除的通道不会一直留在表中
+        self.tunnel_traffic.retain(|_, traffic| {
+            traffic.bytes_in.load(Ordering::Relaxed) > 0
+                || traffic.bytes_out.load(Ordering::Relaxed) > 0
+                || traffic.sessions.load(Ordering::Relaxed) > 0
+        });
+        snapshots
+    }
+
+    /// 刷库失败时归还流量计数
+    pub fn add_tunnel_traffic(&self, snapshot: TunnelTrafficSnapshot) {
+        let (tunnel_id, bytes_in, bytes_out, sessions) = snapshot;
+        let traffic = self.tunnel_traffic.entry(tunnel_id).or_default();
+        traffic.bytes_in.fetch_add(bytes_in, Ordering::Relaxed);
+        traffic.bytes_out.fetch_add(bytes_out, Ordering::Relaxed);
+        traffic.sessions.fetch_add(sessions, Ordering::Relaxed);
+    }
+
+    /// 查询通道尚未刷库的流量计数，返回 (入口→出口字节数, 出口→入口字节数, 新建会话数)
+    pub fn get_tunnel_traffic(&self, tunnel_id: u32) -> (u64, u64, u64) {
+        self.tunnel_traffic
+            .get(&tunnel_id)
+            .map(|traffic| {
+                (
+                    traffic.bytes_in.load(Ordering::Relaxed),
+                    traffic.bytes_out.load(Ordering::Relaxed),
+                    traffic.sessions.load(Ordering::Relaxed),
+                )
+            })
+            .unwrap_or_default()
+    }
+
     pub async fn sync_tunnels(&self) {
         // ── 收集阶段：持有读锁，全程同步，尽快释放 ───────────────────────────────
         let (outlet_removes, inlet_removes, outlets_to_add, inlets_to_add) = {
             let tunnels = GLOBAL_MANAGER.tunnel_manager.tunnels.read().await;
 
+            // 限速变更原地生效，无需重启入口和出口
+            let active: Vec<&tunnel::Model> = tunnels
+                .iter()
+                .filter(|t| t.enabled == 1 && (t.sender == 0 || t.receiver == 0))
+                .collect();
+            for tunnel in &active {
+                self.tunnel_rate_limiters(tunnel);
+            }
+            self.rate_limiters.retain(
+                &active.iter().map(|t| t.id).collect::<Vec<_>>(),
+                &active.iter().map(|t| t.receiver).collect::<Vec<_>>(),
+            );
+
             // 需要删除的出口 id
             let outlet_removes: Vec<u32> = self
                 .outlets
@@ -65,7 +328,18 @@ impl ProxyManager {
             let outlets_to_add: Vec<_> = tunnels
                 .iter()
                 .filter(|t| t.enabled == 1 && t.sender == 0 && !self.outlets.contains_key(&t.id))
-                .map(|t| (t.id, t.receiver, t.sender, t.outlet_description()))
+                .map(|t| {
+                    (
+                        t.id,
+                        t.receiver,
+                        t.sender,
+                        t.outlet_description(),
+                        self.tunnel_rate_limiters(t),
+                        t.proxy_protocol,
+                        t.acl_allow_rules(),
+                        t.acl_deny_rules(),
+                    )
+                })
                 .collect();
 
             // 需要添加的入口（克隆所需字段）
@@ -84,7 +358,12 @@ impl ProxyManager {
                         t.encryption_method.clone(),
                         t.username.clone(),
                         t.password.clone(),
+                        t.parsed_custom_mapping(),
+                        t.tls_certificate_pairs(),
+                        t.acl_allow_rules(),
+                        t.acl_deny_rules(),
                         t.inlet_description(),
+                        self.tunnel_rate_limiters(t),
                     )
                 })
                 .collect();
@@ -115,7 +394,17 @@ impl ProxyManager {
         }
 
         // 添加新出口
-        for (tunnel_id, receiver, sender, outlet_desc) in outlets_to_add {
+        for (
+            tunnel_id,
+            receiver,
+            sender,
+            outlet_desc,
+            rate_limiters,
+            proxy_protocol,
+            acl_allow,
+            acl_deny,
+        ) in outlets_to_add
+        {
             let this_machine = receiver == sender;
             let inlets = self.inlets.clone();
             let player_id = receiver;
@@ -124,6 +413,9 @@ impl ProxyManager {
                 let inlets = inlets.clone();
                 Box::pin(async move {
                     if this_machine {
+                        GLOBAL_MANAGER
+                            .proxy_manager
+                            .record_tunnel_traffic(tunnel_id, &message);
                         if let Some(inlet) = inlets.get(&tunnel_id) {
                             inlet.input(message).await;
                         } else {
@@ -136,8 +428,17 @@ impl ProxyManager {
                 })
             });
             debug!("start outlet({outlet_desc})");
-            self.outlets
-                .insert(tunnel_id, Outlet::new(outlet_output, outlet_desc));
+            self.outlets.insert(
+                tunnel_id,
+                Outlet::new(
+                    outlet_output,
+                    outlet_desc,
+                    rate_limiters,
+                    OutletDataEx::new()
+                        .with_proxy_protocol(proxy_protocol as u32)
+                        .with_access_list(&acl_allow, &acl_deny),
+                ),
+            );
         }
 
         // 添加新入口
@@ -152,7 +453,12 @@ impl ProxyManager {
             encryption_method,
             username,
             password,
+            custom_mapping,
+            tls_certificates,
+            acl_allow,
+            acl_deny,
             inlet_desc,
+            rate_limiters,
         ) in inlets_to_add
         {
             let this_machine = receiver == sender;
@@ -163,6 +469,9 @@ impl ProxyManager {
                 let outlets = outlets.clone();
                 Box::pin(async move {
                     if this_machine {
+                        GLOBAL_MANAGER
+                            .proxy_manager
+                            .record_tunnel_traffic(tunnel_id, &message);
                         if let Some(outlet) = outlets.get(&tunnel_id) {
                             outlet.input(message).await;
                         } else {
@@ -179,7 +488,7 @@ impl ProxyManager {
             if matches!(inlet_proxy_type, InletProxyType::UNKNOWN) {
                 error!("inlet({source}) unknown tunnel type: {tunnel_type}");
             } else {
-                let mut inlet = Inlet::new(inlet_output, inlet_desc);
+                let mut inlet = Inlet::new(inlet_output, inlet_desc, rate_limiters);
                 if let Err(err) = inlet
                     .start(
                         inlet_proxy_type,
@@ -187,7 +496,10 @@ impl ProxyManager {
                         endpoint,
                         is_compressed == 1,
                         encryption_method,
-                        InletDataEx::new(username, password),
+                        InletDataEx::new(username, password)
+                            .with_custom_mapping(custom_mapping)
+                            .with_tls_certificates(tls_certificates)
+                            .with_access_list(&acl_allow, &acl_deny),
                     )
                     .await
                 {
@@ -200,12 +512,34 @@ impl ProxyManager {
         }
     }
 
+    /// 通道的带宽限速器：通道限速 + 接收方用户限速
+    fn tunnel_rate_limiters(&self, tunnel: &tunnel::Model) -> RateLimiters {
+        let (user_upload_limit, user_download_limit) =
+            GLOBAL_MANAGER.player_manager.rate_limit(tunnel.receiver);
+        RateLimiters::new(vec![
+            self.rate_limiters.tunnel(
+                tunnel.id,
+                tunnel.upload_limit.max(0) as u64,
+                tunnel.download_limit.max(0) as u64,
+            ),
+            self.rate_limiters.user(
+                tunnel.receiver,
+                user_upload_limit.max(0) as u64,
+                user_download_limit.max(0) as u64,
+            ),
+        ])
+    }
+
     pub(crate) async fn send_proxy_message(
         from_player_id: PlayerId,
         to_player_id: PlayerId,
         tunnel_id: u32,
         proxy_message: ProxyMessage,
     ) {
+        GLOBAL_MANAGER
+            .proxy_manager
+            .record_tunnel_traffic(tunnel_id, &proxy_message);
+
         if to_player_id == 0 {
             if message_bridge::is_i2o_message(&proxy_message) {
                 send_input_to_outlet(&tunnel_id, proxy_message).await;
@@ -236,6 +570,8 @@ impl ProxyManager {
                 session_id,
                 false,
                 format!("no player {to_player_id} or the player is offline"),
+                String::new(),
+                String::new(),
             )),
 
             ProxyMessage::I2oSendData(session_id, ..)
@@ -244,6 +580,7 @@ impl ProxyManager {
             }
 
             ProxyMessage::O2iConnect(session_id, ..)
+            | ProxyMessage::O2iBindAccept(session_id, ..)
             | ProxyMessage::O2iRecvData(session_id, ..)
             | ProxyMessage::O2iSendDataResult(session_id, ..) => {
                 Some(ProxyMessage::I2oDisconnect(session_id))
@@ -291,3 +628,43 @@ async fn send_input_to_inlet(tunnel_id: &u32, proxy_message: ProxyMessage) {
         inlet.input(proxy_message).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_tunnel_traffic_counters() {
+        let manager = ProxyManager::new();
+        manager.record_tunnel_traffic(
+            1,
+            &ProxyMessage::I2oConnect(
+                1,
+                0,
+                true,
+                false,
+                "127.0.0.1:80".into(),
+                String::new(),
+                String::new(),
+                String::new(),
+                false,
+            ),
+        );
+        manager.record_tunnel_traffic(
+            1,
+            &ProxyMessage::I2oSendData(1, Bytes::from_static(b"hello")),
+        );
+        manager.record_tunnel_traffic(1, &ProxyMessage::O2iRecvData(1, Bytes::from_static(b"ok!")));
+        manager.record_tunnel_traffic(1, &ProxyMessage::O2iSendDataResult(1, 5));
+        assert_eq!(manager.get_tunnel_traffic(1), (5, 3, 1));
+
+        assert_eq!(manager.take_tunnel_traffic(), vec![(1, 5, 3, 1)]);
+        assert_eq!(manager.get_tunnel_traffic(1), (0, 0, 0));
+        assert!(manager.take_tunnel_traffic().is_empty());
+
+        // 刷库失败归还
+        manager.add_tunnel_traffic((2, 10, 20, 1));
+        assert_eq!(manager.get_tunnel_traffic(2), (10, 20, 1));
+    }
+}
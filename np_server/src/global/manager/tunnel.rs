@@ -7,14 +7,38 @@ use crate::utils::str::{
     get_tunnel_address_port, is_valid_tunnel_endpoint_address, is_valid_tunnel_source_address,
 };
 use anyhow::anyhow;
+use np_base::net::tls::SniCertResolver;
+use np_base::proxy::acl::AccessList;
+use np_base::proxy::header_rules::{is_header_rule_key, HeaderRules};
 use np_base::proxy::inlet::InletProxyType;
 use np_proto::message_map::MessageType;
 use np_proto::{class_def, server_client};
 use sea_orm::ActiveValue::Set;
 use sea_orm::{ActiveModelTrait, EntityTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use tokio::sync::RwLock;
 
+/// 通道证书，以 JSON 数组保存在 `tunnel.tls_certificates`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TunnelCertificate {
+    /// PEM 证书链
+    pub certificate: String,
+    /// PEM 私钥
+    pub private_key: String,
+}
+
+impl From<&TunnelCertificate> for class_def::TlsCertificate {
+    fn from(cert: &TunnelCertificate) -> Self {
+        Self {
+            certificate: cert.certificate.clone(),
+            private_key: cert.private_key.clone(),
+        }
+    }
+}
+
 pub struct TunnelManager {
     pub tunnels: RwLock<Vec<tunnel::Model>>,
 }
@@ -49,10 +73,18 @@ impl TunnelManager {
             is_compressed: Set(tunnel.is_compressed),
             custom_mapping: Set(tunnel.custom_mapping.to_owned()),
             encryption_method: Set(tunnel.encryption_method.to_owned()),
+            upload_limit: Set(tunnel.upload_limit),
+            download_limit: Set(tunnel.download_limit),
+            quota_suspended: Set(0),
+            tls_certificates: Set(tunnel.tls_certificates.to_owned()),
+            proxy_protocol: Set(tunnel.proxy_protocol),
+            acl_allow: Set(tunnel.acl_allow.to_owned()),
+            acl_deny: Set(tunnel.acl_deny.to_owned()),
         };
 
         let new_tunnel = new_tunnel.insert(GLOBAL_DB_POOL.get().unwrap()).await?;
         tunnel.id = new_tunnel.id;
+        tunnel.quota_suspended = 0;
 
         Self::broadcast_tunnel_info(tunnel.sender, &tunnel, false).await;
         if tunnel.sender != tunnel.receiver {
@@ -105,21 +137,35 @@ impl TunnelManager {
         mut tunnel: tunnel::Model,
         preserve_password: bool,
     ) -> anyhow::Result<()> {
-        self.tunnel_detection(&tunnel).await?;
-
         // 一次读锁找 position 和旧值，立即 drop
         let found = {
             let guard = self.tunnels.read().await;
             guard.iter().enumerate().find_map(|(i, t)| {
                 if t.id == tunnel.id {
-                    Some((i, t.sender, t.receiver))
+                    Some((
+                        i,
+                        t.sender,
+                        t.receiver,
+                        t.quota_suspended,
+                        t.tls_certificates.clone(),
+                    ))
                 } else {
                     None
                 }
             })
         }; // ← 读锁在此 drop
 
-        if let Some((index, old_sender, old_receiver)) = found {
+        if let Some((index, old_sender, old_receiver, quota_suspended, tls_certificates)) = found {
+            // 证书通过单独的接口维护
+            tunnel.tls_certificates = tls_certificates;
+            self.tunnel_detection(&tunnel).await?;
+
+            // 被配额暂停的通道保持暂停，由配额检查负责恢复
+            tunnel.quota_suspended = quota_suspended;
+            if quota_suspended == 1 {
+                tunnel.enabled = 0;
+            }
+
             let db_tunnel = Tunnel::find_by_id(tunnel.id)
                 .one(GLOBAL_DB_POOL.get().unwrap())
                 .await?;
@@ -143,6 +189,12 @@ impl TunnelManager {
             db_tunnel.is_compressed = Set(tunnel.is_compressed.to_owned());
             db_tunnel.custom_mapping = Set(tunnel.custom_mapping.to_owned());
             db_tunnel.encryption_method = Set(tunnel.encryption_method.to_owned());
+            db_tunnel.upload_limit = Set(tunnel.upload_limit);
+            db_tunnel.download_limit = Set(tunnel.download_limit);
+            db_tunnel.quota_suspended = Set(tunnel.quota_suspended);
+            db_tunnel.proxy_protocol = Set(tunnel.proxy_protocol);
+            db_tunnel.acl_allow = Set(tunnel.acl_allow.to_owned());
+            db_tunnel.acl_deny = Set(tunnel.acl_deny.to_owned());
             db_tunnel.update(GLOBAL_DB_POOL.get().unwrap()).await?;
 
             if old_sender != tunnel.sender {
@@ -163,8 +215,77 @@ impl TunnelManager {
         Err(anyhow!(format!("Unable to find tunnel_id: {}", tunnel.id)))
     }
 
+    /// 更新通道的 TLS 证书
+    pub async fn update_tunnel_certificates(
+        &self,
+        tunnel_id: u32,
+        certificates: Vec<TunnelCertificate>,
+    ) -> anyhow::Result<()> {
+        let found = {
+            let guard = self.tunnels.read().await;
+            guard.iter().enumerate().find_map(|(i, t)| {
+                if t.id == tunnel_id {
+                    Some((i, t.clone()))
+                } else {
+                    None
+                }
+            })
+        };
+
+        if let Some((index, mut tunnel)) = found {
+            tunnel.tls_certificates = if certificates.is_empty() {
+                String::new()
+            } else {
+                serde_json::to_string(&certificates)?
+            };
+            Self::tls_detection(&tunnel)?;
+
+            let db_tunnel = Tunnel::find_by_id(tunnel_id)
+                .one(GLOBAL_DB_POOL.get().unwrap())
+                .await?;
+            anyhow::ensure!(db_tunnel.is_some(), "Can't find tunnel: {}", tunnel_id);
+
+            let mut db_tunnel: tunnel::ActiveModel = db_tunnel.unwrap().into();
+            db_tunnel.tls_certificates = Set(tunnel.tls_certificates.clone());
+            db_tunnel.update(GLOBAL_DB_POOL.get().unwrap()).await?;
+
+            Self::broadcast_tunnel_info(tunnel.sender, &tunnel, false).await;
+            if tunnel.sender != tunnel.receiver {
+                Self::broadcast_tunnel_info(tunnel.receiver, &tunnel, false).await;
+            }
+
+            self.tunnels.write().await[index] = tunnel;
+            GLOBAL_MANAGER.proxy_manager.sync_tunnels().await;
+            return Ok(());
+        }
+
+        Err(anyhow!(format!("Unable to find tunnel_id: {}", tunnel_id)))
+    }
+
     /// 更新通道启用状态
+    ///
+    /// 手动修改会清除配额暂停标记，被配额暂停的通道不允许手动启用。
     pub async fn update_tunnel_status(&self, tunnel_id: u32, enabled: u8) -> anyhow::Result<()> {
+        self.set_tunnel_status(tunnel_id, enabled, 0, true).await
+    }
+
+    /// 因流量配额暂停或恢复通道
+    pub async fn update_tunnel_quota_suspended(
+        &self,
+        tunnel_id: u32,
+        suspended: bool,
+    ) -> anyhow::Result<()> {
+        self.set_tunnel_status(tunnel_id, !suspended as u8, suspended as u8, false)
+            .await
+    }
+
+    async fn set_tunnel_status(
+        &self,
+        tunnel_id: u32,
+        enabled: u8,
+        quota_suspended: u8,
+        manual: bool,
+    ) -> anyhow::Result<()> {
         let found = {
             let guard = self.tunnels.read().await;
             guard.iter().enumerate().find_map(|(i, t)| {
@@ -177,15 +298,23 @@ impl TunnelManager {
         };
 
         if let Some((index, mut tunnel)) = found {
+            anyhow::ensure!(
+                !(manual && enabled == 1 && tunnel.quota_suspended == 1),
+                "tunnel {} is suspended by traffic quota",
+                tunnel_id
+            );
+
             let db_tunnel = Tunnel::find_by_id(tunnel_id)
                 .one(GLOBAL_DB_POOL.get().unwrap())
                 .await?;
             anyhow::ensure!(db_tunnel.is_some(), "Can't find tunnel: {}", tunnel_id);
 
             tunnel.enabled = enabled;
+            tunnel.quota_suspended = quota_suspended;
 
             let mut db_tunnel: tunnel::ActiveModel = db_tunnel.unwrap().into();
             db_tunnel.enabled = Set(enabled);
+            db_tunnel.quota_suspended = Set(quota_suspended);
             db_tunnel.update(GLOBAL_DB_POOL.get().unwrap()).await?;
 
             Self::broadcast_tunnel_info(tunnel.sender, &tunnel, false).await;
@@ -201,6 +330,26 @@ impl TunnelManager {
         Err(anyhow!(format!("Unable to find tunnel_id: {}", tunnel_id)))
     }
 
+    /// 重新下发接收方为指定玩家的通道（玩家限速变更后调用）
+    pub async fn refresh_receiver_tunnels(&self, receiver: PlayerId) {
+        let tunnels: Vec<tunnel::Model> = self
+            .tunnels
+            .read()
+            .await
+            .iter()
+            .filter(|t| t.receiver == receiver)
+            .cloned()
+            .collect();
+
+        for tunnel in &tunnels {
+            Self::broadcast_tunnel_info(tunnel.sender, tunnel, false).await;
+            if tunnel.sender != tunnel.receiver {
+                Self::broadcast_tunnel_info(tunnel.receiver, tunnel, false).await;
+            }
+        }
+        GLOBAL_MANAGER.proxy_manager.sync_tunnels().await;
+    }
+
     /// 广播通道修改通知
     async fn broadcast_tunnel_info(player_id: PlayerId, tunnel: &tunnel::Model, is_delete: bool) {
         if player_id != 0 {
@@ -211,7 +360,7 @@ impl TunnelManager {
                     .send_push(&MessageType::ServerClientModifyTunnelNtf(
                         server_client::ModifyTunnelNtf {
                             is_delete,
-                            tunnel: Some(tunnel.into()),
+                            tunnel: Some(tunnel.to_proto(player_id)),
                         },
                     ));
             }
@@ -224,17 +373,27 @@ impl TunnelManager {
             return Err(anyhow!("source address format error"));
         }
 
-        // SOCKS5 HTTP类型不检测
+        // 代理类型（SOCKS4/5 HTTP MIXED）与透明代理不检测
         let proxy_type = InletProxyType::from_u32(tunnel.tunnel_type);
         match proxy_type {
-            InletProxyType::TCP | InletProxyType::UDP
+            InletProxyType::TCP | InletProxyType::UDP | InletProxyType::VHOST
                 if !is_valid_tunnel_endpoint_address(&tunnel.endpoint) =>
             {
                 return Err(anyhow!("endpoint address format error"));
             }
+            InletProxyType::VHOST => Self::vhost_detection(tunnel)?,
             _ => {}
         }
 
+        Self::tls_detection(tunnel)?;
+        Self::header_rules_detection(tunnel)?;
+        Self::proxy_protocol_detection(tunnel)?;
+        Self::access_list_detection(tunnel)?;
+
+        if tunnel.upload_limit < 0 || tunnel.download_limit < 0 {
+            return Err(anyhow!("rate limit must not be negative"));
+        }
+
         // 玩家id检测
         self.player_id_detection(tunnel.sender).await?;
         self.player_id_detection(tunnel.receiver).await?;
@@ -245,12 +404,136 @@ impl TunnelManager {
                 tunnel.receiver,
                 get_tunnel_address_port(&tunnel.source),
                 Some(tunnel.id),
-                matches!(proxy_type, InletProxyType::UDP),
+                tunnel.tunnel_type,
             )
             .await
         {
             return Err(anyhow!("port already in use"));
         }
+
+        if matches!(proxy_type, InletProxyType::VHOST) {
+            self.vhost_conflict_detection(tunnel).await?;
+        }
+        Ok(())
+    }
+
+    /// 虚拟主机域名检测，目标地址为空的域名使用通道目标地址
+    fn vhost_detection(tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        let domains = tunnel.vhost_domains();
+        if domains.is_empty() {
+            return Err(anyhow!("vhost tunnel requires at least one domain"));
+        }
+        for (domain, target) in &domains {
+            if domain.is_empty() || domain.contains(|c: char| c.is_whitespace() || c == ':') {
+                return Err(anyhow!("vhost domain '{}' format error", domain));
+            }
+            if !target.is_empty() && !is_valid_tunnel_endpoint_address(target) {
+                return Err(anyhow!("vhost domain '{}' endpoint format error", domain));
+            }
+        }
+        Ok(())
+    }
+
+    /// TLS 终止只支持 TCP 与虚拟主机通道，证书必须能被解析
+    fn tls_detection(tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        let certificates = tunnel.tls_certificate_pairs();
+        if certificates.is_empty() {
+            return Ok(());
+        }
+        if !matches!(
+            InletProxyType::from_u32(tunnel.tunnel_type),
+            InletProxyType::TCP | InletProxyType::VHOST
+        ) {
+            return Err(anyhow!(
+                "TLS termination is only supported by tcp and vhost tunnels"
+            ));
+        }
+        SniCertResolver::new(&certificates)?;
+        Ok(())
+    }
+
+    /// HTTP 头改写规则只对 HTTP 与混合代理通道生效
+    fn header_rules_detection(tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        if HeaderRules::parse(&tunnel.parsed_custom_mapping())?.is_empty() {
+            return Ok(());
+        }
+        if !matches!(
+            InletProxyType::from_u32(tunnel.tunnel_type),
+            InletProxyType::HTTP | InletProxyType::MIXED
+        ) {
+            return Err(anyhow!(
+                "header rules are only supported by http and mixed tunnels"
+            ));
+        }
+        Ok(())
+    }
+
+    /// PROXY protocol 头部只能发给 tcp 与 vhost 通道的固定目标，代理类通道的目标由客户端决定
+    fn proxy_protocol_detection(tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        match tunnel.proxy_protocol {
+            0 => Ok(()),
+            1 | 2 => {
+                if matches!(
+                    InletProxyType::from_u32(tunnel.tunnel_type),
+                    InletProxyType::TCP | InletProxyType::VHOST
+                ) {
+                    Ok(())
+                } else {
+                    Err(anyhow!(
+                        "PROXY protocol is only supported by tcp and vhost tunnels"
+                    ))
+                }
+            }
+            _ => Err(anyhow!("PROXY protocol version must be 0, 1 or 2")),
+        }
+    }
+
+    /// 访问控制只对目标由客户端决定的代理类通道生效
+    fn access_list_detection(tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        if AccessList::parse(&tunnel.acl_allow_rules(), &tunnel.acl_deny_rules())?.is_empty() {
+            return Ok(());
+        }
+        if !matches!(
+            InletProxyType::from_u32(tunnel.tunnel_type),
+            InletProxyType::SOCKS5
+                | InletProxyType::HTTP
+                | InletProxyType::SOCKS4
+                | InletProxyType::MIXED
+                | InletProxyType::TRANSPARENT
+        ) {
+            return Err(anyhow!(
+                "access lists are only supported by socks5, http, socks4, mixed and transparent tunnels"
+            ));
+        }
+        Ok(())
+    }
+
+    /// 共用端口的虚拟主机通道必须监听同一地址，且域名不能重复
+    async fn vhost_conflict_detection(&self, tunnel: &tunnel::Model) -> anyhow::Result<()> {
+        let port = get_tunnel_address_port(&tunnel.source);
+        let domains = tunnel.vhost_domains();
+        for x in self.tunnels.read().await.iter() {
+            if x.receiver != tunnel.receiver
+                || x.id == tunnel.id
+                || !matches!(
+                    InletProxyType::from_u32(x.tunnel_type),
+                    InletProxyType::VHOST
+                )
+                || get_tunnel_address_port(&x.source) != port
+            {
+                continue;
+            }
+            if x.source != tunnel.source {
+                return Err(anyhow!("port already in use"));
+            }
+            if let Some(domain) = x
+                .vhost_domains()
+                .keys()
+                .find(|domain| domains.contains_key(*domain))
+            {
+                return Err(anyhow!("vhost domain '{}' already in use", domain));
+            }
+        }
         Ok(())
     }
 
@@ -263,18 +546,28 @@ impl TunnelManager {
         }
     }
 
-    /// 检测端口是否冲突
+    /// 检测端口是否冲突，监听协议（TCP/UDP）有重叠才算冲突
+    ///
+    /// 虚拟主机通道之间可以共用端口，按域名区分
     async fn port_conflict_detection(
         &self,
         receiver: u32,
         port: Option<u16>,
         tunnel_id: Option<u32>,
-        is_udp: bool,
+        tunnel_type: u32,
     ) -> bool {
+        let proxy_type = InletProxyType::from_u32(tunnel_type);
+        let (is_tcp, is_udp) = proxy_type.listen_protocols();
         self.tunnels.read().await.iter().any(|x| {
+            let x_proxy_type = InletProxyType::from_u32(x.tunnel_type);
+            let (x_tcp, x_udp) = x_proxy_type.listen_protocols();
             x.receiver == receiver
                 && tunnel_id != Some(x.id)
-                && is_udp == matches!(InletProxyType::from_u32(x.tunnel_type), InletProxyType::UDP)
+                && !matches!(
+                    (&proxy_type, &x_proxy_type),
+                    (InletProxyType::VHOST, InletProxyType::VHOST)
+                )
+                && ((is_tcp && x_tcp) || (is_udp && x_udp))
                 && get_tunnel_address_port(&x.source) == port
         })
     }
@@ -285,9 +578,9 @@ impl TunnelManager {
         receiver: u32,
         port: Option<u16>,
         tunnel_id: Option<u32>,
-        is_udp: bool,
+        tunnel_type: u32,
     ) -> bool {
-        self.port_conflict_detection(receiver, port, tunnel_id, is_udp)
+        self.port_conflict_detection(receiver, port, tunnel_id, tunnel_type)
             .await
     }
 
@@ -316,17 +609,68 @@ impl TunnelManager {
     }
 }
 
+/// 访问控制规则按行保存，去掉空行与首尾空白
+pub fn format_acl_rules(rules: &[String]) -> String {
+    rules
+        .iter()
+        .map(|rule| rule.trim())
+        .filter(|rule| !rule.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl tunnel::Model {
+    /// 下发给指定玩家的通道信息
+    ///
+    /// 只有入口（接收方）终止 TLS，证书私钥不下发给出口一方。
+    pub fn to_proto(&self, player_id: PlayerId) -> class_def::Tunnel {
+        let custom_mapping = self.parsed_custom_mapping();
+        // 用户限速按接收方计算，两端共享同一用户的限速器
+        let (user_upload_limit, user_download_limit) =
+            GLOBAL_MANAGER.player_manager.rate_limit(self.receiver);
+
+        class_def::Tunnel {
+            source: Some(class_def::TunnelPoint {
+                addr: self.source.clone(),
+            }),
+            endpoint: Some(class_def::TunnelPoint {
+                addr: self.endpoint.clone(),
+            }),
+            id: self.id,
+            enabled: self.enabled == 1,
+            sender: self.sender,
+            receiver: self.receiver,
+            tunnel_type: self.tunnel_type as i32,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            is_compressed: self.is_compressed == 1,
+            encryption_method: self.encryption_method.clone(),
+            custom_mapping,
+            upload_limit: self.upload_limit.max(0) as u64,
+            download_limit: self.download_limit.max(0) as u64,
+            user_upload_limit: user_upload_limit.max(0) as u64,
+            user_download_limit: user_download_limit.max(0) as u64,
+            tls_certificates: if player_id == self.receiver {
+                self.tls_certificates().iter().map(Into::into).collect()
+            } else {
+                Vec::new()
+            },
+            proxy_protocol: self.proxy_protocol as u32,
+            acl_allow: self.acl_allow_rules(),
+            acl_deny: self.acl_deny_rules(),
+        }
+    }
+
     pub fn outlet_description(&self) -> String {
         format!(
-            "id:{}-sender:{}-enabled:{}",
-            self.id, self.sender, self.enabled
+            "id:{}-sender:{}-enabled:{}-proxy_protocol:{}-acl_allow:{}-acl_deny:{}",
+            self.id, self.sender, self.enabled, self.proxy_protocol, self.acl_allow, self.acl_deny
         )
     }
 
     pub fn inlet_description(&self) -> String {
         format!(
-            "id:{}-source:{}-endpoint:{}-sender:{}-receiver:{}-tunnel_type:{}-username:{}-password:{}-enabled:{}-is_compressed:{}-encryption_method:{}-custom_mapping:{}",
+            "id:{}-source:{}-endpoint:{}-sender:{}-receiver:{}-tunnel_type:{}-username:{}-password:{}-enabled:{}-is_compressed:{}-encryption_method:{}-custom_mapping:{}-tls_certificates:{:x}-acl_allow:{}-acl_deny:{}",
             self.id,
             self.source,
             self.endpoint,
@@ -339,32 +683,53 @@ impl tunnel::Model {
             self.is_compressed,
             self.encryption_method,
             self.custom_mapping,
+            self.tls_certificates_hash(),
+            self.acl_allow,
+            self.acl_deny,
         )
     }
-}
 
-impl From<&tunnel::Model> for class_def::Tunnel {
-    fn from(tunnel: &tunnel::Model) -> Self {
-        let custom_mapping: HashMap<String, String> =
-            serde_json::from_str(&tunnel.custom_mapping).map_or(HashMap::new(), |x| x);
+    /// 证书内容较长且包含私钥，描述中只保留摘要
+    fn tls_certificates_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.tls_certificates.hash(&mut hasher);
+        hasher.finish()
+    }
 
-        Self {
-            source: Some(class_def::TunnelPoint {
-                addr: tunnel.source.clone(),
-            }),
-            endpoint: Some(class_def::TunnelPoint {
-                addr: tunnel.endpoint.clone(),
-            }),
-            id: tunnel.id,
-            enabled: tunnel.enabled == 1,
-            sender: tunnel.sender,
-            receiver: tunnel.receiver,
-            tunnel_type: tunnel.tunnel_type as i32,
-            username: tunnel.username.clone(),
-            password: tunnel.password.clone(),
-            is_compressed: tunnel.is_compressed == 1,
-            encryption_method: tunnel.encryption_method.clone(),
-            custom_mapping,
-        }
+    /// 入口终止 TLS 使用的证书
+    pub fn tls_certificates(&self) -> Vec<TunnelCertificate> {
+        serde_json::from_str(&self.tls_certificates).unwrap_or_default()
+    }
+
+    /// 入口终止 TLS 使用的 (证书链, 私钥) 列表
+    pub fn tls_certificate_pairs(&self) -> Vec<(String, String)> {
+        self.tls_certificates()
+            .into_iter()
+            .map(|cert| (cert.certificate, cert.private_key))
+            .collect()
+    }
+
+    /// 允许访问的目标，每行一条规则
+    pub fn acl_allow_rules(&self) -> Vec<String> {
+        self.acl_allow.lines().map(str::to_owned).collect()
+    }
+
+    /// 禁止访问的目标，每行一条规则
+    pub fn acl_deny_rules(&self) -> Vec<String> {
+        self.acl_deny.lines().map(str::to_owned).collect()
+    }
+
+    /// 解析 JSON 保存的 custom_mapping
+    pub fn parsed_custom_mapping(&self) -> HashMap<String, String> {
+        serde_json::from_str(&self.custom_mapping).unwrap_or_default()
+    }
+
+    /// 虚拟主机域名 -> 目标地址，域名统一转为小写，跳过 HTTP 头改写规则
+    pub fn vhost_domains(&self) -> HashMap<String, String> {
+        self.parsed_custom_mapping()
+            .into_iter()
+            .filter(|(key, _)| !is_header_rule_key(key))
+            .map(|(domain, target)| (domain.trim().to_ascii_lowercase(), target))
+            .collect()
     }
 }
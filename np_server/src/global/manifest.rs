@@ -0,0 +1,691 @@
+//! 声明式用户/通道清单。
+//!
+//! 从 JSON/TOML 清单读取用户与通道定义，与数据库及内存中的
+//! `PlayerManager`/`TunnelManager` 对比，按清单新增、修改、删除条目并报告差异。
+//!
+//! 用户按用户名匹配；通道按 `(receiver, source, 是否 UDP)` 匹配，
+//! 这与端口冲突检测使用的唯一性约束一致。虚拟主机通道共用端口，
+//! 另外按域名集合区分，修改域名视为删除后重新创建。
+
+use crate::global::config::GLOBAL_CONFIG;
+use crate::global::manager::tunnel::format_acl_rules;
+use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::GLOBAL_DB_POOL;
+use crate::orm_entity::prelude::User;
+use crate::orm_entity::{tunnel, user};
+use crate::player::PlayerId;
+use crate::utils::password::{
+    hash_password_async, is_password_hash, upgrade_password_hash, verify_password_async,
+};
+use crate::utils::str::is_valid_password;
+use anyhow::{anyhow, bail};
+use log::{info, warn};
+use np_base::proxy::header_rules::is_header_rule_key;
+use once_cell::sync::Lazy;
+use sea_orm::EntityTrait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+/// 清单文件
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    /// 是否删除清单中不存在的用户与通道
+    #[serde(default = "default_manifest_true_function")]
+    pub prune: bool,
+    #[serde(default)]
+    pub users: Vec<ManifestUser>,
+    #[serde(default)]
+    pub tunnels: Vec<ManifestTunnel>,
+}
+
+/// 清单中的用户
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestUser {
+    pub username: String,
+    /// 明文密码或 Argon2id 哈希（`$argon2id$...`），建议提交到仓库时使用哈希
+    pub password: String,
+    #[serde(default = "default_manifest_true_function")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub web_access: bool,
+    /// 上行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub upload_limit: i64,
+    /// 下行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub download_limit: i64,
+}
+
+/// 清单中的通道，`sender`/`receiver` 为用户名，留空表示服务端
+#[derive(Deserialize, Debug, Clone)]
+pub struct ManifestTunnel {
+    pub source: String,
+    pub endpoint: String,
+    #[serde(default)]
+    pub sender: String,
+    #[serde(default)]
+    pub receiver: String,
+    /// tcp / udp / socks5 / http
+    #[serde(default = "default_manifest_tunnel_type_function")]
+    pub tunnel_type: String,
+    #[serde(default = "default_manifest_true_function")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub is_compressed: bool,
+    #[serde(default)]
+    pub custom_mapping: HashMap<String, String>,
+    #[serde(default = "default_manifest_encryption_method_function")]
+    pub encryption_method: String,
+    #[serde(default)]
+    pub upload_limit: i64,
+    #[serde(default)]
+    pub download_limit: i64,
+    /// 出口连接目标时发送的 PROXY protocol 版本，0 为不发送
+    #[serde(default)]
+    pub proxy_protocol: u8,
+    /// 代理类通道允许访问的目标
+    #[serde(default)]
+    pub acl_allow: Vec<String>,
+    /// 代理类通道禁止访问的目标
+    #[serde(default)]
+    pub acl_deny: Vec<String>,
+}
+
+fn default_manifest_true_function() -> bool {
+    true
+}
+fn default_manifest_tunnel_type_function() -> String {
+    "tcp".to_string()
+}
+fn default_manifest_encryption_method_function() -> String {
+    "None".to_string()
+}
+
+/// 清单与当前状态的一条差异
+#[derive(Serialize, Debug, Clone)]
+pub struct ManifestChange {
+    /// user / tunnel
+    pub kind: &'static str,
+    /// create / update / delete
+    pub action: &'static str,
+    pub name: String,
+    pub detail: String,
+}
+
+enum Action {
+    CreateUser(ManifestUser),
+    UpdateUser {
+        id: PlayerId,
+        user: ManifestUser,
+        fields: Vec<&'static str>,
+    },
+    DeleteUser {
+        id: PlayerId,
+        username: String,
+    },
+    CreateTunnel(ManifestTunnel),
+    UpdateTunnel {
+        tunnel: tunnel::Model,
+        fields: Vec<&'static str>,
+    },
+    DeleteTunnel(tunnel::Model),
+}
+
+impl Action {
+    fn describe(&self) -> ManifestChange {
+        match self {
+            Action::CreateUser(user) => ManifestChange {
+                kind: "user",
+                action: "create",
+                name: user.username.clone(),
+                detail: String::new(),
+            },
+            Action::UpdateUser { user, fields, .. } => ManifestChange {
+                kind: "user",
+                action: "update",
+                name: user.username.clone(),
+                detail: fields.join(","),
+            },
+            Action::DeleteUser { id, username } => ManifestChange {
+                kind: "user",
+                action: "delete",
+                name: username.clone(),
+                detail: format!("id:{}", id),
+            },
+            Action::CreateTunnel(tunnel) => ManifestChange {
+                kind: "tunnel",
+                action: "create",
+                name: format!("{}@{}", tunnel.source, tunnel.receiver),
+                detail: format!("endpoint:{}", tunnel.endpoint),
+            },
+            Action::UpdateTunnel { tunnel, fields } => ManifestChange {
+                kind: "tunnel",
+                action: "update",
+                name: format!("#{} {}", tunnel.id, tunnel.source),
+                detail: fields.join(","),
+            },
+            Action::DeleteTunnel(tunnel) => ManifestChange {
+                kind: "tunnel",
+                action: "delete",
+                name: format!("#{} {}", tunnel.id, tunnel.source),
+                detail: format!("endpoint:{}", tunnel.endpoint),
+            },
+        }
+    }
+}
+
+/// 同一时间只允许一次同步
+static RECONCILE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 读取清单，`.toml` 后缀按 TOML 解析，其余按 JSON 解析
+pub fn load_manifest(path: &str) -> anyhow::Result<Manifest> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read manifest '{}': {}", path, e))?;
+    let manifest: Manifest = if path.ends_with(".toml") {
+        toml::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse manifest '{}': {}", path, e))?
+    } else {
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Failed to parse manifest '{}': {}", path, e))?
+    };
+    validate_manifest(&manifest)?;
+    Ok(manifest)
+}
+
+/// 报告配置中的清单与当前状态的差异，不做修改
+pub async fn manifest_drift() -> anyhow::Result<Vec<ManifestChange>> {
+    let manifest = load_configured_manifest()?;
+    let _guard = RECONCILE_LOCK.lock().await;
+
+    let users = User::find().all(GLOBAL_DB_POOL.get().unwrap()).await?;
+    let name_to_id = users.iter().map(|u| (u.username.clone(), u.id)).collect();
+    let tunnels = GLOBAL_MANAGER.tunnel_manager.tunnels.read().await.clone();
+
+    let mut actions = plan_users(&manifest, &users).await;
+    // 删除用户排在最后，与实际同步顺序一致
+    let delete_users = split_user_deletes(&mut actions);
+    actions.extend(plan_tunnels(&manifest, &name_to_id, &tunnels)?);
+    actions.extend(delete_users);
+
+    Ok(actions.iter().map(Action::describe).collect())
+}
+
+/// 按配置中的清单同步用户与通道，返回已应用的变更
+pub async fn reconcile_manifest() -> anyhow::Result<Vec<ManifestChange>> {
+    let manifest = load_configured_manifest()?;
+    let _guard = RECONCILE_LOCK.lock().await;
+    let mut applied = Vec::new();
+
+    // 1. 新增/修改用户
+    let users = User::find().all(GLOBAL_DB_POOL.get().unwrap()).await?;
+    let mut actions = plan_users(&manifest, &users).await;
+    let delete_users = split_user_deletes(&mut actions);
+    for action in actions {
+        apply_action(&action, &HashMap::new()).await?;
+        applied.push(action.describe());
+    }
+
+    // 2. 通道：先删除，再修改，最后新增，避免端口冲突
+    let users = User::find().all(GLOBAL_DB_POOL.get().unwrap()).await?;
+    let name_to_id: HashMap<String, PlayerId> =
+        users.iter().map(|u| (u.username.clone(), u.id)).collect();
+    let tunnels = GLOBAL_MANAGER.tunnel_manager.tunnels.read().await.clone();
+    for action in plan_tunnels(&manifest, &name_to_id, &tunnels)? {
+        apply_action(&action, &name_to_id).await?;
+        applied.push(action.describe());
+    }
+
+    // 3. 删除清单外的用户（会同时删除其通道）
+    for action in delete_users {
+        apply_action(&action, &name_to_id).await?;
+        applied.push(action.describe());
+    }
+
+    for change in &applied {
+        info!(
+            "manifest {} {} '{}' {}",
+            change.action, change.kind, change.name, change.detail
+        );
+    }
+    Ok(applied)
+}
+
+fn load_configured_manifest() -> anyhow::Result<Manifest> {
+    let path = GLOBAL_CONFIG.load().manifest_file.clone();
+    if path.is_empty() {
+        bail!("manifest_file is not configured");
+    }
+    load_manifest(&path)
+}
+
+fn validate_manifest(manifest: &Manifest) -> anyhow::Result<()> {
+    let mut usernames = HashSet::new();
+    for user in &manifest.users {
+        if !usernames.insert(user.username.as_str()) {
+            bail!("duplicate user '{}' in manifest", user.username);
+        }
+        if !is_password_hash(&user.password) && !is_valid_password(&user.password) {
+            bail!("user '{}': password format error", user.username);
+        }
+        if user.upload_limit < 0 || user.download_limit < 0 {
+            bail!("user '{}': rate limit must not be negative", user.username);
+        }
+    }
+
+    let mut keys = HashSet::new();
+    for tunnel in &manifest.tunnels {
+        let tunnel_type = tunnel_type_from_name(&tunnel.tunnel_type)?;
+        if !keys.insert((
+            tunnel.receiver.as_str(),
+            tunnel.source.as_str(),
+            tunnel_type == 1,
+            vhost_domain_key(tunnel_type, tunnel.custom_mapping.keys()),
+        )) {
+            bail!(
+                "duplicate tunnel '{}' for receiver '{}' in manifest",
+                tunnel.source,
+                tunnel.receiver
+            );
+        }
+        // 清除多余用户时，通道只能引用清单中的用户
+        for name in [&tunnel.sender, &tunnel.receiver] {
+            if manifest.prune && !name.is_empty() && !usernames.contains(name.as_str()) {
+                bail!(
+                    "tunnel '{}' references unknown user '{}'",
+                    tunnel.source,
+                    name
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 虚拟主机通道的域名集合（排序后），其余类型为空
+fn vhost_domain_key<'a>(
+    tunnel_type: u32,
+    domains: impl Iterator<Item = &'a String>,
+) -> Vec<String> {
+    if tunnel_type != 7 {
+        return Vec::new();
+    }
+    let mut domains: Vec<String> = domains
+        .filter(|domain| !is_header_rule_key(domain))
+        .map(|domain| domain.trim().to_ascii_lowercase())
+        .collect();
+    domains.sort();
+    domains
+}
+
+fn tunnel_type_from_name(name: &str) -> anyhow::Result<u32> {
+    match name.to_ascii_lowercase().as_str() {
+        "tcp" => Ok(0),
+        "udp" => Ok(1),
+        "socks5" => Ok(2),
+        "http" => Ok(3),
+        "socks4" => Ok(4),
+        "mixed" => Ok(5),
+        "transparent" => Ok(6),
+        "vhost" => Ok(7),
+        _ => Err(anyhow!("unknown tunnel_type '{}'", name)),
+    }
+}
+
+/// 把删除用户的动作移出，放到通道同步之后执行
+fn split_user_deletes(actions: &mut Vec<Action>) -> Vec<Action> {
+    let (deletes, rest): (Vec<_>, Vec<_>) = std::mem::take(actions)
+        .into_iter()
+        .partition(|action| matches!(action, Action::DeleteUser { .. }));
+    *actions = rest;
+    deletes
+}
+
+async fn plan_users(manifest: &Manifest, users: &[user::Model]) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    for desired in &manifest.users {
+        let Some(current) = users.iter().find(|u| u.username == desired.username) else {
+            actions.push(Action::CreateUser(desired.clone()));
+            continue;
+        };
+
+        let mut fields = Vec::new();
+        let password_matches = if is_password_hash(&desired.password) {
+            // Argon2id 哈希按升级后的口令校验值比较
+            upgrade_password_hash(&desired.password).is_ok_and(|hash| hash == current.password)
+        } else {
+            verify_password_async(desired.password.clone(), current.password.clone()).await
+        };
+        if !password_matches {
+            fields.push("password");
+        }
+        if (current.enabled == 1) != desired.enabled {
+            fields.push("enabled");
+        }
+        if (current.web_access == 1) != desired.web_access {
+            fields.push("web_access");
+        }
+        if current.upload_limit != desired.upload_limit
+            || current.download_limit != desired.download_limit
+        {
+            fields.push("rate_limit");
+        }
+        if !fields.is_empty() {
+            actions.push(Action::UpdateUser {
+                id: current.id,
+                user: desired.clone(),
+                fields,
+            });
+        }
+    }
+
+    if manifest.prune {
+        for current in users {
+            if !manifest
+                .users
+                .iter()
+                .any(|u| u.username == current.username)
+            {
+                actions.push(Action::DeleteUser {
+                    id: current.id,
+                    username: current.username.clone(),
+                });
+            }
+        }
+    }
+    actions
+}
+
+fn plan_tunnels(
+    manifest: &Manifest,
+    name_to_id: &HashMap<String, PlayerId>,
+    tunnels: &[tunnel::Model],
+) -> anyhow::Result<Vec<Action>> {
+    let mut deletes = Vec::new();
+    let mut updates = Vec::new();
+    let mut creates = Vec::new();
+    let mut matched = HashSet::new();
+
+    for desired in &manifest.tunnels {
+        let tunnel_type = tunnel_type_from_name(&desired.tunnel_type)?;
+        let receiver = resolve_player_id(name_to_id, &desired.receiver);
+        let sender = resolve_player_id(name_to_id, &desired.sender);
+        if !manifest.prune {
+            // 不清除时通道可以引用数据库中已有的用户
+            for (name, id) in [(&desired.sender, sender), (&desired.receiver, receiver)] {
+                if id.is_none() && !manifest.users.iter().any(|u| &u.username == name) {
+                    bail!(
+                        "tunnel '{}' references unknown user '{}'",
+                        desired.source,
+                        name
+                    );
+                }
+            }
+        }
+
+        let domain_key = vhost_domain_key(tunnel_type, desired.custom_mapping.keys());
+        let current = receiver.and_then(|receiver| {
+            tunnels.iter().find(|t| {
+                t.receiver == receiver
+                    && t.source == desired.source
+                    && (t.tunnel_type == 1) == (tunnel_type == 1)
+                    && vhost_domain_key(t.tunnel_type, t.vhost_domains().keys()) == domain_key
+            })
+        });
+
+        let (Some(current), Some(sender), Some(receiver)) = (current, sender, receiver) else {
+            creates.push(Action::CreateTunnel(desired.clone()));
+            continue;
+        };
+        matched.insert(current.id);
+
+        let tunnel = build_tunnel(desired, current.id, sender, receiver, tunnel_type);
+        let fields = tunnel_diff(current, &tunnel);
+        if !fields.is_empty() {
+            updates.push(Action::UpdateTunnel { tunnel, fields });
+        }
+    }
+
+    if manifest.prune {
+        for current in tunnels {
+            if !matched.contains(&current.id) {
+                deletes.push(Action::DeleteTunnel(current.clone()));
+            }
+        }
+    }
+
+    deletes.extend(updates);
+    deletes.extend(creates);
+    Ok(deletes)
+}
+
+fn resolve_player_id(name_to_id: &HashMap<String, PlayerId>, name: &str) -> Option<PlayerId> {
+    if name.is_empty() {
+        Some(0)
+    } else {
+        name_to_id.get(name).copied()
+    }
+}
+
+fn build_tunnel(
+    desired: &ManifestTunnel,
+    id: u32,
+    sender: PlayerId,
+    receiver: PlayerId,
+    tunnel_type: u32,
+) -> tunnel::Model {
+    tunnel::Model {
+        id,
+        source: desired.source.clone(),
+        endpoint: desired.endpoint.clone(),
+        enabled: desired.enabled as u8,
+        sender,
+        receiver,
+        description: desired.description.clone(),
+        tunnel_type,
+        password: desired.password.clone(),
+        username: desired.username.clone(),
+        is_compressed: desired.is_compressed as u8,
+        custom_mapping: serde_json::to_string(&desired.custom_mapping).unwrap_or_default(),
+        encryption_method: desired.encryption_method.clone(),
+        upload_limit: desired.upload_limit,
+        download_limit: desired.download_limit,
+        quota_suspended: 0,
+        tls_certificates: String::new(),
+        proxy_protocol: desired.proxy_protocol,
+        acl_allow: format_acl_rules(&desired.acl_allow),
+        acl_deny: format_acl_rules(&desired.acl_deny),
+    }
+}
+
+fn tunnel_diff(current: &tunnel::Model, desired: &tunnel::Model) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if current.endpoint != desired.endpoint {
+        fields.push("endpoint");
+    }
+    // 被配额暂停的通道由配额检查负责恢复，不视为漂移
+    if current.enabled != desired.enabled && current.quota_suspended == 0 {
+        fields.push("enabled");
+    }
+    if current.sender != desired.sender {
+        fields.push("sender");
+    }
+    if current.description != desired.description {
+        fields.push("description");
+    }
+    if current.tunnel_type != desired.tunnel_type {
+        fields.push("tunnel_type");
+    }
+    if current.username != desired.username {
+        fields.push("username");
+    }
+    if current.password != desired.password {
+        fields.push("password");
+    }
+    if current.is_compressed != desired.is_compressed {
+        fields.push("is_compressed");
+    }
+    // 映射表以 JSON 保存，键顺序不固定，解析后再比较
+    let current_mapping: HashMap<String, String> =
+        serde_json::from_str(&current.custom_mapping).unwrap_or_default();
+    let desired_mapping: HashMap<String, String> =
+        serde_json::from_str(&desired.custom_mapping).unwrap_or_default();
+    if current_mapping != desired_mapping {
+        fields.push("custom_mapping");
+    }
+    if current.encryption_method != desired.encryption_method {
+        fields.push("encryption_method");
+    }
+    if current.upload_limit != desired.upload_limit {
+        fields.push("upload_limit");
+    }
+    if current.download_limit != desired.download_limit {
+        fields.push("download_limit");
+    }
+    if current.proxy_protocol != desired.proxy_protocol {
+        fields.push("proxy_protocol");
+    }
+    if current.acl_allow != desired.acl_allow {
+        fields.push("acl_allow");
+    }
+    if current.acl_deny != desired.acl_deny {
+        fields.push("acl_deny");
+    }
+    fields
+}
+
+async fn manifest_password_hash(password: &str) -> anyhow::Result<String> {
+    if is_password_hash(password) {
+        upgrade_password_hash(password)
+    } else {
+        hash_password_async(password.to_string()).await
+    }
+}
+
+async fn apply_action(
+    action: &Action,
+    name_to_id: &HashMap<String, PlayerId>,
+) -> anyhow::Result<()> {
+    let player_manager = &GLOBAL_MANAGER.player_manager;
+    let tunnel_manager = &GLOBAL_MANAGER.tunnel_manager;
+
+    match action {
+        Action::CreateUser(user) => {
+            let password_hash = manifest_password_hash(&user.password).await?;
+            let id = player_manager
+                .provision_player(
+                    &user.username,
+                    password_hash,
+                    user.enabled as u8,
+                    user.web_access as u8,
+                )
+                .await?;
+            if user.upload_limit != 0 || user.download_limit != 0 {
+                player_manager
+                    .update_player_rate_limit(id, user.upload_limit, user.download_limit)
+                    .await?;
+            }
+        }
+        Action::UpdateUser { id, user, fields } => {
+            if fields.contains(&"password") {
+                let password_hash = manifest_password_hash(&user.password).await?;
+                player_manager
+                    .set_player_password_hash(*id, password_hash)
+                    .await?;
+            }
+            if fields.contains(&"enabled") {
+                player_manager
+                    .update_player_status(*id, user.enabled as u8)
+                    .await?;
+            }
+            if fields.contains(&"web_access") {
+                player_manager
+                    .update_player_web_access(*id, user.web_access as u8)
+                    .await?;
+            }
+            if fields.contains(&"rate_limit") {
+                player_manager
+                    .update_player_rate_limit(*id, user.upload_limit, user.download_limit)
+                    .await?;
+            }
+        }
+        Action::DeleteUser { id, .. } => player_manager.delete_player(*id).await?,
+        Action::CreateTunnel(desired) => {
+            let tunnel_type = tunnel_type_from_name(&desired.tunnel_type)?;
+            let sender = resolve_player_id(name_to_id, &desired.sender)
+                .ok_or_else(|| anyhow!("unknown user '{}'", desired.sender))?;
+            let receiver = resolve_player_id(name_to_id, &desired.receiver)
+                .ok_or_else(|| anyhow!("unknown user '{}'", desired.receiver))?;
+            let tunnel = build_tunnel(desired, 0, sender, receiver, tunnel_type);
+            tunnel_manager
+                .add_tunnel(tunnel)
+                .await
+                .map_err(|e| anyhow!("create tunnel '{}': {}", desired.source, e))?;
+        }
+        Action::UpdateTunnel { tunnel, .. } => {
+            tunnel_manager
+                .update_tunnel(tunnel.clone(), false)
+                .await
+                .map_err(|e| anyhow!("update tunnel #{}: {}", tunnel.id, e))?;
+        }
+        Action::DeleteTunnel(tunnel) => {
+            if let Err(e) = tunnel_manager.delete_tunnel(tunnel.id).await {
+                // 删除用户时可能已一并删除
+                warn!("delete tunnel #{}: {}", tunnel.id, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest_and_diff() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[users]]
+            username = "alice"
+            password = "secret"
+
+            [[tunnels]]
+            source = "0.0.0.0:2222"
+            endpoint = "127.0.0.1:22"
+            receiver = "alice"
+            tunnel_type = "TCP"
+            custom_mapping = { a = "1", b = "2" }
+            "#,
+        )
+        .unwrap();
+        assert!(manifest.prune);
+        validate_manifest(&manifest).unwrap();
+
+        let name_to_id = HashMap::from([("alice".to_string(), 10000001)]);
+        let mut current = build_tunnel(&manifest.tunnels[0], 7, 0, 10000001, 0);
+        current.custom_mapping = r#"{"b":"2","a":"1"}"#.to_string();
+        let stale = build_tunnel(&manifest.tunnels[0], 8, 0, 0, 0);
+
+        let actions = plan_tunnels(&manifest, &name_to_id, &[current.clone(), stale]).unwrap();
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(&actions[0], Action::DeleteTunnel(t) if t.id == 8));
+
+        current.endpoint = "127.0.0.1:2022".to_string();
+        let actions = plan_tunnels(&manifest, &name_to_id, &[current]).unwrap();
+        assert!(
+            matches!(&actions[0], Action::UpdateTunnel { fields, .. } if fields == &vec!["endpoint"])
+        );
+
+        let mut invalid = manifest;
+        invalid.tunnels[0].receiver = "bob".to_string();
+        assert!(validate_manifest(&invalid).is_err());
+    }
+}
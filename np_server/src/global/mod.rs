@@ -1,14 +1,20 @@
+use crate::global::config::{start_config_reload_loop, GLOBAL_CONFIG};
 use crate::global::database::{init_database, start_traffic_flush_loop};
 use crate::global::logger::init_logger;
 use crate::global::manager::player::start_transport_idle_cleanup_loop;
 use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::manifest::reconcile_manifest;
+use crate::global::opts::GLOBAL_OPTS;
+use crate::global::quota::start_quota_check_loop;
 
 pub mod config;
 pub mod database;
 pub mod forward_rule;
 pub mod logger;
 pub mod manager;
+pub mod manifest;
 pub mod opts;
+pub mod quota;
 
 pub(crate) use database::GLOBAL_DB_POOL;
 
@@ -24,11 +30,22 @@ pub(crate) async fn init_global() -> anyhow::Result<()> {
 
     GLOBAL_MANAGER.proxy_manager.sync_tunnels().await;
 
+    // 按清单同步用户与通道
+    if !GLOBAL_CONFIG.load().manifest_file.is_empty() && !GLOBAL_OPTS.manifest_check {
+        reconcile_manifest().await?;
+    }
+
     // 启动流量定期刷库任务
     start_traffic_flush_loop();
 
+    // 启动流量配额检查任务
+    start_quota_check_loop();
+
     // 启动转发连接空闲清理任务
     start_transport_idle_cleanup_loop();
 
+    // 启动配置重载任务
+    start_config_reload_loop();
+
     Ok(())
 }
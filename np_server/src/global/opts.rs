@@ -27,4 +27,8 @@ pub struct Opts {
     /// Set log level
     #[arg(long, default_value = "error")]
     pub base_log_level: String,
+
+    /// Print the drift between manifest_file and the database, then exit (non-zero if drifted)
+    #[arg(long, default_value_t = false)]
+    pub manifest_check: bool,
 }
@@ -0,0 +1,259 @@
+use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::GLOBAL_DB_POOL;
+use crate::orm_entity::{operation_log, traffic_hourly, user};
+use crate::player::PlayerId;
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Utc};
+use sea_orm::ActiveValue::{NotSet, Set};
+use sea_orm::{ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// 配额周期：每月在重置日清零
+pub const QUOTA_PERIOD_MONTHLY: u8 = 0;
+/// 配额周期：累计总量，不自动清零
+pub const QUOTA_PERIOD_TOTAL: u8 = 1;
+
+/// 配额检查间隔（秒）
+const QUOTA_CHECK_INTERVAL_SECS: u64 = 60;
+
+/// 避免定时检查与修改配额后的检查并发执行
+static QUOTA_CHECK_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// 玩家流量配额设置
+pub struct PlayerQuota {
+    /// 配额字节数（上行 + 下行），0 为不限额
+    pub quota_bytes: i64,
+    /// 配额周期，见 QUOTA_PERIOD_*
+    pub quota_period: u8,
+    /// 每月重置日（1-28），仅按月周期有效
+    pub quota_reset_day: u8,
+    /// 硬限额超额后暂停通道，软限额仅记录告警
+    pub quota_hard: bool,
+}
+
+impl PlayerQuota {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.quota_bytes >= 0, "quota must not be negative");
+        anyhow::ensure!(
+            self.quota_period == QUOTA_PERIOD_MONTHLY || self.quota_period == QUOTA_PERIOD_TOTAL,
+            "invalid quota period: {}",
+            self.quota_period
+        );
+        anyhow::ensure!(
+            (1..=28).contains(&self.quota_reset_day),
+            "quota reset day must be between 1 and 28"
+        );
+        Ok(())
+    }
+}
+
+/// 玩家当前周期的配额使用情况
+pub struct QuotaUsage {
+    pub used_bytes: i64,
+    /// 当前周期起始时间（UTC），累计总量为 None
+    pub period_start: Option<NaiveDateTime>,
+    /// 下次重置时间（UTC），累计总量为 None
+    pub next_reset: Option<NaiveDateTime>,
+}
+
+/// 计算当前计费周期的起始时间（UTC），累计总量返回 None
+pub fn period_start(now: NaiveDateTime, period: u8, reset_day: u8) -> Option<NaiveDateTime> {
+    if period != QUOTA_PERIOD_MONTHLY {
+        return None;
+    }
+    let day = reset_day.clamp(1, 28) as u32;
+    let (mut year, mut month) = (now.year(), now.month());
+    if now.day() < day {
+        if month == 1 {
+            year -= 1;
+            month = 12;
+        } else {
+            month -= 1;
+        }
+    }
+    NaiveDate::from_ymd_opt(year, month, day).and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+/// 统计玩家当前周期已用流量（已刷库部分 + 内存中尚未刷库部分）
+pub async fn quota_usage(user: &user::Model) -> anyhow::Result<QuotaUsage> {
+    let start = period_start(
+        Utc::now().naive_utc(),
+        user.quota_period,
+        user.quota_reset_day,
+    );
+
+    let mut query =
+        traffic_hourly::Entity::find().filter(traffic_hourly::Column::UserId.eq(user.id));
+    if let Some(start) = start {
+        // hour 格式为 "%Y-%m-%d %H"，按字符串比较即可
+        query =
+            query.filter(traffic_hourly::Column::Hour.gte(start.format("%Y-%m-%d %H").to_string()));
+    }
+    let rows = query.all(GLOBAL_DB_POOL.get().unwrap()).await?;
+    let mut used_bytes: i64 = rows.iter().map(|row| row.bytes_in + row.bytes_out).sum();
+
+    if let Some(player) = GLOBAL_MANAGER.player_manager.get_player(user.id) {
+        let (rx, tx) = player.read().await.get_traffic();
+        used_bytes += (rx + tx) as i64;
+    }
+
+    Ok(QuotaUsage {
+        used_bytes,
+        period_start: start,
+        next_reset: start.and_then(|start| start.checked_add_months(Months::new(1))),
+    })
+}
+
+pub(crate) fn start_quota_check_loop() {
+    tokio::spawn(async move {
+        // 启动时先检查一次，停机期间跨过重置日的通道可以及时恢复
+        loop {
+            if let Err(err) = check_quotas().await {
+                log::error!("quota check error: {}", err);
+            }
+            tokio::time::sleep(Duration::from_secs(QUOTA_CHECK_INTERVAL_SECS)).await;
+        }
+    });
+}
+
+/// 检查所有玩家的流量配额
+///
+/// - 超额或恢复时更新玩家的超额标记并记录操作日志
+/// - 硬限额超额的玩家，其作为发送方或接收方的通道被暂停
+/// - 被暂停的通道在双方都不再超额后（新周期、调高或取消配额）自动恢复
+pub(crate) async fn check_quotas() -> anyhow::Result<()> {
+    let _guard = QUOTA_CHECK_LOCK.lock().await;
+    let db = GLOBAL_DB_POOL.get().unwrap();
+
+    let mut suspended_players: HashSet<PlayerId> = HashSet::new();
+    for user in user::Entity::find().all(db).await? {
+        let used_bytes = if user.quota_bytes > 0 {
+            quota_usage(&user).await?.used_bytes
+        } else {
+            0
+        };
+        let exceeded = user.quota_bytes > 0 && used_bytes >= user.quota_bytes;
+        if exceeded && user.quota_hard == 1 {
+            suspended_players.insert(user.id);
+        }
+
+        if exceeded != (user.quota_exceeded == 1) {
+            let (action, detail) = if exceeded {
+                log::warn!(
+                    "user {}({}) exceeded traffic quota, used:{}, quota:{}, hard:{}",
+                    user.username,
+                    user.id,
+                    used_bytes,
+                    user.quota_bytes,
+                    user.quota_hard == 1
+                );
+                (
+                    "quota_exceeded",
+                    format!(
+                        "used: {}; quota: {}; hard: {}",
+                        used_bytes,
+                        user.quota_bytes,
+                        user.quota_hard == 1
+                    ),
+                )
+            } else {
+                log::info!("user {}({}) traffic quota restored", user.username, user.id);
+                (
+                    "quota_restored",
+                    format!("used: {}; quota: {}", used_bytes, user.quota_bytes),
+                )
+            };
+            record_quota_event(action, user.id, &user.username, &detail).await;
+
+            let mut active: user::ActiveModel = user.into();
+            active.quota_exceeded = Set(exceeded as u8);
+            active.update(db).await?;
+        }
+    }
+
+    let tunnels: Vec<(u32, bool)> = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .filter_map(|tunnel| {
+            let over_quota = suspended_players.contains(&tunnel.sender)
+                || suspended_players.contains(&tunnel.receiver);
+            if over_quota && tunnel.enabled == 1 {
+                Some((tunnel.id, true))
+            } else if !over_quota && tunnel.quota_suspended == 1 {
+                Some((tunnel.id, false))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for (tunnel_id, suspend) in tunnels {
+        match GLOBAL_MANAGER
+            .tunnel_manager
+            .update_tunnel_quota_suspended(tunnel_id, suspend)
+            .await
+        {
+            Ok(()) => log::info!(
+                "tunnel {} {} by traffic quota",
+                tunnel_id,
+                if suspend { "suspended" } else { "resumed" }
+            ),
+            Err(err) => log::error!("quota update tunnel {} error: {}", tunnel_id, err),
+        }
+    }
+
+    Ok(())
+}
+
+/// 配额事件由系统触发，操作人记为 system
+async fn record_quota_event(action: &str, user_id: u32, username: &str, detail: &str) {
+    let model = operation_log::ActiveModel {
+        id: NotSet,
+        actor: Set("system".to_owned()),
+        action: Set(action.to_owned()),
+        target_type: Set("player".to_owned()),
+        target_id: Set(user_id),
+        target_name: Set(username.to_owned()),
+        detail: Set(detail.to_owned()),
+        created_at: Set(Utc::now().naive_utc()),
+    };
+    if let Err(err) = model.insert(GLOBAL_DB_POOL.get().unwrap()).await {
+        log::error!("operation log insert error: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_period_start() {
+        let now = datetime("2024-03-15 10:00:00");
+        assert_eq!(
+            period_start(now, QUOTA_PERIOD_MONTHLY, 1),
+            Some(datetime("2024-03-01 00:00:00"))
+        );
+        assert_eq!(
+            period_start(now, QUOTA_PERIOD_MONTHLY, 15),
+            Some(datetime("2024-03-15 00:00:00"))
+        );
+        assert_eq!(
+            period_start(now, QUOTA_PERIOD_MONTHLY, 20),
+            Some(datetime("2024-02-20 00:00:00"))
+        );
+        // 跨年
+        assert_eq!(
+            period_start(datetime("2024-01-05 00:00:00"), QUOTA_PERIOD_MONTHLY, 10),
+            Some(datetime("2023-12-10 00:00:00"))
+        );
+        assert_eq!(period_start(now, QUOTA_PERIOD_TOTAL, 1), None);
+    }
+}
@@ -5,7 +5,7 @@ mod player;
 mod utils;
 mod web;
 
-use crate::global::config::GLOBAL_CONFIG;
+use crate::global::config::{tls_cert_resolver, GLOBAL_CONFIG};
 use crate::global::opts::GLOBAL_OPTS;
 use crate::peer::Peer;
 use anyhow::anyhow;
@@ -56,10 +56,16 @@ async fn run_tcp_server(addr: String) -> anyhow::Result<()> {
     let mut builder =
         np_base::net::tcp_server::Builder::new(Box::new(|| -> Box<dyn SessionDelegate> {
             Box::new(Peer::new("tcp"))
-        }));
+        }))
+        .set_proxy_protocol(GLOBAL_CONFIG.load().proxy_protocol);
 
-    if GLOBAL_CONFIG.enable_tls {
-        builder = builder.set_tls_configuration(&GLOBAL_CONFIG.tls_cert, &GLOBAL_CONFIG.tls_key);
+    if GLOBAL_CONFIG.load().enable_tls {
+        builder = builder.set_tls_cert_resolver(tls_cert_resolver()?);
+        let config = GLOBAL_CONFIG.load();
+        if !config.tls_client_ca.is_empty() {
+            builder =
+                builder.set_tls_client_auth(&config.tls_client_ca, config.tls_client_cert_required);
+        }
     }
 
     builder.build(addr, shutdown_signal()).await
@@ -83,8 +89,13 @@ async fn run_kcp_server(addr: String) -> anyhow::Result<()> {
             allow_recv_empty_packet: false,
         });
 
-    if GLOBAL_CONFIG.enable_tls {
-        builder = builder.set_tls_configuration(&GLOBAL_CONFIG.tls_cert, &GLOBAL_CONFIG.tls_key);
+    if GLOBAL_CONFIG.load().enable_tls {
+        builder = builder.set_tls_cert_resolver(tls_cert_resolver()?);
+        let config = GLOBAL_CONFIG.load();
+        if !config.tls_client_ca.is_empty() {
+            builder =
+                builder.set_tls_client_auth(&config.tls_client_ca, config.tls_client_cert_required);
+        }
     }
 
     builder.build(addr, shutdown_signal()).await
@@ -96,10 +107,49 @@ async fn run_ws_server(addr: String) -> anyhow::Result<()> {
     let mut builder =
         np_base::net::ws_server::Builder::new(Box::new(|| -> Box<dyn SessionDelegate> {
             Box::new(Peer::new("ws"))
-        }));
+        }))
+        .set_proxy_protocol(GLOBAL_CONFIG.load().proxy_protocol);
+
+    let config = GLOBAL_CONFIG.load();
+    if !config.ws_path.is_empty() {
+        builder = builder.set_path(&config.ws_path);
+    }
+    for (name, value) in &config.ws_required_headers {
+        http::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("invalid header name in ws_required_headers: {}", name))?;
+        builder = builder.add_required_header(name, value);
+    }
+    if !config.ws_subprotocols.is_empty() {
+        builder = builder.set_subprotocols(config.ws_subprotocols.clone());
+    }
+
+    if config.enable_tls {
+        builder = builder.set_tls_cert_resolver(tls_cert_resolver()?);
+        if !config.tls_client_ca.is_empty() {
+            builder =
+                builder.set_tls_client_auth(&config.tls_client_ca, config.tls_client_cert_required);
+        }
+    }
 
-    if GLOBAL_CONFIG.enable_tls {
-        builder = builder.set_tls_configuration(&GLOBAL_CONFIG.tls_cert, &GLOBAL_CONFIG.tls_key);
+    builder.build(addr, shutdown_signal()).await
+}
+
+#[cfg(feature = "h2")]
+async fn run_h2_server(addr: String) -> anyhow::Result<()> {
+    info!("HTTP/2 Server listening: {}", addr);
+    let mut builder =
+        np_base::net::h2_server::Builder::new(Box::new(|| -> Box<dyn SessionDelegate> {
+            Box::new(Peer::new("h2"))
+        }))
+        .set_proxy_protocol(GLOBAL_CONFIG.load().proxy_protocol);
+
+    if GLOBAL_CONFIG.load().enable_tls {
+        builder = builder.set_tls_cert_resolver(tls_cert_resolver()?);
+        let config = GLOBAL_CONFIG.load();
+        if !config.tls_client_ca.is_empty() {
+            builder =
+                builder.set_tls_client_auth(&config.tls_client_ca, config.tls_client_cert_required);
+        }
     }
 
     builder.build(addr, shutdown_signal()).await
@@ -113,8 +163,12 @@ async fn run_quic_server(addr: String) -> anyhow::Result<()> {
             Box::new(Peer::new("quic"))
         }));
 
-    if GLOBAL_CONFIG.enable_tls {
-        builder = builder.set_tls_configuration(&GLOBAL_CONFIG.tls_cert, &GLOBAL_CONFIG.tls_key);
+    if GLOBAL_CONFIG.load().enable_tls {
+        builder = builder.set_tls_cert_resolver(tls_cert_resolver()?);
+    }
+    if !GLOBAL_CONFIG.load().tls_client_ca.is_empty() {
+        // s2n-quic 使用自己的 TLS 实现，不请求客户端证书
+        log::warn!("QUIC Server does not verify client certificates, QUIC clients must log in with passwords");
     }
 
     builder.build(&addr, shutdown_signal()).await
@@ -146,13 +200,29 @@ pub async fn main() -> anyhow::Result<()> {
     Lazy::force(&GLOBAL_CONFIG);
     global::init_global().await?;
 
+    if GLOBAL_OPTS.manifest_check {
+        let changes = global::manifest::manifest_drift().await?;
+        for change in &changes {
+            println!(
+                "{} {} '{}' {}",
+                change.action, change.kind, change.name, change.detail
+            );
+        }
+        if changes.is_empty() {
+            println!("no drift");
+            return Ok(());
+        }
+        std::process::exit(1);
+    }
+
     let mut set = JoinSet::new();
+    let config = GLOBAL_CONFIG.load_full();
 
-    if !GLOBAL_CONFIG.web_password.is_empty()
-        && !GLOBAL_CONFIG.web_username.is_empty()
-        && !GLOBAL_CONFIG.web_addr.is_empty()
+    if !config.web_password.is_empty()
+        && !config.web_username.is_empty()
+        && !config.web_addr.is_empty()
     {
-        let name = if GLOBAL_CONFIG.web_enable_tls {
+        let name = if config.web_enable_tls {
             "HTTPS"
         } else {
             "HTTP"
@@ -160,12 +230,12 @@ pub async fn main() -> anyhow::Result<()> {
         spawn_server(
             &mut set,
             name,
-            GLOBAL_CONFIG.web_addr.clone(),
-            web::run_http_server(&GLOBAL_CONFIG.web_addr, &GLOBAL_CONFIG.web_base_dir),
+            config.web_addr.clone(),
+            web::run_http_server(config.web_addr.clone(), config.web_base_dir.clone()),
         );
     }
 
-    GLOBAL_CONFIG
+    config
         .listen_addr
         .split(",")
         .filter_map(|s| {
@@ -197,6 +267,11 @@ pub async fn main() -> anyhow::Result<()> {
                 Ok(addr) => spawn_server(&mut set, "WebSocket", addr.clone(), run_ws_server(addr)),
                 Err(err) => error!("Invalid WebSocket listen address '{}': {}", request, err),
             },
+            #[cfg(feature = "h2")]
+            Some("h2") => match uri_to_socket_addr(&request) {
+                Ok(addr) => spawn_server(&mut set, "HTTP/2", addr.clone(), run_h2_server(addr)),
+                Err(err) => error!("Invalid HTTP/2 listen address '{}': {}", request, err),
+            },
             #[cfg(feature = "quic")]
             Some("quic") => match uri_to_socket_addr(&request) {
                 Ok(addr) => spawn_server(&mut set, "QUIC", addr.clone(), run_quic_server(addr)),
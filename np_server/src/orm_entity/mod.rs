@@ -7,4 +7,5 @@ pub mod operation_log;
 pub mod schema_version;
 pub mod traffic_hourly;
 pub mod tunnel;
+pub mod tunnel_traffic_hourly;
 pub mod user;
@@ -6,4 +6,5 @@ pub use super::operation_log::Entity as OperationLog;
 pub use super::schema_version::Entity as SchemaVersion;
 pub use super::traffic_hourly::Entity as TrafficHourly;
 pub use super::tunnel::Entity as Tunnel;
+pub use super::tunnel_traffic_hourly::Entity as TunnelTrafficHourly;
 pub use super::user::Entity as User;
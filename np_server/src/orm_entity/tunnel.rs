@@ -20,6 +20,16 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     pub custom_mapping: String,
     pub encryption_method: String,
+    pub upload_limit: i64,
+    pub download_limit: i64,
+    pub quota_suspended: u8,
+    #[sea_orm(column_type = "Text")]
+    pub tls_certificates: String,
+    pub proxy_protocol: u8,
+    #[sea_orm(column_type = "Text")]
+    pub acl_allow: String,
+    #[sea_orm(column_type = "Text")]
+    pub acl_deny: String,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,24 @@
+//! `SeaORM` Entity — 通道流量按小时统计表
+
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "tunnel_traffic_hourly")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: u32,
+    pub tunnel_id: u32,
+    /// 入口发往出口的数据字节数
+    pub bytes_in: i64,
+    /// 出口返回入口的数据字节数
+    pub bytes_out: i64,
+    /// 新建会话数
+    pub sessions: i64,
+    /// 小时标识，格式 "2026-05-28 14"
+    pub hour: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
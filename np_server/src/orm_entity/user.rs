@@ -12,6 +12,13 @@ pub struct Model {
     pub create_time: DateTime,
     pub enabled: u8,
     pub web_access: u8,
+    pub upload_limit: i64,
+    pub download_limit: i64,
+    pub quota_bytes: i64,
+    pub quota_period: u8,
+    pub quota_reset_day: u8,
+    pub quota_hard: u8,
+    pub quota_exceeded: u8,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
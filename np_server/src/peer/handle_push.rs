@@ -8,6 +8,20 @@ use std::sync::atomic::Ordering;
 impl Peer {
     // 收到玩家向服务器推送消息
     pub(crate) async fn handle_push(&self, message: MessageType) -> anyhow::Result<()> {
+        match message {
+            MessageType::ClientServerSessionListAck(ref msg) => {
+                let request_id = msg.request_id;
+                self.on_session_response(request_id, message).await;
+                return Ok(());
+            }
+            MessageType::ClientServerSessionCloseAck(ref msg) => {
+                let request_id = msg.request_id;
+                self.on_session_response(request_id, message).await;
+                return Ok(());
+            }
+            _ => {}
+        }
+
         // 在 message 被 move 之前计算代理流量大小
         let proxy_bytes = get_message_size(&message) as u64 + 13;
 
@@ -54,4 +68,14 @@ impl Peer {
         }
         Ok(())
     }
+
+    // 客户端回复会话查询/关闭请求
+    async fn on_session_response(&self, request_id: u32, message: MessageType) {
+        if let Some(player) = &self.player {
+            let player_id = player.read().await.get_player_id();
+            GLOBAL_MANAGER
+                .proxy_manager
+                .on_session_response(player_id, request_id, message);
+        }
+    }
 }
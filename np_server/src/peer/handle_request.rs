@@ -5,9 +5,16 @@ use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::login_history;
 use crate::orm_entity::prelude::User;
 use crate::orm_entity::user;
+use crate::utils::password::{
+    challenge_params, fake_challenge_params, generate_login_nonce, login_keys,
+    verify_password_async,
+};
 use chrono::Utc;
 use log::{debug, info, trace, warn};
 use np_proto::message_map::MessageType;
+use np_proto::utils::login::{
+    server_signature, verify_login_proof, LOGIN_PROTOCOL_VERSION_CHALLENGE,
+};
 use np_proto::utils::transport::TRANSPORT_CONNECTION_TYPE_FORWARD;
 use np_proto::{client_server, generic, server_client};
 use sea_orm::ActiveValue::{NotSet, Set};
@@ -21,6 +28,9 @@ impl Peer {
     ) -> anyhow::Result<MessageType> {
         match message {
             MessageType::GenericPing(msg) => return self.on_ping_request(msg).await,
+            MessageType::ClientServerLoginChallengeReq(msg) => {
+                return self.on_login_challenge_request(msg).await
+            }
             MessageType::ClientServerLoginReq(msg) => return self.on_login_request(msg).await,
             MessageType::ClientServerBindTransportReq(msg) => {
                 return self.on_bind_transport_request(msg).await
@@ -48,6 +58,40 @@ impl Peer {
         }))
     }
 
+    async fn on_login_challenge_request(
+        &mut self,
+        message: client_server::LoginChallengeReq,
+    ) -> anyhow::Result<MessageType> {
+        if self.player.is_some() {
+            return Ok(MessageType::GenericError(generic::Error {
+                number: -1,
+                message: "repeat login".into(),
+            }));
+        }
+
+        let user_result = User::find()
+            .filter(user::Column::Username.eq(message.username.as_str()))
+            .one(GLOBAL_DB_POOL.get().unwrap())
+            .await?;
+        // 用户不存在时返回伪造参数，避免泄露用户名是否存在
+        let params = user_result
+            .and_then(|user| challenge_params(&user.password))
+            .unwrap_or_else(|| fake_challenge_params(&message.username));
+
+        let nonce = generate_login_nonce();
+        self.login_nonce = Some(nonce.clone());
+
+        Ok(MessageType::ServerClientLoginChallengeAck(
+            server_client::LoginChallengeAck {
+                nonce,
+                salt: params.salt,
+                m_cost: params.m_cost,
+                t_cost: params.t_cost,
+                p_cost: params.p_cost,
+            },
+        ))
+    }
+
     async fn on_login_request(
         &mut self,
         message: client_server::LoginReq,
@@ -65,11 +109,61 @@ impl Peer {
         let requested_transport_max_connections = message.transport_max_connections;
         let requested_transport_idle_timeout_secs = message.transport_idle_timeout_secs;
 
+        let config = GLOBAL_CONFIG.load_full();
+
+        // 客户端证书对应请求的用户时跳过密码校验
+        let certificate_username = self.certificate_username(&username);
+        if certificate_username.is_none() && config.tls_client_cert_required {
+            return Ok(MessageType::GenericError(generic::Error {
+                number: -6,
+                message: "a client certificate is required to log in".into(),
+            }));
+        }
+
         let user_result = User::find()
-            .filter(user::Column::Username.eq(username))
-            .filter(user::Column::Password.eq(password))
+            .filter(user::Column::Username.eq(certificate_username.as_deref().unwrap_or(&username)))
             .one(GLOBAL_DB_POOL.get().unwrap())
             .await?;
+        // 挑战登录成功时回复服务端签名
+        let mut signature = Vec::new();
+        let user_result = if let Some(certificate_username) = certificate_username {
+            info!(
+                "login with client certificate, username:{}, identities:{:?}",
+                certificate_username, self.client_identities
+            );
+            user_result
+        } else if message.protocol_version >= LOGIN_PROTOCOL_VERSION_CHALLENGE {
+            // 挑战随机数只能使用一次
+            let nonce = self.login_nonce.take().unwrap_or_default();
+            user_result.filter(|user| {
+                !nonce.is_empty()
+                    && login_keys(&user.password).is_some_and(|keys| {
+                        let verified = verify_login_proof(
+                            &keys.stored_key,
+                            &nonce,
+                            &user.username,
+                            &message.proof,
+                        );
+                        if verified {
+                            signature = server_signature(&keys.server_key, &nonce, &user.username);
+                        }
+                        verified
+                    })
+            })
+        } else {
+            if !config.allow_plaintext_login {
+                return Ok(MessageType::GenericError(generic::Error {
+                    number: -5,
+                    message: "plaintext login is disabled, please upgrade the client".into(),
+                }));
+            }
+            match user_result {
+                Some(user) if verify_password_async(password, user.password.clone()).await => {
+                    Some(user)
+                }
+                _ => None,
+            }
+        };
 
         if user_result.is_none() {
             return Ok(MessageType::GenericError(generic::Error {
@@ -90,20 +184,20 @@ impl Peer {
         if let Some(player) = GLOBAL_MANAGER.player_manager.get_player(user.id) {
             let transport_max_connections = negotiate_transport_max_connections(
                 requested_transport_max_connections,
-                GLOBAL_CONFIG.transport_max_connections_per_player,
+                config.transport_max_connections_per_player,
             );
             let transport_idle_timeout_secs = negotiate_transport_idle_timeout_secs(
                 requested_transport_idle_timeout_secs,
-                GLOBAL_CONFIG.transport_idle_timeout_secs,
+                config.transport_idle_timeout_secs,
             );
             info!(
                 "transport negotiated, player_id:{}, client_max_connections:{}, server_max_connections:{}, negotiated_max_connections:{}, client_idle_timeout_secs:{}, server_idle_timeout_secs:{}, negotiated_idle_timeout_secs:{}",
                 user.id,
                 requested_transport_max_connections,
-                GLOBAL_CONFIG.transport_max_connections_per_player,
+                config.transport_max_connections_per_player,
                 transport_max_connections,
                 requested_transport_idle_timeout_secs,
-                GLOBAL_CONFIG.transport_idle_timeout_secs,
+                config.transport_idle_timeout_secs,
                 transport_idle_timeout_secs
             );
 
@@ -157,7 +251,7 @@ impl Peer {
                 .await
                 .iter()
                 .filter(|x| x.receiver == user.id || x.sender == user.id)
-                .map(|x| x.into())
+                .map(|x| x.to_proto(user.id))
                 .collect();
             trace!("login success, player_id:{}", user.id);
             return Ok(MessageType::ServerClientLoginAck(server_client::LoginAck {
@@ -166,6 +260,7 @@ impl Peer {
                 transport_token,
                 transport_max_connections,
                 transport_idle_timeout_secs,
+                server_signature: signature,
             }));
         }
 
@@ -175,6 +270,30 @@ impl Peer {
         }))
     }
 
+    /// 客户端证书对应的用户名
+    ///
+    /// 证书身份（CN 或 SAN）只有在 `tls_client_cert_users` 中配置了映射才对应到用户；
+    /// 开启 `tls_client_cert_cn_as_username` 时未配置映射的主题 CN 即用户名，SAN 不会直接作为用户名。
+    /// 请求的用户名为空时使用证书对应的第一个用户。
+    fn certificate_username(&self, username: &str) -> Option<String> {
+        let config = GLOBAL_CONFIG.load();
+        let mapped = self
+            .client_identities
+            .iter()
+            .filter_map(|identity| config.tls_client_cert_users.get(identity));
+        let common_name = self
+            .client_identities
+            .common_name
+            .as_ref()
+            .filter(|_| config.tls_client_cert_cn_as_username);
+        let mut usernames = mapped.chain(common_name);
+        if username.is_empty() {
+            usernames.next().cloned()
+        } else {
+            usernames.find(|name| *name == username).cloned()
+        }
+    }
+
     async fn on_bind_transport_request(
         &mut self,
         message: client_server::BindTransportReq,
@@ -264,7 +383,7 @@ impl Peer {
             server_client::BindTransportAck {
                 player_id,
                 connection_id,
-                transport_idle_timeout_secs: GLOBAL_CONFIG.transport_idle_timeout_secs,
+                transport_idle_timeout_secs: GLOBAL_CONFIG.load().transport_idle_timeout_secs,
             },
         ))
     }
@@ -13,6 +13,7 @@ use bytes::{Bytes, BytesMut};
 use chrono::Utc;
 use log::{debug, error, trace};
 use np_base::net::session_delegate::SessionDelegate;
+use np_base::net::tls::ClientIdentities;
 use np_base::net::WriterMessage;
 use np_proto::message_map::{encode_raw_message, get_message_id, get_message_size, MessageType};
 use np_proto::{generic, message_map};
@@ -72,6 +73,10 @@ pub struct Peer {
     last_recv_time: Option<Arc<AtomicI64>>,
     /// 登录历史记录 ID，用于登出时更新记录。
     login_record_id: u32,
+    /// 挑战登录下发的随机数，只能使用一次。
+    login_nonce: Option<Vec<u8>>,
+    /// TLS 客户端证书中的身份（主题 CN 与 SAN），未出示证书时为空。
+    client_identities: ClientIdentities,
 }
 
 impl Peer {
@@ -89,6 +94,8 @@ impl Peer {
             traffic_tx: None,
             last_recv_time: None,
             login_record_id: 0,
+            login_nonce: None,
+            client_identities: ClientIdentities::default(),
         }
     }
 
@@ -211,6 +218,7 @@ impl Peer {
     async fn create_traffic_forward_channel(&mut self, buf: &[u8]) -> anyhow::Result<()> {
         if let Some(ref tx) = self.tx {
             let target = GLOBAL_CONFIG
+                .load()
                 .forward_rules
                 .iter()
                 .find(|rule| match_rule(&rule.matcher, buf))
@@ -444,6 +452,10 @@ impl SessionDelegate for Peer {
 
         Ok(())
     }
+
+    fn set_client_identities(&mut self, identities: ClientIdentities) {
+        self.client_identities = identities;
+    }
 }
 
 #[inline]
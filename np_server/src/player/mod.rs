@@ -57,6 +57,10 @@ pub struct Player {
     pub traffic_rx: Arc<AtomicU64>,
     /// 出站流量计数器，发送给客户端的数据量，`Peer` 可通过共享引用无锁累加。
     pub traffic_tx: Arc<AtomicU64>,
+    /// 已被刷库取走的入站流量，与 `traffic_rx` 相加即为进程启动以来的累计值。
+    traffic_rx_flushed: AtomicU64,
+    /// 已被刷库取走的出站流量，与 `traffic_tx` 相加即为进程启动以来的累计值。
+    traffic_tx_flushed: AtomicU64,
     /// 通过临时令牌绑定后的转发连接池，键为客户端生成的连接 ID。
     forward_connections: HashMap<u64, PlayerConnection>,
     /// 代理会话到转发连接的路由表，键为代理 `session_id`，值为连接 ID。
@@ -82,6 +86,8 @@ impl Player {
             connection_protocol: String::new(),
             traffic_rx: Arc::new(AtomicU64::new(0)),
             traffic_tx: Arc::new(AtomicU64::new(0)),
+            traffic_rx_flushed: AtomicU64::new(0),
+            traffic_tx_flushed: AtomicU64::new(0),
             forward_connections: HashMap::new(),
             forward_session_routes: HashMap::new(),
             transport_token: String::new(),
@@ -93,18 +99,30 @@ impl Player {
 
     /// 获取并重置流量计数，返回 (rx, tx)
     pub fn take_traffic(&self) -> (u64, u64) {
-        (
-            self.traffic_rx.swap(0, Ordering::Relaxed),
-            self.traffic_tx.swap(0, Ordering::Relaxed),
-        )
+        let rx = self.traffic_rx.swap(0, Ordering::Relaxed);
+        let tx = self.traffic_tx.swap(0, Ordering::Relaxed);
+        self.traffic_rx_flushed.fetch_add(rx, Ordering::Relaxed);
+        self.traffic_tx_flushed.fetch_add(tx, Ordering::Relaxed);
+        (rx, tx)
     }
 
     /// 将已取出的流量加回计数器，用于刷库失败后的补偿
     pub fn add_traffic(&self, rx: u64, tx: u64) {
+        self.traffic_rx_flushed.fetch_sub(rx, Ordering::Relaxed);
+        self.traffic_tx_flushed.fetch_sub(tx, Ordering::Relaxed);
         self.traffic_rx.fetch_add(rx, Ordering::Relaxed);
         self.traffic_tx.fetch_add(tx, Ordering::Relaxed);
     }
 
+    /// 获取进程启动以来的累计流量（单调递增，供监控指标使用）
+    pub fn total_traffic(&self) -> (u64, u64) {
+        let (rx, tx) = self.get_traffic();
+        (
+            self.traffic_rx_flushed.load(Ordering::Relaxed) + rx,
+            self.traffic_tx_flushed.load(Ordering::Relaxed) + tx,
+        )
+    }
+
     /// 获取当前流量（不重置）
     pub fn get_traffic(&self) -> (u64, u64) {
         (
@@ -148,6 +166,12 @@ impl Player {
         &self.connection_protocol
     }
 
+    /// 当前转发连接数量
+    #[inline]
+    pub fn forward_connection_count(&self) -> usize {
+        self.forward_connections.len()
+    }
+
     // 是否在线
     #[inline]
     pub fn is_online(&self) -> bool {
@@ -409,7 +433,6 @@ impl Player {
     // }
 
     #[inline]
-    #[allow(dead_code)]
     pub fn send_push(&self, message: &MessageType) -> anyhow::Result<()> {
         package_and_send_message(&self.tx, 0, message, true)
     }
@@ -545,6 +568,8 @@ mod tests {
             connection_protocol: "tcp".to_string(),
             traffic_rx: Arc::new(AtomicU64::new(0)),
             traffic_tx: Arc::new(AtomicU64::new(0)),
+            traffic_rx_flushed: AtomicU64::new(0),
+            traffic_tx_flushed: AtomicU64::new(0),
             forward_connections: HashMap::new(),
             forward_session_routes: HashMap::new(),
             transport_token: String::new(),
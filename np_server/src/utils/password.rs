@@ -0,0 +1,273 @@
+use anyhow::anyhow;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{Ident, Output, PasswordHash, PasswordHasher, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use np_proto::utils::login::{
+    derive_login_keys, login_proof, verify_login_proof, LOGIN_KEY_LEN, LOGIN_MAX_M_COST,
+    LOGIN_MAX_P_COST, LOGIN_MAX_T_COST,
+};
+use once_cell::sync::Lazy;
+
+/// 数据库中保存的口令校验值的算法标识
+const SCRAM_ALGORITHM: &str = "scram-argon2id";
+
+/// 计算保存到数据库的口令校验值
+///
+/// 沿用 PHC 字符串格式：`$scram-argon2id$v=19$m=..,t=..,p=..$<salt>$<StoredKey|ServerKey>`，
+/// 只保存由 Argon2id 输出派生的 SCRAM StoredKey 与 ServerKey，不保存 Argon2id 输出本身，
+/// 数据库泄露后无法直接用于挑战登录。
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow!("hash password error: {}", err))?;
+    scram_from_argon2(&hash)
+}
+
+/// 由 Argon2id PHC 哈希计算口令校验值，盐值与参数保持不变
+fn scram_from_argon2(hash: &PasswordHash) -> anyhow::Result<String> {
+    if hash.version != Some(Version::V0x13.into()) {
+        return Err(anyhow!("unsupported argon2 version: {:?}", hash.version));
+    }
+    // 超过客户端参数上限的哈希无法用于挑战登录
+    let params = Params::try_from(hash).map_err(|err| anyhow!("invalid argon2 params: {}", err))?;
+    if params.m_cost() > LOGIN_MAX_M_COST
+        || params.t_cost() > LOGIN_MAX_T_COST
+        || params.p_cost() > LOGIN_MAX_P_COST
+    {
+        return Err(anyhow!("argon2 params exceed the client login limits"));
+    }
+    let salted_password = hash
+        .hash
+        .filter(|output| output.len() == LOGIN_KEY_LEN)
+        .ok_or_else(|| anyhow!("unsupported argon2 output length"))?;
+    let (stored_key, server_key) = derive_login_keys(salted_password.as_bytes());
+    let mut keys = stored_key.to_vec();
+    keys.extend_from_slice(&server_key);
+
+    let scram = PasswordHash {
+        algorithm: Ident::new(SCRAM_ALGORITHM).map_err(|err| anyhow!("{}", err))?,
+        version: hash.version,
+        params: hash.params.clone(),
+        salt: hash.salt,
+        hash: Some(Output::new(&keys).map_err(|err| anyhow!("{}", err))?),
+    };
+    Ok(scram.to_string())
+}
+
+/// 将 Argon2id 哈希升级为口令校验值，已经是口令校验值时原样返回
+///
+/// 用于迁移旧版本保存的哈希以及导入清单中的哈希。
+pub fn upgrade_password_hash(password_hash: &str) -> anyhow::Result<String> {
+    let hash = PasswordHash::new(password_hash)
+        .map_err(|err| anyhow!("invalid password hash: {}", err))?;
+    match hash.algorithm.as_str() {
+        SCRAM_ALGORITHM => Ok(password_hash.to_string()),
+        "argon2id" => scram_from_argon2(&hash),
+        algorithm => Err(anyhow!(
+            "unsupported password hash algorithm: {}",
+            algorithm
+        )),
+    }
+}
+
+/// 校验密码与数据库中保存的口令校验值是否匹配
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let (Some(params), Some(keys)) = (challenge_params(password_hash), login_keys(password_hash))
+    else {
+        return false;
+    };
+    // 与挑战登录走同一套校验，使用空随机数
+    salted_password(password, &params).is_some_and(|salted_password| {
+        verify_login_proof(
+            &keys.stored_key,
+            &[],
+            "",
+            &login_proof(&salted_password, &[], ""),
+        )
+    })
+}
+
+/// 使用挑战登录参数计算 Argon2id 输出，与客户端的计算方式一致
+fn salted_password(password: &str, params: &ChallengeParams) -> Option<[u8; LOGIN_KEY_LEN]> {
+    let argon2_params = Params::new(
+        params.m_cost,
+        params.t_cost,
+        params.p_cost,
+        Some(LOGIN_KEY_LEN),
+    )
+    .ok()?;
+    let mut salted_password = [0u8; LOGIN_KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+        .hash_password_into(password.as_bytes(), &params.salt, &mut salted_password)
+        .ok()?;
+    Some(salted_password)
+}
+
+/// 是否已经是密码哈希或口令校验值（用于迁移旧的明文密码）
+pub fn is_password_hash(s: &str) -> bool {
+    (s.starts_with("$argon2") || s.starts_with(&format!("${SCRAM_ALGORITHM}$")))
+        && PasswordHash::new(s).is_ok()
+}
+
+/// 在阻塞线程池中校验密码，避免 Argon2 计算阻塞异步运行时
+pub async fn verify_password_async(password: String, password_hash: String) -> bool {
+    tokio::task::spawn_blocking(move || verify_password(&password, &password_hash))
+        .await
+        .unwrap_or(false)
+}
+
+/// 在阻塞线程池中计算密码哈希
+pub async fn hash_password_async(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || hash_password(&password)).await?
+}
+
+/// 挑战登录所需的密码哈希参数
+pub struct ChallengeParams {
+    pub salt: Vec<u8>,
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+/// 从保存的密码哈希中取出挑战登录参数
+pub fn challenge_params(password_hash: &str) -> Option<ChallengeParams> {
+    let hash = PasswordHash::new(password_hash).ok()?;
+    let params = Params::try_from(&hash).ok()?;
+    let mut salt = [0u8; 64];
+    let salt = hash.salt?.decode_b64(&mut salt).ok()?.to_vec();
+    Some(ChallengeParams {
+        salt,
+        m_cost: params.m_cost(),
+        t_cost: params.t_cost(),
+        p_cost: params.p_cost(),
+    })
+}
+
+/// 不存在的用户也返回稳定的伪造参数，避免通过挑战请求枚举用户名
+pub fn fake_challenge_params(username: &str) -> ChallengeParams {
+    static FAKE_SALT_KEY: Lazy<[u8; 32]> = Lazy::new(|| {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        key
+    });
+    let mut data = FAKE_SALT_KEY.to_vec();
+    data.extend_from_slice(username.as_bytes());
+    let params = Params::default();
+    ChallengeParams {
+        salt: md5::compute(data).0.to_vec(),
+        m_cost: params.m_cost(),
+        t_cost: params.t_cost(),
+        p_cost: params.p_cost(),
+    }
+}
+
+/// 服务端保存的 SCRAM 密钥
+pub struct LoginKeys {
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+/// 从口令校验值中取出 StoredKey 与 ServerKey
+pub fn login_keys(password_hash: &str) -> Option<LoginKeys> {
+    let hash = PasswordHash::new(password_hash).ok()?;
+    if hash.algorithm.as_str() != SCRAM_ALGORITHM {
+        return None;
+    }
+    let keys = hash.hash?;
+    if keys.len() != LOGIN_KEY_LEN * 2 {
+        return None;
+    }
+    let (stored_key, server_key) = keys.as_bytes().split_at(LOGIN_KEY_LEN);
+    Some(LoginKeys {
+        stored_key: stored_key.to_vec(),
+        server_key: server_key.to_vec(),
+    })
+}
+
+/// 生成登录挑战随机数
+pub fn generate_login_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash_password("secret").unwrap();
+        assert!(is_password_hash(&hash));
+        assert!(!is_password_hash("secret"));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("wrong", &hash));
+        assert!(!verify_password("secret", "secret"));
+    }
+
+    #[test]
+    fn test_challenge_login() {
+        let hash = hash_password("secret").unwrap();
+        let params = challenge_params(&hash).unwrap();
+        let keys = login_keys(&hash).unwrap();
+        let nonce = generate_login_nonce();
+
+        // 客户端按下发的参数计算，与服务端保存的值一致
+        let salted = salted_password("secret", &params).unwrap();
+        let proof = login_proof(&salted, &nonce, "alice");
+        assert!(verify_login_proof(
+            &keys.stored_key,
+            &nonce,
+            "alice",
+            &proof
+        ));
+        assert!(!verify_login_proof(&keys.stored_key, &nonce, "bob", &proof));
+        let wrong = salted_password("wrong", &params).unwrap();
+        let proof = login_proof(&wrong, &nonce, "alice");
+        assert!(!verify_login_proof(
+            &keys.stored_key,
+            &nonce,
+            "alice",
+            &proof
+        ));
+
+        // 只拿到数据库中保存的值无法构造有效应答
+        let stored = PasswordHash::new(&hash).unwrap().hash.unwrap();
+        for key in [
+            stored.as_bytes(),
+            keys.stored_key.as_slice(),
+            keys.server_key.as_slice(),
+        ] {
+            let proof = login_proof(key, &nonce, "alice");
+            assert!(!verify_login_proof(
+                &keys.stored_key,
+                &nonce,
+                "alice",
+                &proof
+            ));
+        }
+
+        assert_eq!(
+            fake_challenge_params("nobody").salt,
+            fake_challenge_params("nobody").salt
+        );
+    }
+
+    #[test]
+    fn test_upgrade_argon2_hash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2_hash = Argon2::default()
+            .hash_password(b"secret", &salt)
+            .unwrap()
+            .to_string();
+        assert!(is_password_hash(&argon2_hash));
+
+        let upgraded = upgrade_password_hash(&argon2_hash).unwrap();
+        assert!(upgraded.starts_with("$scram-argon2id$"));
+        assert!(is_password_hash(&upgraded));
+        assert!(verify_password("secret", &upgraded));
+        assert!(!verify_password("secret", &argon2_hash));
+        assert_eq!(upgrade_password_hash(&upgraded).unwrap(), upgraded);
+    }
+}
@@ -4,6 +4,7 @@ use crate::global::config::GLOBAL_CONFIG;
 use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::login_history;
 use crate::orm_entity::prelude::User;
+use crate::utils::password::verify_password_async;
 use actix_identity::Identity;
 use actix_web::{error, Error, HttpMessage, HttpRequest, HttpResponse, Responder};
 use chrono::Utc;
@@ -68,9 +69,10 @@ pub(super) async fn login(
         .to_owned();
 
     // 管理员登录（配置文件中的账号）
-    if !GLOBAL_CONFIG.web_username.is_empty()
-        && GLOBAL_CONFIG.web_username == req.username
-        && GLOBAL_CONFIG.web_password == req.password
+    let config = GLOBAL_CONFIG.load();
+    if !config.web_username.is_empty()
+        && config.web_username == req.username
+        && config.web_password == req.password
     {
         Identity::login(&request.extensions(), "admin".to_owned())?;
         record_web_login(0, ip_addr, 1).await;
@@ -84,13 +86,19 @@ pub(super) async fn login(
         }));
     }
 
-    if let Some(user) = User::find()
+    let user = User::find()
         .filter(crate::orm_entity::user::Column::Username.eq(&req.username))
-        .filter(crate::orm_entity::user::Column::Password.eq(&req.password))
         .one(GLOBAL_DB_POOL.get().unwrap())
         .await
-        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?
-    {
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+    let user = match user {
+        Some(user) if verify_password_async(req.password.clone(), user.password.clone()).await => {
+            Some(user)
+        }
+        _ => None,
+    };
+
+    if let Some(user) = user {
         if user.enabled != 1 {
             record_web_login(user.id, ip_addr, 0).await;
             return Ok(HttpResponse::Ok().json(proto::LoginResponse {
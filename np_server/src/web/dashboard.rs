@@ -1,9 +1,13 @@
 use super::proto;
-use super::support::require_admin;
+use super::support::{quota_usage_item, require_admin};
 use crate::global::config::GLOBAL_CONFIG;
 use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::GLOBAL_DB_POOL;
+use crate::orm_entity::prelude::User;
+use crate::orm_entity::user;
 use actix_identity::Identity;
-use actix_web::{HttpResponse, Responder};
+use actix_web::{error, HttpResponse, Responder};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
 use sysinfo::{System, MINIMUM_CPU_UPDATE_INTERVAL};
 
 pub(super) async fn dashboard_overview(
@@ -26,28 +30,40 @@ pub(super) async fn dashboard_overview(
     let enabled_tunnels = tunnels.iter().filter(|tunnel| tunnel.enabled == 1).count();
     drop(tunnels);
 
+    let quota_users = User::find()
+        .filter(user::Column::QuotaBytes.gt(0))
+        .order_by_asc(user::Column::Id)
+        .all(GLOBAL_DB_POOL.get().unwrap())
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+    let mut quotas = Vec::with_capacity(quota_users.len());
+    for user in &quota_users {
+        quotas.push(quota_usage_item(user).await?);
+    }
+
+    let config = GLOBAL_CONFIG.load_full();
     Ok(HttpResponse::Ok().json(proto::DashboardOverviewResponse {
         online_players,
         total_players,
         enabled_tunnels,
         total_tunnels,
+        quotas,
         config: proto::DashboardConfigInfo {
-            listen_addr: GLOBAL_CONFIG.listen_addr.clone(),
-            web_addr: GLOBAL_CONFIG.web_addr.clone(),
-            enable_tls: GLOBAL_CONFIG.enable_tls,
-            web_enable_tls: GLOBAL_CONFIG.web_enable_tls,
-            web_tls_cert: GLOBAL_CONFIG.web_tls_cert.clone(),
-            web_tls_auto_self_signed: GLOBAL_CONFIG.web_tls_auto_self_signed,
-            web_cookie_secure: GLOBAL_CONFIG.web_cookie_secure,
-            tls_cert: GLOBAL_CONFIG.tls_cert.clone(),
-            web_base_dir: GLOBAL_CONFIG.web_base_dir.clone(),
-            illegal_traffic_forward: GLOBAL_CONFIG.illegal_traffic_forward.clone(),
-            transport_max_connections_per_player: GLOBAL_CONFIG
-                .transport_max_connections_per_player,
-            transport_idle_timeout_secs: GLOBAL_CONFIG.transport_idle_timeout_secs,
-            quiet: GLOBAL_CONFIG.quiet,
-            log_dir: GLOBAL_CONFIG.log_dir.clone(),
-            database: database_kind(&GLOBAL_CONFIG.database_url).to_string(),
+            listen_addr: config.listen_addr.clone(),
+            web_addr: config.web_addr.clone(),
+            enable_tls: config.enable_tls,
+            web_enable_tls: config.web_enable_tls,
+            web_tls_cert: config.web_tls_cert.clone(),
+            web_tls_auto_self_signed: config.web_tls_auto_self_signed,
+            web_cookie_secure: config.web_cookie_secure,
+            tls_cert: config.tls_cert.clone(),
+            web_base_dir: config.web_base_dir.clone(),
+            illegal_traffic_forward: config.illegal_traffic_forward.clone(),
+            transport_max_connections_per_player: config.transport_max_connections_per_player,
+            transport_idle_timeout_secs: config.transport_idle_timeout_secs,
+            quiet: config.quiet,
+            log_dir: config.log_dir.clone(),
+            database: database_kind(&config.database_url).to_string(),
         },
         system: collect_system_info().await,
     }))
@@ -1,9 +1,12 @@
 use super::proto;
 use super::support::{record_operation, require_admin};
+use crate::global::config::reload_config;
+use crate::global::manifest::{manifest_drift, reconcile_manifest, ManifestChange};
 use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::login_history;
 use crate::orm_entity::operation_log;
 use crate::orm_entity::traffic_hourly;
+use crate::orm_entity::tunnel_traffic_hourly;
 use actix_identity::Identity;
 use actix_web::{error, HttpResponse, Responder};
 use chrono::{Duration as ChronoDuration, Utc};
@@ -162,17 +165,25 @@ pub(super) async fn cleanup_database(
         .rows_affected;
 
     let traffic_hourly_deleted = traffic_hourly::Entity::delete_many()
-        .filter(traffic_hourly::Column::Hour.lt(traffic_cutoff))
+        .filter(traffic_hourly::Column::Hour.lt(traffic_cutoff.as_str()))
+        .exec(db)
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?
+        .rows_affected;
+
+    let tunnel_traffic_hourly_deleted = tunnel_traffic_hourly::Entity::delete_many()
+        .filter(tunnel_traffic_hourly::Column::Hour.lt(traffic_cutoff))
         .exec(db)
         .await
         .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?
         .rows_affected;
 
     let detail = format!(
-        "login_history: {}; operation_log: {}; traffic_hourly: {}; keep_days: login={}, operation={}, traffic={}",
+        "login_history: {}; operation_log: {}; traffic_hourly: {}; tunnel_traffic_hourly: {}; keep_days: login={}, operation={}, traffic={}",
         login_history_deleted,
         operation_log_deleted,
         traffic_hourly_deleted,
+        tunnel_traffic_hourly_deleted,
         login_days,
         operation_days,
         traffic_days
@@ -183,5 +194,80 @@ pub(super) async fn cleanup_database(
         login_history_deleted,
         operation_log_deleted,
         traffic_hourly_deleted,
+        tunnel_traffic_hourly_deleted,
     }))
 }
+
+pub(super) async fn reload_server_config(
+    identity: Option<Identity>,
+) -> actix_web::Result<impl Responder> {
+    if let Err(result) = require_admin(identity).await? {
+        return Ok(result);
+    }
+
+    let response = match reload_config() {
+        Ok(()) => {
+            record_operation("reload_config", "system", 0, "config", "").await;
+            proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }
+        }
+        Err(err) => proto::GeneralResponse {
+            code: -1,
+            msg: format!("Failed to reload config: {}", err),
+        },
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub(super) async fn manifest_diff(identity: Option<Identity>) -> actix_web::Result<impl Responder> {
+    if let Err(result) = require_admin(identity).await? {
+        return Ok(result);
+    }
+    Ok(HttpResponse::Ok().json(manifest_response(manifest_drift().await)))
+}
+
+pub(super) async fn apply_manifest(
+    identity: Option<Identity>,
+) -> actix_web::Result<impl Responder> {
+    if let Err(result) = require_admin(identity).await? {
+        return Ok(result);
+    }
+
+    let result = reconcile_manifest().await;
+    if let Ok(changes) = &result {
+        record_operation(
+            "apply_manifest",
+            "system",
+            0,
+            "manifest",
+            &format!("changes: {}", changes.len()),
+        )
+        .await;
+    }
+    Ok(HttpResponse::Ok().json(manifest_response(result)))
+}
+
+fn manifest_response(result: anyhow::Result<Vec<ManifestChange>>) -> proto::ManifestResponse {
+    match result {
+        Ok(changes) => proto::ManifestResponse {
+            code: 0,
+            msg: "Success".into(),
+            changes: changes
+                .into_iter()
+                .map(|change| proto::ManifestChangeInfo {
+                    kind: change.kind.to_string(),
+                    action: change.action.to_string(),
+                    name: change.name,
+                    detail: change.detail,
+                })
+                .collect(),
+        },
+        Err(err) => proto::ManifestResponse {
+            code: -1,
+            msg: err.to_string(),
+            changes: vec![],
+        },
+    }
+}
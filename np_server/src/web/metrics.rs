@@ -0,0 +1,197 @@
+use crate::global::config::GLOBAL_CONFIG;
+use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::GLOBAL_DB_POOL;
+use crate::orm_entity::prelude::User;
+use actix_web::http::header;
+use actix_web::{error, HttpRequest, HttpResponse, Responder};
+use np_base::metrics::{tunnel_type_label, MetricsWriter, METRICS_CONTENT_TYPE};
+use sea_orm::EntityTrait;
+use std::collections::HashMap;
+
+/// Prometheus 指标接口
+///
+/// 需要在配置中开启 `metrics_enabled`；配置了 `metrics_token` 时要求
+/// `Authorization: Bearer <token>`。
+pub(super) async fn metrics(req: HttpRequest) -> actix_web::Result<impl Responder> {
+    let config = GLOBAL_CONFIG.load_full();
+    if !config.metrics_enabled {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    if !config.metrics_token.is_empty() {
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if token != Some(config.metrics_token.as_str()) {
+            return Ok(HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .finish());
+        }
+    }
+
+    let body = collect_metrics()
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+    Ok(HttpResponse::Ok()
+        .content_type(METRICS_CONTENT_TYPE)
+        .body(body))
+}
+
+struct PlayerSample {
+    user_id: String,
+    username: String,
+    online: bool,
+    protocol: String,
+    forward_connections: usize,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+async fn collect_metrics() -> anyhow::Result<String> {
+    let usernames: HashMap<u32, String> = User::find()
+        .all(GLOBAL_DB_POOL.get().unwrap())
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user.username))
+        .collect();
+
+    let mut players: Vec<_> = GLOBAL_MANAGER
+        .player_manager
+        .player_map
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().clone()))
+        .collect();
+    players.sort_by_key(|(player_id, _)| *player_id);
+    let mut samples = Vec::with_capacity(players.len());
+    for (player_id, player) in players {
+        let player = player.read().await;
+        let (bytes_in, bytes_out) = player.total_traffic();
+        samples.push(PlayerSample {
+            user_id: player_id.to_string(),
+            username: usernames.get(&player_id).cloned().unwrap_or_default(),
+            online: player.is_online(),
+            protocol: player.get_connection_protocol().to_string(),
+            forward_connections: player.forward_connection_count(),
+            bytes_in,
+            bytes_out,
+        });
+    }
+
+    let mut writer = MetricsWriter::new();
+    writer.single(
+        "npipe_players",
+        "gauge",
+        "Number of registered users",
+        samples.len(),
+    );
+    writer.single(
+        "npipe_players_online",
+        "gauge",
+        "Number of online users",
+        samples.iter().filter(|sample| sample.online).count(),
+    );
+
+    writer.family(
+        "npipe_player_online",
+        "gauge",
+        "Whether the user is online, labeled with the control connection protocol",
+    );
+    for sample in &samples {
+        writer.sample(
+            "npipe_player_online",
+            &[
+                ("user_id", &sample.user_id),
+                ("user", &sample.username),
+                ("protocol", &sample.protocol),
+            ],
+            sample.online as u8,
+        );
+    }
+
+    writer.family(
+        "npipe_player_forward_connections",
+        "gauge",
+        "Number of pooled forward connections of the user",
+    );
+    for sample in samples.iter().filter(|sample| sample.online) {
+        writer.sample(
+            "npipe_player_forward_connections",
+            &[
+                ("user_id", &sample.user_id),
+                ("user", &sample.username),
+                ("protocol", &sample.protocol),
+            ],
+            sample.forward_connections,
+        );
+    }
+
+    writer.family(
+        "npipe_player_traffic_bytes_total",
+        "counter",
+        "Bytes relayed for the user since server start, in = received from the client, out = sent to the client",
+    );
+    for sample in &samples {
+        for (direction, value) in [("in", sample.bytes_in), ("out", sample.bytes_out)] {
+            writer.sample(
+                "npipe_player_traffic_bytes_total",
+                &[
+                    ("user_id", &sample.user_id),
+                    ("user", &sample.username),
+                    ("direction", direction),
+                ],
+                value,
+            );
+        }
+    }
+
+    let tunnels = GLOBAL_MANAGER.tunnel_manager.tunnels.read().await.clone();
+    writer.single(
+        "npipe_tunnels",
+        "gauge",
+        "Number of configured tunnels",
+        tunnels.len(),
+    );
+    writer.family(
+        "npipe_tunnel_enabled",
+        "gauge",
+        "Whether the tunnel is enabled",
+    );
+    let mut tunnel_types = HashMap::with_capacity(tunnels.len());
+    for tunnel in &tunnels {
+        let tunnel_id = tunnel.id.to_string();
+        let tunnel_type = tunnel_type_label(tunnel.tunnel_type);
+        tunnel_types.insert(tunnel.id, tunnel_type);
+        writer.sample(
+            "npipe_tunnel_enabled",
+            &[
+                ("tunnel", &tunnel_id),
+                ("type", tunnel_type),
+                ("sender", &tunnel.sender.to_string()),
+                ("receiver", &tunnel.receiver.to_string()),
+            ],
+            tunnel.enabled,
+        );
+    }
+
+    writer.family(
+        "npipe_tunnel_sessions",
+        "gauge",
+        "Number of active sessions of tunnel inlets/outlets hosted by the server",
+    );
+    let mut session_counts = GLOBAL_MANAGER.proxy_manager.session_counts();
+    session_counts.sort();
+    for (tunnel_id, is_inlet, count) in session_counts {
+        writer.sample(
+            "npipe_tunnel_sessions",
+            &[
+                ("tunnel", &tunnel_id.to_string()),
+                ("type", tunnel_types.get(&tunnel_id).copied().unwrap_or("")),
+                ("side", if is_inlet { "inlet" } else { "outlet" }),
+            ],
+            count,
+        );
+    }
+
+    Ok(writer.finish())
+}
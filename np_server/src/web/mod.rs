@@ -2,6 +2,7 @@ mod auth;
 mod dashboard;
 mod logs;
 mod maintenance;
+mod metrics;
 mod player;
 mod proto;
 mod support;
@@ -15,33 +16,29 @@ use actix_web::{
     middleware, web, App, HttpServer,
 };
 
-use crate::global::config::GLOBAL_CONFIG;
+use crate::global::config::{GLOBAL_CONFIG, WEB_TLS_CERT_RESOLVER};
 use log::warn;
+use np_base::net::tls::ReloadableCertResolver;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use std::collections::BTreeSet;
-use std::fs::File;
-use std::io::BufReader;
 
 fn load_web_tls_config() -> anyhow::Result<rustls::ServerConfig> {
-    if GLOBAL_CONFIG.web_tls_cert.is_empty() || GLOBAL_CONFIG.web_tls_key.is_empty() {
-        if !GLOBAL_CONFIG.web_tls_auto_self_signed {
+    let config = GLOBAL_CONFIG.load();
+    if config.web_tls_cert.is_empty() || config.web_tls_key.is_empty() {
+        if !config.web_tls_auto_self_signed {
             anyhow::bail!("web TLS is enabled, but web_tls_cert or web_tls_key is empty");
         }
         return generate_self_signed_web_tls_config();
     }
 
-    let cert_file = File::open(&GLOBAL_CONFIG.web_tls_cert)?;
-    let key_file = File::open(&GLOBAL_CONFIG.web_tls_key)?;
-    let mut cert_reader = BufReader::new(cert_file);
-    let mut key_reader = BufReader::new(key_file);
-
-    let cert_chain = rustls_pemfile::certs(&mut cert_reader).collect::<Result<Vec<_>, _>>()?;
-    let private_key = rustls_pemfile::private_key(&mut key_reader)?
-        .ok_or_else(|| anyhow::anyhow!("web TLS private key not found"))?;
+    // 使用可热更新的证书，重载配置时替换
+    let resolver = WEB_TLS_CERT_RESOLVER
+        .get_or_try_init(|| ReloadableCertResolver::new(&config.web_tls_cert, &config.web_tls_key))?
+        .clone();
 
     let mut config = rustls::ServerConfig::builder()
         .with_no_client_auth()
-        .with_single_cert(cert_chain, private_key)?;
+        .with_cert_resolver(resolver);
 
     config.alpn_protocols.push(b"h2".to_vec());
     config.alpn_protocols.push(b"http/1.1".to_vec());
@@ -76,6 +73,7 @@ fn generate_self_signed_web_tls_config() -> anyhow::Result<rustls::ServerConfig>
 
 fn web_tls_subject_name() -> String {
     GLOBAL_CONFIG
+        .load()
         .web_addr
         .rsplit_once(':')
         .map(|(host, _)| host.trim_matches(['[', ']']).to_owned())
@@ -84,11 +82,11 @@ fn web_tls_subject_name() -> String {
 }
 
 /// http server
-pub async fn run_http_server(addr: &str, web_base_dir: &str) -> anyhow::Result<()> {
+pub async fn run_http_server(addr: String, web_base_dir: String) -> anyhow::Result<()> {
     let secret_key = Key::generate();
-    let web_base_dir = web_base_dir.to_string();
 
-    let cookie_secure = GLOBAL_CONFIG.web_enable_tls || GLOBAL_CONFIG.web_cookie_secure;
+    let config = GLOBAL_CONFIG.load_full();
+    let cookie_secure = config.web_enable_tls || config.web_cookie_secure;
     let server = HttpServer::new(move || {
         App::new()
             // 添加 Cors 中间件，并允许所有跨域请求
@@ -124,6 +122,14 @@ pub async fn run_http_server(addr: &str, web_base_dir: &str) -> anyhow::Result<(
                 web::resource("/api/update_player_web_access")
                     .route(web::post().to(player::update_player_web_access)),
             )
+            .service(
+                web::resource("/api/update_player_rate_limit")
+                    .route(web::post().to(player::update_player_rate_limit)),
+            )
+            .service(
+                web::resource("/api/update_player_quota")
+                    .route(web::post().to(player::update_player_quota)),
+            )
             .service(web::resource("/api/kick_player").route(web::post().to(player::kick_player)))
             .service(
                 web::resource("/api/player_detail").route(web::post().to(player::player_detail)),
@@ -147,6 +153,18 @@ pub async fn run_http_server(addr: &str, web_base_dir: &str) -> anyhow::Result<(
                 web::resource("/api/cleanup_database")
                     .route(web::post().to(maintenance::cleanup_database)),
             )
+            .service(
+                web::resource("/api/reload_config")
+                    .route(web::post().to(maintenance::reload_server_config)),
+            )
+            .service(
+                web::resource("/api/manifest_diff")
+                    .route(web::post().to(maintenance::manifest_diff)),
+            )
+            .service(
+                web::resource("/api/apply_manifest")
+                    .route(web::post().to(maintenance::apply_manifest)),
+            )
             .service(web::resource("/api/tunnel_list").route(web::post().to(tunnel::tunnel_list)))
             .service(
                 web::resource("/api/tunnel_detail").route(web::post().to(tunnel::tunnel_detail)),
@@ -162,10 +180,31 @@ pub async fn run_http_server(addr: &str, web_base_dir: &str) -> anyhow::Result<(
                 web::resource("/api/update_tunnel_status")
                     .route(web::post().to(tunnel::update_tunnel_status)),
             )
+            .service(
+                web::resource("/api/upload_tunnel_cert")
+                    .route(web::post().to(tunnel::upload_tunnel_cert)),
+            )
+            .service(
+                web::resource("/api/remove_tunnel_cert")
+                    .route(web::post().to(tunnel::remove_tunnel_cert)),
+            )
             .service(
                 web::resource("/api/tunnel_diagnose")
                     .route(web::post().to(tunnel::tunnel_diagnose)),
             )
+            .service(
+                web::resource("/api/tunnel_traffic_stats")
+                    .route(web::post().to(tunnel::tunnel_traffic_stats)),
+            )
+            .service(
+                web::resource("/api/tunnel_sessions")
+                    .route(web::post().to(tunnel::tunnel_sessions)),
+            )
+            .service(
+                web::resource("/api/close_tunnel_session")
+                    .route(web::post().to(tunnel::close_tunnel_session)),
+            )
+            .service(web::resource("/metrics").route(web::get().to(metrics::metrics)))
             .service(actix_files::Files::new("/", &web_base_dir).index_file("index.html"))
             .wrap(IdentityMiddleware::default())
             .wrap(
@@ -180,7 +219,7 @@ pub async fn run_http_server(addr: &str, web_base_dir: &str) -> anyhow::Result<(
             .wrap(middleware::NormalizePath::trim())
     });
 
-    let server = if GLOBAL_CONFIG.web_enable_tls {
+    let server = if config.web_enable_tls {
         server.bind_rustls_0_23(addr, load_web_tls_config()?)?
     } else {
         server.bind(addr)?
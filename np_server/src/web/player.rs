@@ -1,9 +1,10 @@
 use super::proto;
 use super::support::{
-    auth_context, bool_text, forbidden_response, player_name, player_online, record_operation,
-    require_admin,
+    auth_context, bool_text, forbidden_response, player_name, player_online, quota_usage_item,
+    record_operation, require_admin,
 };
 use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::quota::{PlayerQuota, QUOTA_PERIOD_TOTAL};
 use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::login_history;
 use crate::orm_entity::prelude::User;
@@ -84,6 +85,8 @@ pub(super) async fn player_list(
             username: data.username,
             enabled: data.enabled == 1,
             web_access: data.web_access == 1,
+            upload_limit: data.upload_limit,
+            download_limit: data.download_limit,
             online,
             ip_addr,
             connection_protocol,
@@ -412,6 +415,65 @@ pub(super) async fn update_player_web_access(
     }
 }
 
+pub(super) async fn update_player_rate_limit(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    if let Err(result) = require_admin(identity).await? {
+        return Ok(result);
+    }
+
+    let req = serde_json::from_str::<proto::PlayerRateLimitUpdateReq>(&body)?;
+    let old_user = User::find_by_id(req.id)
+        .one(GLOBAL_DB_POOL.get().unwrap())
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+    match GLOBAL_MANAGER
+        .player_manager
+        .update_player_rate_limit(req.id, req.upload_limit, req.download_limit)
+        .await
+    {
+        Ok(()) => {
+            let target_name = old_user
+                .as_ref()
+                .map(|user| user.username.clone())
+                .unwrap_or_default();
+            let detail = old_user
+                .map(|user| {
+                    format!(
+                        "upload_limit: {} -> {}; download_limit: {} -> {}",
+                        user.upload_limit,
+                        req.upload_limit,
+                        user.download_limit,
+                        req.download_limit
+                    )
+                })
+                .unwrap_or_else(|| {
+                    format!(
+                        "upload_limit: {}; download_limit: {}",
+                        req.upload_limit, req.download_limit
+                    )
+                });
+            record_operation(
+                "update_player_rate_limit",
+                "player",
+                req.id,
+                &target_name,
+                &detail,
+            )
+            .await;
+            Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }))
+        }
+        Err(err) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: err.to_string(),
+        })),
+    }
+}
+
 async fn kick_player_session(player_id: u32) {
     if let Some(p) = GLOBAL_MANAGER.player_manager.get_player(player_id) {
         let mut player = p.write().await;
@@ -421,6 +483,52 @@ async fn kick_player_session(player_id: u32) {
     }
 }
 
+pub(super) async fn update_player_quota(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    if let Err(result) = require_admin(identity).await? {
+        return Ok(result);
+    }
+
+    let req = serde_json::from_str::<proto::PlayerQuotaUpdateReq>(&body)?;
+    let quota = PlayerQuota {
+        quota_bytes: req.quota_bytes,
+        quota_period: req.quota_period,
+        quota_reset_day: req.quota_reset_day,
+        quota_hard: req.quota_hard,
+    };
+    match GLOBAL_MANAGER
+        .player_manager
+        .update_player_quota(req.id, &quota)
+        .await
+    {
+        Ok(()) => {
+            let name = player_name(req.id).await;
+            let period = if quota.quota_period == QUOTA_PERIOD_TOTAL {
+                "total".to_string()
+            } else {
+                format!("monthly (reset day {})", quota.quota_reset_day)
+            };
+            let detail = format!(
+                "quota: {}; period: {}; mode: {}",
+                quota.quota_bytes,
+                period,
+                if quota.quota_hard { "hard" } else { "soft" }
+            );
+            record_operation("update_player_quota", "player", req.id, &name, &detail).await;
+            Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }))
+        }
+        Err(err) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: err.to_string(),
+        })),
+    }
+}
+
 pub(super) async fn kick_player(
     identity: Option<Identity>,
     body: String,
@@ -551,12 +659,16 @@ pub(super) async fn player_detail(
     traffic_24h_in += bytes_in;
     traffic_24h_out += bytes_out;
 
+    let quota = quota_usage_item(&user).await?;
+
     Ok(HttpResponse::Ok().json(proto::PlayerDetailResponse {
         player: Some(proto::PlayerDetailItem {
             id: user.id,
             username: user.username,
             enabled: user.enabled == 1,
             web_access: user.web_access == 1,
+            upload_limit: user.upload_limit,
+            download_limit: user.download_limit,
             create_time: user.create_time.format("%Y-%m-%d %H:%M:%S").to_string(),
             online,
             ip_addr,
@@ -566,6 +678,7 @@ pub(super) async fn player_detail(
             bytes_out,
             traffic_24h_in,
             traffic_24h_out,
+            quota,
             tunnels,
             recent_logins,
         }),
@@ -1,3 +1,4 @@
+use super::QuotaUsageItem;
 use serde::{Deserialize, Serialize};
 
 /// 运行概览响应
@@ -7,6 +8,8 @@ pub struct DashboardOverviewResponse {
     pub total_players: usize,
     pub enabled_tunnels: usize,
     pub total_tunnels: usize,
+    // 设置了流量配额的玩家及其使用情况
+    pub quotas: Vec<QuotaUsageItem>,
     pub config: DashboardConfigInfo,
     pub system: DashboardSystemInfo,
 }
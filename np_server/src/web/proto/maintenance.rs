@@ -14,6 +14,7 @@ pub struct CleanupDatabaseResponse {
     pub login_history_deleted: u64,
     pub operation_log_deleted: u64,
     pub traffic_hourly_deleted: u64,
+    pub tunnel_traffic_hourly_deleted: u64,
 }
 
 /// 数据库维护表信息
@@ -32,3 +33,20 @@ pub struct DatabaseMaintenanceInfoResponse {
     pub operation_log: DatabaseMaintenanceTableInfo,
     pub traffic_hourly: DatabaseMaintenanceTableInfo,
 }
+
+/// 清单差异条目
+#[derive(Serialize, Deserialize)]
+pub struct ManifestChangeInfo {
+    pub kind: String,
+    pub action: String,
+    pub name: String,
+    pub detail: String,
+}
+
+/// 清单差异/同步响应
+#[derive(Serialize, Deserialize)]
+pub struct ManifestResponse {
+    pub code: i32,
+    pub msg: String,
+    pub changes: Vec<ManifestChangeInfo>,
+}
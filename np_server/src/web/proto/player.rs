@@ -16,6 +16,10 @@ pub struct PlayerListItem {
     pub username: String,
     pub enabled: bool,
     pub web_access: bool,
+    // 上行限速（字节/秒，0为不限速）
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    pub download_limit: i64,
     pub online: bool,
     pub ip_addr: String,
     pub connection_protocol: String,
@@ -81,6 +85,47 @@ pub struct PlayerWebAccessUpdateReq {
     pub web_access: u8,
 }
 
+/// 修改玩家带宽限速
+#[derive(Serialize, Deserialize)]
+pub struct PlayerRateLimitUpdateReq {
+    pub id: u32,
+    // 上行限速（字节/秒，0为不限速）
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    pub download_limit: i64,
+}
+
+/// 修改玩家流量配额
+#[derive(Serialize, Deserialize)]
+pub struct PlayerQuotaUpdateReq {
+    pub id: u32,
+    // 配额字节数（上行 + 下行，0为不限额）
+    pub quota_bytes: i64,
+    // 配额周期：0 按月重置，1 累计总量
+    pub quota_period: u8,
+    // 每月重置日（1-28）
+    pub quota_reset_day: u8,
+    // 硬限额：超额后暂停通道；软限额：仅记录告警
+    pub quota_hard: bool,
+}
+
+/// 玩家流量配额使用情况
+#[derive(Serialize, Deserialize, Clone)]
+pub struct QuotaUsageItem {
+    pub user_id: u32,
+    pub username: String,
+    pub quota_bytes: i64,
+    pub quota_period: u8,
+    pub quota_reset_day: u8,
+    pub quota_hard: bool,
+    pub quota_exceeded: bool,
+    pub used_bytes: i64,
+    // 当前周期起始时间戳，累计总量为 0
+    pub period_start: i64,
+    // 下次重置时间戳，累计总量为 0
+    pub next_reset: i64,
+}
+
 /// 踢玩家下线
 #[derive(Serialize, Deserialize)]
 pub struct KickPlayerReq {
@@ -113,6 +158,10 @@ pub struct PlayerDetailItem {
     pub enabled: bool,
     pub web_access: bool,
     pub create_time: String,
+    // 上行限速（字节/秒，0为不限速）
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    pub download_limit: i64,
     pub online: bool,
     pub ip_addr: String,
     pub connection_protocol: String,
@@ -121,6 +170,7 @@ pub struct PlayerDetailItem {
     pub bytes_out: i64,
     pub traffic_24h_in: i64,
     pub traffic_24h_out: i64,
+    pub quota: QuotaUsageItem,
     pub tunnels: Vec<PlayerTunnelItem>,
     pub recent_logins: Vec<LoginHistoryItem>,
 }
@@ -26,11 +26,31 @@ pub struct TunnelListItem {
     pub is_compressed: bool,
     pub encryption_method: String,
     pub custom_mapping: HashMap<String, String>,
+    // 上行限速（字节/秒，0为不限速）
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    pub download_limit: i64,
+    // 是否因流量配额超额被暂停
+    pub quota_suspended: bool,
+    // 入口终止 TLS 使用的证书
+    pub tls_certificates: Vec<TunnelCertificateItem>,
+    // 出口连接目标时发送的 PROXY protocol 版本（0为不发送）
+    pub proxy_protocol: u8,
+    // 代理类通道允许访问的目标
+    pub acl_allow: Vec<String>,
+    // 代理类通道禁止访问的目标
+    pub acl_deny: Vec<String>,
     pub sender_online: bool,
     pub receiver_online: bool,
     pub available: bool,
 }
 
+/// 通道证书信息，不返回证书与私钥内容
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TunnelCertificateItem {
+    pub domains: Vec<String>,
+}
+
 /// 通道详情请求
 #[derive(Serialize, Deserialize)]
 pub struct TunnelDetailRequest {
@@ -53,6 +73,20 @@ pub struct TunnelDetailItem {
     pub is_compressed: bool,
     pub encryption_method: String,
     pub custom_mapping: HashMap<String, String>,
+    // 上行限速（字节/秒，0为不限速）
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    pub download_limit: i64,
+    // 是否因流量配额超额被暂停
+    pub quota_suspended: bool,
+    // 入口终止 TLS 使用的证书
+    pub tls_certificates: Vec<TunnelCertificateItem>,
+    // 出口连接目标时发送的 PROXY protocol 版本（0为不发送）
+    pub proxy_protocol: u8,
+    // 代理类通道允许访问的目标
+    pub acl_allow: Vec<String>,
+    // 代理类通道禁止访问的目标
+    pub acl_deny: Vec<String>,
     pub sender_online: bool,
     pub receiver_online: bool,
     pub available: bool,
@@ -85,6 +119,23 @@ pub struct TunnelStatusUpdateReq {
     pub enabled: u8,
 }
 
+/// 上传通道证书请求，证书域名与已有证书相同时替换
+#[derive(Serialize, Deserialize)]
+pub struct TunnelCertUploadReq {
+    pub tunnel_id: u32,
+    // PEM 证书链
+    pub certificate: String,
+    // PEM 私钥
+    pub private_key: String,
+}
+
+/// 删除通道证书请求
+#[derive(Serialize, Deserialize)]
+pub struct TunnelCertRemoveReq {
+    pub tunnel_id: u32,
+    pub index: usize,
+}
+
 /// 通道诊断请求
 #[derive(Serialize, Deserialize)]
 pub struct TunnelDiagnoseRequest {
@@ -126,6 +177,21 @@ pub struct TunnelAddReq {
     pub is_compressed: u8,
     pub encryption_method: String,
     pub custom_mapping: HashMap<String, String>,
+    // 上行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub download_limit: i64,
+    // 出口连接目标时发送的 PROXY protocol 版本（0为不发送）
+    #[serde(default)]
+    pub proxy_protocol: u8,
+    // 代理类通道允许访问的目标
+    #[serde(default)]
+    pub acl_allow: Vec<String>,
+    // 代理类通道禁止访问的目标
+    #[serde(default)]
+    pub acl_deny: Vec<String>,
 }
 
 /// 修改通道请求
@@ -144,5 +210,108 @@ pub struct TunnelUpdateReq {
     pub is_compressed: u8,
     pub encryption_method: String,
     pub custom_mapping: HashMap<String, String>,
+    // 上行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub upload_limit: i64,
+    // 下行限速（字节/秒，0为不限速）
+    #[serde(default)]
+    pub download_limit: i64,
+    // 出口连接目标时发送的 PROXY protocol 版本（0为不发送）
+    #[serde(default)]
+    pub proxy_protocol: u8,
+    // 代理类通道允许访问的目标
+    #[serde(default)]
+    pub acl_allow: Vec<String>,
+    // 代理类通道禁止访问的目标
+    #[serde(default)]
+    pub acl_deny: Vec<String>,
     pub preserve_password: Option<bool>,
 }
+
+/// 通道流量统计请求
+#[derive(Serialize, Deserialize)]
+pub struct TunnelTrafficStatsRequest {
+    /// 通道 ID，为空时查询所有可见通道
+    pub tunnel_id: Option<u32>,
+    /// 起始时间（Unix 秒），默认为结束时间前 24 小时
+    pub start_time: Option<i64>,
+    /// 结束时间（Unix 秒），默认为当前时间
+    pub end_time: Option<i64>,
+}
+
+/// 通道流量小时数据
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TunnelTrafficHourItem {
+    pub tunnel_id: u32,
+    pub hour: String,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub sessions: i64,
+}
+
+/// 通道流量汇总，按总流量从大到小排序
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TunnelTrafficSummaryItem {
+    pub tunnel_id: u32,
+    // 通道已删除时为空
+    pub source: String,
+    pub description: String,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub sessions: i64,
+}
+
+/// 通道流量统计响应
+#[derive(Serialize, Deserialize)]
+pub struct TunnelTrafficStatsResponse {
+    /// 查询范围（UTC 小时，含首尾）
+    pub start_hour: String,
+    pub end_hour: String,
+    pub items: Vec<TunnelTrafficHourItem>,
+    pub tunnels: Vec<TunnelTrafficSummaryItem>,
+    pub total_in: i64,
+    pub total_out: i64,
+    pub total_sessions: i64,
+}
+
+/// 通道活跃会话查询请求
+#[derive(Serialize, Deserialize)]
+pub struct TunnelSessionsRequest {
+    pub tunnel_id: u32,
+}
+
+/// 通道活跃会话
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TunnelSessionItem {
+    /// 会话所在的玩家，0 为服务端
+    pub player_id: u32,
+    pub session_id: u32,
+    /// 入口会话或出口会话
+    pub is_inlet: bool,
+    pub client_addr: String,
+    pub target_addr: String,
+    /// 会话开始时间（Unix 秒）
+    pub start_time: u64,
+    /// 上行字节数（客户端 -> 目标地址）
+    pub bytes_in: u64,
+    /// 下行字节数（目标地址 -> 客户端）
+    pub bytes_out: u64,
+}
+
+/// 通道活跃会话查询响应
+#[derive(Serialize, Deserialize)]
+pub struct TunnelSessionsResponse {
+    pub sessions: Vec<TunnelSessionItem>,
+    /// 在线但未能查询到会话的玩家
+    pub unreachable: Vec<u32>,
+}
+
+/// 强制关闭通道会话请求
+#[derive(Serialize, Deserialize)]
+pub struct TunnelSessionCloseReq {
+    pub tunnel_id: u32,
+    /// 会话所在的玩家，0 为服务端
+    pub player_id: u32,
+    pub session_id: u32,
+    pub is_inlet: bool,
+}
@@ -1,9 +1,10 @@
 use super::proto;
 use crate::global::config::GLOBAL_CONFIG;
 use crate::global::manager::GLOBAL_MANAGER;
+use crate::global::quota::quota_usage;
 use crate::global::GLOBAL_DB_POOL;
-use crate::orm_entity::operation_log;
 use crate::orm_entity::prelude::User;
+use crate::orm_entity::{operation_log, user};
 use actix_identity::Identity;
 use actix_web::{error, Error, HttpResponse};
 use chrono::Utc;
@@ -34,7 +35,7 @@ pub(super) async fn auth_context(
         return Ok(AuthContext {
             role: "admin".to_owned(),
             user_id: None,
-            username: Some(GLOBAL_CONFIG.web_username.clone()),
+            username: Some(GLOBAL_CONFIG.load().web_username.clone()),
         });
     }
 
@@ -134,6 +135,31 @@ pub(super) async fn player_online(player_id: u32) -> bool {
     false
 }
 
+/// 玩家流量配额及当前周期使用情况
+pub(super) async fn quota_usage_item(
+    user: &user::Model,
+) -> actix_web::Result<proto::QuotaUsageItem, Error> {
+    let usage = quota_usage(user)
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+    Ok(proto::QuotaUsageItem {
+        user_id: user.id,
+        username: user.username.clone(),
+        quota_bytes: user.quota_bytes,
+        quota_period: user.quota_period,
+        quota_reset_day: user.quota_reset_day,
+        quota_hard: user.quota_hard == 1,
+        quota_exceeded: user.quota_exceeded == 1,
+        used_bytes: usage.used_bytes,
+        period_start: usage
+            .period_start
+            .map_or(0, |time| time.and_utc().timestamp()),
+        next_reset: usage
+            .next_reset
+            .map_or(0, |time| time.and_utc().timestamp()),
+    })
+}
+
 pub(super) fn bool_text(value: bool) -> &'static str {
     if value {
         "enabled"
@@ -2,19 +2,26 @@ use super::proto;
 use super::support::{
     auth_context, bool_text, forbidden_response, player_online, record_operation, AuthContext,
 };
+use crate::global::manager::tunnel::{format_acl_rules, TunnelCertificate};
 use crate::global::manager::GLOBAL_MANAGER;
 use crate::global::GLOBAL_DB_POOL;
 use crate::orm_entity::prelude::User;
 use crate::orm_entity::tunnel;
+use crate::orm_entity::tunnel_traffic_hourly;
 use crate::orm_entity::user;
 use crate::utils::str::{
     get_tunnel_address_port, is_valid_tunnel_endpoint_address, is_valid_tunnel_source_address,
 };
 use actix_identity::Identity;
-use actix_web::{HttpResponse, Responder};
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use actix_web::{error, HttpResponse, Responder};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use np_base::net::tls::certificate_domains;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
 use std::collections::HashMap;
 
+/// 通道流量统计单次查询的最大时间跨度（小时）
+const MAX_TUNNEL_TRAFFIC_RANGE_HOURS: i64 = 24 * 31;
+
 pub(super) async fn tunnel_list(
     identity: Option<Identity>,
     body: String,
@@ -90,6 +97,9 @@ pub(super) async fn tunnel_list(
         let sender_online = player_online(data.sender).await;
         let receiver_online = player_online(data.receiver).await;
         let available = data.enabled == 1 && sender_online && receiver_online;
+        let tls_certificates = tunnel_certificate_items(&data);
+        let acl_allow = data.acl_allow_rules();
+        let acl_deny = data.acl_deny_rules();
         let sender_name = user_name_map.get(&data.sender).cloned().unwrap_or_default();
         let receiver_name = user_name_map
             .get(&data.receiver)
@@ -110,6 +120,13 @@ pub(super) async fn tunnel_list(
             username: data.username,
             is_compressed: data.is_compressed == 1,
             encryption_method: data.encryption_method,
+            upload_limit: data.upload_limit,
+            download_limit: data.download_limit,
+            quota_suspended: data.quota_suspended == 1,
+            tls_certificates,
+            proxy_protocol: data.proxy_protocol,
+            acl_allow,
+            acl_deny,
             custom_mapping,
             sender_online,
             receiver_online,
@@ -124,6 +141,16 @@ pub(super) async fn tunnel_list(
     }))
 }
 
+/// 通道证书列表，只返回证书中的域名
+fn tunnel_certificate_items(data: &tunnel::Model) -> Vec<proto::TunnelCertificateItem> {
+    data.tls_certificates()
+        .iter()
+        .map(|cert| proto::TunnelCertificateItem {
+            domains: certificate_domains(&cert.certificate, &cert.private_key).unwrap_or_default(),
+        })
+        .collect()
+}
+
 fn user_tunnel_allowed(auth: &AuthContext, sender: u32, receiver: u32) -> bool {
     auth.role == "admin"
         || auth
@@ -187,6 +214,13 @@ pub(super) async fn tunnel_detail(
                 username: data.username.clone(),
                 is_compressed: data.is_compressed == 1,
                 encryption_method: data.encryption_method.clone(),
+                upload_limit: data.upload_limit,
+                download_limit: data.download_limit,
+                quota_suspended: data.quota_suspended == 1,
+                tls_certificates: tunnel_certificate_items(data),
+                proxy_protocol: data.proxy_protocol,
+                acl_allow: data.acl_allow_rules(),
+                acl_deny: data.acl_deny_rules(),
                 custom_mapping,
                 sender_online: false,
                 receiver_online: false,
@@ -275,6 +309,13 @@ pub(super) async fn add_tunnel(
         is_compressed: req.is_compressed,
         custom_mapping: serde_json::to_string(&req.custom_mapping).map_or("".to_string(), |x| x),
         encryption_method: req.encryption_method,
+        upload_limit: req.upload_limit,
+        download_limit: req.download_limit,
+        quota_suspended: 0,
+        tls_certificates: String::new(),
+        proxy_protocol: req.proxy_protocol,
+        acl_allow: format_acl_rules(&req.acl_allow),
+        acl_deny: format_acl_rules(&req.acl_deny),
     };
     let source = new_tunnel.source.clone();
     match GLOBAL_MANAGER
@@ -310,6 +351,10 @@ fn tunnel_type_name(tunnel_type: u32) -> &'static str {
         1 => "UDP",
         2 => "SOCKS5",
         3 => "HTTP",
+        4 => "SOCKS4",
+        5 => "MIXED",
+        6 => "TRANSPARENT",
+        7 => "VHOST",
         _ => "Unknown",
     }
 }
@@ -360,6 +405,36 @@ fn build_tunnel_update_detail(old: &tunnel::Model, new: &tunnel::Model) -> Strin
         &old.encryption_method,
         &new.encryption_method,
     );
+    push_change(
+        &mut changes,
+        "upload_limit",
+        old.upload_limit,
+        new.upload_limit,
+    );
+    push_change(
+        &mut changes,
+        "download_limit",
+        old.download_limit,
+        new.download_limit,
+    );
+    push_change(
+        &mut changes,
+        "proxy_protocol",
+        old.proxy_protocol,
+        new.proxy_protocol,
+    );
+    push_change(
+        &mut changes,
+        "acl_allow",
+        old.acl_allow.replace('\n', ", "),
+        new.acl_allow.replace('\n', ", "),
+    );
+    push_change(
+        &mut changes,
+        "acl_deny",
+        old.acl_deny.replace('\n', ", "),
+        new.acl_deny.replace('\n', ", "),
+    );
     push_change(
         &mut changes,
         "mapping",
@@ -401,6 +476,27 @@ fn build_tunnel_snapshot(tunnel: &tunnel::Model) -> String {
         format!("encryption: {}", tunnel.encryption_method),
     ];
 
+    if tunnel.upload_limit > 0 || tunnel.download_limit > 0 {
+        parts.push(format!(
+            "rate_limit: up {} B/s, down {} B/s",
+            tunnel.upload_limit, tunnel.download_limit
+        ));
+    }
+
+    if tunnel.proxy_protocol > 0 {
+        parts.push(format!("proxy_protocol: v{}", tunnel.proxy_protocol));
+    }
+
+    if !tunnel.acl_allow.is_empty() {
+        parts.push(format!(
+            "acl_allow: {}",
+            tunnel.acl_allow.replace('\n', ", ")
+        ));
+    }
+    if !tunnel.acl_deny.is_empty() {
+        parts.push(format!("acl_deny: {}", tunnel.acl_deny.replace('\n', ", ")));
+    }
+
     if !tunnel.custom_mapping.is_empty() && tunnel.custom_mapping != "{}" {
         parts.push(format!("mapping: {}", tunnel.custom_mapping));
     }
@@ -446,6 +542,13 @@ pub(super) async fn update_tunnel(
         is_compressed: req.is_compressed,
         custom_mapping: serde_json::to_string(&req.custom_mapping).map_or("".to_string(), |x| x),
         encryption_method: req.encryption_method,
+        upload_limit: req.upload_limit,
+        download_limit: req.download_limit,
+        quota_suspended: 0,
+        tls_certificates: String::new(),
+        proxy_protocol: req.proxy_protocol,
+        acl_allow: format_acl_rules(&req.acl_allow),
+        acl_deny: format_acl_rules(&req.acl_deny),
     };
     let mut log_tunnel = new_tunnel.clone();
     if req.preserve_password.unwrap_or(false) && log_tunnel.password.is_empty() {
@@ -536,6 +639,140 @@ pub(super) async fn update_tunnel_status(
     }
 }
 
+pub(super) async fn upload_tunnel_cert(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    let auth = auth_context(identity).await?;
+
+    let req = serde_json::from_str::<proto::TunnelCertUploadReq>(&body)?;
+    if !user_can_manage_tunnel(&auth, req.tunnel_id).await {
+        return Ok(forbidden_response());
+    }
+    let domains = match certificate_domains(&req.certificate, &req.private_key) {
+        Ok(domains) if !domains.is_empty() => domains,
+        Ok(_) => {
+            return Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: -1,
+                msg: "certificate has no dns name".into(),
+            }))
+        }
+        Err(err) => {
+            return Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: -1,
+                msg: err.to_string(),
+            }))
+        }
+    };
+    let Some(mut certificates) = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .find(|item| item.id == req.tunnel_id)
+        .map(|item| item.tls_certificates())
+    else {
+        return Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: format!("Unable to find tunnel_id: {}", req.tunnel_id),
+        }));
+    };
+
+    // 域名完全相同视为证书续期，替换旧证书
+    let certificate = TunnelCertificate {
+        certificate: req.certificate,
+        private_key: req.private_key,
+    };
+    match certificates.iter().position(|cert| {
+        certificate_domains(&cert.certificate, &cert.private_key)
+            .is_ok_and(|names| names == domains)
+    }) {
+        Some(index) => certificates[index] = certificate,
+        None => certificates.push(certificate),
+    }
+
+    match GLOBAL_MANAGER
+        .tunnel_manager
+        .update_tunnel_certificates(req.tunnel_id, certificates)
+        .await
+    {
+        Ok(()) => {
+            record_operation(
+                "upload_tunnel_cert",
+                "tunnel",
+                req.tunnel_id,
+                &format!("#{}", req.tunnel_id),
+                &format!("domains: {}", domains.join(", ")),
+            )
+            .await;
+            Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }))
+        }
+        Err(err) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: err.to_string(),
+        })),
+    }
+}
+
+pub(super) async fn remove_tunnel_cert(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    let auth = auth_context(identity).await?;
+
+    let req = serde_json::from_str::<proto::TunnelCertRemoveReq>(&body)?;
+    if !user_can_manage_tunnel(&auth, req.tunnel_id).await {
+        return Ok(forbidden_response());
+    }
+    let mut certificates = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .find(|item| item.id == req.tunnel_id)
+        .map(|item| item.tls_certificates())
+        .unwrap_or_default();
+    if req.index >= certificates.len() {
+        return Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: "certificate not found".into(),
+        }));
+    }
+    let removed = certificates.remove(req.index);
+
+    match GLOBAL_MANAGER
+        .tunnel_manager
+        .update_tunnel_certificates(req.tunnel_id, certificates)
+        .await
+    {
+        Ok(()) => {
+            let domains =
+                certificate_domains(&removed.certificate, &removed.private_key).unwrap_or_default();
+            record_operation(
+                "remove_tunnel_cert",
+                "tunnel",
+                req.tunnel_id,
+                &format!("#{}", req.tunnel_id),
+                &format!("domains: {}", domains.join(", ")),
+            )
+            .await;
+            Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }))
+        }
+        Err(err) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: err.to_string(),
+        })),
+    }
+}
+
 pub(super) async fn tunnel_diagnose(
     identity: Option<Identity>,
     body: String,
@@ -561,7 +798,7 @@ pub(super) async fn tunnel_diagnose(
         "Source address format error",
     );
 
-    let needs_endpoint = matches!(req.tunnel_type, 0 | 1);
+    let needs_endpoint = matches!(req.tunnel_type, 0 | 1 | 7);
     if needs_endpoint {
         push_diagnose(
             &mut items,
@@ -606,7 +843,7 @@ pub(super) async fn tunnel_diagnose(
             req.receiver,
             get_tunnel_address_port(&req.source),
             req.id,
-            req.tunnel_type == 1,
+            req.tunnel_type,
         )
         .await;
     push_diagnose(
@@ -655,3 +892,291 @@ fn push_runtime_diagnose(
         message: message.to_owned(),
     });
 }
+
+pub(super) async fn tunnel_traffic_stats(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    let auth = auth_context(identity).await?;
+
+    let req = serde_json::from_str::<proto::TunnelTrafficStatsRequest>(&body)?;
+    let end = match req.end_time {
+        Some(time) => DateTime::from_timestamp(time, 0)
+            .ok_or_else(|| error::ErrorBadRequest("invalid end_time"))?,
+        None => Utc::now(),
+    };
+    let start = match req.start_time {
+        Some(time) => DateTime::from_timestamp(time, 0)
+            .ok_or_else(|| error::ErrorBadRequest("invalid start_time"))?,
+        None => end - ChronoDuration::hours(24),
+    };
+    if start > end {
+        return Err(error::ErrorBadRequest("start_time is after end_time"));
+    }
+    if end - start > ChronoDuration::hours(MAX_TUNNEL_TRAFFIC_RANGE_HOURS) {
+        return Err(error::ErrorBadRequest(format!(
+            "time range exceeds {} hours",
+            MAX_TUNNEL_TRAFFIC_RANGE_HOURS
+        )));
+    }
+    let start_hour = start.format("%Y-%m-%d %H").to_string();
+    let end_hour = end.format("%Y-%m-%d %H").to_string();
+
+    // 普通用户只能查看自己作为发送方或接收方的通道
+    let tunnels: HashMap<u32, tunnel::Model> = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .filter(|it| {
+            auth.role == "admin"
+                || auth
+                    .user_id
+                    .is_some_and(|user_id| it.sender == user_id || it.receiver == user_id)
+        })
+        .map(|it| (it.id, it.clone()))
+        .collect();
+    if let Some(tunnel_id) = req.tunnel_id {
+        if auth.role != "admin" && !tunnels.contains_key(&tunnel_id) {
+            return Ok(forbidden_response());
+        }
+    }
+
+    let mut query = tunnel_traffic_hourly::Entity::find()
+        .filter(tunnel_traffic_hourly::Column::Hour.gte(start_hour.as_str()))
+        .filter(tunnel_traffic_hourly::Column::Hour.lte(end_hour.as_str()));
+    if let Some(tunnel_id) = req.tunnel_id {
+        query = query.filter(tunnel_traffic_hourly::Column::TunnelId.eq(tunnel_id));
+    } else if auth.role != "admin" {
+        query = query.filter(
+            tunnel_traffic_hourly::Column::TunnelId
+                .is_in(tunnels.keys().copied().collect::<Vec<_>>()),
+        );
+    }
+    let rows = query
+        .order_by_desc(tunnel_traffic_hourly::Column::Hour)
+        .order_by_asc(tunnel_traffic_hourly::Column::TunnelId)
+        .all(GLOBAL_DB_POOL.get().unwrap())
+        .await
+        .map_err(|err| error::ErrorInternalServerError(format!("sql error:{}", err)))?;
+
+    let mut items: Vec<proto::TunnelTrafficHourItem> = rows
+        .into_iter()
+        .map(|row| proto::TunnelTrafficHourItem {
+            tunnel_id: row.tunnel_id,
+            hour: row.hour,
+            bytes_in: row.bytes_in,
+            bytes_out: row.bytes_out,
+            sessions: row.sessions,
+        })
+        .collect();
+
+    // 尚未刷库的计数归入当前小时
+    let current_hour = Utc::now().format("%Y-%m-%d %H").to_string();
+    if start_hour <= current_hour && current_hour <= end_hour {
+        let tunnel_ids: Vec<u32> = match req.tunnel_id {
+            Some(tunnel_id) => vec![tunnel_id],
+            None => tunnels.keys().copied().collect(),
+        };
+        for tunnel_id in tunnel_ids {
+            let (bytes_in, bytes_out, sessions) =
+                GLOBAL_MANAGER.proxy_manager.get_tunnel_traffic(tunnel_id);
+            if bytes_in == 0 && bytes_out == 0 && sessions == 0 {
+                continue;
+            }
+            if let Some(item) = items
+                .iter_mut()
+                .find(|item| item.tunnel_id == tunnel_id && item.hour == current_hour)
+            {
+                item.bytes_in += bytes_in as i64;
+                item.bytes_out += bytes_out as i64;
+                item.sessions += sessions as i64;
+            } else {
+                items.push(proto::TunnelTrafficHourItem {
+                    tunnel_id,
+                    hour: current_hour.clone(),
+                    bytes_in: bytes_in as i64,
+                    bytes_out: bytes_out as i64,
+                    sessions: sessions as i64,
+                });
+            }
+        }
+        items.sort_by(|a, b| b.hour.cmp(&a.hour).then(a.tunnel_id.cmp(&b.tunnel_id)));
+    }
+
+    let mut summaries: HashMap<u32, proto::TunnelTrafficSummaryItem> = HashMap::new();
+    for item in &items {
+        let summary = summaries.entry(item.tunnel_id).or_insert_with(|| {
+            let tunnel = tunnels.get(&item.tunnel_id);
+            proto::TunnelTrafficSummaryItem {
+                tunnel_id: item.tunnel_id,
+                source: tunnel.map(|it| it.source.clone()).unwrap_or_default(),
+                description: tunnel.map(|it| it.description.clone()).unwrap_or_default(),
+                bytes_in: 0,
+                bytes_out: 0,
+                sessions: 0,
+            }
+        });
+        summary.bytes_in += item.bytes_in;
+        summary.bytes_out += item.bytes_out;
+        summary.sessions += item.sessions;
+    }
+    let mut summaries: Vec<_> = summaries.into_values().collect();
+    summaries.sort_by(|a, b| {
+        (b.bytes_in + b.bytes_out)
+            .cmp(&(a.bytes_in + a.bytes_out))
+            .then(a.tunnel_id.cmp(&b.tunnel_id))
+    });
+
+    Ok(HttpResponse::Ok().json(proto::TunnelTrafficStatsResponse {
+        start_hour,
+        end_hour,
+        total_in: summaries.iter().map(|it| it.bytes_in).sum(),
+        total_out: summaries.iter().map(|it| it.bytes_out).sum(),
+        total_sessions: summaries.iter().map(|it| it.sessions).sum(),
+        items,
+        tunnels: summaries,
+    }))
+}
+
+pub(super) async fn tunnel_sessions(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    let auth = auth_context(identity).await?;
+
+    let req = serde_json::from_str::<proto::TunnelSessionsRequest>(&body)?;
+    // 普通用户只能查看自己作为发送方或接收方的通道
+    let Some((sender, receiver)) = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .find(|it| it.id == req.tunnel_id)
+        .map(|it| (it.sender, it.receiver))
+    else {
+        return Err(error::ErrorNotFound("tunnel not found"));
+    };
+    if auth.role != "admin"
+        && !auth
+            .user_id
+            .is_some_and(|user_id| sender == user_id || receiver == user_id)
+    {
+        return Ok(forbidden_response());
+    }
+
+    let mut player_ids = vec![sender, receiver];
+    player_ids.dedup();
+    let mut sessions = Vec::new();
+    let mut unreachable = Vec::new();
+    for player_id in player_ids {
+        let infos = if player_id == 0 {
+            GLOBAL_MANAGER.proxy_manager.local_sessions(req.tunnel_id)
+        } else if player_online(player_id).await {
+            match GLOBAL_MANAGER
+                .proxy_manager
+                .remote_sessions(player_id, req.tunnel_id)
+                .await
+            {
+                Ok(infos) => infos,
+                Err(err) => {
+                    log::warn!(
+                        "query sessions of tunnel {} from player {} error: {}",
+                        req.tunnel_id,
+                        player_id,
+                        err
+                    );
+                    unreachable.push(player_id);
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+        sessions.extend(infos.into_iter().map(|info| proto::TunnelSessionItem {
+            player_id,
+            session_id: info.session_id,
+            is_inlet: info.is_inlet,
+            client_addr: info.client_addr,
+            target_addr: info.target_addr,
+            start_time: info.start_time,
+            bytes_in: info.bytes_in,
+            bytes_out: info.bytes_out,
+        }));
+    }
+    sessions.sort_by_key(|it| (it.start_time, it.session_id));
+
+    Ok(HttpResponse::Ok().json(proto::TunnelSessionsResponse {
+        sessions,
+        unreachable,
+    }))
+}
+
+pub(super) async fn close_tunnel_session(
+    identity: Option<Identity>,
+    body: String,
+) -> actix_web::Result<impl Responder> {
+    let auth = auth_context(identity).await?;
+
+    let req = serde_json::from_str::<proto::TunnelSessionCloseReq>(&body)?;
+    if !user_can_manage_tunnel(&auth, req.tunnel_id).await {
+        return Ok(forbidden_response());
+    }
+    let belongs_to_tunnel = GLOBAL_MANAGER
+        .tunnel_manager
+        .tunnels
+        .read()
+        .await
+        .iter()
+        .any(|it| {
+            it.id == req.tunnel_id && (it.sender == req.player_id || it.receiver == req.player_id)
+        });
+    if !belongs_to_tunnel {
+        return Err(error::ErrorBadRequest("player is not part of the tunnel"));
+    }
+
+    let result = if req.player_id == 0 {
+        Ok(GLOBAL_MANAGER.proxy_manager.close_local_session(
+            req.tunnel_id,
+            req.session_id,
+            req.is_inlet,
+        ))
+    } else {
+        GLOBAL_MANAGER
+            .proxy_manager
+            .close_remote_session(req.player_id, req.tunnel_id, req.session_id, req.is_inlet)
+            .await
+    };
+
+    match result {
+        Ok(true) => {
+            record_operation(
+                "close_tunnel_session",
+                "tunnel",
+                req.tunnel_id,
+                &format!("#{}", req.tunnel_id),
+                &format!(
+                    "player: {}; session: {}; side: {}",
+                    req.player_id,
+                    req.session_id,
+                    if req.is_inlet { "inlet" } else { "outlet" }
+                ),
+            )
+            .await;
+            Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+                code: 0,
+                msg: "Success".into(),
+            }))
+        }
+        Ok(false) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: "session not found".into(),
+        })),
+        Err(err) => Ok(HttpResponse::Ok().json(proto::GeneralResponse {
+            code: -1,
+            msg: err.to_string(),
+        })),
+    }
+}
@@ -3,6 +3,7 @@ import type {
   CleanupDatabaseRequest,
   CleanupDatabaseResponse,
   DatabaseMaintenanceInfoResponse,
+  GeneralResponse,
   OperationLogRequest,
   OperationLogResponse,
 } from '@/types'
@@ -19,4 +20,8 @@ export const operationApi = {
   maintenanceInfo(data: CleanupDatabaseRequest) {
     return request.post<DatabaseMaintenanceInfoResponse>('/api/database_maintenance_info', data)
   },
+
+  reloadConfig() {
+    return request.post<GeneralResponse>('/api/reload_config', {})
+  },
 }
@@ -9,6 +9,8 @@ import type {
   PlayerResetPasswordRequest,
   PlayerStatusUpdateRequest,
   PlayerWebAccessUpdateRequest,
+  PlayerRateLimitUpdateRequest,
+  PlayerQuotaUpdateRequest,
   PlayerRemoveRequest,
   KickPlayerRequest,
   PlayerDetailRequest,
@@ -41,6 +43,12 @@ export const playerApi = {
   updateWebAccess(data: PlayerWebAccessUpdateRequest) {
     return request.post<GeneralResponse>('/api/update_player_web_access', data)
   },
+  updateRateLimit(data: PlayerRateLimitUpdateRequest) {
+    return request.post<GeneralResponse>('/api/update_player_rate_limit', data)
+  },
+  updateQuota(data: PlayerQuotaUpdateRequest) {
+    return request.post<GeneralResponse>('/api/update_player_quota', data)
+  },
   remove(data: PlayerRemoveRequest) {
     return request.post<GeneralResponse>('/api/remove_player', data)
   },
@@ -1,6 +1,8 @@
 import request from './request'
 import type {
   GeneralResponse,
+  TunnelCertRemoveRequest,
+  TunnelCertUploadRequest,
   TunnelDetailRequest,
   TunnelDetailResponse,
   TunnelDiagnoseRequest,
@@ -9,7 +11,12 @@ import type {
   TunnelListResponse,
   TunnelMutateRequest,
   TunnelRemoveRequest,
+  TunnelSessionCloseRequest,
+  TunnelSessionsRequest,
+  TunnelSessionsResponse,
   TunnelStatusUpdateRequest,
+  TunnelTrafficStatsRequest,
+  TunnelTrafficStatsResponse,
 } from '@/types'
 
 export const tunnelApi = {
@@ -28,11 +35,26 @@ export const tunnelApi = {
   updateStatus(data: TunnelStatusUpdateRequest) {
     return request.post<GeneralResponse>('/api/update_tunnel_status', data)
   },
+  uploadCert(data: TunnelCertUploadRequest) {
+    return request.post<GeneralResponse>('/api/upload_tunnel_cert', data)
+  },
+  removeCert(data: TunnelCertRemoveRequest) {
+    return request.post<GeneralResponse>('/api/remove_tunnel_cert', data)
+  },
   diagnose(data: TunnelDiagnoseRequest) {
     return request.post<TunnelDiagnoseResponse>('/api/tunnel_diagnose', data)
   },
   remove(data: TunnelRemoveRequest) {
     return request.post<GeneralResponse>('/api/remove_tunnel', data)
   },
+  trafficStats(data: TunnelTrafficStatsRequest) {
+    return request.post<TunnelTrafficStatsResponse>('/api/tunnel_traffic_stats', data)
+  },
+  sessions(data: TunnelSessionsRequest) {
+    return request.post<TunnelSessionsResponse>('/api/tunnel_sessions', data)
+  },
+  closeSession(data: TunnelSessionCloseRequest) {
+    return request.post<GeneralResponse>('/api/close_tunnel_session', data)
+  },
 }
 
@@ -54,6 +54,7 @@ export interface DashboardOverviewResponse {
   total_players: number
   enabled_tunnels: number
   total_tunnels: number
+  quotas: QuotaUsageItem[]
   config: DashboardConfigInfo
   system: DashboardSystemInfo
 }
@@ -64,6 +65,8 @@ export interface Player {
   username: string
   enabled: boolean
   web_access: boolean
+  upload_limit: number
+  download_limit: number
   online: boolean
   ip_addr: string
   connection_protocol: string
@@ -147,6 +150,7 @@ export interface CleanupDatabaseResponse {
   login_history_deleted: number
   operation_log_deleted: number
   traffic_hourly_deleted: number
+  tunnel_traffic_hourly_deleted: number
 }
 
 export interface DatabaseMaintenanceTableInfo {
@@ -204,6 +208,35 @@ export interface PlayerWebAccessUpdateRequest {
   web_access: number
 }
 
+export interface PlayerRateLimitUpdateRequest {
+  id: number
+  upload_limit: number   // 字节/秒，0 为不限速
+  download_limit: number
+}
+
+export type QuotaPeriod = 0 | 1 // 0=按月重置 1=累计总量
+
+export interface PlayerQuotaUpdateRequest {
+  id: number
+  quota_bytes: number   // 字节，0 为不限额
+  quota_period: QuotaPeriod
+  quota_reset_day: number
+  quota_hard: boolean
+}
+
+export interface QuotaUsageItem {
+  user_id: number
+  username: string
+  quota_bytes: number
+  quota_period: QuotaPeriod
+  quota_reset_day: number
+  quota_hard: boolean
+  quota_exceeded: boolean
+  used_bytes: number
+  period_start: number  // 时间戳，累计总量为 0
+  next_reset: number    // 时间戳，累计总量为 0
+}
+
 export interface PlayerRemoveRequest {
   id: number
 }
@@ -231,6 +264,8 @@ export interface PlayerDetail {
   username: string
   enabled: boolean
   web_access: boolean
+  upload_limit: number
+  download_limit: number
   create_time: string
   online: boolean
   ip_addr: string
@@ -240,6 +275,7 @@ export interface PlayerDetail {
   bytes_out: number
   traffic_24h_in: number
   traffic_24h_out: number
+  quota: QuotaUsageItem
   tunnels: PlayerTunnelItem[]
   recent_logins: LoginHistoryItem[]
 }
@@ -249,8 +285,8 @@ export interface PlayerDetailResponse {
 }
 
 // ── Tunnel ─────────────────────────────────────────────────────────────────
-export type TunnelType = 0 | 1 | 2 | 3 // 0=TCP 1=UDP 2=SOCKS5 3=HTTP
-export type EncryptionMethod = 'None' | 'Xor' | 'Aes128'
+export type TunnelType = 0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 // 0=TCP 1=UDP 2=SOCKS5 3=HTTP 4=SOCKS4 5=MIXED 6=TRANSPARENT 7=VHOST
+export type EncryptionMethod = 'None' | 'Xor' | 'Aes128' | 'Aes256Gcm' | 'ChaCha20Poly1305'
 
 export interface Tunnel {
   id: number
@@ -267,11 +303,23 @@ export interface Tunnel {
   is_compressed: boolean
   encryption_method: EncryptionMethod
   custom_mapping: Record<string, string>
+  upload_limit: number
+  download_limit: number
+  quota_suspended: boolean
+  tls_certificates: TunnelCertificateItem[]
+  proxy_protocol: number  // 0 不发送，1 v1，2 v2
+  acl_allow: string[]     // 允许访问的目标
+  acl_deny: string[]      // 禁止访问的目标
   sender_online: boolean
   receiver_online: boolean
   available: boolean
 }
 
+/** 通道证书，只包含证书中的域名 */
+export interface TunnelCertificateItem {
+  domains: string[]
+}
+
 export interface TunnelDetail extends Tunnel {
   password: string
 }
@@ -309,6 +357,11 @@ export interface TunnelMutateRequest {
   is_compressed: number  // 0 | 1
   encryption_method: string
   custom_mapping: Record<string, string>
+  upload_limit: number   // 字节/秒，0 为不限速
+  download_limit: number
+  proxy_protocol: number  // 0 不发送，1 v1，2 v2
+  acl_allow: string[]
+  acl_deny: string[]
   preserve_password?: boolean
 }
 
@@ -321,6 +374,17 @@ export interface TunnelStatusUpdateRequest {
   enabled: number
 }
 
+export interface TunnelCertUploadRequest {
+  tunnel_id: number
+  certificate: string
+  private_key: string
+}
+
+export interface TunnelCertRemoveRequest {
+  tunnel_id: number
+  index: number
+}
+
 export interface TunnelDiagnoseRequest {
   id?: number
   source: string
@@ -340,3 +404,63 @@ export interface TunnelDiagnoseResponse {
   ok: boolean
   items: TunnelDiagnoseItem[]
 }
+
+export interface TunnelTrafficStatsRequest {
+  tunnel_id?: number
+  start_time?: number // Unix 秒
+  end_time?: number // Unix 秒
+}
+
+export interface TunnelTrafficHourItem {
+  tunnel_id: number
+  hour: string
+  bytes_in: number
+  bytes_out: number
+  sessions: number
+}
+
+export interface TunnelTrafficSummaryItem {
+  tunnel_id: number
+  source: string
+  description: string
+  bytes_in: number
+  bytes_out: number
+  sessions: number
+}
+
+export interface TunnelTrafficStatsResponse {
+  start_hour: string
+  end_hour: string
+  items: TunnelTrafficHourItem[]
+  tunnels: TunnelTrafficSummaryItem[]
+  total_in: number
+  total_out: number
+  total_sessions: number
+}
+
+export interface TunnelSessionsRequest {
+  tunnel_id: number
+}
+
+export interface TunnelSessionItem {
+  player_id: number // 0 = 服务端
+  session_id: number
+  is_inlet: boolean
+  client_addr: string
+  target_addr: string
+  start_time: number // Unix 秒
+  bytes_in: number
+  bytes_out: number
+}
+
+export interface TunnelSessionsResponse {
+  sessions: TunnelSessionItem[]
+  unreachable: number[]
+}
+
+export interface TunnelSessionCloseRequest {
+  tunnel_id: number
+  player_id: number
+  session_id: number
+  is_inlet: boolean
+}